@@ -0,0 +1,184 @@
+//! Worst-case stall benchmark for the interactive event queue
+//!
+//! Compares how long the generator thread can be held up by the IPC side:
+//!
+//! - **before**: `Arc<Mutex<VecDeque>>` locked once per output sample, while the
+//!   producer pushes phrases and drains + re-sorts the whole deque on every
+//!   out-of-order insert (the previous `Player::schedule_register_write`).
+//! - **after**: the lock-free submission ring, drained once per buffer and merged
+//!   on the generator side.
+//!
+//! Run with `cargo bench --bench event_queue_latency`.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ym2151_log_play_server::player::ProcessedEvent;
+use ym2151_log_play_server::submission_ring::{
+    submission_ring, SubmissionOp, DEFAULT_RING_CAPACITY,
+};
+
+const BUFFER_SAMPLES: u32 = 1024;
+const BUFFERS: u32 = 2_000;
+const PHRASE_EVENTS: u32 = 4_000;
+
+struct Stats {
+    samples: Vec<Duration>,
+}
+
+impl Stats {
+    fn new() -> Self {
+        Self {
+            samples: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, d: Duration) {
+        self.samples.push(d);
+    }
+
+    fn report(&mut self, label: &str) {
+        self.samples.sort();
+        let n = self.samples.len().max(1);
+        let total: Duration = self.samples.iter().sum();
+        let pct = |p: f64| self.samples[((n as f64 * p) as usize).min(n - 1)];
+        println!(
+            "  {:<38} n={:>9} mean={:>9.2?} p99={:>9.2?} p99.9={:>9.2?} max={:>9.2?}",
+            label,
+            self.samples.len(),
+            total / n as u32,
+            pct(0.99),
+            pct(0.999),
+            self.samples.last().copied().unwrap_or_default()
+        );
+    }
+}
+
+/// Phrase with a few out-of-order writes, like a client re-sending overlapping notes
+fn phrase(base: u32) -> impl Iterator<Item = ProcessedEvent> {
+    (0..PHRASE_EVENTS).map(move |i| {
+        let jitter = if i % 16 == 0 { 0 } else { 8 };
+        ProcessedEvent {
+            time: base + i * 4 + jitter,
+            addr: 0x08,
+            data: (i & 0xFF) as u8,
+        }
+    })
+}
+
+fn bench_mutex_vecdeque() {
+    let queue: Arc<Mutex<VecDeque<ProcessedEvent>>> = Arc::new(Mutex::new(VecDeque::new()));
+    let running = Arc::new(AtomicBool::new(true));
+
+    let producer = {
+        let queue = queue.clone();
+        let running = running.clone();
+        thread::spawn(move || {
+            let mut hold = Stats::new();
+            let mut base = 0;
+            while running.load(Ordering::Relaxed) {
+                for event in phrase(base) {
+                    let start = Instant::now();
+                    let mut q = queue.lock().unwrap();
+                    q.push_back(event);
+                    let len = q.len();
+                    if len >= 2 && q[len - 1].time < q[len - 2].time {
+                        let mut vec: Vec<_> = q.drain(..).collect();
+                        vec.sort_by_key(|e| e.time);
+                        q.extend(vec);
+                    }
+                    drop(q);
+                    hold.record(start.elapsed());
+                }
+                base += PHRASE_EVENTS * 4;
+            }
+            hold
+        })
+    };
+
+    let mut stall = Stats::new();
+    let mut now = 0u32;
+    for _ in 0..BUFFERS {
+        for _ in 0..BUFFER_SAMPLES {
+            let start = Instant::now();
+            let mut q = queue.lock().unwrap();
+            while q.front().is_some_and(|e| e.time <= now) {
+                q.pop_front();
+            }
+            drop(q);
+            stall.record(start.elapsed());
+            now += 1;
+        }
+    }
+    running.store(false, Ordering::Relaxed);
+    let mut hold = producer.join().unwrap();
+
+    println!("before (Mutex<VecDeque>, lock per sample):");
+    stall.report("generator lock wait+hold per sample");
+    hold.report("producer lock hold per write");
+}
+
+fn bench_submission_ring() {
+    let (submitter, mut receiver) = submission_ring(DEFAULT_RING_CAPACITY);
+    let running = Arc::new(AtomicBool::new(true));
+
+    let producer = {
+        let running = running.clone();
+        thread::spawn(move || {
+            let mut submit = Stats::new();
+            let mut base = 0;
+            while running.load(Ordering::Relaxed) {
+                for event in phrase(base) {
+                    let start = Instant::now();
+                    if submitter.try_submit(SubmissionOp::Write(event)).is_err() {
+                        // Ring full: the producer backs off, the generator is unaffected
+                        thread::yield_now();
+                        continue;
+                    }
+                    submit.record(start.elapsed());
+                }
+                base += PHRASE_EVENTS * 4;
+            }
+            submit
+        })
+    };
+
+    let mut drain = Stats::new();
+    let mut schedule: VecDeque<ProcessedEvent> = VecDeque::new();
+    let mut now = 0u32;
+    for _ in 0..BUFFERS {
+        let start = Instant::now();
+        while let Some(op) = receiver.pop() {
+            if let SubmissionOp::Write(event) = op {
+                let pos = schedule.partition_point(|e| e.time <= event.time);
+                schedule.insert(pos, event);
+            }
+        }
+        drain.record(start.elapsed());
+
+        for _ in 0..BUFFER_SAMPLES {
+            while schedule.front().is_some_and(|e| e.time <= now) {
+                schedule.pop_front();
+            }
+            now += 1;
+        }
+    }
+    running.store(false, Ordering::Relaxed);
+    let mut submit = producer.join().unwrap();
+
+    println!("after (submission ring, drained per buffer):");
+    drain.report("generator drain+merge per buffer");
+    submit.report("producer submit per write (no lock)");
+}
+
+fn main() {
+    println!(
+        "event queue latency: {} buffers x {} samples, {} events per phrase",
+        BUFFERS, BUFFER_SAMPLES, PHRASE_EVENTS
+    );
+    bench_mutex_vecdeque();
+    bench_submission_ring();
+}
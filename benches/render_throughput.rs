@@ -0,0 +1,124 @@
+//! OPM rendering throughput: per-sample FFI vs one FFI call per buffer
+//!
+//! Throughput is reported in samples/s. Dividing by the OPM sample rate
+//! (55930 Hz) gives the realtime factor, i.e. how much headroom the generator
+//! thread has per buffer.
+//!
+//! Run with `cargo bench --bench render_throughput`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::mem;
+
+use ym2151_log_play_server::events::{EventLog, RegisterEvent};
+use ym2151_log_play_server::opm::{ChipWrite, OpmChip};
+use ym2151_log_play_server::opm_ffi;
+use ym2151_log_play_server::player::Player;
+
+const BUFFER_SIZES: [usize; 3] = [64, 1024, 4096];
+
+/// Previous implementation: one FFI call (64 clocks) per output sample
+fn render_per_sample(chip: &mut opm_ffi::opm_t, buffer: &mut [i16]) {
+    for frame in buffer.chunks_exact_mut(2) {
+        let mut output = [0i32; 2];
+        unsafe {
+            opm_ffi::call_opm_clock_64times(chip, output.as_mut_ptr());
+        }
+        frame[0] = (output[0] / 2).clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        frame[1] = (output[1] / 2).clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+    }
+}
+
+fn bench_chip(c: &mut Criterion) {
+    let mut group = c.benchmark_group("chip");
+
+    for &samples in &BUFFER_SIZES {
+        let mut buffer = vec![0i16; samples * 2];
+        group.throughput(Throughput::Elements(samples as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("per_sample_ffi", samples),
+            &samples,
+            |b, _| {
+                let mut chip: opm_ffi::opm_t = unsafe { mem::zeroed() };
+                unsafe { opm_ffi::OPM_Reset(&mut chip) };
+                b.iter(|| render_per_sample(&mut chip, black_box(&mut buffer)));
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("batch_render", samples),
+            &samples,
+            |b, _| {
+                let mut chip = OpmChip::new();
+                let writes: [ChipWrite; 0] = [];
+                b.iter(|| chip.render(black_box(&mut buffer), &writes));
+            },
+        );
+    }
+
+    group.finish();
+}
+
+/// A few seconds of notes on all 8 channels, as an offline render workload
+fn phrase_log(seconds: f64) -> EventLog {
+    let mut events = Vec::new();
+    let mut time = 0.0;
+    let mut note = 0u8;
+    while time < seconds {
+        for ch in 0..8u8 {
+            for (addr, data) in [
+                (0x20 + ch, 0xC7),
+                (0x28 + ch, 0x30 + (note + ch) % 0x40),
+                (0x08, 0x78 | ch),
+            ] {
+                events.push(RegisterEvent {
+                    time,
+                    addr,
+                    data,
+                    is_data: None,
+                });
+            }
+        }
+        time += 0.125;
+        for ch in 0..8u8 {
+            events.push(RegisterEvent {
+                time,
+                addr: 0x08,
+                data: ch,
+                is_data: None,
+            });
+        }
+        note = note.wrapping_add(3);
+    }
+    EventLog {
+        events,
+        ..Default::default()
+    }
+}
+
+fn bench_player(c: &mut Criterion) {
+    let log = phrase_log(2.0);
+    let total_samples = Player::new(log.clone()).total_samples() as usize;
+
+    let mut group = c.benchmark_group("player");
+    group.sample_size(20);
+    group.throughput(Throughput::Elements(total_samples as u64));
+
+    group.bench_function("offline_render_2s", |b| {
+        let mut buffer = vec![0i16; 2048];
+        b.iter(|| {
+            let mut player = Player::new(log.clone());
+            let mut rendered = 0;
+            while rendered < total_samples {
+                player.generate_samples(&mut buffer);
+                rendered += buffer.len() / 2;
+            }
+            black_box(&buffer);
+        });
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_chip, bench_player);
+criterion_main!(benches);
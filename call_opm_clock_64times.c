@@ -4,6 +4,15 @@
 // Include opm.c so this wrapper shares the same translation unit, ensuring optimizer visibility.
 #include "opm.c"
 
+// A register write applied at a sample offset within an opm_render() call.
+// Must match opm_write_t in src/opm_ffi.rs.
+typedef struct
+{
+    uint32_t offset;
+    uint8_t port;
+    uint8_t data;
+} opm_write_t;
+
 void call_opm_clock_64times(opm_t *chip, int32_t *output)
 {
     for (int i = 0; i < 64; i++)
@@ -11,3 +20,58 @@ void call_opm_clock_64times(opm_t *chip, int32_t *output)
         OPM_Clock(chip, output, NULL, NULL, NULL);
     }
 }
+
+// Clock one output sample, first applying the writes due at sample index i.
+// *w is the index of the next write not yet applied.
+static inline void render_frame(opm_t *chip, uint32_t i, const opm_write_t *writes,
+                                uint32_t num_writes, uint32_t *w, int32_t *output)
+{
+    while (*w < num_writes && writes[*w].offset <= i)
+    {
+        OPM_Write(chip, writes[*w].port, writes[*w].data);
+        (*w)++;
+    }
+
+    output[0] = 0;
+    output[1] = 0;
+    for (int c = 0; c < 64; c++)
+    {
+        OPM_Clock(chip, output, NULL, NULL, NULL);
+    }
+}
+
+// Render num_samples stereo samples into an interleaved int16 buffer in one call.
+// Register writes are applied just before the sample at their offset is clocked.
+// writes must be sorted by offset; writes with offset >= num_samples are ignored.
+void opm_render(opm_t *chip, int16_t *buffer, uint32_t num_samples,
+                const opm_write_t *writes, uint32_t num_writes)
+{
+    uint32_t w = 0;
+    for (uint32_t i = 0; i < num_samples; i++)
+    {
+        int32_t output[2];
+        render_frame(chip, i, writes, num_writes, &w, output);
+
+        for (int ch = 0; ch < 2; ch++)
+        {
+            int32_t s = output[ch] / 2;
+            if (s > INT16_MAX)
+                s = INT16_MAX;
+            if (s < INT16_MIN)
+                s = INT16_MIN;
+            buffer[i * 2 + ch] = (int16_t)s;
+        }
+    }
+}
+
+// Like opm_render, but stores the DAC output as it is (twice the int16 scale,
+// not clamped).
+void opm_render_full(opm_t *chip, int32_t *buffer, uint32_t num_samples,
+                     const opm_write_t *writes, uint32_t num_writes)
+{
+    uint32_t w = 0;
+    for (uint32_t i = 0; i < num_samples; i++)
+    {
+        render_frame(chip, i, writes, num_writes, &w, &buffer[i * 2]);
+    }
+}
@@ -10,18 +10,19 @@ use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 use crate::audio::commands::AudioCommand;
+use crate::audio::markers::MarkerFeed;
 use crate::audio_config::buffer::GENERATION_BUFFER_SIZE;
 use crate::debug_wav;
 use crate::events::EventLog;
 use crate::logging;
 use crate::player::Player;
-use crate::resampler::{AudioResampler, OPM_SAMPLE_RATE};
+use crate::resampler::{opm_sample_rate, AudioResampler, OUTPUT_SAMPLE_RATE, YM2151_CLOCK};
 
 /// Run the audio sample generation thread
 ///
 /// This function implements the core audio generation loop with the following features:
 /// - Windows MMCSS "Pro Audio" priority for minimal latency
-/// - OPM emulation at 55930 Hz native rate
+/// - OPM emulation at the player's native rate (55930 Hz at the default clock)
 /// - Real-time resampling to 48000 Hz output rate
 /// - WAV buffer recording for debugging
 /// - Graceful shutdown on Stop command
@@ -34,9 +35,11 @@ use crate::resampler::{AudioResampler, OPM_SAMPLE_RATE};
 /// * `wav_buffer_48k` - Shared buffer for 48kHz WAV samples
 /// * `event_log` - Optional event log for WAV file generation
 /// * `resampling_quality` - Quality setting for the resampler
+/// * `marker_feed` - Receives the log's markers as playback renders past them
 ///
 /// # Returns
 /// * `Result<()>` - Success or error result
+#[allow(clippy::too_many_arguments)]
 pub fn run_generator_thread(
     mut player: Player,
     sample_tx: SyncSender<Vec<f32>>,
@@ -45,13 +48,16 @@ pub fn run_generator_thread(
     wav_buffer_48k: Arc<Mutex<Vec<i16>>>,
     event_log: Option<EventLog>,
     resampling_quality: crate::resampler::ResamplingQuality,
+    marker_feed: MarkerFeed,
 ) -> Result<()> {
     // Set MMCSS Pro Audio priority for this thread on Windows
     // This handle will automatically revert priority when dropped
     let _mmcss_handle = crate::mmcss::MmcssHandle::set_pro_audio_priority();
 
-    let mut resampler = AudioResampler::with_quality(resampling_quality)
-        .context("Failed to initialize resampler")?;
+    let native_rate = player.current_sample_rate();
+    let mut resampler =
+        AudioResampler::with_rates_and_quality(native_rate, OUTPUT_SAMPLE_RATE, resampling_quality)
+            .context("Failed to initialize resampler")?;
     let mut generation_buffer = vec![0i16; GENERATION_BUFFER_SIZE * 2];
     let total_samples = player.total_samples();
 
@@ -60,7 +66,7 @@ pub fn run_generator_thread(
     logging::log_verbose_server("▶  Playing sequence...");
     logging::log_verbose_server(&format!(
         "  Duration: {:.2} seconds",
-        total_samples as f64 / OPM_SAMPLE_RATE as f64
+        total_samples as f64 / native_rate as f64
     ));
 
     let mut tail_reported = false;
@@ -82,7 +88,7 @@ pub fn run_generator_thread(
             ));
 
             if let Some((tail_samples, _)) = player.tail_info() {
-                let tail_ms = tail_samples as f64 / OPM_SAMPLE_RATE as f64 * 1000.0;
+                let tail_ms = tail_samples as f64 / native_rate as f64 * 1000.0;
                 logging::log_verbose_server(&format!(
                     "  演奏データの余韻{}ms 波形生成 OK",
                     tail_ms as u32
@@ -113,6 +119,19 @@ pub fn run_generator_thread(
         // Generate samples from the OPM emulation
         player.generate_samples(&mut generation_buffer);
 
+        let reached = player.take_reached_markers();
+        if !reached.is_empty() {
+            if logging::is_server_verbose() {
+                for marker in reached {
+                    logging::log_verbose_server(&format!(
+                        "📍 マーカー到達: {} ({:.3}秒)",
+                        marker.name, marker.time
+                    ));
+                }
+            }
+            marker_feed.publish(reached.len());
+        }
+
         // Store samples in 55kHz WAV buffer
         if let Ok(mut buffer) = wav_buffer_55k.lock() {
             buffer.extend_from_slice(&generation_buffer);
@@ -163,9 +182,13 @@ fn save_debug_wav_files(
     match debug_wav::generate_post_playback_buffers(event_log, resampling_quality) {
         Ok((post_55k, post_48k)) => {
             // Save all 4 WAV files
-            if let Err(e) =
-                debug_wav::save_debug_wav_files(&realtime_55k, &realtime_48k, &post_55k, &post_48k)
-            {
+            if let Err(e) = debug_wav::save_debug_wav_files_at(
+                &realtime_55k,
+                &realtime_48k,
+                &post_55k,
+                &post_48k,
+                opm_sample_rate(event_log.clock_or(YM2151_CLOCK)),
+            ) {
                 logging::log_always_server(&format!(
                     "⚠️  警告: WAVファイルの保存に失敗しました: {}",
                     e
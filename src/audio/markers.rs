@@ -0,0 +1,89 @@
+//! Marker-reached notifications
+//!
+//! The generator thread publishes each marker of the playing log as it renders
+//! past it; the server hands them to clients by sequence number, so a client can
+//! poll with the last number it saw and get every marker reached since.
+//!
+//! Markers are published when their sample is generated, which runs ahead of
+//! what is audible by the output buffering (a few tens of milliseconds).
+
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::events::Marker;
+
+/// A marker reached during playback
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarkerReached {
+    /// Sequence number, counting from 1 for each playback
+    pub seq: u64,
+    pub name: String,
+    /// Marker time in the log, in seconds
+    pub time: f64,
+}
+
+/// Markers reached so far, shared between the generator thread and the server
+///
+/// The feed holds the log's markers in playback order from the start, so the
+/// generator thread only advances an atomic count: publishing neither locks nor
+/// allocates on the realtime thread.
+#[derive(Debug, Clone, Default)]
+pub struct MarkerFeed {
+    inner: Arc<FeedState>,
+}
+
+#[derive(Debug, Default)]
+struct FeedState {
+    markers: Box<[Marker]>,
+    reached: AtomicUsize,
+}
+
+impl MarkerFeed {
+    /// A feed for `markers`, in the order playback reaches them
+    pub fn new(markers: Vec<Marker>) -> Self {
+        Self {
+            inner: Arc::new(FeedState {
+                markers: markers.into_boxed_slice(),
+                reached: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Record the next `count` markers as reached
+    pub fn publish(&self, count: usize) {
+        let state = &self.inner;
+        let reached = state.reached.load(Ordering::Relaxed);
+        let reached = (reached + count).min(state.markers.len());
+        state.reached.store(reached, Ordering::Release);
+    }
+
+    /// Markers reached after sequence number `after` (0 for all)
+    pub fn since(&self, after: u64) -> Vec<MarkerReached> {
+        let reached = self.reached();
+        let first = usize::try_from(after).map_or(reached, |after| after.min(reached));
+        (first..reached)
+            .map(|index| self.notification(index))
+            .collect()
+    }
+
+    /// The most recently reached marker
+    pub fn latest(&self) -> Option<MarkerReached> {
+        self.reached()
+            .checked_sub(1)
+            .map(|index| self.notification(index))
+    }
+
+    fn reached(&self) -> usize {
+        self.inner.reached.load(Ordering::Acquire)
+    }
+
+    fn notification(&self, index: usize) -> MarkerReached {
+        let marker = &self.inner.markers[index];
+        MarkerReached {
+            seq: index as u64 + 1,
+            name: marker.name.clone(),
+            time: marker.time,
+        }
+    }
+}
@@ -4,7 +4,7 @@
 //! to minimize audio dropouts:
 //!
 //! 1. **Generator Thread** (see `generator` module):
-//!    - Runs OPM emulation: `player.generate_samples()` → `chip.generate_samples()` → `call_opm_clock_64times()`
+//!    - Runs OPM emulation: `player.generate_samples()` → `chip.render()` → `opm_render()` (one FFI call per buffer)
 //!    - Priority boost: Windows MMCSS "Pro Audio" task (via `mmcss` module)
 //!    - Generates samples at OPM's native 55930 Hz rate
 //!
@@ -17,6 +17,7 @@
 pub mod buffers;
 pub mod commands;
 pub mod generator;
+pub mod markers;
 pub mod player;
 pub mod scheduler;
 pub mod stream;
@@ -24,5 +25,6 @@ pub mod stream;
 // Re-export the main public interfaces
 pub use buffers::WavBuffers;
 pub use commands::AudioCommand;
+pub use markers::{MarkerFeed, MarkerReached};
 pub use player::AudioPlayer;
 pub use scheduler::AudioScheduler;
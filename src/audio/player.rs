@@ -10,11 +10,14 @@ use std::time::Instant;
 use crate::audio::buffers::WavBuffers;
 use crate::audio::commands::AudioCommand;
 use crate::audio::generator;
+use crate::audio::markers::{MarkerFeed, MarkerReached};
 use crate::audio::scheduler::AudioScheduler;
 use crate::audio::stream::AudioStream;
 use crate::audio_config::buffer::SYNC_CHANNEL_CAPACITY;
+use crate::event_stream::StreamWriter;
 use crate::events::EventLog;
 use crate::player::Player;
+use crate::session_recorder::SessionRecorder;
 
 /// Main audio player with dual-thread architecture
 ///
@@ -38,6 +41,12 @@ pub struct AudioPlayer {
     event_log: Option<EventLog>,
     /// Interactive scheduler for real-time register writes
     scheduler: Option<AudioScheduler>,
+    /// Native OPM sample rate of the player (clock / 64)
+    native_sample_rate: u32,
+    /// Markers reached by the generator thread
+    markers: MarkerFeed,
+    /// Writer for streamed playback (None unless created with `new_streaming`)
+    event_stream: Option<StreamWriter>,
 }
 
 impl AudioPlayer {
@@ -86,17 +95,15 @@ impl AudioPlayer {
         let stream = AudioStream::new(sample_rx).context("Failed to create audio stream")?;
 
         // Set up interactive scheduler if needed
-        let scheduler = if player.is_interactive() {
-            Some(AudioScheduler::new(
-                player.get_event_queue(),
-                Instant::now(),
-            ))
-        } else {
-            None
-        };
+        let native_sample_rate = player.current_sample_rate();
+        let scheduler = player
+            .submitter()
+            .map(|submitter| AudioScheduler::new(submitter, Instant::now(), player.clock()));
 
         // Clone data for the generator thread
         let event_log_for_thread = event_log.clone();
+        let markers = MarkerFeed::new(player.markers().to_vec());
+        let marker_feed = markers.clone();
 
         // Spawn the generator thread
         let generator_handle = std::thread::spawn(move || {
@@ -108,6 +115,7 @@ impl AudioPlayer {
                 wav_buffer_48k,
                 event_log_for_thread,
                 resampling_quality,
+                marker_feed,
             ) {
                 // Sample generation errors should always be logged
                 crate::logging::log_always_server(&format!("Sample generation error: {}", e));
@@ -121,9 +129,46 @@ impl AudioPlayer {
             wav_buffers,
             event_log,
             scheduler,
+            native_sample_rate,
+            markers,
+            event_stream: None,
         })
     }
 
+    /// Create an AudioPlayer for a log uploaded in chunks
+    ///
+    /// Events are appended through [`AudioPlayer::stream_mut`]; playback starts
+    /// with the first chunk (see [`crate::event_stream`]).
+    pub fn new_streaming(
+        clock: u32,
+        resampling_quality: crate::resampler::ResamplingQuality,
+    ) -> Result<Self> {
+        let (player, writer) = Player::new_streaming_with_clock(clock);
+        let mut audio_player = Self::new_with_quality(player, None, resampling_quality)?;
+        audio_player.event_stream = Some(writer);
+        Ok(audio_player)
+    }
+
+    /// The stream writer, if this player plays a streamed log
+    pub fn stream_mut(&mut self) -> Option<&mut StreamWriter> {
+        self.event_stream.as_mut()
+    }
+
+    /// Markers reached after sequence number `after` (0 for all)
+    pub fn markers_since(&self, after: u64) -> Vec<MarkerReached> {
+        self.markers.since(after)
+    }
+
+    /// The most recently reached marker
+    pub fn last_marker(&self) -> Option<MarkerReached> {
+        self.markers.latest()
+    }
+
+    /// Native OPM sample rate of the running player (clock / 64)
+    pub fn native_sample_rate(&self) -> u32 {
+        self.native_sample_rate
+    }
+
     /// Schedule a register write in interactive mode
     ///
     /// # Arguments
@@ -263,6 +308,16 @@ impl AudioPlayer {
         }
     }
 
+    /// Recorder of the interactive session (None when not in interactive mode)
+    pub fn session_recorder(&self) -> Option<&SessionRecorder> {
+        self.scheduler.as_ref().map(|s| s.recorder())
+    }
+
+    /// The interactive session recorded so far (None when not in interactive mode)
+    pub fn recorded_session(&self) -> Option<EventLog> {
+        self.scheduler.as_ref().map(|s| s.recorded_session())
+    }
+
     /// Get current schedule queue size (number of scheduled events)
     pub fn get_scheduled_event_count(&self) -> Option<usize> {
         self.scheduler
@@ -3,35 +3,53 @@
 //! This module handles the scheduling of OPM register writes in interactive mode,
 //! allowing real-time manipulation of the audio stream. It provides time-based
 //! scheduling with sample-accurate timing.
+//!
+//! Writes are handed to the generator thread through the lock-free submission ring
+//! (see `submission_ring`), so scheduling never blocks audio generation. Every
+//! submission is also mirrored into a [`SessionRecorder`] so the session can be saved
+//! once recording has been started.
 
 use anyhow::Result;
-use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
+use crate::events::EventLog;
+use crate::resampler::opm_sample_rate;
+use crate::session_recorder::SessionRecorder;
+use crate::submission_ring::{EventSubmitter, SubmissionOp};
+
 /// Interactive audio scheduler for real-time register writes
 pub struct AudioScheduler {
-    /// Shared reference to player's event queue
-    player_event_queue: Arc<Mutex<std::collections::VecDeque<crate::player::ProcessedEvent>>>,
+    /// Producer handle to the player's submission ring
+    submitter: EventSubmitter,
     /// Audio stream start time for continuous time-based scheduling
     audio_start_time: Instant,
+    /// Native OPM sample rate of the player, for seconds to samples conversion
+    sample_rate: u32,
+    /// What was submitted while recording, for saving the session
+    recorder: SessionRecorder,
 }
 
 impl AudioScheduler {
     /// Create a new audio scheduler
     ///
     /// # Arguments
-    /// * `player_event_queue` - Shared event queue from the player
+    /// * `submitter` - Submission ring handle from the player
     /// * `audio_start_time` - Time when the audio stream started
-    pub fn new(
-        player_event_queue: Arc<Mutex<std::collections::VecDeque<crate::player::ProcessedEvent>>>,
-        audio_start_time: Instant,
-    ) -> Self {
+    /// * `clock` - Chip master clock of the player in Hz
+    pub fn new(submitter: EventSubmitter, audio_start_time: Instant, clock: u32) -> Self {
         Self {
-            player_event_queue,
+            submitter,
             audio_start_time,
+            sample_rate: opm_sample_rate(clock),
+            recorder: SessionRecorder::new(clock),
         }
     }
 
+    /// Native OPM sample rate used for scheduling
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
     /// Schedule a register write in interactive mode
     ///
     /// # Arguments
@@ -42,13 +60,8 @@ impl AudioScheduler {
         // Store addr-data pair directly in a single event
         // The 2-sample delay between address and data writes will be applied
         // at the final stage in generate_samples()
-        let mut q = self.player_event_queue.lock().unwrap();
-
-        q.push_back(crate::player::ProcessedEvent {
-            time: scheduled_samples,
-            addr,
-            data,
-        });
+        self.submitter.submit_write(scheduled_samples, addr, data);
+        self.recorder.record_write(scheduled_samples, addr, data);
     }
 
     /// Get elapsed time since audio stream started
@@ -72,7 +85,8 @@ impl AudioScheduler {
     ) -> Result<()> {
         let elapsed_sec = self.audio_start_time.elapsed().as_secs_f64();
         let absolute_time_sec = elapsed_sec + event_time_sec;
-        let scheduled_samples = crate::scheduler::sec_to_samples(absolute_time_sec);
+        let scheduled_samples =
+            crate::scheduler::sec_to_samples_at(absolute_time_sec, self.sample_rate);
         self.schedule_register_write(scheduled_samples, addr, data);
         Ok(())
     }
@@ -91,16 +105,7 @@ impl AudioScheduler {
         addr: u8,
         data: u8,
     ) -> (u32, u32) {
-        // Store addr-data pair directly in a single event
-        // The 2-sample delay between address and data writes will be applied
-        // at the final stage in generate_samples()
-        let mut q = self.player_event_queue.lock().unwrap();
-
-        q.push_back(crate::player::ProcessedEvent {
-            time: scheduled_samples,
-            addr,
-            data,
-        });
+        self.schedule_register_write(scheduled_samples, addr, data);
 
         // Both are scheduled at the same time, delay will be applied at final stage
         (scheduled_samples, scheduled_samples)
@@ -120,7 +125,8 @@ impl AudioScheduler {
     ) -> Result<(u32, u32)> {
         let elapsed_sec = self.audio_start_time.elapsed().as_secs_f64();
         let absolute_time_sec = elapsed_sec + event_time_sec;
-        let scheduled_samples = crate::scheduler::sec_to_samples(absolute_time_sec);
+        let scheduled_samples =
+            crate::scheduler::sec_to_samples_at(absolute_time_sec, self.sample_rate);
 
         let times = self.schedule_register_write_with_times(scheduled_samples, addr, data);
         Ok(times)
@@ -143,7 +149,8 @@ impl AudioScheduler {
         data: u8,
     ) -> Result<(u32, u32)> {
         let absolute_time_sec = base_audio_elapsed + event_time_sec;
-        let scheduled_samples = crate::scheduler::sec_to_samples(absolute_time_sec);
+        let scheduled_samples =
+            crate::scheduler::sec_to_samples_at(absolute_time_sec, self.sample_rate);
 
         let times = self.schedule_register_write_with_times(scheduled_samples, addr, data);
         Ok(times)
@@ -168,7 +175,8 @@ impl AudioScheduler {
         data: u8,
     ) -> Result<(u32, u32)> {
         let absolute_time_sec = audio_stream_elapsed_sec + future_offset_sec + event_time_sec;
-        let scheduled_samples = crate::scheduler::sec_to_samples(absolute_time_sec);
+        let scheduled_samples =
+            crate::scheduler::sec_to_samples_at(absolute_time_sec, self.sample_rate);
 
         let times = self.schedule_register_write_with_times(scheduled_samples, addr, data);
         Ok(times)
@@ -178,8 +186,11 @@ impl AudioScheduler {
     ///
     /// This allows seamless phrase transitions without audio gaps
     pub fn clear_schedule(&self) {
-        let mut q = self.player_event_queue.lock().unwrap();
-        q.clear();
+        self.submitter.submit(SubmissionOp::ClearAll);
+        // Writes before the current audio position have already been played
+        let now_samples =
+            crate::scheduler::sec_to_samples_at(self.get_audio_elapsed_sec(), self.sample_rate);
+        self.recorder.clear_from(now_samples);
     }
 
     /// Clear scheduled events from a specific sample time onwards
@@ -190,13 +201,26 @@ impl AudioScheduler {
     /// # Arguments
     /// * `from_sample_time` - Sample time threshold; events at or after this time are removed
     pub fn clear_schedule_from(&self, from_sample_time: u32) {
-        let mut q = self.player_event_queue.lock().unwrap();
-        q.retain(|event| event.time < from_sample_time);
+        self.submitter
+            .submit(SubmissionOp::ClearFrom(from_sample_time));
+        self.recorder.clear_from(from_sample_time);
     }
 
     /// Get current schedule queue size (number of scheduled events)
+    ///
+    /// The count is published by the generator thread once per buffer, so it is
+    /// approximate while submissions are in flight.
     pub fn get_scheduled_event_count(&self) -> usize {
-        let q = self.player_event_queue.lock().unwrap();
-        q.len()
+        self.submitter.scheduled_event_count()
+    }
+
+    /// Recorder of the writes submitted to this scheduler
+    pub fn recorder(&self) -> &SessionRecorder {
+        &self.recorder
+    }
+
+    /// The session recorded so far, as an event log starting at the first write
+    pub fn recorded_session(&self) -> EventLog {
+        self.recorder.to_event_log()
     }
 }
@@ -32,6 +32,20 @@ pub mod timing {
     /// Must be larger than total buffer latency to prevent audio dropouts
     pub const FUTURE_SCHEDULING_OFFSET_SEC: f64 = 0.030; // 上記のバッファ数値をagentが実装した段階では400ms必要だったが、削ったら30msでもOKになった。20msは遅延発生（この場合の遅延とはverboseログで遅延と表示されて音が崩れる現象のこと）
 
+    /// Streamed playback: how far ahead of playback a client may upload (seconds)
+    /// Chunks sent beyond it are turned away until playback catches up, which paces the client
+    pub const STREAM_MAX_BUFFERED_SEC: f64 = 5.0;
+
+    /// Streamed playback: chunks that may wait for the generator thread to pick them up
+    /// Chunks sent beyond it are turned away like those beyond `STREAM_MAX_BUFFERED_SEC`
+    pub const STREAM_MAX_QUEUED_CHUNKS: usize = 64;
+
+    /// Shortest wait before a client sends a turned-away chunk again (milliseconds)
+    pub const STREAM_RETRY_WAIT_MS: u64 = 10;
+
+    /// Streamed playback ends after waiting this long for the client's next events (seconds)
+    pub const STREAM_IDLE_TIMEOUT_SEC: f64 = 10.0;
+
     /// Audio system stabilization wait time (milliseconds)
     pub const AUDIO_STABILIZATION_WAIT_MS: u64 = 1;
 }
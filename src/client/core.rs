@@ -3,6 +3,8 @@
 //! This module provides basic client-server communication functionality.
 
 use super::config::log_verbose_client;
+use crate::audio::MarkerReached;
+use crate::compression::Compression;
 use crate::ipc::pipe_windows::NamedPipe;
 use crate::ipc::protocol::{Command, Response};
 use anyhow::{Context, Result};
@@ -14,15 +16,24 @@ const RETRY_MAX_WAIT_MS: u64 = 50; // 指数関数的バックオフを利用し
 
 /// Send a standard command to the server
 pub fn send_command(command: Command) -> Result<()> {
-    send_command_internal(command, false)
+    send_command_internal(command, false, Compression::None)
+}
+
+/// Send a command with its payload compressed (for large JSON logs)
+pub fn send_command_compressed(command: Command, compression: Compression) -> Result<()> {
+    send_command_internal(command, false, compression)
 }
 
 /// Send command specifically for interactive mode (includes [インタラクティブ] tag in debug messages)
 pub fn send_command_interactive(command: Command) -> Result<()> {
-    send_command_internal(command, true)
+    send_command_internal(command, true, Compression::None)
 }
 
-fn send_command_internal(command: Command, is_interactive: bool) -> Result<()> {
+fn send_command_internal(
+    command: Command,
+    is_interactive: bool,
+    compression: Compression,
+) -> Result<()> {
     let debug_tag = if is_interactive {
         "[インタラクティブ]"
     } else {
@@ -71,7 +82,7 @@ fn send_command_internal(command: Command, is_interactive: bool) -> Result<()> {
         // Connection successful, proceed with command
         // Serialize command to binary format
         let binary_data = command
-            .to_binary()
+            .to_binary_compressed(compression)
             .map_err(|e| anyhow::anyhow!("Failed to serialize command: {}", e))?;
 
         log_verbose_client(&format!(
@@ -175,3 +186,47 @@ pub fn stop_playback() -> Result<()> {
 pub fn shutdown_server() -> Result<()> {
     send_command(Command::Shutdown)
 }
+
+/// Get the markers reached by the current playback after sequence number `after`
+///
+/// Pass 0 to get every marker kept by the server, then the `seq` of the last
+/// marker received to poll for new ones.
+///
+/// # Example
+/// ```no_run
+/// # use ym2151_log_play_server::client;
+/// let mut last_seq = 0;
+/// for marker in client::get_markers(last_seq)? {
+///     println!("{} ({:.3}s)", marker.name, marker.time);
+///     last_seq = marker.seq;
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn get_markers(after: u64) -> Result<Vec<MarkerReached>> {
+    let mut writer = NamedPipe::connect_default()
+        .context("Failed to connect to server. Is the server running?")?;
+
+    let binary_data = Command::GetMarkers { after }
+        .to_binary()
+        .map_err(|e| anyhow::anyhow!("Failed to serialize command: {}", e))?;
+
+    writer
+        .write_binary(&binary_data)
+        .context("Failed to send command to server")?;
+
+    let response_data = writer
+        .read_binary_response()
+        .context("Failed to read response from server")?;
+
+    let response = Response::from_binary(&response_data)
+        .map_err(|e| anyhow::anyhow!("Failed to parse server response: {}", e))?;
+
+    match response {
+        Response::Markers { markers } => {
+            log_verbose_client(&format!("📍 マーカー: {} 件", markers.len()));
+            Ok(markers)
+        }
+        Response::Error { message } => Err(anyhow::anyhow!("Server returned error: {}", message)),
+        _ => Err(anyhow::anyhow!("Unexpected response type for GetMarkers")),
+    }
+}
@@ -5,6 +5,7 @@
 use super::config::log_always_client;
 use super::config::log_verbose_client;
 use super::core::send_command_interactive;
+use crate::events::EventLog;
 use crate::ipc::pipe_windows::NamedPipe;
 use crate::ipc::protocol::{Command, Response};
 use crate::server::ServerState;
@@ -118,7 +119,7 @@ pub fn get_server_state() -> Result<String> {
     log_verbose_client(&format!("response server state: {:?}", response));
 
     match response {
-        Response::ServerState { state } => Ok(state),
+        Response::ServerState { state, .. } => Ok(state),
         Response::Error { message } => Err(anyhow::anyhow!("Server error: {}", message)),
         _ => Err(anyhow::anyhow!("Unexpected response type")),
     }
@@ -193,13 +194,66 @@ pub fn stop_interactive() -> Result<()> {
     result
 }
 
+/// Start recording the interactive session on the server
+///
+/// Sessions are only recorded between this and [`stop_session_recording`];
+/// starting again discards the previous recording. The server keeps at most
+/// [`MAX_RECORDED_WRITES`](crate::session_recorder::MAX_RECORDED_WRITES) writes.
+///
+/// # Example
+/// ```no_run
+/// # use ym2151_log_play_server::client::interactive;
+/// interactive::start_session_recording()?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn start_session_recording() -> Result<()> {
+    log_verbose_client("⏺️  [インタラクティブモード] セッションの記録を開始中...");
+    send_command_interactive(Command::StartSessionRecording)
+}
+
+/// Stop recording the interactive session; it can still be saved afterwards
+pub fn stop_session_recording() -> Result<()> {
+    log_verbose_client("⏹️  [インタラクティブモード] セッションの記録を停止中...");
+    send_command_interactive(Command::StopSessionRecording)
+}
+
+/// Save the current interactive session to a file
+///
+/// The server writes every register write scheduled since
+/// [`start_session_recording`] (minus anything cleared before it played) to
+/// `path`. A `.vgm`/`.vgz` path produces a VGM file, `.json` a JSON log. The
+/// path is relative to the server's working directory and may not contain `..`.
+///
+/// # Example
+/// ```no_run
+/// # use ym2151_log_play_server::client::interactive;
+/// interactive::start_session_recording()?;
+/// // ... play ...
+/// interactive::save_interactive_session("session.vgm")?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn save_interactive_session(path: &str) -> Result<()> {
+    log_verbose_client(&format!(
+        "💾 [インタラクティブモード] セッションを保存中: {}",
+        path
+    ));
+    let result = send_command_interactive(Command::SaveInteractiveSession {
+        path: path.to_string(),
+    });
+    if result.is_ok() {
+        log_verbose_client("✅ [インタラクティブモード] セッションを保存しました");
+    }
+    result
+}
+
 /// Send ym2151log format JSON data to interactive mode
 ///
 /// This is a convenience function that accepts ym2151log format JSON data
 /// and converts it to f64 second timing before sending to the server. The conversion:
-/// - Takes JSON with time in sample units (i64, 55930 Hz)
-/// - Converts to JSON with time in seconds (f64)
-/// - Sends the converted JSON to server for processing
+/// - Reads the JSON with [`EventLog::from_json_str`], so `"time_unit"` may be
+///   `"seconds"` (default), `"samples"` (55930 Hz at the default clock) or
+///   `"ticks"` with `"bpm"`/`"ppq"`
+/// - Sends the log to the server with time in seconds (f64)
 ///
 /// This function does NOT start or stop interactive mode - the client must
 /// manage the interactive mode lifecycle using `start_interactive()` and
@@ -211,7 +265,7 @@ pub fn stop_interactive() -> Result<()> {
 /// seamless phrase transitions without audio gaps.
 ///
 /// # Arguments
-/// * `json_data` - JSON string in ym2151log format with time in sample units
+/// * `json_data` - JSON string in ym2151log format
 ///
 /// # Example
 /// ```no_run
@@ -221,13 +275,13 @@ pub fn stop_interactive() -> Result<()> {
 ///
 /// // Send multiple JSONs without stopping - no audio gaps!
 /// // Each JSON automatically clears conflicting future events
-/// let json1 = r#"{"events": [
+/// let json1 = r#"{"time_unit": "samples", "events": [
 ///     {"time": 0, "addr": "0x08", "data": "0x00"},
 ///     {"time": 2797, "addr": "0x20", "data": "0xC7"}
 /// ]}"#;
 /// interactive::play_json_interactive(json1)?;
 ///
-/// let json2 = r#"{"events": [
+/// let json2 = r#"{"time_unit": "samples", "events": [
 ///     {"time": 5594, "addr": "0x28", "data": "0x3E"}
 /// ]}"#;
 /// interactive::play_json_interactive(json2)?;
@@ -238,8 +292,7 @@ pub fn stop_interactive() -> Result<()> {
 /// ```
 ///
 /// # Notes
-/// - Input JSON has time in sample units (i64) at 55930 Hz
-/// - Converted to time in seconds (f64) for precise interactive timing
+/// - Input JSON times are converted to seconds (f64) for precise interactive timing
 /// - Interactive mode must be started before calling this function
 /// - Interactive mode must be stopped manually when done
 /// - JSON parsing and timing conversion are handled client-side
@@ -248,16 +301,10 @@ pub fn stop_interactive() -> Result<()> {
 pub fn play_json_interactive(json_data: &str) -> Result<()> {
     log_verbose_client("🎵 JSONデータをパース中...");
 
-    // TODO: Re-implement JSON time conversion from samples to seconds
-    // For now, assume the input JSON already has time in f64 seconds format
-    let converted_json = json_data.to_string();
+    let json_value = seconds_json(json_data)?;
 
     log_verbose_client("✅ JSONデータのパースが完了しました");
 
-    // Parse the converted JSON to check if it has any events
-    let json_value: serde_json::Value =
-        serde_json::from_str(&converted_json).context("Failed to parse converted JSON")?;
-
     // Check if events array is empty
     if let Some(events) = json_value.get("events") {
         if let Some(events_array) = events.as_array() {
@@ -276,3 +323,9 @@ pub fn play_json_interactive(json_data: &str) -> Result<()> {
     log_verbose_client("✅ 変換されたJSONデータをサーバーに送信しました");
     Ok(())
 }
+
+/// Parse ym2151log JSON in any time unit and re-encode it with times in seconds
+pub(crate) fn seconds_json(json_data: &str) -> Result<serde_json::Value> {
+    let log = EventLog::from_json_str(json_data).context("Failed to parse JSON")?;
+    serde_json::to_value(&log).context("Failed to encode converted JSON")
+}
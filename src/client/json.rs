@@ -2,14 +2,22 @@
 //!
 //! This module handles JSON data sending and processing for the client.
 
-use super::core::send_command;
+use super::core::{send_command, send_command_compressed};
+use super::log_verbose_client;
+use crate::compression::{self, Compression};
+use crate::formats;
 use crate::ipc::protocol::Command;
 use anyhow::{Context, Result};
+use std::path::Path;
+
+/// JSON larger than this is sent with a zstd compressed payload
+pub const COMPRESS_THRESHOLD_BYTES: usize = 64 * 1024;
 
 /// Send JSON data to the server
 ///
 /// This function sends JSON data via the binary protocol.
-/// The protocol uses length-prefixed JSON for robust transmission.
+/// The protocol uses length-prefixed JSON for robust transmission; JSON larger
+/// than [`COMPRESS_THRESHOLD_BYTES`] is sent zstd compressed.
 ///
 /// # Arguments
 /// * `json_data` - JSON string data to send
@@ -26,5 +34,47 @@ pub fn send_json(json_data: &str) -> Result<()> {
         serde_json::from_str(json_data).context("Failed to parse JSON data")?;
 
     let command = Command::PlayJson { data: json_value };
+    if json_data.len() > COMPRESS_THRESHOLD_BYTES {
+        log_verbose_client("🗜️  大きなJSONをzstd圧縮して送信します");
+        return send_command_compressed(command, Compression::Zstd);
+    }
     send_command(command)
 }
+
+/// Send a log file to the server
+///
+/// `.vgm`/`.vgz`, `.s98`, `.mdx` and `.mid` files are imported on the client
+/// side and sent as a JSON event log, and `.ndjson`/`.jsonl` files are streamed
+/// with [`super::stream_ndjson`]; any other file is sent as JSON as-is.
+///
+/// # Example
+/// ```no_run
+/// # use ym2151_log_play_server::client::json;
+/// json::send_file("music.vgm").unwrap();
+/// ```
+pub fn send_file<P: AsRef<Path>>(path: P) -> Result<()> {
+    let path = path.as_ref();
+
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    if extension.eq_ignore_ascii_case("ndjson") || extension.eq_ignore_ascii_case("jsonl") {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        return super::stream_ndjson(std::io::BufReader::new(file), None);
+    }
+
+    if formats::LogFormat::from_path(path) != formats::LogFormat::Json {
+        let loaded = formats::load_event_log(path)?;
+        for warning in &loaded.warnings {
+            log_verbose_client(&format!("⚠️  {}", warning));
+        }
+        let json_data = loaded.log.to_json_string()?;
+        return send_json(&json_data);
+    }
+
+    // .json.gz / .json.zst are decompressed here and sent as JSON
+    let bytes = compression::read_file(path)
+        .with_context(|| format!("Failed to read JSON file: {}", path.display()))?;
+    let json_data = String::from_utf8(bytes)
+        .with_context(|| format!("JSON file is not valid UTF-8: {}", path.display()))?;
+    send_json(&json_data)
+}
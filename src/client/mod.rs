@@ -32,6 +32,21 @@
 //! # Ok::<(), anyhow::Error>(())
 //! ```
 //!
+//! Use [`send_file`] to send a log file; VGM/VGZ files are converted first:
+//!
+//! ```no_run
+//! use ym2151_log_play_server::client;
+//!
+//! client::send_file("music.vgz")?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Use [`stream_ndjson`] for very long logs: events are sent in chunks as
+//! playback proceeds instead of in one message.
+//!
+//! Use [`render_pcm`] to have the server render a log offline and get the
+//! PCM back, without playing it.
+//!
 //! ## Interactive Mode with JSON Data
 //!
 //! Use [`play_json_interactive`] to send ym2151log format JSON data to interactive mode:
@@ -43,7 +58,8 @@
 //! client::start_interactive()?;
 //!
 //! // Send multiple JSONs without stopping - no audio gaps!
-//! let json1 = r#"{"events": [
+//! // Times in OPM samples (55930 Hz) instead of seconds
+//! let json1 = r#"{"time_unit": "samples", "events": [
 //!     {"time": 0, "addr": "0x08", "data": "0x00"},
 //!     {"time": 100, "addr": "0x20", "data": "0xC7"}
 //! ]}"#;
@@ -75,7 +91,9 @@ pub mod config;
 pub mod core;
 pub mod interactive;
 pub mod json;
+pub mod render;
 pub mod server;
+pub mod stream;
 
 // Re-export commonly used functions from submodules
 // This maintains backward compatibility while organizing code by responsibility
@@ -84,17 +102,26 @@ pub mod server;
 pub use config::{init_client, is_client_verbose, log_verbose_client};
 
 // Core client communication
-pub use core::{send_command, shutdown_server, stop_playback};
+pub use core::{
+    get_markers, send_command, send_command_compressed, shutdown_server, stop_playback,
+};
 
 // JSON-related functionality
-pub use json::send_json;
+pub use json::{send_file, send_json};
 
 // Interactive mode functionality
 pub use interactive::{
     get_interactive_mode_state_with_retry, get_server_time, play_json_interactive,
-    start_interactive, stop_interactive,
+    save_interactive_session, start_interactive, start_session_recording, stop_interactive,
+    stop_session_recording,
 };
 
+// Streamed upload
+pub use stream::stream_ndjson;
+
+// Offline rendering on the server
+pub use render::{render_pcm, render_pcm_to_file, RenderedPcm};
+
 // Server management functionality
 pub use server::{ensure_server_ready, is_app_in_path, is_server_running_with_retry};
 
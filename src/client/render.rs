@@ -0,0 +1,122 @@
+//! Offline rendering on the server
+//!
+//! The server renders a log with the same emulator build it plays with, on a
+//! worker thread and without touching its audio device, and returns the PCM or
+//! writes it to a file on its side.
+
+use super::json::COMPRESS_THRESHOLD_BYTES;
+use super::log_verbose_client;
+use crate::compression::Compression;
+use crate::ipc::pipe_windows::NamedPipe;
+use crate::ipc::protocol::{Command, Response};
+use anyhow::{Context, Result};
+
+/// PCM rendered by the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedPcm {
+    pub sample_rate: u32,
+    /// Sample format name: `16`, `24` or `32f`
+    pub format: String,
+    pub frames: usize,
+    /// Interleaved stereo little-endian samples (24-bit samples take 3 bytes)
+    pub data: Vec<u8>,
+}
+
+/// Render JSON log data on the server and get the PCM
+///
+/// `sample_rate` defaults to the chip's native rate (55930 Hz at the default
+/// clock) and `format` is `16`, `24` or `32f`.
+///
+/// # Example
+/// ```no_run
+/// # use ym2151_log_play_server::client;
+/// let json = std::fs::read_to_string("song.json")?;
+/// let pcm = client::render_pcm(&json, Some(48000), "16")?;
+/// println!("{} frames at {} Hz", pcm.frames, pcm.sample_rate);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn render_pcm(json_data: &str, sample_rate: Option<u32>, format: &str) -> Result<RenderedPcm> {
+    match request_render(json_data, sample_rate, format, None)? {
+        Response::Pcm {
+            sample_rate,
+            format,
+            frames,
+            data,
+        } => {
+            log_verbose_client(&format!(
+                "✅ レンダリング結果を受信しました ({}フレーム, {} Hz)",
+                frames, sample_rate
+            ));
+            Ok(RenderedPcm {
+                sample_rate,
+                format,
+                frames,
+                data,
+            })
+        }
+        _ => Err(anyhow::anyhow!("Unexpected response type for RenderPcm")),
+    }
+}
+
+/// Render JSON log data on the server into a WAV or FLAC file (by extension)
+/// at `path` on the server side
+///
+/// `path` is relative to the server's working directory and may not contain `..`.
+pub fn render_pcm_to_file(
+    json_data: &str,
+    sample_rate: Option<u32>,
+    format: &str,
+    path: &str,
+) -> Result<()> {
+    match request_render(json_data, sample_rate, format, Some(path))? {
+        Response::Ok => {
+            log_verbose_client(&format!("✅ サーバー側に保存しました: {}", path));
+            Ok(())
+        }
+        _ => Err(anyhow::anyhow!("Unexpected response type for RenderPcm")),
+    }
+}
+
+fn request_render(
+    json_data: &str,
+    sample_rate: Option<u32>,
+    format: &str,
+    path: Option<&str>,
+) -> Result<Response> {
+    let data: serde_json::Value =
+        serde_json::from_str(json_data).context("Failed to parse JSON data")?;
+    let command = Command::RenderPcm {
+        data,
+        sample_rate,
+        format: format.to_string(),
+        path: path.map(str::to_string),
+    };
+    let compression = if json_data.len() > COMPRESS_THRESHOLD_BYTES {
+        Compression::Zstd
+    } else {
+        Compression::None
+    };
+
+    let mut writer = NamedPipe::connect_default()
+        .context("Failed to connect to server. Is the server running?")?;
+
+    let binary_data = command
+        .to_binary_compressed(compression)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize command: {}", e))?;
+
+    log_verbose_client("🎛️  サーバーにレンダリングを要求中...");
+    writer
+        .write_binary(&binary_data)
+        .context("Failed to send command to server")?;
+
+    let response_data = writer
+        .read_binary_response()
+        .context("Failed to read response from server")?;
+
+    match Response::from_binary(&response_data)
+        .map_err(|e| anyhow::anyhow!("Failed to parse server response: {}", e))?
+    {
+        Response::Error { message } => Err(anyhow::anyhow!("Server returned error: {}", message)),
+        response => Ok(response),
+    }
+}
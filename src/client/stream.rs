@@ -0,0 +1,164 @@
+//! Streamed upload of long logs
+//!
+//! Instead of sending a whole log with `PlayJson`, these functions open a stream
+//! on the server and send the events in short chunks, so playback starts after
+//! the first chunk. The server turns chunks away while playback is far behind;
+//! they are sent again after a wait, which keeps the client from running far ahead.
+
+use super::core::send_command;
+use super::log_verbose_client;
+use crate::audio_config::timing::{STREAM_MAX_BUFFERED_SEC, STREAM_RETRY_WAIT_MS};
+use crate::events::RegisterEvent;
+use crate::ipc::pipe_windows::NamedPipe;
+use crate::ipc::protocol::{Command, Response};
+use anyhow::{Context, Result};
+use std::io::BufRead;
+use std::time::Duration;
+
+/// Most events sent in one chunk
+pub const STREAM_CHUNK_EVENTS: usize = 1024;
+/// Longest time span of one chunk, in seconds
+pub const STREAM_CHUNK_SEC: f64 = 1.0;
+
+/// Play NDJSON events (one register event object per line) as a stream
+///
+/// Each line is an event as in a JSON log, e.g.
+/// `{"time": 0.5, "addr": "0x08", "data": "0x78"}`, with times in seconds from
+/// the start. Blank lines are skipped. `clock` defaults to the server's clock.
+///
+/// # Example
+/// ```no_run
+/// # use ym2151_log_play_server::client;
+/// let file = std::fs::File::open("session.ndjson")?;
+/// client::stream_ndjson(std::io::BufReader::new(file), None)?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn stream_ndjson<R: BufRead>(reader: R, clock: Option<u32>) -> Result<()> {
+    send_command(Command::BeginStream { clock }).context("Failed to start stream")?;
+
+    let result = send_chunks(reader);
+    let ended = send_command(Command::EndStream).context("Failed to end stream");
+    // A failed upload still ends the stream, so the server plays what it has
+    // and finishes instead of waiting for more
+    result.and(ended)
+}
+
+fn send_chunks<R: BufRead>(reader: R) -> Result<()> {
+    let mut sent = 0;
+    for chunk in NdjsonChunks::new(reader) {
+        let chunk = chunk?;
+        sent += chunk.len();
+        let data = serde_json::json!({ "events": chunk });
+        let buffered_sec = append_events(Command::AppendEvents { data })?;
+        log_verbose_client(&format!(
+            "🌊 {}個のイベントを送信しました (先行 {:.2}秒)",
+            sent, buffered_sec
+        ));
+    }
+    Ok(())
+}
+
+/// Send a chunk, again after a wait for each time the server turns it away,
+/// and get how far the stream runs ahead of playback
+fn append_events(command: Command) -> Result<f64> {
+    let binary_data = command
+        .to_binary()
+        .map_err(|e| anyhow::anyhow!("Failed to serialize command: {}", e))?;
+    loop {
+        let mut writer = NamedPipe::connect_default()
+            .context("Failed to connect to server. Is the server running?")?;
+        writer
+            .write_binary(&binary_data)
+            .context("Failed to send events")?;
+        let response_data = writer
+            .read_binary_response()
+            .context("Failed to read response from server")?;
+
+        match Response::from_binary(&response_data)
+            .map_err(|e| anyhow::anyhow!("Failed to parse server response: {}", e))?
+        {
+            Response::StreamBuffer {
+                accepted: true,
+                buffered_sec,
+            } => return Ok(buffered_sec),
+            Response::StreamBuffer {
+                accepted: false,
+                buffered_sec,
+            } => {
+                // Wait until playback is back within the limit
+                let catch_up_ms = ((buffered_sec - STREAM_MAX_BUFFERED_SEC) * 1000.0) as u64;
+                std::thread::sleep(Duration::from_millis(catch_up_ms.max(STREAM_RETRY_WAIT_MS)));
+            }
+            Response::Error { message } => {
+                return Err(anyhow::anyhow!("Server returned error: {}", message))
+            }
+            _ => return Err(anyhow::anyhow!("Unexpected response type for AppendEvents")),
+        }
+    }
+}
+
+/// Events of an NDJSON reader, grouped into chunks for streaming
+///
+/// A chunk holds at most [`STREAM_CHUNK_EVENTS`] events spanning less than
+/// [`STREAM_CHUNK_SEC`] seconds.
+pub struct NdjsonChunks<R> {
+    lines: std::io::Lines<R>,
+    line_number: usize,
+    /// First event of the next chunk, read past the end of the previous one
+    carried: Option<RegisterEvent>,
+}
+
+impl<R: BufRead> NdjsonChunks<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            line_number: 0,
+            carried: None,
+        }
+    }
+
+    fn next_event(&mut self) -> Option<Result<RegisterEvent>> {
+        if let Some(event) = self.carried.take() {
+            return Some(Ok(event));
+        }
+        for line in self.lines.by_ref() {
+            self.line_number += 1;
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e.into())),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(
+                serde_json::from_str(&line)
+                    .with_context(|| format!("line {}: invalid event", self.line_number)),
+            );
+        }
+        None
+    }
+}
+
+impl<R: BufRead> Iterator for NdjsonChunks<R> {
+    type Item = Result<Vec<RegisterEvent>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk: Vec<RegisterEvent> = Vec::new();
+        while chunk.len() < STREAM_CHUNK_EVENTS {
+            let event = match self.next_event() {
+                Some(Ok(event)) => event,
+                Some(Err(e)) => return Some(Err(e)),
+                None => break,
+            };
+            if chunk
+                .first()
+                .is_some_and(|first| event.time - first.time >= STREAM_CHUNK_SEC)
+            {
+                self.carried = Some(event);
+                break;
+            }
+            chunk.push(event);
+        }
+        (!chunk.is_empty()).then_some(Ok(chunk))
+    }
+}
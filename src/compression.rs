@@ -0,0 +1,119 @@
+//! gzip and zstd compression of log files and IPC payloads
+//!
+//! Compressed data is recognised by its magic number rather than by file
+//! extension, so `song.json.gz`, `song.json.zst` and plain JSON all load the
+//! same way. Decompression is always bounded: data that expands past the given
+//! limit is rejected instead of filling memory.
+
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// Largest size a compressed log file may expand to
+pub const MAX_FILE_DECOMPRESSED_SIZE: usize = 1 << 30;
+/// zstd level used for writing (the zstd default)
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Compression of in-memory data, from its magic number
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else if bytes.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Compression for a file to be written, from its extension
+    /// (`.gz`/`.vgz` gzip, `.zst` zstd)
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("gz") | Some("vgz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// Compress `bytes` (returned as they are for [`Compression::None`])
+pub fn compress(bytes: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    match compression {
+        Compression::None => Ok(bytes.to_vec()),
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(bytes)?;
+            Ok(encoder.finish()?)
+        }
+        Compression::Zstd => {
+            zstd::encode_all(bytes, ZSTD_LEVEL).context("Failed to compress with zstd")
+        }
+    }
+}
+
+/// Decompress `bytes`, failing if the result would exceed `limit` bytes
+pub fn decompress(bytes: &[u8], compression: Compression, limit: usize) -> Result<Vec<u8>> {
+    let reader: Box<dyn Read + '_> = match compression {
+        Compression::None => {
+            if bytes.len() > limit {
+                bail!("data is larger than {} bytes", limit);
+            }
+            return Ok(bytes.to_vec());
+        }
+        Compression::Gzip => Box::new(GzDecoder::new(bytes)),
+        Compression::Zstd => {
+            Box::new(zstd::Decoder::new(bytes).context("Failed to start zstd decompression")?)
+        }
+    };
+
+    // One byte past the limit tells an exact fit from an overflow
+    let mut decompressed = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .with_context(|| format!("Failed to decompress {:?} data", compression))?;
+    if decompressed.len() > limit {
+        bail!(
+            "{:?} data expands to more than {} bytes",
+            compression,
+            limit
+        );
+    }
+    Ok(decompressed)
+}
+
+/// Decompress `bytes` if they start with a gzip or zstd magic number
+pub fn decompress_detected(bytes: &[u8], limit: usize) -> Result<Vec<u8>> {
+    decompress(bytes, Compression::detect(bytes), limit)
+}
+
+/// Read a file, decompressing it if it is gzip or zstd compressed
+pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if Compression::detect(&bytes) == Compression::None {
+        return Ok(bytes);
+    }
+    decompress_detected(&bytes, MAX_FILE_DECOMPRESSED_SIZE)
+        .with_context(|| format!("Failed to decompress {}", path.display()))
+}
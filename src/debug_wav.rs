@@ -79,8 +79,12 @@ pub fn generate_post_playback_buffers(
     resampling_quality: ResamplingQuality,
 ) -> Result<(Vec<i16>, Vec<i16>)> {
     let mut player = Player::new(log.clone());
-    let mut resampler = AudioResampler::with_quality(resampling_quality)
-        .context("Failed to initialize resampler")?;
+    let mut resampler = AudioResampler::with_rates_and_quality(
+        player.current_sample_rate(),
+        OUTPUT_SAMPLE_RATE,
+        resampling_quality,
+    )
+    .context("Failed to initialize resampler")?;
 
     let mut buffer_55k = Vec::new();
     let mut buffer_48k = Vec::new();
@@ -136,10 +140,29 @@ pub fn save_debug_wav_files(
     realtime_48k: &[i16],
     post_55k: &[i16],
     post_48k: &[i16],
+) -> Result<()> {
+    save_debug_wav_files_at(
+        realtime_55k,
+        realtime_48k,
+        post_55k,
+        post_48k,
+        OPM_SAMPLE_RATE,
+    )
+}
+
+/// Save all 4 debug WAV files, with the native buffers at the given OPM sample rate
+///
+/// Use this when the log was played at a non-default chip clock.
+pub fn save_debug_wav_files_at(
+    realtime_55k: &[i16],
+    realtime_48k: &[i16],
+    post_55k: &[i16],
+    post_48k: &[i16],
+    native_sample_rate: u32,
 ) -> Result<()> {
     println!("\n保存中: デバッグ用WAVファイル...");
 
-    wav_writer::write_wav("realtime_55k.wav", realtime_55k, OPM_SAMPLE_RATE)
+    wav_writer::write_wav("realtime_55k.wav", realtime_55k, native_sample_rate)
         .context("Failed to write realtime_55k.wav")?;
     println!("✅ realtime_55k.wav を作成しました");
 
@@ -147,7 +170,7 @@ pub fn save_debug_wav_files(
         .context("Failed to write realtime_48k.wav")?;
     println!("✅ realtime_48k.wav を作成しました");
 
-    wav_writer::write_wav("post_55k.wav", post_55k, OPM_SAMPLE_RATE)
+    wav_writer::write_wav("post_55k.wav", post_55k, native_sample_rate)
         .context("Failed to write post_55k.wav")?;
     println!("✅ post_55k.wav を作成しました");
 
@@ -0,0 +1,274 @@
+//! Semantic diff of two event logs
+//!
+//! Writes are aligned per register (key-on/off per channel, AMD and PMD
+//! separately) in time order. Two writes to the same register within
+//! [`DiffOptions::time_epsilon`] of each other are the same write, reported as
+//! changed if their data differs; anything left over is added or removed.
+//! Changes are reported grouped by channel and parameter, using the register
+//! names of the [`crate::disasm`] text format.
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use crate::disasm::{describe_write, write_parameter};
+use crate::events::{EventLog, RegisterEvent};
+use crate::optimizer;
+use crate::register_shadow::RegisterShadow;
+use crate::resampler::{opm_sample_rate, YM2151_CLOCK};
+
+/// Time difference below which two writes are aligned, in seconds
+pub const DEFAULT_TIME_EPSILON: f64 = 0.001;
+/// Changes listed per parameter in [`LogDiff::report`]
+const REPORT_CHANGES_PER_PARAMETER: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffOptions {
+    /// Writes to the same register closer than this (seconds) are aligned
+    pub time_epsilon: f64,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            time_epsilon: DEFAULT_TIME_EPSILON,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChangeKind {
+    Changed,
+    Added,
+    Removed,
+}
+
+/// One difference between the logs
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// Channel the register belongs to, `None` for global registers
+    pub channel: Option<u8>,
+    /// Parameter name such as `KC` or `M1 TL`
+    pub parameter: String,
+    /// The write in the old log (changed and removed)
+    pub old: Option<RegisterEvent>,
+    /// The write in the new log (changed and added)
+    pub new: Option<RegisterEvent>,
+}
+
+impl Change {
+    /// Time of the change (in the new log if the write exists there)
+    pub fn time(&self) -> f64 {
+        self.new
+            .as_ref()
+            .or(self.old.as_ref())
+            .map_or(0.0, |event| event.time)
+    }
+
+    fn describe(&self) -> String {
+        let text = |event: &Option<RegisterEvent>| {
+            event
+                .as_ref()
+                .map(|e| describe_write(e.addr, e.data))
+                .unwrap_or_default()
+        };
+        match self.kind {
+            ChangeKind::Changed => format!("{} → {}", text(&self.old), text(&self.new)),
+            ChangeKind::Added => format!("+ {}", text(&self.new)),
+            ChangeKind::Removed => format!("- {}", text(&self.old)),
+        }
+    }
+}
+
+/// Differences between two logs, in time order
+#[derive(Debug, Clone, Default)]
+pub struct LogDiff {
+    pub changes: Vec<Change>,
+    /// Effective clocks of the old and new log if they differ
+    pub clock: Option<(u32, u32)>,
+    /// Aligned writes whose data is the same
+    pub unchanged: usize,
+}
+
+impl LogDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.clock.is_none()
+    }
+
+    pub fn count(&self, kind: ChangeKind) -> usize {
+        self.changes.iter().filter(|c| c.kind == kind).count()
+    }
+
+    /// Changes grouped by channel (global registers first) and parameter
+    pub fn grouped(&self) -> BTreeMap<Option<u8>, BTreeMap<&str, Vec<&Change>>> {
+        let mut groups: BTreeMap<Option<u8>, BTreeMap<&str, Vec<&Change>>> = BTreeMap::new();
+        for change in &self.changes {
+            groups
+                .entry(change.channel)
+                .or_default()
+                .entry(change.parameter.as_str())
+                .or_default()
+                .push(change);
+        }
+        groups
+    }
+
+    /// Text report of the changes, grouped by channel and parameter
+    pub fn report(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(
+            text,
+            "{} changed, {} added, {} removed, {} unchanged",
+            self.count(ChangeKind::Changed),
+            self.count(ChangeKind::Added),
+            self.count(ChangeKind::Removed),
+            self.unchanged
+        );
+        if let Some((old, new)) = self.clock {
+            let _ = writeln!(text, "clock: {} Hz → {} Hz", old, new);
+        }
+        for (channel, parameters) in self.grouped() {
+            match channel {
+                Some(channel) => {
+                    let _ = writeln!(text, "ch{}:", channel);
+                }
+                None => {
+                    let _ = writeln!(text, "global:");
+                }
+            }
+            for (parameter, changes) in parameters {
+                let _ = writeln!(text, "  {}: {}", parameter, summary(&changes));
+                for change in changes.iter().take(REPORT_CHANGES_PER_PARAMETER) {
+                    let _ = writeln!(text, "    {:>12.6}s  {}", change.time(), change.describe());
+                }
+                if changes.len() > REPORT_CHANGES_PER_PARAMETER {
+                    let _ = writeln!(
+                        text,
+                        "    ... and {} more",
+                        changes.len() - REPORT_CHANGES_PER_PARAMETER
+                    );
+                }
+            }
+        }
+        text
+    }
+}
+
+fn summary(changes: &[&Change]) -> String {
+    let parts: Vec<String> = [
+        (ChangeKind::Changed, "changed"),
+        (ChangeKind::Added, "added"),
+        (ChangeKind::Removed, "removed"),
+    ]
+    .iter()
+    .filter_map(|&(kind, name)| {
+        let count = changes.iter().filter(|c| c.kind == kind).count();
+        (count > 0).then(|| format!("{} {}", count, name))
+    })
+    .collect();
+    parts.join(", ")
+}
+
+/// Compare two logs write by write
+pub fn diff(old: &EventLog, new: &EventLog, options: &DiffOptions) -> LogDiff {
+    let mut registers: BTreeMap<usize, (Vec<&RegisterEvent>, Vec<&RegisterEvent>)> =
+        BTreeMap::new();
+    for event in &old.events {
+        registers
+            .entry(RegisterShadow::key(event.addr, event.data))
+            .or_default()
+            .0
+            .push(event);
+    }
+    for event in &new.events {
+        registers
+            .entry(RegisterShadow::key(event.addr, event.data))
+            .or_default()
+            .1
+            .push(event);
+    }
+
+    let mut result = LogDiff::default();
+    for (old_writes, new_writes) in registers.values() {
+        align(old_writes, new_writes, options.time_epsilon, &mut result);
+    }
+    result.changes.sort_by(|a, b| a.time().total_cmp(&b.time()));
+
+    let clocks = (old.clock_or(YM2151_CLOCK), new.clock_or(YM2151_CLOCK));
+    result.clock = (clocks.0 != clocks.1).then_some(clocks);
+    result
+}
+
+/// Align the writes to one register, both in time order
+fn align(old: &[&RegisterEvent], new: &[&RegisterEvent], epsilon: f64, result: &mut LogDiff) {
+    let change = |kind, old: Option<&RegisterEvent>, new: Option<&RegisterEvent>| {
+        let event = new.or(old).expect("a change has at least one write");
+        let (channel, parameter) = write_parameter(event.addr, event.data);
+        Change {
+            kind,
+            channel,
+            parameter,
+            old: old.cloned(),
+            new: new.cloned(),
+        }
+    };
+
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        match (old.get(i), new.get(j)) {
+            (Some(a), Some(b)) if (a.time - b.time).abs() <= epsilon => {
+                if a.data == b.data {
+                    result.unchanged += 1;
+                } else {
+                    result
+                        .changes
+                        .push(change(ChangeKind::Changed, Some(a), Some(b)));
+                }
+                i += 1;
+                j += 1;
+            }
+            (Some(a), Some(b)) if a.time < b.time => {
+                result
+                    .changes
+                    .push(change(ChangeKind::Removed, Some(a), None));
+                i += 1;
+            }
+            (Some(a), None) => {
+                result
+                    .changes
+                    .push(change(ChangeKind::Removed, Some(a), None));
+                i += 1;
+            }
+            (_, Some(b)) => {
+                result
+                    .changes
+                    .push(change(ChangeKind::Added, None, Some(b)));
+                j += 1;
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+/// Where the rendered output of two logs first differs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Divergence {
+    /// Frame at the native OPM sample rate
+    pub frame: usize,
+    pub time: f64,
+}
+
+/// Render both logs and find the first sample where the PCM differs
+///
+/// Renders up to half a second past the last write of either log. Both logs
+/// must be for the same clock.
+pub fn first_divergence(old: &EventLog, new: &EventLog) -> Result<Option<Divergence>> {
+    let rate = opm_sample_rate(old.clock_or(YM2151_CLOCK));
+    Ok(
+        optimizer::first_divergence(old, new)?.map(|frame| Divergence {
+            frame,
+            time: frame as f64 / rate as f64,
+        }),
+    )
+}
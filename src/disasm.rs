@@ -0,0 +1,723 @@
+//! Register log disassembler and assembler
+//!
+//! [`disassemble`] turns an event log into one line per register write, decoded
+//! with the OPM register map and annotated after a `;`:
+//!
+//! ```text
+//! .clock 3579545
+//!            0s  ch0 RL=LR FB=7 CON=7             ; carriers C2
+//!            0s  ch0 KC=A4                        ; A4+0c
+//!        0.001s  ch0 C2 TL=0x1F                   ; -23.25 dB
+//!        0.002s  KEY ON ch0 ops=M1,C1,M2,C2
+//! ```
+//!
+//! `KC=` names the note the code plays at the standard 3.579545 MHz clock; the
+//! comment on KC and KF writes gives the pitch that sounds at the log's clock.
+//! Operators are named by slot (`M1`, `M2`, `C1`, `C2`).
+//!
+//! Markers are written as `.marker 1.5s "chorus"` lines between the writes.
+//!
+//! [`assemble`] reads the same text back (comments are ignored), so a
+//! disassembly can be edited by hand and turned into a log again. Writes that
+//! do not fit a register's fields (unused bits set, unused addresses) are shown
+//! as `REG 0xAA=0xDD`.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::fmt::Write as _;
+
+use crate::events::{EventLog, LogMetadata, Marker, RegisterEvent};
+use crate::opm::REG_KEY_ON;
+use crate::opm_tables::{
+    key_code, key_code_semitone, KEY_CODE_BASE_NOTE, KEY_CODE_SEMITONES, KF_STEPS, TL_STEP_DB,
+};
+use crate::resampler::YM2151_CLOCK;
+
+/// Operator names by register slot (M1, M2, C1, C2 at +0x00, +0x08, +0x10, +0x18)
+const SLOT_NAMES: [&str; 4] = ["M1", "M2", "C1", "C2"];
+/// Operator names by key-on bit (bits 3-6 of register 0x08)
+const KEY_ON_NAMES: [&str; 4] = ["M1", "C1", "M2", "C2"];
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+const KF_CENTS: f64 = 100.0 / KF_STEPS as f64;
+const LFO_WAVEFORMS: [&str; 4] = ["saw", "square", "triangle", "noise"];
+const TIME_WIDTH: usize = 12;
+const BODY_WIDTH: usize = 32;
+
+/// How a field value is written
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FieldKind {
+    Decimal,
+    Hex,
+    /// Output bits: `-`, `L`, `R` or `LR`
+    Pan,
+    /// Timer A/B bit pair: `-`, `A`, `B` or `AB`
+    Timers,
+    /// KC as a note name
+    KeyCode,
+}
+
+#[derive(Clone, Copy)]
+struct Field {
+    name: &'static str,
+    shift: u8,
+    bits: u8,
+    kind: FieldKind,
+}
+
+impl Field {
+    fn mask(&self) -> u8 {
+        (((1u16 << self.bits) - 1) as u8) << self.shift
+    }
+}
+
+const fn field(name: &'static str, shift: u8, bits: u8, kind: FieldKind) -> Field {
+    Field {
+        name,
+        shift,
+        bits,
+        kind,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Global,
+    /// One register per channel at base + channel
+    Channel,
+    /// One register per operator at base + slot * 8 + channel
+    Slot,
+}
+
+struct Register {
+    base: u8,
+    scope: Scope,
+    /// Bits that must have this value for the register to match (0x19 AMD/PMD)
+    fixed: (u8, u8),
+    fields: &'static [Field],
+}
+
+use FieldKind::{Decimal, Hex, KeyCode, Pan, Timers};
+
+const REGISTERS: &[Register] = &[
+    Register {
+        base: 0x01,
+        scope: Scope::Global,
+        fixed: (0, 0),
+        fields: &[field("TEST", 0, 8, Hex)],
+    },
+    Register {
+        base: 0x0F,
+        scope: Scope::Global,
+        fixed: (0, 0),
+        fields: &[field("NE", 7, 1, Decimal), field("NFRQ", 0, 5, Decimal)],
+    },
+    Register {
+        base: 0x10,
+        scope: Scope::Global,
+        fixed: (0, 0),
+        fields: &[field("CLKA1", 0, 8, Hex)],
+    },
+    Register {
+        base: 0x11,
+        scope: Scope::Global,
+        fixed: (0, 0),
+        fields: &[field("CLKA2", 0, 2, Decimal)],
+    },
+    Register {
+        base: 0x12,
+        scope: Scope::Global,
+        fixed: (0, 0),
+        fields: &[field("CLKB", 0, 8, Hex)],
+    },
+    Register {
+        base: 0x14,
+        scope: Scope::Global,
+        fixed: (0, 0),
+        fields: &[
+            field("CSM", 7, 1, Decimal),
+            field("RESET", 4, 2, Timers),
+            field("IRQ", 2, 2, Timers),
+            field("LOAD", 0, 2, Timers),
+        ],
+    },
+    Register {
+        base: 0x18,
+        scope: Scope::Global,
+        fixed: (0, 0),
+        fields: &[field("LFRQ", 0, 8, Decimal)],
+    },
+    Register {
+        base: 0x19,
+        scope: Scope::Global,
+        fixed: (0x80, 0x00),
+        fields: &[field("AMD", 0, 7, Decimal)],
+    },
+    Register {
+        base: 0x19,
+        scope: Scope::Global,
+        fixed: (0x80, 0x80),
+        fields: &[field("PMD", 0, 7, Decimal)],
+    },
+    Register {
+        base: 0x1B,
+        scope: Scope::Global,
+        fixed: (0, 0),
+        fields: &[field("CT", 6, 2, Decimal), field("W", 0, 2, Decimal)],
+    },
+    Register {
+        base: 0x20,
+        scope: Scope::Channel,
+        fixed: (0, 0),
+        fields: &[
+            field("RL", 6, 2, Pan),
+            field("FB", 3, 3, Decimal),
+            field("CON", 0, 3, Decimal),
+        ],
+    },
+    Register {
+        base: 0x28,
+        scope: Scope::Channel,
+        fixed: (0, 0),
+        fields: &[field("KC", 0, 7, KeyCode)],
+    },
+    Register {
+        base: 0x30,
+        scope: Scope::Channel,
+        fixed: (0, 0),
+        fields: &[field("KF", 2, 6, Decimal)],
+    },
+    Register {
+        base: 0x38,
+        scope: Scope::Channel,
+        fixed: (0, 0),
+        fields: &[field("PMS", 4, 3, Decimal), field("AMS", 0, 2, Decimal)],
+    },
+    Register {
+        base: 0x40,
+        scope: Scope::Slot,
+        fixed: (0, 0),
+        fields: &[field("DT1", 4, 3, Decimal), field("MUL", 0, 4, Decimal)],
+    },
+    Register {
+        base: 0x60,
+        scope: Scope::Slot,
+        fixed: (0, 0),
+        fields: &[field("TL", 0, 7, Hex)],
+    },
+    Register {
+        base: 0x80,
+        scope: Scope::Slot,
+        fixed: (0, 0),
+        fields: &[field("KS", 6, 2, Decimal), field("AR", 0, 5, Decimal)],
+    },
+    Register {
+        base: 0xA0,
+        scope: Scope::Slot,
+        fixed: (0, 0),
+        fields: &[field("AMS-EN", 7, 1, Decimal), field("D1R", 0, 5, Decimal)],
+    },
+    Register {
+        base: 0xC0,
+        scope: Scope::Slot,
+        fixed: (0, 0),
+        fields: &[field("DT2", 6, 2, Decimal), field("D2R", 0, 5, Decimal)],
+    },
+    Register {
+        base: 0xE0,
+        scope: Scope::Slot,
+        fixed: (0, 0),
+        fields: &[field("D1L", 4, 4, Decimal), field("RR", 0, 4, Decimal)],
+    },
+];
+
+/// Register definition, channel and slot of an address
+fn lookup(addr: u8, data: u8) -> Option<(&'static Register, u8, u8)> {
+    REGISTERS.iter().find_map(|register| {
+        let (channel, slot) = match register.scope {
+            Scope::Global if addr == register.base => (0, 0),
+            Scope::Channel if (register.base..register.base + 8).contains(&addr) => {
+                (addr - register.base, 0)
+            }
+            Scope::Slot if addr >= register.base && addr - register.base < 0x20 => {
+                let offset = addr - register.base;
+                (offset & 0x07, offset >> 3)
+            }
+            _ => return None,
+        };
+        let (mask, value) = register.fixed;
+        (data & mask == value).then_some((register, channel, slot))
+    })
+}
+
+/// Register values the annotations depend on
+struct Shadow {
+    /// Semitones the log clock shifts every pitch by
+    tuning: f64,
+    key_code: [u8; 8],
+    key_fraction: [u8; 8],
+}
+
+impl Shadow {
+    fn new(clock: u32) -> Self {
+        Self {
+            tuning: 12.0 * (clock as f64 / YM2151_CLOCK as f64).log2(),
+            key_code: [0; 8],
+            key_fraction: [0; 8],
+        }
+    }
+}
+
+/// Disassemble a log into annotated text that [`assemble`] reads back
+pub fn disassemble(log: &EventLog) -> String {
+    let mut text = String::new();
+    let _ = writeln!(text, "; YM2151 register log, {} writes", log.events.len());
+    if let Some(clock) = log.clock {
+        let _ = writeln!(text, ".clock {}", clock);
+    }
+    if let Some(loop_time) = log.loop_time {
+        let _ = writeln!(text, ".loop {}s", loop_time);
+    }
+    if let Some(metadata) = &log.metadata {
+        for (name, value) in metadata_fields(metadata) {
+            if let Some(value) = value {
+                let quoted = serde_json::to_string(value).unwrap_or_default();
+                let _ = writeln!(text, ".{} {}", name, quoted);
+            }
+        }
+    }
+
+    let mut markers: Vec<&Marker> = log.markers.iter().collect();
+    markers.sort_by(|a, b| a.time.total_cmp(&b.time));
+    let mut markers = markers.into_iter().peekable();
+    let mut shadow = Shadow::new(log.clock_or(YM2151_CLOCK));
+    for event in &log.events {
+        while let Some(marker) = markers.next_if(|marker| marker.time <= event.time) {
+            write_marker(&mut text, marker);
+        }
+        let (body, comment) = disassemble_write(event.addr, event.data, &mut shadow);
+        let time = format!("{}s", event.time);
+        let line = format!("{:>tw$}  {}", time, body, tw = TIME_WIDTH);
+        match comment {
+            Some(comment) => {
+                let _ = writeln!(
+                    text,
+                    "{:<w$} ; {}",
+                    line,
+                    comment,
+                    w = TIME_WIDTH + 2 + BODY_WIDTH
+                );
+            }
+            None => {
+                let _ = writeln!(text, "{}", line);
+            }
+        }
+    }
+    for marker in markers {
+        write_marker(&mut text, marker);
+    }
+    text
+}
+
+fn write_marker(text: &mut String, marker: &Marker) {
+    let name = serde_json::to_string(&marker.name).unwrap_or_default();
+    let _ = writeln!(text, ".marker {}s {}", marker.time, name);
+}
+
+fn metadata_fields(metadata: &LogMetadata) -> [(&'static str, &Option<String>); 6] {
+    [
+        ("title", &metadata.title),
+        ("author", &metadata.author),
+        ("game", &metadata.game),
+        ("system", &metadata.system),
+        ("date", &metadata.date),
+        ("notes", &metadata.notes),
+    ]
+}
+
+/// Text of one write without its annotation, e.g. `ch1 M2 TL=0x1F`
+pub fn describe_write(addr: u8, data: u8) -> String {
+    disassemble_write(addr, data, &mut Shadow::new(YM2151_CLOCK)).0
+}
+
+/// Channel and parameter a write sets, e.g. `(Some(1), "M2 TL")` or `(None, "LFRQ")`
+pub fn write_parameter(addr: u8, data: u8) -> (Option<u8>, String) {
+    if addr == REG_KEY_ON {
+        return (Some(data & 0x07), "KEY".to_string());
+    }
+    let Some((register, channel, slot)) = lookup(addr, data) else {
+        return (None, format!("REG 0x{:02X}", addr));
+    };
+    let names: Vec<&str> = register.fields.iter().map(|field| field.name).collect();
+    let names = names.join("/");
+    match register.scope {
+        Scope::Global => (None, names),
+        Scope::Channel => (Some(channel), names),
+        Scope::Slot => (
+            Some(channel),
+            format!("{} {}", SLOT_NAMES[slot as usize], names),
+        ),
+    }
+}
+
+/// Text and annotation for one write
+fn disassemble_write(addr: u8, data: u8, shadow: &mut Shadow) -> (String, Option<String>) {
+    if addr == REG_KEY_ON && data & 0x80 == 0 {
+        let channel = data & 0x07;
+        let ops: Vec<&str> = (0..4)
+            .filter(|bit| data & (0x08 << bit) != 0)
+            .map(|bit| KEY_ON_NAMES[bit])
+            .collect();
+        let body = if ops.is_empty() {
+            format!("KEY OFF ch{}", channel)
+        } else {
+            format!("KEY ON ch{} ops={}", channel, ops.join(","))
+        };
+        return (body, None);
+    }
+
+    let Some((register, channel, slot)) = lookup(addr, data) else {
+        return raw(addr, data);
+    };
+    let used = register
+        .fields
+        .iter()
+        .fold(register.fixed.0, |mask, field| mask | field.mask());
+    if data & !used != 0 {
+        return raw(addr, data);
+    }
+
+    let mut body = String::new();
+    match register.scope {
+        Scope::Global => {}
+        Scope::Channel => body.push_str(&format!("ch{} ", channel)),
+        Scope::Slot => body.push_str(&format!("ch{} {} ", channel, SLOT_NAMES[slot as usize])),
+    }
+    let values: Vec<String> = register
+        .fields
+        .iter()
+        .map(|field| {
+            let value = (data & field.mask()) >> field.shift;
+            format!("{}={}", field.name, format_value(field.kind, value))
+        })
+        .collect();
+    body.push_str(&values.join(" "));
+
+    let channel = channel as usize;
+    let comment = match register.base {
+        0x0F => Some(format!(
+            "noise {}",
+            if data & 0x80 != 0 { "on" } else { "off" }
+        )),
+        0x1B => Some(format!("LFO {}", LFO_WAVEFORMS[(data & 0x03) as usize])),
+        0x20 => {
+            let carriers: Vec<&str> = (0..4)
+                .filter(|&op| crate::formats::is_carrier(data, op))
+                .map(|op| SLOT_NAMES[op])
+                .collect();
+            Some(format!("carriers {}", carriers.join(",")))
+        }
+        0x28 => {
+            shadow.key_code[channel] = data;
+            pitch(shadow, channel)
+        }
+        0x30 => {
+            shadow.key_fraction[channel] = data >> 2;
+            pitch(shadow, channel)
+        }
+        0x60 => Some(format!("{} dB", 0.0 - data as f64 * TL_STEP_DB)),
+        0xE0 => {
+            let d1l = data >> 4;
+            // D1L 15 is -93 dB rather than -45 dB
+            let db = if d1l == 15 { -93 } else { -3 * d1l as i32 };
+            Some(format!("D1L {} dB", db))
+        }
+        _ => None,
+    };
+    (body, comment)
+}
+
+fn raw(addr: u8, data: u8) -> (String, Option<String>) {
+    (
+        format!("REG 0x{:02X}=0x{:02X}", addr, data),
+        Some("no register field decoding".to_string()),
+    )
+}
+
+/// Sounding pitch of a channel at the log clock, e.g. `A4+12c`
+fn pitch(shadow: &Shadow, channel: usize) -> Option<String> {
+    let note = key_code_note(shadow.key_code[channel])?;
+    let cents =
+        (note as f64 + shadow.tuning) * 100.0 + shadow.key_fraction[channel] as f64 * KF_CENTS;
+    let nearest = (cents / 100.0).round() as i32;
+    let offset = (cents - nearest as f64 * 100.0).round() as i32;
+    Some(format!("{}{:+}c", note_name(nearest), offset))
+}
+
+/// MIDI note number of a KC value (at the standard clock)
+fn key_code_note(key_code: u8) -> Option<i32> {
+    Some(key_code_semitone(key_code)? as i32 + KEY_CODE_BASE_NOTE)
+}
+
+fn note_name(note: i32) -> String {
+    format!(
+        "{}{}",
+        NOTE_NAMES[note.rem_euclid(12) as usize],
+        note.div_euclid(12) - 1
+    )
+}
+
+fn format_value(kind: FieldKind, value: u8) -> String {
+    let letters = |names: [&str; 2]| match value {
+        0 => "-".to_string(),
+        1 => names[0].to_string(),
+        2 => names[1].to_string(),
+        _ => format!("{}{}", names[0], names[1]),
+    };
+    match kind {
+        Decimal => value.to_string(),
+        Hex => format!("0x{:02X}", value),
+        Pan => letters(["L", "R"]),
+        Timers => letters(["A", "B"]),
+        KeyCode => match key_code_note(value) {
+            Some(note) => note_name(note),
+            None => format!("0x{:02X}", value),
+        },
+    }
+}
+
+/// Assemble disassembly text back into an event log
+pub fn assemble(text: &str) -> Result<EventLog> {
+    let mut log = EventLog::default();
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        // Directive values are JSON strings that may contain `;`
+        if let Some(directive) = line.trim().strip_prefix('.') {
+            apply_directive(&mut log, directive)
+                .with_context(|| format!("line {}", line_number))?;
+            continue;
+        }
+        let line = strip_comment(line);
+        if line.is_empty() {
+            continue;
+        }
+        let event = assemble_line(line).with_context(|| format!("line {}", line_number))?;
+        log.events.push(event);
+    }
+    if let Some(clock) = log.clock {
+        crate::events::check_clock(clock)?;
+    }
+    Ok(log)
+}
+
+fn apply_directive(log: &mut EventLog, directive: &str) -> Result<()> {
+    let (name, value) = directive
+        .split_once(char::is_whitespace)
+        .map(|(name, value)| (name, value.trim()))
+        .ok_or_else(|| anyhow!("directive `.{}` has no value", directive))?;
+    match name {
+        "clock" => {
+            let clock = parse_number(strip_comment(value))?;
+            log.clock =
+                Some(u32::try_from(clock).map_err(|_| anyhow!("clock {} is too large", clock))?);
+        }
+        "loop" => log.loop_time = Some(parse_time(strip_comment(value))?),
+        "marker" => {
+            let (time, name) = value
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| anyhow!("expected `.marker <time> \"name\"`"))?;
+            let name = serde_json::from_str(name.trim())
+                .map_err(|_| anyhow!("`.marker` needs a quoted name"))?;
+            log.markers.push(Marker {
+                time: parse_time(time)?,
+                name,
+            });
+        }
+        _ => {
+            let value: String = serde_json::from_str(value)
+                .map_err(|_| anyhow!("`.{}` needs a quoted string", name))?;
+            let metadata = log.metadata.get_or_insert_with(LogMetadata::default);
+            let slot = match name {
+                "title" => &mut metadata.title,
+                "author" => &mut metadata.author,
+                "game" => &mut metadata.game,
+                "system" => &mut metadata.system,
+                "date" => &mut metadata.date,
+                "notes" => &mut metadata.notes,
+                _ => bail!("unknown directive `.{}`", name),
+            };
+            *slot = Some(value);
+        }
+    }
+    Ok(())
+}
+
+fn strip_comment(line: &str) -> &str {
+    line.split(';').next().unwrap_or("").trim()
+}
+
+fn assemble_line(line: &str) -> Result<RegisterEvent> {
+    let mut tokens = line.split_whitespace();
+    let time = parse_time(tokens.next().unwrap_or(""))?;
+    let tokens: Vec<&str> = tokens.collect();
+    let (addr, data) = assemble_write(&tokens)?;
+    Ok(RegisterEvent {
+        time,
+        addr,
+        data,
+        is_data: None,
+    })
+}
+
+fn assemble_write(tokens: &[&str]) -> Result<(u8, u8)> {
+    match tokens {
+        ["KEY", "OFF", channel] => Ok((REG_KEY_ON, parse_channel(channel)?)),
+        ["KEY", "ON", channel, ops] => {
+            let ops = ops
+                .strip_prefix("ops=")
+                .ok_or_else(|| anyhow!("expected `ops=` after KEY ON"))?;
+            let mut data = parse_channel(channel)?;
+            for op in ops.split(',') {
+                let bit = KEY_ON_NAMES
+                    .iter()
+                    .position(|&name| name == op)
+                    .ok_or_else(|| anyhow!("unknown operator `{}`", op))?;
+                data |= 0x08 << bit;
+            }
+            Ok((REG_KEY_ON, data))
+        }
+        ["REG", write] => {
+            let (addr, data) = write
+                .split_once('=')
+                .ok_or_else(|| anyhow!("expected REG 0xAA=0xDD"))?;
+            Ok((parse_byte(addr)?, parse_byte(data)?))
+        }
+        _ => assemble_fields(tokens),
+    }
+}
+
+fn assemble_fields(tokens: &[&str]) -> Result<(u8, u8)> {
+    let mut tokens = tokens;
+    let mut channel = None;
+    let mut slot = None;
+    if let Some(token) = tokens.first().filter(|token| token.starts_with("ch")) {
+        channel = Some(parse_channel(token)?);
+        tokens = &tokens[1..];
+        if let Some(position) = tokens
+            .first()
+            .and_then(|token| SLOT_NAMES.iter().position(|name| name == token))
+        {
+            slot = Some(position as u8);
+            tokens = &tokens[1..];
+        }
+    }
+    let scope = match (channel, slot) {
+        (None, _) => Scope::Global,
+        (Some(_), None) => Scope::Channel,
+        (Some(_), Some(_)) => Scope::Slot,
+    };
+
+    let values: Vec<(&str, &str)> = tokens
+        .iter()
+        .map(|token| {
+            token
+                .split_once('=')
+                .ok_or_else(|| anyhow!("expected FIELD=VALUE, found `{}`", token))
+        })
+        .collect::<Result<_>>()?;
+    let register = REGISTERS
+        .iter()
+        .find(|register| {
+            register.scope == scope
+                && register.fields.len() == values.len()
+                && register
+                    .fields
+                    .iter()
+                    .all(|field| values.iter().any(|(name, _)| *name == field.name))
+        })
+        .ok_or_else(|| anyhow!("no register has the fields `{}`", tokens.join(" ")))?;
+
+    let mut data = register.fixed.1;
+    for field in register.fields {
+        let (_, text) = values
+            .iter()
+            .find(|(name, _)| *name == field.name)
+            .copied()
+            .unwrap_or_default();
+        let value = parse_value(field.kind, text)
+            .with_context(|| format!("invalid {} value `{}`", field.name, text))?;
+        if value as u16 >= 1 << field.bits {
+            bail!("{} {} is out of range", field.name, value);
+        }
+        data |= value << field.shift;
+    }
+
+    let addr = match scope {
+        Scope::Global => register.base,
+        Scope::Channel => register.base + channel.unwrap_or(0),
+        Scope::Slot => register.base + slot.unwrap_or(0) * 8 + channel.unwrap_or(0),
+    };
+    Ok((addr, data))
+}
+
+fn parse_value(kind: FieldKind, text: &str) -> Result<u8> {
+    let letters = |names: [char; 2]| -> Result<u8> {
+        if text == "-" {
+            return Ok(0);
+        }
+        text.chars()
+            .try_fold(0, |value, c| match names.iter().position(|&n| n == c) {
+                Some(bit) => Ok(value | 1 << bit),
+                None => bail!("unexpected `{}`", c),
+            })
+    };
+    match kind {
+        Pan => letters(['L', 'R']),
+        Timers => letters(['A', 'B']),
+        KeyCode => match parse_note(text) {
+            Some(key_code) => Ok(key_code),
+            None => parse_byte(text),
+        },
+        Decimal | Hex => parse_byte(text),
+    }
+}
+
+/// KC for a note name such as `A4` or `C#5`
+fn parse_note(text: &str) -> Option<u8> {
+    let split = text.find(|c: char| c.is_ascii_digit() || c == '-')?;
+    let (name, octave) = text.split_at(split);
+    let pitch_class = NOTE_NAMES.iter().position(|&n| n == name)? as i32;
+    let note = (octave.parse::<i32>().ok()? + 1) * 12 + pitch_class;
+    let offset = note - KEY_CODE_BASE_NOTE;
+    if !(0..KEY_CODE_SEMITONES).contains(&offset) {
+        return None;
+    }
+    Some(key_code(offset as u32))
+}
+
+fn parse_channel(token: &str) -> Result<u8> {
+    token
+        .strip_prefix("ch")
+        .and_then(|n| n.parse::<u8>().ok())
+        .filter(|&n| n < 8)
+        .ok_or_else(|| anyhow!("expected a channel ch0-ch7, found `{}`", token))
+}
+
+fn parse_time(token: &str) -> Result<f64> {
+    token
+        .strip_suffix('s')
+        .and_then(|t| t.parse().ok())
+        .ok_or_else(|| anyhow!("expected a time like `0.5s`, found `{}`", token))
+}
+
+fn parse_number(text: &str) -> Result<u64> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| anyhow!("invalid number `{}`", text))
+}
+
+fn parse_byte(text: &str) -> Result<u8> {
+    u8::try_from(parse_number(text)?).map_err(|_| anyhow!("{} does not fit in a byte", text))
+}
@@ -0,0 +1,241 @@
+//! Timer-driven event logs
+//!
+//! Real YM2151 sound drivers do not use absolute timestamps: they write a batch of
+//! registers, then wait for timer A to overflow (IRQ) before the next batch.
+//! This module accepts logs in that style and resolves them into a regular timed
+//! [`EventLog`] by running the driver loop against a scratch chip:
+//!
+//! ```json
+//! {
+//!   "driver": "timer_a",
+//!   "events": [
+//!     {"addr": "0x10", "data": "0xC8"},
+//!     {"addr": "0x14", "data": "0x15"},
+//!     {"addr": "0x08", "data": "0x78"},
+//!     {"wait_timer_a": 4},
+//!     {"addr": "0x08", "data": "0x00"}
+//!   ]
+//! }
+//! ```
+//!
+//! Writes are spaced exactly as `Player` spaces them (address, data 2 samples
+//! later, next address 2 samples after that), so the resolved log reproduces the
+//! emulated timing. Each timer A overflow is acknowledged the way a driver IRQ
+//! handler would, by writing the flag-reset bit to register 0x14; those writes are
+//! included in the resolved log. `loop_time` and `markers` are given in seconds of
+//! the resolved log and carried over as they are.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Deserializer};
+
+use crate::events::{
+    parse_hex_string, EventLog, LogMetadata, Marker, RegisterEvent, MAX_REASONABLE_DURATION_SEC,
+};
+use crate::opm::{
+    OpmChip, REG_TIMER_CONTROL, TIMER_IRQ_EN_A, TIMER_LOAD_A, TIMER_RESET_A, TIMER_RESET_B,
+};
+use crate::resampler::{opm_sample_rate, YM2151_CLOCK};
+
+/// Value of the `"driver"` field for timer A driven logs
+pub const TIMER_A_DRIVER: &str = "timer_a";
+
+const OPM_ADDRESS_REGISTER: u8 = 0;
+const OPM_DATA_REGISTER: u8 = 1;
+const DELAY_SAMPLES: u32 = 2;
+
+/// Longest possible timer A period in samples (CLKA = 0), plus margin for the
+/// load latency after 0x14 is written
+const MAX_TIMER_A_WAIT_SAMPLES: u32 = 1024 + 64;
+
+/// One step of a timer-driven log
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum DriverStep {
+    /// Wait for the given number of timer A overflows
+    WaitTimerA { wait_timer_a: u32 },
+    /// Register write (addr-data pair), issued as soon as the write spacing allows
+    Write {
+        #[serde(deserialize_with = "parse_hex_string")]
+        addr: u8,
+        #[serde(deserialize_with = "parse_hex_string")]
+        data: u8,
+    },
+}
+
+/// A log whose timing comes from timer A rather than timestamps
+#[derive(Debug, Clone, Deserialize)]
+pub struct TimerDrivenLog {
+    #[serde(deserialize_with = "deserialize_driver")]
+    pub driver: String,
+    pub events: Vec<DriverStep>,
+    /// Chip master clock in Hz (see `EventLog::clock`)
+    #[serde(default)]
+    pub clock: Option<u32>,
+    /// Descriptive tags, passed through to the resolved log
+    #[serde(default)]
+    pub metadata: Option<LogMetadata>,
+    /// Loop start time in seconds of the resolved log (see `EventLog::loop_time`)
+    #[serde(default)]
+    pub loop_time: Option<f64>,
+    /// Cue points in seconds of the resolved log, passed through
+    #[serde(default)]
+    pub markers: Vec<Marker>,
+}
+
+fn deserialize_driver<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let driver: String = Deserialize::deserialize(deserializer)?;
+    if driver != TIMER_A_DRIVER {
+        return Err(serde::de::Error::custom(format!(
+            "unsupported driver '{}' (expected '{}')",
+            driver, TIMER_A_DRIVER
+        )));
+    }
+    Ok(driver)
+}
+
+impl TimerDrivenLog {
+    pub fn from_json_str(json_str: &str) -> Result<Self> {
+        let log: TimerDrivenLog = serde_json::from_str(json_str)?;
+        Ok(log)
+    }
+
+    /// Run the driver loop on a scratch chip and return the equivalent timed log
+    pub fn resolve(&self) -> Result<EventLog> {
+        let sample_rate = opm_sample_rate(self.clock.unwrap_or(YM2151_CLOCK));
+        let max_samples = (MAX_REASONABLE_DURATION_SEC * sample_rate as f64) as u64;
+
+        // Every overflow takes at least a sample, so this bounds the work up front
+        let total_waits: u64 = self
+            .events
+            .iter()
+            .map(|step| match *step {
+                DriverStep::WaitTimerA { wait_timer_a } => u64::from(wait_timer_a),
+                DriverStep::Write { .. } => 0,
+            })
+            .sum();
+        if total_waits > max_samples {
+            bail!(
+                "{} timer A overflows run past {} seconds",
+                total_waits,
+                MAX_REASONABLE_DURATION_SEC
+            );
+        }
+
+        let mut driver = DriverEmulator::new(sample_rate);
+        for (index, step) in self.events.iter().enumerate() {
+            match *step {
+                DriverStep::Write { addr, data } => driver.write(addr, data),
+                DriverStep::WaitTimerA { wait_timer_a } => {
+                    for _ in 0..wait_timer_a {
+                        if let Err(e) = driver.wait_timer_a() {
+                            bail!("event {}: {}", index, e);
+                        }
+                        if u64::from(driver.sample) > max_samples {
+                            break;
+                        }
+                    }
+                }
+            }
+            if u64::from(driver.sample) > max_samples {
+                bail!(
+                    "event {}: the log runs past {} seconds",
+                    index,
+                    MAX_REASONABLE_DURATION_SEC
+                );
+            }
+        }
+
+        Ok(EventLog {
+            events: driver.events,
+            clock: self.clock,
+            loop_time: self.loop_time,
+            metadata: self.metadata.clone(),
+            markers: self.markers.clone(),
+        })
+    }
+}
+
+struct DriverEmulator {
+    chip: OpmChip,
+    frame: [i16; 2],
+    sample_rate: u32,
+    sample: u32,
+    next_available_write_time: u32,
+    // Last value written to 0x14, without the one-shot flag reset bits
+    timer_control: u8,
+    events: Vec<RegisterEvent>,
+}
+
+impl DriverEmulator {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            chip: OpmChip::new(),
+            frame: [0; 2],
+            sample_rate,
+            sample: 0,
+            next_available_write_time: 0,
+            timer_control: 0,
+            events: Vec::new(),
+        }
+    }
+
+    fn clock_sample(&mut self) {
+        self.chip.generate_samples(&mut self.frame);
+        self.sample += 1;
+    }
+
+    fn write(&mut self, addr: u8, data: u8) {
+        while self.sample < self.next_available_write_time {
+            self.clock_sample();
+        }
+
+        self.events.push(RegisterEvent {
+            time: self.sample as f64 / self.sample_rate as f64,
+            addr,
+            data,
+            is_data: None,
+        });
+
+        self.chip.write(OPM_ADDRESS_REGISTER, addr);
+        for _ in 0..DELAY_SAMPLES {
+            self.clock_sample();
+        }
+        self.chip.write(OPM_DATA_REGISTER, data);
+        self.next_available_write_time = self.sample + DELAY_SAMPLES;
+
+        if addr == REG_TIMER_CONTROL {
+            self.timer_control = data & !(TIMER_RESET_A | TIMER_RESET_B);
+        }
+    }
+
+    fn wait_timer_a(&mut self) -> Result<()> {
+        let required = TIMER_LOAD_A | TIMER_IRQ_EN_A;
+        if self.timer_control & required != required {
+            bail!(
+                "wait_timer_a requires timer A to be loaded with IRQ enabled (0x14 = 0x{:02X})",
+                self.timer_control
+            );
+        }
+
+        // Let any pending write (e.g. the previous acknowledge) reach the chip first
+        while self.sample < self.next_available_write_time {
+            self.clock_sample();
+        }
+
+        let mut waited = 0;
+        while !self.chip.timer_a_flag() {
+            if waited >= MAX_TIMER_A_WAIT_SAMPLES {
+                bail!("timer A did not overflow within {} samples", waited);
+            }
+            self.clock_sample();
+            waited += 1;
+        }
+
+        // IRQ handler: acknowledge the overflow, keeping timers running
+        self.write(REG_TIMER_CONTROL, self.timer_control | TIMER_RESET_A);
+        Ok(())
+    }
+}
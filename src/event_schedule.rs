@@ -0,0 +1,72 @@
+//! Time-ordered schedule of interactive register writes
+//!
+//! The generator-local schedule used by interactive mode. Events are keyed on
+//! `(time, sequence)` in a `BTreeMap`, where the sequence number is assigned on
+//! insertion. This gives:
+//!
+//! - O(log n) insertion regardless of submission order
+//! - stable ordering of writes scheduled at the same sample time
+//! - O(log n) `clear_from`, by splitting the tree at the cut-off time
+
+use std::collections::BTreeMap;
+
+use crate::player::ProcessedEvent;
+
+/// Register writes ordered by (sample time, insertion order)
+#[derive(Debug, Default)]
+pub struct EventSchedule {
+    events: BTreeMap<(u32, u64), (u8, u8)>,
+    next_sequence: u64,
+}
+
+impl EventSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Schedule an event after any events already scheduled at the same time
+    pub fn push(&mut self, event: ProcessedEvent) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.events
+            .insert((event.time, sequence), (event.addr, event.data));
+    }
+
+    /// Sample time of the earliest scheduled event
+    pub fn next_time(&self) -> Option<u32> {
+        self.events.first_key_value().map(|(&(time, _), _)| time)
+    }
+
+    /// Remove and return the earliest scheduled event
+    pub fn pop_first(&mut self) -> Option<ProcessedEvent> {
+        self.events
+            .pop_first()
+            .map(|((time, _), (addr, data))| ProcessedEvent { time, addr, data })
+    }
+
+    /// Remove all events with time >= `from_sample_time`
+    pub fn clear_from(&mut self, from_sample_time: u32) {
+        // Everything at or after the split key moves into the returned map and is dropped
+        drop(self.events.split_off(&(from_sample_time, 0)));
+    }
+
+    /// Remove all events
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Iterate over scheduled events in playback order
+    pub fn iter(&self) -> impl Iterator<Item = ProcessedEvent> + '_ {
+        self.events
+            .iter()
+            .map(|(&(time, _), &(addr, data))| ProcessedEvent { time, addr, data })
+    }
+}
@@ -0,0 +1,256 @@
+//! Incremental event upload for streamed playback
+//!
+//! A streamed log reaches the generator thread in chunks instead of as one
+//! [`EventLog`](crate::events::EventLog), so playback of a long log starts as soon
+//! as the first chunk arrives and the whole log never has to be held in memory.
+//!
+//! - The **writer** ([`StreamWriter`], IPC side) converts each chunk to sample
+//!   times and checks that it continues the previous one.
+//! - The **reader** ([`StreamReader`], owned by `Player`) takes the next chunk
+//!   once the previous one has been played and publishes the playhead, so the
+//!   writer can tell how far ahead of playback the client is.
+//!
+//! Chunk buffers go around in a circle: the reader swaps each chunk in for the
+//! one just played and hands that buffer back, and the writer fills it with a
+//! later chunk. The generator thread never allocates or frees event storage.
+//!
+//! The playhead never passes the last event received while the stream is open:
+//! if the client falls behind, playback holds there (sounding notes keep
+//! sounding) until more events arrive or the stream ends. At most
+//! [`STREAM_MAX_QUEUED_CHUNKS`] chunks wait for the reader; further chunks are
+//! turned away until it catches up. A stream held for
+//! [`STREAM_IDLE_TIMEOUT_SEC`] ends on its own, so a client that goes away
+//! without ending it does not keep the server playing forever.
+
+use anyhow::{bail, Result};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::sync::Arc;
+
+use crate::audio_config::timing::{STREAM_IDLE_TIMEOUT_SEC, STREAM_MAX_QUEUED_CHUNKS};
+use crate::events::{EventLog, RegisterEvent};
+use crate::player::ProcessedEvent;
+
+/// Log fields a chunk may not set: the stream's clock and time base are fixed
+/// when it begins, and markers and loop points apply to whole logs only
+const LOG_WIDE_FIELDS: [&str; 5] = ["clock", "time_unit", "driver", "loop_time", "markers"];
+
+/// Parse the JSON of an `AppendEvents` chunk: a log with `events` in seconds
+/// from the start of the stream
+pub fn parse_chunk(data: serde_json::Value) -> Result<Vec<RegisterEvent>> {
+    if let Some(object) = data.as_object() {
+        if let Some(field) = LOG_WIDE_FIELDS
+            .iter()
+            .find(|field| object.contains_key(**field))
+        {
+            bail!("{} cannot be set in a stream chunk", field);
+        }
+    }
+    let chunk = EventLog::from_json_value(data)?;
+    chunk.check_playable()?;
+    Ok(chunk.events)
+}
+
+/// Create a connected writer/reader pair for a player at `sample_rate`
+pub fn event_stream(sample_rate: u32) -> (StreamWriter, StreamReader) {
+    let (tx, rx) = mpsc::sync_channel(STREAM_MAX_QUEUED_CHUNKS);
+    // Room for every buffer in circulation: the queued chunks, the one being
+    // played and the one being filled, so handing a buffer back never fails
+    let (recycle, recycled) = mpsc::sync_channel(STREAM_MAX_QUEUED_CHUNKS + 2);
+    let playhead = Arc::new(AtomicU32::new(0));
+    let timed_out = Arc::new(AtomicBool::new(false));
+    let end_sent = Arc::new(AtomicBool::new(false));
+    (
+        StreamWriter {
+            tx,
+            recycled,
+            spare: None,
+            playhead: Arc::clone(&playhead),
+            timed_out: Arc::clone(&timed_out),
+            end_sent: Arc::clone(&end_sent),
+            sample_rate,
+            last_time: 0.0,
+            ended: false,
+        },
+        StreamReader {
+            rx,
+            recycle,
+            playhead,
+            timed_out,
+            end_sent,
+            received_until: 0,
+            ended: false,
+            idle_samples: 0,
+            idle_limit: (STREAM_IDLE_TIMEOUT_SEC * sample_rate as f64) as u32,
+        },
+    )
+}
+
+/// Producer side of a stream
+pub struct StreamWriter {
+    tx: SyncSender<Vec<ProcessedEvent>>,
+    /// Buffers of played chunks, handed back by the reader
+    recycled: Receiver<Vec<ProcessedEvent>>,
+    /// Buffer of a chunk turned away, reused for the next one
+    spare: Option<Vec<ProcessedEvent>>,
+    playhead: Arc<AtomicU32>,
+    timed_out: Arc<AtomicBool>,
+    /// Set after the last chunk has been sent, so the end never waits for room
+    end_sent: Arc<AtomicBool>,
+    sample_rate: u32,
+    /// Time of the last event appended, in seconds
+    last_time: f64,
+    ended: bool,
+}
+
+impl StreamWriter {
+    /// Append events, in time order and not before the last event appended so far
+    ///
+    /// Returns `false`, appending nothing, while [`STREAM_MAX_QUEUED_CHUNKS`]
+    /// chunks are still waiting for the reader.
+    pub fn append(&mut self, events: &[RegisterEvent]) -> Result<bool> {
+        if self.ended {
+            bail!("the stream has already ended");
+        }
+        if self.is_timed_out() {
+            bail!("the stream timed out waiting for events");
+        }
+        let Some(last) = events.last() else {
+            return Ok(true);
+        };
+        if events[0].time < self.last_time {
+            bail!(
+                "events at {} s go back before the previous chunk, which ends at {} s",
+                events[0].time,
+                self.last_time
+            );
+        }
+        let mut chunk = self
+            .spare
+            .take()
+            .or_else(|| self.recycled.try_recv().ok())
+            .unwrap_or_default();
+        chunk.clear();
+        crate::player::Player::convert_events_into(events, self.sample_rate, &mut chunk);
+        match self.tx.try_send(chunk) {
+            Ok(()) => {}
+            Err(TrySendError::Full(chunk)) => {
+                self.spare = Some(chunk);
+                return Ok(false);
+            }
+            Err(TrySendError::Disconnected(_)) => bail!("playback has already finished"),
+        }
+        self.last_time = last.time;
+        Ok(true)
+    }
+
+    /// Mark the end of the stream: playback finishes after the last event
+    pub fn end(&mut self) -> Result<()> {
+        if self.ended {
+            bail!("the stream has already ended");
+        }
+        self.ended = true;
+        if self.is_disconnected() {
+            bail!("playback has already finished");
+        }
+        self.end_sent.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn is_ended(&self) -> bool {
+        self.ended
+    }
+
+    /// Position of playback in the stream's timeline, in seconds
+    pub fn playhead_sec(&self) -> f64 {
+        self.playhead.load(Ordering::Acquire) as f64 / self.sample_rate as f64
+    }
+
+    /// How far the events received run ahead of playback, in seconds
+    pub fn buffered_sec(&self) -> f64 {
+        (self.last_time - self.playhead_sec()).max(0.0)
+    }
+
+    /// Whether the reader is gone, i.e. playback has stopped or finished
+    pub fn is_disconnected(&self) -> bool {
+        Arc::strong_count(&self.playhead) == 1
+    }
+
+    /// Whether the reader ended the stream after waiting too long for events
+    pub fn is_timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::Acquire)
+    }
+}
+
+/// Consumer side of a stream, drained by the generator thread
+pub struct StreamReader {
+    rx: Receiver<Vec<ProcessedEvent>>,
+    recycle: SyncSender<Vec<ProcessedEvent>>,
+    playhead: Arc<AtomicU32>,
+    timed_out: Arc<AtomicBool>,
+    end_sent: Arc<AtomicBool>,
+    /// Sample time of the last event received
+    received_until: u32,
+    ended: bool,
+    /// Samples waited for the client since the last events arrived
+    idle_samples: u32,
+    idle_limit: u32,
+}
+
+impl StreamReader {
+    /// Swap the next chunk received into `events`, handing the buffer `events`
+    /// held back to the writer
+    ///
+    /// Returns whether a chunk was taken. A writer dropped without calling
+    /// [`StreamWriter::end`] ends the stream as well.
+    pub fn next_chunk(&mut self, events: &mut Vec<ProcessedEvent>) -> bool {
+        // Every chunk was sent before the end was marked, so once it is seen
+        // here an empty queue means the stream is over
+        let end_sent = self.end_sent.load(Ordering::Acquire);
+        match self.rx.try_recv() {
+            Ok(mut chunk) => {
+                if let Some(last) = chunk.last() {
+                    self.received_until = last.time;
+                    self.idle_samples = 0;
+                }
+                std::mem::swap(events, &mut chunk);
+                // Only fails once the writer is gone, when the stream is over anyway
+                let _ = self.recycle.try_send(chunk);
+                true
+            }
+            Err(TryRecvError::Disconnected) => {
+                self.ended = true;
+                false
+            }
+            Err(TryRecvError::Empty) => {
+                self.ended |= end_sent;
+                false
+            }
+        }
+    }
+
+    /// Sample time of the last event received
+    pub fn received_until(&self) -> u32 {
+        self.received_until
+    }
+
+    /// Whether the writer has ended the stream
+    pub fn is_ended(&self) -> bool {
+        self.ended
+    }
+
+    /// Publish the playback position in the stream's timeline
+    pub fn publish_playhead(&self, sample_time: u32) {
+        self.playhead.store(sample_time, Ordering::Release);
+    }
+
+    /// Count one sample of playback held waiting for the client; the stream ends
+    /// once it has been held for [`STREAM_IDLE_TIMEOUT_SEC`] without new events
+    pub fn wait_sample(&mut self) {
+        self.idle_samples += 1;
+        if self.idle_samples >= self.idle_limit && !self.ended {
+            self.ended = true;
+            self.timed_out.store(true, Ordering::Release);
+        }
+    }
+}
@@ -1,41 +1,210 @@
-use serde::{Deserialize, Deserializer};
-use std::fs;
+use crate::driver::TimerDrivenLog;
+use crate::opm::{REG_KEY_ON, REG_TEST};
+use crate::resampler::{opm_sample_rate, MAX_CLOCK, MIN_CLOCK, YM2151_CLOCK};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 use std::path::Path;
 
-fn parse_hex_string<'de, D>(deserializer: D) -> Result<u8, D::Error>
+pub mod edit;
+
+/// Read a register byte: a hex string (`"0x1F"` or `"1F"`) or a JSON integer (`31`)
+pub(crate) fn parse_hex_string<'de, D>(deserializer: D) -> Result<u8, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let s: String = Deserialize::deserialize(deserializer)?;
-    let without_prefix = s.trim_start_matches("0x").trim_start_matches("0X");
-    u8::from_str_radix(without_prefix, 16).map_err(serde::de::Error::custom)
+    deserializer.deserialize_any(RegisterByteVisitor)
 }
 
-#[derive(Debug, Clone, Deserialize)]
+struct RegisterByteVisitor;
+
+impl Visitor<'_> for RegisterByteVisitor {
+    type Value = u8;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a hex string like \"0x1F\" or an integer from 0 to 255")
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<u8, E> {
+        let without_prefix = s.trim_start_matches("0x").trim_start_matches("0X");
+        u8::from_str_radix(without_prefix, 16).map_err(E::custom)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<u8, E> {
+        u8::try_from(value)
+            .map_err(|_| E::custom(format!("register value {} is out of range (0-255)", value)))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<u8, E> {
+        u8::try_from(value)
+            .map_err(|_| E::custom(format!("register value {} is out of range (0-255)", value)))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<u8, E> {
+        if value.fract() != 0.0 || !(0.0..=255.0).contains(&value) {
+            return Err(E::custom(format!(
+                "register value {} is not an integer from 0 to 255",
+                value
+            )));
+        }
+        Ok(value as u8)
+    }
+}
+
+/// Write a register byte in the same `"0xNN"` form the log format reads
+pub(crate) fn serialize_hex_string<S>(value: &u8, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&format!("0x{:02X}", value))
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RegisterEvent {
     pub time: f64,
 
-    #[serde(deserialize_with = "parse_hex_string")]
+    #[serde(
+        deserialize_with = "parse_hex_string",
+        serialize_with = "serialize_hex_string"
+    )]
     pub addr: u8,
 
-    #[serde(deserialize_with = "parse_hex_string")]
+    #[serde(
+        deserialize_with = "parse_hex_string",
+        serialize_with = "serialize_hex_string"
+    )]
     pub data: u8,
 
-    #[serde(default, skip_deserializing)]
+    #[serde(default, skip)]
     pub is_data: Option<u8>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct EventLog {
     pub events: Vec<RegisterEvent>,
+
+    /// Chip master clock in Hz the log was captured at (e.g. 4000000 for X68000).
+    /// `None` means the player's default clock.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<u32>,
+
+    /// Loop start time in seconds, for formats with a loop point (VGM).
+    /// Playback itself does not loop.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loop_time: Option<f64>,
+
+    /// Descriptive tags, carried into the GD3 block on VGM export
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<LogMetadata>,
+
+    /// Named cue points; playback reports each one as it is reached
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub markers: Vec<Marker>,
+}
+
+/// A named cue point (section start, beat, etc.) in a log
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Marker {
+    /// Time in seconds (or in the log's `time_unit` in JSON)
+    pub time: f64,
+    pub name: String,
+}
+
+/// Descriptive tags of a log (`"metadata"` in JSON)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LogMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    /// Composer or arranger (`"composer"` is accepted as well)
+    #[serde(default, alias = "composer", skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+
+    /// Game or other work the music comes from (`"source"` is accepted as well)
+    #[serde(default, alias = "source", skip_serializing_if = "Option::is_none")]
+    pub game: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+/// Unit of `time` values (and `loop_time`) in a JSON log (`"time_unit"`)
+///
+/// Logs are always held in seconds; other units are converted on load.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeUnit {
+    #[default]
+    Seconds,
+    /// OPM samples at the log's clock (55930 Hz at the default clock)
+    Samples,
+    /// Sequencer ticks, with the tempo given by `"bpm"` and `"ppq"`
+    Ticks,
+}
+
+/// Fields inspected before choosing how to parse a log
+#[derive(Deserialize)]
+struct LogHeader {
+    #[serde(default)]
+    driver: Option<String>,
+    #[serde(default)]
+    time_unit: TimeUnit,
+    /// Tempo in quarter notes per minute, for `"ticks"`
+    #[serde(default)]
+    bpm: Option<f64>,
+    /// Ticks per quarter note, for `"ticks"`
+    #[serde(default)]
+    ppq: Option<u32>,
+}
+
+impl LogHeader {
+    /// Length of one time unit in seconds
+    fn seconds_per_unit(&self, clock: u32) -> anyhow::Result<f64> {
+        match self.time_unit {
+            TimeUnit::Seconds => Ok(1.0),
+            TimeUnit::Samples => Ok(1.0 / opm_sample_rate(clock) as f64),
+            TimeUnit::Ticks => {
+                let (Some(bpm), Some(ppq)) = (self.bpm, self.ppq) else {
+                    anyhow::bail!("time_unit \"ticks\" needs \"bpm\" and \"ppq\"");
+                };
+                if !(bpm.is_finite() && bpm > 0.0) || ppq == 0 {
+                    anyhow::bail!("bpm {} and ppq {} must be positive", bpm, ppq);
+                }
+                Ok(60.0 / (bpm * ppq as f64))
+            }
+        }
+    }
+}
+
+/// Check that a master clock is within the supported range
+pub fn check_clock(clock: u32) -> anyhow::Result<()> {
+    if !(MIN_CLOCK..=MAX_CLOCK).contains(&clock) {
+        anyhow::bail!(
+            "clock {} Hz is out of range ({}..={} Hz)",
+            clock,
+            MIN_CLOCK,
+            MAX_CLOCK
+        );
+    }
+    Ok(())
 }
 
 impl EventLog {
     /// Load event log from a file path
+    ///
+    /// gzip and zstd compressed files (`.json.gz`, `.json.zst`) are decompressed
+    /// transparently, whatever their extension.
     pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
-        let content = fs::read_to_string(path)?;
-        let log: EventLog = serde_json::from_str(&content)?;
-        Ok(log)
+        let bytes = crate::compression::read_file(path)?;
+        let content = String::from_utf8(bytes)
+            .map_err(|e| anyhow::anyhow!("log file is not valid UTF-8: {}", e))?;
+        Self::from_json_str(&content)
     }
 
     /// Parse event log directly from a JSON string
@@ -43,6 +212,13 @@ impl EventLog {
     /// This is useful when receiving JSON data via IPC (e.g., named pipes)
     /// without writing to an intermediate file first.
     ///
+    /// Logs with a `"driver"` field are timer-driven and are resolved to
+    /// timestamps first (see [`crate::driver`]).
+    ///
+    /// Times are in seconds unless `"time_unit"` says otherwise: `"samples"` counts
+    /// OPM samples at the log's clock, and `"ticks"` needs `"bpm"` and `"ppq"`.
+    /// `addr`/`data` may be hex strings or plain integers.
+    ///
     /// # Example
     /// ```
     /// # use ym2151_log_play_server::events::EventLog;
@@ -55,20 +231,278 @@ impl EventLog {
     ///
     /// let log = EventLog::from_json_str(json_str).unwrap();
     /// assert!(log.validate());
+    ///
+    /// // 480 ticks at 120 BPM is half a second
+    /// let ticks = r#"{
+    ///     "time_unit": "ticks", "bpm": 120, "ppq": 480,
+    ///     "events": [{"time": 480, "addr": 8, "data": 120}]
+    /// }"#;
+    /// let log = EventLog::from_json_str(ticks).unwrap();
+    /// assert_eq!((log.events[0].time, log.events[0].data), (0.5, 0x78));
     /// ```
     pub fn from_json_str(json_str: &str) -> anyhow::Result<Self> {
-        let log: EventLog = serde_json::from_str(json_str)?;
+        Self::from_json_value(serde_json::from_str(json_str)?)
+    }
+
+    /// Parse event log from JSON already parsed into a value, as
+    /// [`from_json_str`](Self::from_json_str) does for text
+    ///
+    /// The text is only parsed once; the header fields are read from the value
+    /// before it is turned into a log.
+    pub fn from_json_value(value: serde_json::Value) -> anyhow::Result<Self> {
+        let header = LogHeader::deserialize(&value)?;
+        let mut log = if header.driver.is_some() {
+            if header.time_unit != TimeUnit::Seconds {
+                anyhow::bail!("time_unit does not apply to timer-driven logs");
+            }
+            TimerDrivenLog::deserialize(value)?.resolve()?
+        } else {
+            EventLog::deserialize(value)?
+        };
+
+        if let Some(clock) = log.clock {
+            check_clock(clock)?;
+        }
+        if header.time_unit != TimeUnit::Seconds {
+            let scale = header.seconds_per_unit(log.clock_or(YM2151_CLOCK))?;
+            for event in &mut log.events {
+                event.time *= scale;
+            }
+            if let Some(loop_time) = log.loop_time.as_mut() {
+                *loop_time *= scale;
+            }
+            for marker in &mut log.markers {
+                marker.time *= scale;
+            }
+        }
         Ok(log)
     }
 
+    /// Serialize to the JSON log format (hex-string `addr`/`data`)
+    pub fn to_json_string(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Master clock to play this log at
+    pub fn clock_or(&self, default_clock: u32) -> u32 {
+        self.clock.unwrap_or(default_clock)
+    }
+
+    /// Whether the log can be played: times are finite, non-negative and in order
+    ///
+    /// See [`validate_detailed`](Self::validate_detailed) for what is wrong.
     pub fn validate(&self) -> bool {
-        // Check if events are sorted by time
-        for i in 1..self.events.len() {
-            if self.events[i].time < self.events[i - 1].time {
-                return false;
+        !self
+            .validate_detailed()
+            .iter()
+            .any(ValidationIssue::is_error)
+    }
+
+    /// Fail with a report of the errors found by
+    /// [`validate_detailed`](Self::validate_detailed); otherwise return the warnings
+    pub fn check_playable(&self) -> anyhow::Result<Vec<ValidationIssue>> {
+        let (errors, warnings): (Vec<_>, Vec<_>) = self
+            .validate_detailed()
+            .into_iter()
+            .partition(ValidationIssue::is_error);
+        if !errors.is_empty() {
+            anyhow::bail!(
+                "Event log validation failed: {}",
+                validation_report(&errors)
+            );
+        }
+        Ok(warnings)
+    }
+
+    /// Every problem found in the log, in event order
+    ///
+    /// Out-of-order and invalid times are errors ([`ValidationIssue::is_error`]);
+    /// the rest are suspicious writes that the chip accepts anyway.
+    pub fn validate_detailed(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        let mut issue = |index: usize, time: f64, kind, message: String| {
+            issues.push(ValidationIssue {
+                index,
+                time,
+                kind,
+                message,
+            })
+        };
+
+        let mut last_time = f64::NEG_INFINITY;
+        let mut voice_loaded = [false; 8];
+        let mut reported_key_on = [false; 8];
+        let mut reported_length = false;
+
+        for (index, event) in self.events.iter().enumerate() {
+            let time = event.time;
+            if !time.is_finite() || time < 0.0 {
+                issue(
+                    index,
+                    time,
+                    ValidationIssueKind::InvalidTime,
+                    format!("time {} is not a finite, non-negative number", time),
+                );
+            } else {
+                if time < last_time {
+                    issue(
+                        index,
+                        time,
+                        ValidationIssueKind::OutOfOrder,
+                        format!("time goes back from {} to {}", last_time, time),
+                    );
+                }
+                last_time = last_time.max(time);
+
+                if time > MAX_REASONABLE_DURATION_SEC && !reported_length {
+                    reported_length = true;
+                    issue(
+                        index,
+                        time,
+                        ValidationIssueKind::TooLong,
+                        format!(
+                            "log runs past {} seconds ({:.0} s)",
+                            MAX_REASONABLE_DURATION_SEC, time
+                        ),
+                    );
+                }
+            }
+
+            let channel = (event.addr & 0x07) as usize;
+            match event.addr {
+                REG_TEST if event.data & !LFO_RESET != 0 => issue(
+                    index,
+                    time,
+                    ValidationIssueKind::TestRegister,
+                    format!(
+                        "test register 0x01 written with 0x{:02X} (only bit 1, LFO reset, is for software)",
+                        event.data
+                    ),
+                ),
+                REG_KEY_ON if event.data & 0x78 != 0 => {
+                    let channel = (event.data & 0x07) as usize;
+                    if !voice_loaded[channel] && !reported_key_on[channel] {
+                        reported_key_on[channel] = true;
+                        issue(
+                            index,
+                            time,
+                            ValidationIssueKind::KeyOnWithoutVoice,
+                            format!("key-on for channel {} before any operator register is written", channel),
+                        );
+                    }
+                }
+                0x40..=0xFF => voice_loaded[channel] = true,
+                addr if !is_register_in_use(addr) => issue(
+                    index,
+                    time,
+                    ValidationIssueKind::UnusedRegister,
+                    format!("write to unused register 0x{:02X}", addr),
+                ),
+                _ => {}
+            }
+        }
+
+        for (index, marker) in self.markers.iter().enumerate() {
+            if !marker.time.is_finite() || marker.time < 0.0 {
+                issue(
+                    index,
+                    marker.time,
+                    ValidationIssueKind::InvalidMarker,
+                    format!(
+                        "marker \"{}\" time {} is not a finite, non-negative number",
+                        marker.name, marker.time
+                    ),
+                );
             }
         }
+        issues
+    }
+}
+
+/// Bit of the test register that resets the LFO phase
+const LFO_RESET: u8 = 0x02;
+
+/// Logs longer than this are probably broken timestamps (e.g. samples read as seconds)
+pub const MAX_REASONABLE_DURATION_SEC: f64 = 3600.0;
+
+/// Whether an OPM register address does anything
+///
+/// The OPM has no read-only register addresses (the status byte is read from
+/// the data port), so the test register is the only one software should avoid.
+fn is_register_in_use(addr: u8) -> bool {
+    matches!(
+        addr,
+        0x01 | 0x08 | 0x0F | 0x10..=0x12 | 0x14 | 0x18 | 0x19 | 0x1B | 0x20..=0xFF
+    )
+}
+
+/// Kind of problem reported by [`EventLog::validate_detailed`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationIssueKind {
+    /// Time earlier than a previous event
+    OutOfOrder,
+    /// Negative, NaN or infinite time
+    InvalidTime,
+    /// Write to an address the chip does not use
+    UnusedRegister,
+    /// Write to the LSI test register other than the LFO reset
+    TestRegister,
+    /// Key-on for a channel with no operator registers written yet
+    KeyOnWithoutVoice,
+    /// Event past [`MAX_REASONABLE_DURATION_SEC`]
+    TooLong,
+    /// Marker with a negative, NaN or infinite time (`index` is the marker's)
+    InvalidMarker,
+}
+
+/// One problem in an event log
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    /// Index of the event in `events` (of the marker in `markers` for marker issues)
+    pub index: usize,
+    pub time: f64,
+    pub kind: ValidationIssueKind,
+    pub message: String,
+}
+
+impl ValidationIssue {
+    /// Whether the log cannot be played as written
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self.kind,
+            ValidationIssueKind::OutOfOrder
+                | ValidationIssueKind::InvalidTime
+                | ValidationIssueKind::InvalidMarker
+        )
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let item = match self.kind {
+            ValidationIssueKind::InvalidMarker => "marker",
+            _ => "event",
+        };
+        write!(
+            f,
+            "{} {} (time {}): {}",
+            item, self.index, self.time, self.message
+        )
+    }
+}
+
+/// Number of issues spelled out by [`validation_report`]
+const REPORT_LIMIT: usize = 10;
 
-        true
+/// One-line summary of validation issues for error messages
+pub fn validation_report(issues: &[ValidationIssue]) -> String {
+    let mut report: Vec<String> = issues
+        .iter()
+        .take(REPORT_LIMIT)
+        .map(ToString::to_string)
+        .collect();
+    if issues.len() > REPORT_LIMIT {
+        report.push(format!("and {} more", issues.len() - REPORT_LIMIT));
     }
+    report.join("; ")
 }
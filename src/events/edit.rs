@@ -0,0 +1,391 @@
+//! Editing operations on event logs
+//!
+//! Every function returns a new log whose events stay sorted by time, so the
+//! result passes [`EventLog::validate`] whenever the inputs do.
+
+use anyhow::{bail, Result};
+
+use super::{EventLog, Marker, RegisterEvent};
+use crate::opm::{REG_KEY_ON, REG_TEST, REG_TIMER_CONTROL};
+use crate::register_shadow::RegisterShadow;
+use crate::resampler::YM2151_CLOCK;
+
+const REG_TOTAL_LEVEL: std::ops::RangeInclusive<u8> = 0x60..=0x7F;
+const TOTAL_LEVEL_MUTE: u8 = 0x7F;
+const CHANNELS: usize = 8;
+
+/// Cut the events in `start..end` (seconds) into a log starting at 0
+///
+/// The slice begins with a preamble at time 0 that restores every register
+/// written before `start`, and keys on again the channels that were sounding
+/// (their notes restart from the attack). Channels still keyed on at `end`
+/// are keyed off there. Pass `f64::INFINITY` as `end` to keep the rest of the log.
+pub fn slice(log: &EventLog, start: f64, end: f64) -> Result<EventLog> {
+    log.check_playable()?;
+    if !(start >= 0.0 && start < end) {
+        bail!("invalid slice range {}..{}", start, end);
+    }
+
+    let mut shadow = RegisterShadow::default();
+    let mut events = Vec::new();
+    let mut preamble_done = false;
+    for event in &log.events {
+        if event.time >= end {
+            break;
+        }
+        if event.time < start {
+            shadow.store(event.addr, event.data);
+            continue;
+        }
+        if !preamble_done {
+            events.extend(preamble(&shadow));
+            preamble_done = true;
+        }
+        shadow.store(event.addr, event.data);
+        events.push(RegisterEvent {
+            time: event.time - start,
+            ..event.clone()
+        });
+    }
+    if !preamble_done {
+        events.extend(preamble(&shadow));
+    }
+    if end.is_finite() {
+        events.extend(key_offs(&shadow, end - start));
+    }
+
+    Ok(EventLog {
+        events,
+        loop_time: log
+            .loop_time
+            .filter(|&t| (start..end).contains(&t))
+            .map(|t| t - start),
+        markers: log
+            .markers
+            .iter()
+            .filter(|marker| (start..end).contains(&marker.time))
+            .map(|marker| Marker {
+                time: marker.time - start,
+                ..marker.clone()
+            })
+            .collect(),
+        ..log.clone()
+    })
+}
+
+/// Move every event (and the loop point) by `offset` seconds
+pub fn shift(log: &EventLog, offset: f64) -> Result<EventLog> {
+    log.check_playable()?;
+    if !offset.is_finite() {
+        bail!("invalid time offset {}", offset);
+    }
+    if let Some(first) = log.events.first() {
+        if first.time + offset < 0.0 {
+            bail!(
+                "shifting by {} s would move the first event to {} s",
+                offset,
+                first.time + offset
+            );
+        }
+    }
+    Ok(retime(log, |t| t + offset))
+}
+
+/// Multiply every time (and the loop point) by `factor`, e.g. 0.5 for double speed
+pub fn scale(log: &EventLog, factor: f64) -> Result<EventLog> {
+    log.check_playable()?;
+    if !(factor.is_finite() && factor > 0.0) {
+        bail!("invalid time scale factor {}", factor);
+    }
+    Ok(retime(log, |t| t * factor))
+}
+
+/// Play the loop section `count` times in all, e.g. 2 for one repeat
+///
+/// The loop runs from the loop point (the start of the log if it has none) to
+/// the last event; each repeat starts at the time the previous pass ends, and
+/// markers in the loop section repeat with it. The result has no loop point.
+pub fn unroll_loop(log: &EventLog, count: u32) -> Result<EventLog> {
+    log.check_playable()?;
+    if count == 0 {
+        bail!("loop count must be at least 1");
+    }
+    if count == 1 {
+        return Ok(log.clone());
+    }
+    let loop_start = log.loop_time.unwrap_or(0.0);
+    let end = log.events.last().map_or(0.0, |event| event.time);
+    let length = end - loop_start;
+    if length <= 0.0 {
+        bail!("the loop section from {} s is empty", loop_start);
+    }
+
+    let mut events = log.events.clone();
+    let mut markers = log.markers.clone();
+    for pass in 1..count {
+        let offset = length * pass as f64;
+        events.extend(
+            log.events
+                .iter()
+                .filter(|event| event.time >= loop_start)
+                .map(|event| RegisterEvent {
+                    time: event.time + offset,
+                    ..event.clone()
+                }),
+        );
+        markers.extend(
+            log.markers
+                .iter()
+                .filter(|marker| marker.time >= loop_start && marker.time < end)
+                .map(|marker| Marker {
+                    time: marker.time + offset,
+                    ..marker.clone()
+                }),
+        );
+    }
+    markers.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    Ok(EventLog {
+        events,
+        loop_time: None,
+        markers,
+        ..log.clone()
+    })
+}
+
+fn retime(log: &EventLog, map: impl Fn(f64) -> f64) -> EventLog {
+    EventLog {
+        events: log
+            .events
+            .iter()
+            .map(|event| RegisterEvent {
+                time: map(event.time),
+                ..event.clone()
+            })
+            .collect(),
+        loop_time: log.loop_time.map(&map),
+        markers: log
+            .markers
+            .iter()
+            .map(|marker| Marker {
+                time: map(marker.time),
+                ..marker.clone()
+            })
+            .collect(),
+        ..log.clone()
+    }
+}
+
+/// Play logs one after another, `gap` seconds apart
+///
+/// Each log starts `gap` seconds after the last event of the previous one, and
+/// channels still keyed on at the end of a log are keyed off there. Markers move
+/// with their log. The result takes its clock and metadata from the first log
+/// and has no loop point.
+pub fn concat(logs: &[EventLog], gap: f64) -> Result<EventLog> {
+    let clock = common_clock(logs)?;
+    if !(gap.is_finite() && gap >= 0.0) {
+        bail!("invalid gap {}", gap);
+    }
+
+    let mut events = Vec::new();
+    let mut markers = Vec::new();
+    let mut offset = 0.0;
+    for log in logs {
+        markers.extend(log.markers.iter().map(|marker| Marker {
+            time: marker.time + offset,
+            ..marker.clone()
+        }));
+        let mut shadow = RegisterShadow::default();
+        for event in &log.events {
+            shadow.store(event.addr, event.data);
+            events.push(RegisterEvent {
+                time: event.time + offset,
+                ..event.clone()
+            });
+        }
+        let end = log.events.last().map_or(0.0, |event| event.time) + offset;
+        events.extend(key_offs(&shadow, end));
+        offset = end + gap;
+    }
+
+    Ok(EventLog {
+        events,
+        clock,
+        loop_time: None,
+        metadata: logs.first().and_then(|log| log.metadata.clone()),
+        markers,
+    })
+}
+
+/// Play logs at the same time, moving channels so the logs do not share any
+///
+/// Channels keep their number when it is still free and otherwise move to the
+/// lowest free channel; it is an error if the logs use more than 8 channels in
+/// total. Key-offs on channels a log never plays are dropped. Global registers
+/// (LFO, noise, timers) cannot be separated and are merged as they are, and so
+/// are the markers of all logs. The result takes its clock, loop point and
+/// metadata from the first log.
+pub fn merge(logs: &[EventLog]) -> Result<EventLog> {
+    let clock = common_clock(logs)?;
+
+    let mut taken = [false; CHANNELS];
+    let mut events = Vec::new();
+    for log in logs {
+        let used = used_channels(&log.events);
+        let mut map = [None; CHANNELS];
+        // Keep channel numbers first so a log that fits is left untouched
+        for channel in 0..CHANNELS {
+            if used[channel] && !taken[channel] {
+                map[channel] = Some(channel as u8);
+                taken[channel] = true;
+            }
+        }
+        for channel in 0..CHANNELS {
+            if !used[channel] || map[channel].is_some() {
+                continue;
+            }
+            let Some(free) = (0..CHANNELS).find(|&c| !taken[c]) else {
+                bail!("the logs use more than {} channels in total", CHANNELS);
+            };
+            map[channel] = Some(free as u8);
+            taken[free] = true;
+        }
+        // Key-offs for channels the log never plays would cut other logs' notes
+        let plays = |event: &&RegisterEvent| {
+            write_channel(event.addr, event.data).is_none_or(|channel| used[channel])
+        };
+        let map = map.map(|channel| channel.unwrap_or(0));
+        events.extend(log.events.iter().filter(plays).map(|event| {
+            let (addr, data) = remap_channel(event.addr, event.data, &map);
+            RegisterEvent {
+                addr,
+                data,
+                ..event.clone()
+            }
+        }));
+    }
+    // Stable, so simultaneous events keep the order of the logs
+    events.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    let mut markers: Vec<Marker> = logs.iter().flat_map(|log| log.markers.clone()).collect();
+    markers.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    let first = logs.first();
+    Ok(EventLog {
+        events,
+        clock,
+        loop_time: first.and_then(|log| log.loop_time),
+        metadata: first.and_then(|log| log.metadata.clone()),
+        markers,
+    })
+}
+
+/// Leave only the channels in `channels` (bit n for channel n) audible
+///
+/// Key-ons of the other channels become key-offs and their total levels are
+/// held at full attenuation. Every other write (LFO, noise, timers) stays as it
+/// is, so the kept channels sound exactly as they do in the full log.
+pub fn mask_channels(log: &EventLog, channels: u8) -> EventLog {
+    let muted = |channel: u8| channels & (1 << channel) == 0;
+    let events = log
+        .events
+        .iter()
+        .map(|event| {
+            let data = match event.addr {
+                REG_KEY_ON if muted(event.data & 0x07) => event.data & 0x07,
+                addr if REG_TOTAL_LEVEL.contains(&addr) && muted(addr & 0x07) => TOTAL_LEVEL_MUTE,
+                _ => event.data,
+            };
+            RegisterEvent {
+                data,
+                ..event.clone()
+            }
+        })
+        .collect();
+    EventLog {
+        events,
+        ..log.clone()
+    }
+}
+
+/// The clock shared by all logs (`None` if none sets one)
+fn common_clock(logs: &[EventLog]) -> Result<Option<u32>> {
+    for log in logs {
+        log.check_playable()?;
+    }
+    let mut clocks = logs.iter().map(|log| log.clock_or(YM2151_CLOCK));
+    if let Some(first) = clocks.next() {
+        if let Some(other) = clocks.find(|&clock| clock != first) {
+            bail!("logs use different clocks ({} Hz and {} Hz)", first, other);
+        }
+    }
+    Ok(logs.iter().find_map(|log| log.clock))
+}
+
+/// Channel a write belongs to, if any
+fn write_channel(addr: u8, data: u8) -> Option<usize> {
+    match addr {
+        REG_KEY_ON => Some((data & 0x07) as usize),
+        0x20..=0xFF => Some((addr & 0x07) as usize),
+        _ => None,
+    }
+}
+
+/// Channels a log plays on; key-offs alone (as in initialization) do not count
+fn used_channels(events: &[RegisterEvent]) -> [bool; CHANNELS] {
+    let mut used = [false; CHANNELS];
+    for event in events {
+        let key_off = event.addr == REG_KEY_ON && event.data & 0x78 == 0;
+        match write_channel(event.addr, event.data) {
+            Some(channel) if !key_off => used[channel] = true,
+            _ => {}
+        }
+    }
+    used
+}
+
+fn remap_channel(addr: u8, data: u8, map: &[u8; CHANNELS]) -> (u8, u8) {
+    match write_channel(addr, data) {
+        Some(channel) if addr == REG_KEY_ON => (addr, data & !0x07 | map[channel]),
+        Some(channel) => (addr & !0x07 | map[channel], data),
+        None => (addr, data),
+    }
+}
+
+/// Writes at time 0 that recreate the state in `shadow`
+///
+/// The test register and timer control are skipped, as writing them resets
+/// the LFO and timer flags rather than restoring anything.
+fn preamble(shadow: &RegisterShadow) -> Vec<RegisterEvent> {
+    let mut events: Vec<_> = shadow
+        .writes()
+        .filter(|&(addr, _)| addr != REG_TEST && addr != REG_TIMER_CONTROL)
+        .map(|(addr, data)| write(0.0, addr, data))
+        .collect();
+    events.extend(
+        shadow
+            .keys()
+            .filter(|&data| data & 0x78 != 0)
+            .map(|data| write(0.0, REG_KEY_ON, data)),
+    );
+    events
+}
+
+/// Key-off writes at `time` for every channel keyed on in `shadow`
+fn key_offs(shadow: &RegisterShadow, time: f64) -> Vec<RegisterEvent> {
+    shadow
+        .keys()
+        .filter(|&data| data & 0x78 != 0)
+        .map(|data| write(time, REG_KEY_ON, data & 0x07))
+        .collect()
+}
+
+fn write(time: f64, addr: u8, data: u8) -> RegisterEvent {
+    RegisterEvent {
+        time,
+        addr,
+        data,
+        is_data: None,
+    }
+}
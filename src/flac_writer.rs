@@ -0,0 +1,428 @@
+//! FLAC output for rendered audio
+//!
+//! A small pure-Rust encoder: fixed-size blocks of two independent channels,
+//! each coded as the smallest of a constant, verbatim samples, or one of the
+//! fixed predictors of order 0-4 with partitioned Rice coding of the residual.
+//! The MD5 signature in STREAMINFO is left unset (all zeros), which decoders
+//! read as "not computed".
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+
+use crate::pcm::{self, Dither, SampleFormat};
+
+/// Samples per channel in each frame (except the last)
+const BLOCK_SIZE: usize = 4096;
+const CHANNELS: usize = 2;
+const MAX_FIXED_ORDER: usize = 4;
+const MAX_PARTITION_ORDER: u32 = 8;
+/// Largest Rice parameter of the 4-bit parameter method (15 is the escape code)
+const MAX_RICE4_PARAMETER: u32 = 14;
+/// Largest Rice parameter of the 5-bit parameter method (31 is the escape code)
+const MAX_RICE5_PARAMETER: u32 = 30;
+/// Highest sample rate STREAMINFO can hold
+const MAX_SAMPLE_RATE: u32 = 655_350;
+
+/// Write full-precision stereo samples (see [`crate::pcm`]) as 16-bit or 24-bit
+/// FLAC, reduced to 16 bits as `dither` says
+pub fn write_flac(
+    path: &str,
+    samples: &[f32],
+    sample_rate: u32,
+    format: SampleFormat,
+    dither: Dither,
+) -> Result<()> {
+    let (samples, bits_per_sample): (Vec<i32>, u32) = match format {
+        SampleFormat::Int16 => (
+            pcm::to_i16(samples, dither)
+                .into_iter()
+                .map(i32::from)
+                .collect(),
+            16,
+        ),
+        SampleFormat::Int24 => (pcm::to_i24(samples), 24),
+        SampleFormat::Float32 => bail!("FLAC cannot store 32-bit float samples"),
+    };
+    let bytes = encode(&samples, sample_rate, bits_per_sample)?;
+    fs::write(path, bytes).with_context(|| format!("Failed to write FLAC file: {}", path))
+}
+
+/// Encode interleaved stereo samples of `bits_per_sample` (8, 12, 16, 20 or 24)
+/// bits as a FLAC stream
+pub fn encode(samples: &[i32], sample_rate: u32, bits_per_sample: u32) -> Result<Vec<u8>> {
+    if !samples.len().is_multiple_of(CHANNELS) {
+        bail!("Input buffer must have even length (stereo samples)");
+    }
+    if sample_rate == 0 || sample_rate > MAX_SAMPLE_RATE {
+        bail!("FLAC cannot store a sample rate of {} Hz", sample_rate);
+    }
+    let Some(sample_size_code) = sample_size_code(bits_per_sample) else {
+        bail!(
+            "FLAC output supports 8, 12, 16, 20 or 24 bits, not {}",
+            bits_per_sample
+        );
+    };
+    let limit = 1i32 << (bits_per_sample - 1);
+    if let Some(&sample) = samples.iter().find(|&&s| s < -limit || s >= limit) {
+        bail!("sample {} does not fit in {} bits", sample, bits_per_sample);
+    }
+
+    let total_frames = samples.len() / CHANNELS;
+    let mut frames = Vec::new();
+    let mut min_frame_size = u32::MAX;
+    let mut max_frame_size = 0;
+    for (frame_number, block) in samples.chunks(BLOCK_SIZE * CHANNELS).enumerate() {
+        let frame = encode_frame(
+            block,
+            frame_number as u32,
+            bits_per_sample,
+            sample_size_code,
+        );
+        min_frame_size = min_frame_size.min(frame.len() as u32);
+        max_frame_size = max_frame_size.max(frame.len() as u32);
+        frames.push(frame);
+    }
+    if frames.is_empty() {
+        min_frame_size = 0;
+    }
+
+    // Blocks are BLOCK_SIZE long except the last, which the minimum ignores
+    let block_size = total_frames.clamp(16, BLOCK_SIZE) as u64;
+    let mut stream = BitWriter::new();
+    stream.write(u64::from(u32::from_be_bytes(*b"fLaC")), 32);
+    stream.write(1, 1); // last metadata block
+    stream.write(0, 7); // STREAMINFO
+    stream.write(34, 24);
+    stream.write(block_size, 16);
+    stream.write(block_size, 16);
+    stream.write(u64::from(min_frame_size), 24);
+    stream.write(u64::from(max_frame_size), 24);
+    stream.write(u64::from(sample_rate), 20);
+    stream.write(CHANNELS as u64 - 1, 3);
+    stream.write(u64::from(bits_per_sample) - 1, 5);
+    stream.write(total_frames as u64 >> 32, 4);
+    stream.write(total_frames as u64 & 0xFFFF_FFFF, 32);
+    for _ in 0..4 {
+        stream.write(0, 32); // MD5 not computed
+    }
+
+    let mut bytes = stream.finish();
+    for frame in frames {
+        bytes.extend(frame);
+    }
+    Ok(bytes)
+}
+
+fn sample_size_code(bits_per_sample: u32) -> Option<u64> {
+    match bits_per_sample {
+        8 => Some(0b001),
+        12 => Some(0b010),
+        16 => Some(0b100),
+        20 => Some(0b101),
+        24 => Some(0b110),
+        _ => None,
+    }
+}
+
+fn encode_frame(
+    block: &[i32],
+    frame_number: u32,
+    bits_per_sample: u32,
+    sample_size_code: u64,
+) -> Vec<u8> {
+    let block_size = block.len() / CHANNELS;
+    let mut frame = BitWriter::new();
+
+    frame.write(0b11_1111_1111_1110, 14); // sync code
+    frame.write(0, 1); // reserved
+    frame.write(0, 1); // fixed block size
+    let block_size_code = if block_size == BLOCK_SIZE {
+        0b1100 // 256 * 2^(12 - 8)
+    } else {
+        0b0111 // 16-bit (block size - 1) at the end of the header
+    };
+    frame.write(block_size_code, 4);
+    frame.write(0b0000, 4); // sample rate from STREAMINFO
+    frame.write(0b0001, 4); // left, right
+    frame.write(sample_size_code, 3);
+    frame.write(0, 1); // reserved
+    frame.write_utf8(frame_number);
+    if block_size_code == 0b0111 {
+        frame.write(block_size as u64 - 1, 16);
+    }
+    let crc = crc8(frame.bytes());
+    frame.write(u64::from(crc), 8);
+
+    for channel in 0..CHANNELS {
+        let samples: Vec<i64> = block
+            .iter()
+            .skip(channel)
+            .step_by(CHANNELS)
+            .map(|&sample| i64::from(sample))
+            .collect();
+        write_subframe(&mut frame, &samples, bits_per_sample);
+    }
+
+    frame.pad_to_byte();
+    let crc = crc16(frame.bytes());
+    frame.write(u64::from(crc), 16);
+    frame.finish()
+}
+
+fn write_subframe(out: &mut BitWriter, samples: &[i64], bits_per_sample: u32) {
+    let bits = u64::from(bits_per_sample);
+
+    if samples.iter().all(|&sample| sample == samples[0]) {
+        out.write(0b0000_0000, 8); // CONSTANT
+        out.write_signed(samples[0], bits_per_sample);
+        return;
+    }
+
+    let verbatim_bits = samples.len() as u64 * bits;
+    let best = (0..=MAX_FIXED_ORDER.min(samples.len() - 1))
+        .map(|order| FixedCoding::plan(samples, order))
+        .min_by_key(|coding| coding.bits + coding.order as u64 * bits);
+
+    match best {
+        Some(coding) if coding.bits + coding.order as u64 * bits < verbatim_bits => {
+            out.write((0b001000 | coding.order as u64) << 1, 8); // FIXED
+            for &sample in &samples[..coding.order] {
+                out.write_signed(sample, bits_per_sample);
+            }
+            coding.write_residual(out);
+        }
+        _ => {
+            out.write(0b0000_0010, 8); // VERBATIM
+            for &sample in samples {
+                out.write_signed(sample, bits_per_sample);
+            }
+        }
+    }
+}
+
+/// Residual of a fixed predictor and the Rice partitioning chosen for it
+struct FixedCoding {
+    order: usize,
+    /// Zigzag-mapped residual (the first `order` samples are warm-up, not coded)
+    residual: Vec<u64>,
+    partition_order: u32,
+    parameters: Vec<u32>,
+    /// Whether the parameters need the 5-bit method
+    wide_parameters: bool,
+    /// Estimated size of the residual coding in bits
+    bits: u64,
+}
+
+impl FixedCoding {
+    fn plan(samples: &[i64], order: usize) -> Self {
+        let residual: Vec<u64> = (order..samples.len())
+            .map(|i| {
+                let s = |back: usize| samples[i - back];
+                let prediction_error = match order {
+                    0 => s(0),
+                    1 => s(0) - s(1),
+                    2 => s(0) - 2 * s(1) + s(2),
+                    3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+                    _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+                };
+                zigzag(prediction_error)
+            })
+            .collect();
+
+        let block_size = samples.len();
+        let max_partition_order = (0..=MAX_PARTITION_ORDER)
+            .rev()
+            .find(|&p| block_size.is_multiple_of(1 << p) && (block_size >> p) > order)
+            .unwrap_or(0);
+
+        // Sums of the residual per partition at the finest order, merged pairwise
+        // for the coarser ones
+        let partition_len = block_size >> max_partition_order;
+        let mut sums: Vec<(u64, u64)> = (0..1usize << max_partition_order)
+            .map(|partition| {
+                let start = (partition * partition_len).max(order) - order;
+                let end = (partition + 1) * partition_len - order;
+                let values = &residual[start..end];
+                (values.iter().sum(), values.len() as u64)
+            })
+            .collect();
+
+        let mut best: Option<(u64, u32, Vec<u32>, bool)> = None;
+        for partition_order in (0..=max_partition_order).rev() {
+            let parameters: Vec<(u32, u64)> = sums
+                .iter()
+                .map(|&(sum, count)| rice_parameter(sum, count))
+                .collect();
+            let wide = parameters
+                .iter()
+                .any(|&(parameter, _)| parameter > MAX_RICE4_PARAMETER);
+            let header_bits = if wide { 5 } else { 4 };
+            let bits = 6 + parameters
+                .iter()
+                .map(|&(_, bits)| bits + header_bits)
+                .sum::<u64>();
+            if best
+                .as_ref()
+                .is_none_or(|(best_bits, ..)| bits < *best_bits)
+            {
+                let parameters = parameters.iter().map(|&(parameter, _)| parameter).collect();
+                best = Some((bits, partition_order, parameters, wide));
+            }
+            sums = sums
+                .chunks(2)
+                .map(|pair| pair.iter().fold((0, 0), |a, b| (a.0 + b.0, a.1 + b.1)))
+                .collect();
+        }
+
+        let (bits, partition_order, parameters, wide_parameters) = best.unwrap();
+        Self {
+            order,
+            residual,
+            partition_order,
+            parameters,
+            wide_parameters,
+            bits,
+        }
+    }
+
+    fn write_residual(&self, out: &mut BitWriter) {
+        out.write(u64::from(self.wide_parameters), 2);
+        out.write(u64::from(self.partition_order), 4);
+        let parameter_bits = if self.wide_parameters { 5 } else { 4 };
+        let block_size = self.residual.len() + self.order;
+        let partition_len = block_size >> self.partition_order;
+        for (partition, &parameter) in self.parameters.iter().enumerate() {
+            out.write(u64::from(parameter), parameter_bits);
+            let start = (partition * partition_len).max(self.order) - self.order;
+            let end = (partition + 1) * partition_len - self.order;
+            for &value in &self.residual[start..end] {
+                out.write_unary(value >> parameter);
+                out.write(value & ((1 << parameter) - 1), parameter);
+            }
+        }
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Rice parameter for `count` values summing to `sum`, with the estimated bits
+fn rice_parameter(sum: u64, count: u64) -> (u32, u64) {
+    (0..=MAX_RICE5_PARAMETER)
+        .map(|parameter| {
+            let bits = count * (u64::from(parameter) + 1) + (sum >> parameter);
+            (parameter, bits)
+        })
+        .min_by_key(|&(_, bits)| bits)
+        .unwrap()
+}
+
+/// CRC-8 of frame headers (polynomial x^8 + x^2 + x + 1)
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |crc, &byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// CRC-16 of whole frames (polynomial x^16 + x^15 + x^2 + 1)
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ (u16::from(byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// MSB-first bit writer
+struct BitWriter {
+    bytes: Vec<u8>,
+    pending: u64,
+    pending_bits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            pending: 0,
+            pending_bits: 0,
+        }
+    }
+
+    /// Write the low `bits` (at most 32) bits of `value`
+    fn write(&mut self, value: u64, bits: u32) {
+        debug_assert!(bits <= 32);
+        if bits == 0 {
+            return;
+        }
+        self.pending = (self.pending << bits) | (value & ((1 << bits) - 1));
+        self.pending_bits += bits;
+        while self.pending_bits >= 8 {
+            self.pending_bits -= 8;
+            self.bytes.push((self.pending >> self.pending_bits) as u8);
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64, bits);
+    }
+
+    /// `value` zeros followed by a one
+    fn write_unary(&mut self, mut value: u64) {
+        while value >= 32 {
+            self.write(0, 32);
+            value -= 32;
+        }
+        self.write(1, value as u32 + 1);
+    }
+
+    /// Frame number in the UTF-8-like coding of frame headers
+    fn write_utf8(&mut self, value: u32) {
+        if value < 0x80 {
+            self.write(u64::from(value), 8);
+            return;
+        }
+        let continuation_bytes = match value {
+            0..0x800 => 1,
+            0x800..0x1_0000 => 2,
+            0x1_0000..0x20_0000 => 3,
+            0x20_0000..0x400_0000 => 4,
+            _ => 5,
+        };
+        let lead_marker = !(0xFFu64 >> (continuation_bytes + 1)) & 0xFF;
+        self.write(
+            lead_marker | u64::from(value >> (6 * continuation_bytes)),
+            8,
+        );
+        for i in (0..continuation_bytes).rev() {
+            self.write(0x80 | u64::from((value >> (6 * i)) & 0x3F), 8);
+        }
+    }
+
+    fn pad_to_byte(&mut self) {
+        if self.pending_bits > 0 {
+            self.write(0, 8 - self.pending_bits);
+        }
+    }
+
+    /// Bytes completed so far
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.pad_to_byte();
+        self.bytes
+    }
+}
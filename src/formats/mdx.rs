@@ -0,0 +1,988 @@
+//! MDX (X68000 MXDRV) sequence import
+//!
+//! An MDX file is a compiled MML song rather than a register log: a title ending
+//! in `CR LF 0x1A`, the NUL-terminated name of its PDX sample bank, then a data
+//! block whose offsets are all relative to its own start:
+//!
+//! - a big-endian `u16` offset to the voice table, then one `u16` offset per
+//!   channel (A-H and P, or A-H and P-W for EX-PCM songs)
+//! - 27-byte FM voices: number, FL/CON, slot mask, then DT1/MUL, TL, KS/AR,
+//!   AME/D1R, DT2/D2R and D1L/RR for the four operators in register order
+//! - MXDRV bytecode for each channel
+//!
+//! [`import`] runs a built-in MXDRV-style sequencer over the FM channels A-H and
+//! records the YM2151 writes it makes into an [`EventLog`]. One sequencer tick is
+//! one timer B period at the X68000 clock, `1024 * (256 - @t) / 4 MHz` seconds.
+//! ADPCM/PCM8 channels need the PDX samples and are ignored with a warning.
+//!
+//! Pitches are kept in 1/256 KF steps (1/16384 semitone), the unit used by the
+//! portamento and pitch LFO amounts.
+
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::events::{EventLog, LogMetadata, RegisterEvent};
+use crate::opm_tables::{key_code, KEY_CODE_SEMITONES, KF_STEPS, MAX_TL};
+use crate::resampler::X68000_CLOCK;
+use crate::voice::{OpmOperator, OpmVoice};
+
+const TITLE_TERMINATOR: &[u8; 3] = b"\r\n\x1A";
+const VOICE_SIZE: usize = 27;
+
+/// Number of FM channels (A-H); ADPCM/PCM8 channels follow them
+pub const FM_CHANNELS: usize = 8;
+const MAX_CHANNELS: usize = 16;
+const CHANNEL_NAMES: &[u8; MAX_CHANNELS] = b"ABCDEFGHPQRSTUVW";
+
+/// Timer B value used until the song sets `@t`
+pub const DEFAULT_TIMER_B: u8 = 200;
+/// Master clock cycles per timer B step
+const TIMER_B_CYCLES: u64 = 1024;
+/// Import stops here even if channels are still playing
+const MAX_SONG_SECONDS: f64 = 30.0 * 60.0;
+/// Commands a channel may run without advancing time before it is considered stuck
+const MAX_COMMANDS_PER_TICK: usize = 65536;
+
+/// Carrier TL attenuation for `v0`..`v15`
+const VOLUME_TABLE: [u8; 16] = [
+    0x2A, 0x28, 0x25, 0x22, 0x20, 0x1D, 0x1A, 0x18, 0x15, 0x12, 0x10, 0x0D, 0x0A, 0x08, 0x05, 0x02,
+];
+const DEFAULT_VOLUME: u8 = 8;
+const DEFAULT_PAN: u8 = 3;
+const DEFAULT_GATE: u8 = 8;
+const DEFAULT_SLOT_MASK: u8 = 0x0F;
+
+const PITCH_FRACTION: i32 = 256;
+const SEMITONE: i32 = KF_STEPS * PITCH_FRACTION;
+/// Note 0x80 is o0 D#, two semitones above key code 0x00 (o0 C#)
+const NOTE_OFFSET_SEMITONES: i32 = 2;
+const MAX_PITCH: i32 = KEY_CODE_SEMITONES * SEMITONE - 1;
+
+const CMD_TEMPO: u8 = 0xFF;
+const CMD_REGISTER: u8 = 0xFE;
+const CMD_VOICE: u8 = 0xFD;
+const CMD_PAN: u8 = 0xFC;
+const CMD_VOLUME: u8 = 0xFB;
+const CMD_VOLUME_DOWN: u8 = 0xFA;
+const CMD_VOLUME_UP: u8 = 0xF9;
+const CMD_GATE: u8 = 0xF8;
+const CMD_LEGATO: u8 = 0xF7;
+const CMD_REPEAT_START: u8 = 0xF6;
+const CMD_REPEAT_END: u8 = 0xF5;
+const CMD_REPEAT_ESCAPE: u8 = 0xF4;
+const CMD_DETUNE: u8 = 0xF3;
+const CMD_PORTAMENTO: u8 = 0xF2;
+const CMD_END: u8 = 0xF1;
+const CMD_KEY_ON_DELAY: u8 = 0xF0;
+const CMD_SYNC_SEND: u8 = 0xEF;
+const CMD_SYNC_WAIT: u8 = 0xEE;
+const CMD_NOISE: u8 = 0xED;
+const CMD_PITCH_LFO: u8 = 0xEC;
+const CMD_AMP_LFO: u8 = 0xEB;
+const CMD_OPM_LFO: u8 = 0xEA;
+const CMD_LFO_DELAY: u8 = 0xE9;
+const CMD_PCM8: u8 = 0xE8;
+const CMD_FADE_OUT: u8 = 0xE7;
+
+/// LFO command arguments that switch a configured LFO off or back on
+const LFO_OFF: u8 = 0x80;
+const LFO_ON: u8 = 0x81;
+
+/// Name of an MDX channel ("A"-"H" for FM, "P"-"W" for ADPCM/PCM8)
+pub fn channel_name(index: usize) -> char {
+    CHANNEL_NAMES.get(index).map_or('?', |&name| name as char)
+}
+
+/// Voice from a 27-byte voice table entry
+fn parse_voice(bytes: &[u8]) -> OpmVoice {
+    let fb_con = bytes[1];
+    let operators = std::array::from_fn(|op| {
+        OpmOperator::from_register_values(std::array::from_fn(|block| bytes[3 + block * 4 + op]))
+    });
+    OpmVoice {
+        number: bytes[0] as u16,
+        fb: fb_con >> 3 & 0x07,
+        alg: fb_con & 0x07,
+        slot_mask: bytes[2] & 0x0F,
+        operators,
+        ..Default::default()
+    }
+}
+
+/// Parsed MDX header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MdxHeader {
+    /// Title bytes without the terminator (usually Shift_JIS)
+    pub title: Vec<u8>,
+    /// PDX sample bank name, if the song uses one
+    pub pdx_name: Option<String>,
+    /// Absolute file offset of the data block the other offsets are relative to
+    pub data_offset: usize,
+    /// Absolute file offset of the voice table
+    pub voice_offset: usize,
+    /// Absolute file offset of each channel's bytecode
+    pub channel_offsets: Vec<usize>,
+}
+
+/// Sequencer options for [`import_with_options`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MdxOptions {
+    /// How many times looping channels jump back before the song is cut off
+    /// (at least once)
+    pub loop_count: u32,
+}
+
+impl Default for MdxOptions {
+    fn default() -> Self {
+        Self { loop_count: 1 }
+    }
+}
+
+/// Result of an MDX import
+#[derive(Debug, Clone)]
+pub struct MdxImport {
+    pub header: MdxHeader,
+    pub voices: BTreeMap<u8, OpmVoice>,
+    /// Register writes made by the sequencer, at the X68000 clock
+    pub log: EventLog,
+    /// Song length in sequencer ticks
+    pub ticks: u64,
+    notes: Vec<String>,
+}
+
+impl MdxImport {
+    /// Human-readable notes about channels and commands that were not played
+    pub fn warnings(&self) -> Vec<String> {
+        self.notes.clone()
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+/// Parse the title, PDX name and offset table
+pub fn parse_header(bytes: &[u8]) -> Result<MdxHeader> {
+    let title_end = bytes
+        .windows(TITLE_TERMINATOR.len())
+        .position(|window| window == TITLE_TERMINATOR)
+        .context("Not an MDX file (no title terminator CR LF 0x1A)")?;
+    let pdx_start = title_end + TITLE_TERMINATOR.len();
+    let pdx_len = bytes[pdx_start..]
+        .iter()
+        .position(|&b| b == 0)
+        .context("Truncated MDX file (PDX name is not terminated)")?;
+    let pdx_name = (pdx_len > 0)
+        .then(|| String::from_utf8_lossy(&bytes[pdx_start..pdx_start + pdx_len]).into_owned());
+    let data_offset = pdx_start + pdx_len + 1;
+
+    let relative = |index: usize| -> Result<usize> {
+        read_u16(bytes, data_offset + index * 2)
+            .map(|offset| offset as usize)
+            .context("Truncated MDX file (offset table)")
+    };
+    let voice_relative = relative(0)?;
+    // The offset table ends where the first thing it points at begins
+    let table_end = voice_relative.min(relative(1)?);
+    let channel_count = (table_end.saturating_sub(2) / 2).min(MAX_CHANNELS);
+    if channel_count == 0 {
+        bail!("Invalid MDX file (empty channel table)");
+    }
+
+    let absolute = |offset: usize, what: String| -> Result<usize> {
+        let offset = data_offset + offset;
+        if offset >= bytes.len() {
+            bail!(
+                "Invalid MDX file ({} offset 0x{:X} is past the end)",
+                what,
+                offset
+            );
+        }
+        Ok(offset)
+    };
+    let voice_offset = absolute(voice_relative, "voice table".to_string())?;
+    let channel_offsets = (0..channel_count)
+        .map(|index| {
+            absolute(
+                relative(index + 1)?,
+                format!("channel {}", channel_name(index)),
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(MdxHeader {
+        title: bytes[..title_end].to_vec(),
+        pdx_name,
+        data_offset,
+        voice_offset,
+        channel_offsets,
+    })
+}
+
+/// Read the voice table, which runs up to the next channel's data or the end of file
+pub fn parse_voices(bytes: &[u8], header: &MdxHeader) -> BTreeMap<u8, OpmVoice> {
+    let end = header
+        .channel_offsets
+        .iter()
+        .copied()
+        .filter(|&offset| offset > header.voice_offset)
+        .min()
+        .unwrap_or(bytes.len());
+
+    bytes[header.voice_offset..end]
+        .chunks_exact(VOICE_SIZE)
+        .map(|entry| (entry[0], parse_voice(entry)))
+        .collect()
+}
+
+/// Import an MDX file with the default options
+pub fn import(bytes: &[u8]) -> Result<MdxImport> {
+    import_with_options(bytes, &MdxOptions::default())
+}
+
+/// Import an MDX file by running its FM channels through the sequencer
+pub fn import_with_options(bytes: &[u8], options: &MdxOptions) -> Result<MdxImport> {
+    let header = parse_header(bytes)?;
+    let voices = parse_voices(bytes, &header);
+
+    let mut output = Output {
+        bytes,
+        voices: &voices,
+        time: 0.0,
+        timer_b: DEFAULT_TIMER_B,
+        sync: [false; MAX_CHANNELS],
+        events: Vec::new(),
+        notes: Vec::new(),
+    };
+
+    for (index, &offset) in header.channel_offsets.iter().enumerate().skip(FM_CHANNELS) {
+        if bytes[offset] != CMD_END {
+            output.warn(format!(
+                "channel {}: ADPCM/PCM8 channel ignored (PDX samples are not supported)",
+                channel_name(index)
+            ));
+        }
+    }
+
+    let mut channels: Vec<Channel> = header
+        .channel_offsets
+        .iter()
+        .take(FM_CHANNELS)
+        .enumerate()
+        .map(|(index, &offset)| Channel::new(index, offset))
+        .collect();
+
+    let mut cycles: u64 = 0;
+    let mut ticks: u64 = 0;
+    loop {
+        output.time = cycles as f64 / X68000_CLOCK as f64;
+        for channel in channels.iter_mut() {
+            channel.tick(&mut output)?;
+        }
+        if channels
+            .iter()
+            .all(|channel| channel.is_finished(options.loop_count))
+        {
+            break;
+        }
+        // Not done yet, so channels that paused on their last loop jump keep playing
+        for channel in channels.iter_mut() {
+            channel.resume(&mut output)?;
+        }
+
+        ticks += 1;
+        cycles += TIMER_B_CYCLES * (256 - output.timer_b as u64);
+        if cycles as f64 / X68000_CLOCK as f64 > MAX_SONG_SECONDS {
+            output.warn(format!(
+                "song cut off after {} minutes",
+                MAX_SONG_SECONDS / 60.0
+            ));
+            break;
+        }
+    }
+
+    output.time = cycles as f64 / X68000_CLOCK as f64;
+    for channel in channels.iter_mut() {
+        channel.key_off(&mut output);
+    }
+
+    let title = std::str::from_utf8(&header.title)
+        .ok()
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .map(str::to_string);
+    if title.is_none() && !header.title.is_empty() {
+        output.warn("title is not UTF-8 (Shift_JIS titles are not decoded)".to_string());
+    }
+    if let Some(pdx) = &header.pdx_name {
+        output.warn(format!("PDX sample bank '{}' not loaded", pdx));
+    }
+
+    let Output { events, notes, .. } = output;
+    let log = EventLog {
+        events,
+        clock: Some(X68000_CLOCK),
+        loop_time: None,
+        metadata: title.map(|title| LogMetadata {
+            title: Some(title),
+            system: Some("X68000".to_string()),
+            ..Default::default()
+        }),
+        markers: Vec::new(),
+    };
+
+    Ok(MdxImport {
+        header,
+        voices,
+        log,
+        ticks,
+        notes,
+    })
+}
+
+/// State shared by all channels while sequencing
+struct Output<'a> {
+    bytes: &'a [u8],
+    voices: &'a BTreeMap<u8, OpmVoice>,
+    /// Time of the current tick in seconds
+    time: f64,
+    timer_b: u8,
+    /// Pending sync signals per channel (`0xEF` sets, `0xEE` waits for)
+    sync: [bool; MAX_CHANNELS],
+    events: Vec<RegisterEvent>,
+    notes: Vec<String>,
+}
+
+impl Output<'_> {
+    fn write(&mut self, addr: u8, data: u8) {
+        self.events.push(RegisterEvent {
+            time: self.time,
+            addr,
+            data,
+            is_data: None,
+        });
+    }
+
+    fn warn(&mut self, note: String) {
+        if !self.notes.contains(&note) {
+            self.notes.push(note);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Volume {
+    /// `v0`-`v15`, through the volume table
+    Table(u8),
+    /// `@v0`-`@v127`, 127 being loudest
+    Direct(u8),
+}
+
+impl Volume {
+    fn attenuation(self) -> i32 {
+        match self {
+            Volume::Table(volume) => VOLUME_TABLE[volume.min(15) as usize] as i32,
+            Volume::Direct(volume) => 0x7F - volume.min(0x7F) as i32,
+        }
+    }
+
+    fn step(self, delta: i32) -> Self {
+        match self {
+            Volume::Table(volume) => Volume::Table((volume as i32 + delta).clamp(0, 15) as u8),
+            Volume::Direct(volume) => Volume::Direct((volume as i32 + delta).clamp(0, 0x7F) as u8),
+        }
+    }
+}
+
+/// Software LFO for pitch (`MP`) or volume (`MA`)
+#[derive(Debug, Clone, Copy, Default)]
+struct SoftLfo {
+    waveform: u8,
+    /// Half a cycle, in ticks
+    period: u16,
+    /// Peak deviation (pitch units or TL steps)
+    amplitude: i16,
+    configured: bool,
+    enabled: bool,
+    phase: u32,
+}
+
+impl SoftLfo {
+    fn value(&self) -> i32 {
+        if !self.enabled || self.period == 0 {
+            return 0;
+        }
+        let period = self.period as i32;
+        let amplitude = self.amplitude as i32;
+        let t = self.phase as i32;
+        match self.waveform {
+            // Sawtooth: -A up to A over the cycle
+            0 => amplitude * (t - period) / period,
+            // Square
+            1 if t < period => amplitude,
+            1 => -amplitude,
+            // Triangle: -A up to A and back
+            _ if t < period => amplitude * (2 * t - period) / period,
+            _ => amplitude * (3 * period - 2 * t) / period,
+        }
+    }
+
+    fn advance(&mut self) {
+        if self.period > 0 {
+            self.phase = (self.phase + 1) % (2 * self.period as u32);
+        }
+    }
+}
+
+/// Sequencer state of one FM channel
+struct Channel {
+    index: usize,
+    /// Absolute file offset of the next command
+    pos: usize,
+    /// Ticks until the next command is read
+    wait: u32,
+    ended: bool,
+    /// Paused right after a loop jump until the song is known to continue
+    paused: bool,
+    waiting_sync: bool,
+    loop_jumps: u32,
+
+    voice: Option<OpmVoice>,
+    volume: Volume,
+    pan: u8,
+    gate: u8,
+    key_on_delay: u32,
+    /// Set by `&`: the next note is not keyed off
+    tie_next: bool,
+    /// The sounding note is tied into the next one
+    tied: bool,
+    key_on: bool,
+    gate_remaining: u32,
+    pending_key_on: u32,
+
+    note_pitch: i32,
+    detune: i32,
+    portamento: i32,
+    next_portamento: i32,
+    slide: i32,
+    pitch_lfo: SoftLfo,
+    amp_lfo: SoftLfo,
+    lfo_delay: u32,
+    ticks_since_key_on: u32,
+    opm_lfo_pms_ams: u8,
+    opm_lfo_sync: bool,
+
+    /// Repeat counters, keyed by the file offset of the counter byte
+    repeat_counters: HashMap<usize, u8>,
+    written_pitch: Option<(u8, u8)>,
+    written_attenuation: Option<i32>,
+}
+
+impl Channel {
+    fn new(index: usize, pos: usize) -> Self {
+        Channel {
+            index,
+            pos,
+            wait: 0,
+            ended: false,
+            paused: false,
+            waiting_sync: false,
+            loop_jumps: 0,
+            voice: None,
+            volume: Volume::Table(DEFAULT_VOLUME),
+            pan: DEFAULT_PAN,
+            gate: DEFAULT_GATE,
+            key_on_delay: 0,
+            tie_next: false,
+            tied: false,
+            key_on: false,
+            gate_remaining: 0,
+            pending_key_on: 0,
+            note_pitch: 0,
+            detune: 0,
+            portamento: 0,
+            next_portamento: 0,
+            slide: 0,
+            pitch_lfo: SoftLfo::default(),
+            amp_lfo: SoftLfo::default(),
+            lfo_delay: 0,
+            ticks_since_key_on: 0,
+            opm_lfo_pms_ams: 0,
+            opm_lfo_sync: false,
+            repeat_counters: HashMap::new(),
+            written_pitch: None,
+            written_attenuation: None,
+        }
+    }
+
+    fn ch(&self) -> u8 {
+        self.index as u8
+    }
+
+    fn name(&self) -> char {
+        channel_name(self.index)
+    }
+
+    fn is_finished(&self, loop_count: u32) -> bool {
+        self.ended || self.waiting_sync || self.loop_jumps >= loop_count.max(1)
+    }
+
+    fn tick(&mut self, output: &mut Output) -> Result<()> {
+        if self.pending_key_on > 0 {
+            self.pending_key_on -= 1;
+            if self.pending_key_on == 0 {
+                self.write_key_on(output);
+            }
+        }
+        if self.key_on && !self.tied && self.gate_remaining > 0 {
+            self.gate_remaining -= 1;
+            if self.gate_remaining == 0 {
+                self.key_off(output);
+            }
+        }
+        self.update_effects(output);
+
+        if self.wait > 0 {
+            self.wait -= 1;
+        }
+        self.run(output)
+    }
+
+    fn resume(&mut self, output: &mut Output) -> Result<()> {
+        if !self.paused {
+            return Ok(());
+        }
+        self.paused = false;
+        self.run(output)
+    }
+
+    /// Run commands until one of them takes time
+    fn run(&mut self, output: &mut Output) -> Result<()> {
+        if self.waiting_sync && output.sync[self.index] {
+            output.sync[self.index] = false;
+            self.waiting_sync = false;
+        }
+
+        let mut commands = 0;
+        while self.wait == 0 && !self.ended && !self.paused && !self.waiting_sync {
+            commands += 1;
+            if commands > MAX_COMMANDS_PER_TICK {
+                bail!(
+                    "channel {} loops without advancing time (offset 0x{:X})",
+                    self.name(),
+                    self.pos
+                );
+            }
+            self.execute(output)?;
+        }
+        Ok(())
+    }
+
+    fn read_u8(&mut self, output: &Output) -> Result<u8> {
+        let value = output.bytes.get(self.pos).copied().with_context(|| {
+            format!(
+                "Truncated MDX data on channel {} (offset 0x{:X})",
+                self.name(),
+                self.pos
+            )
+        })?;
+        self.pos += 1;
+        Ok(value)
+    }
+
+    fn read_i16(&mut self, output: &Output) -> Result<i16> {
+        let high = self.read_u8(output)?;
+        let low = self.read_u8(output)?;
+        Ok(i16::from_be_bytes([high, low]))
+    }
+
+    /// Absolute offset of a jump relative to the current position
+    fn jump_target(&self, output: &Output, offset: i16) -> Result<usize> {
+        let target = self.pos as i64 + offset as i64;
+        if target < 0 || target as usize >= output.bytes.len() {
+            bail!(
+                "channel {}: jump to 0x{:X} from 0x{:X} is outside the file",
+                self.name(),
+                target,
+                self.pos
+            );
+        }
+        Ok(target as usize)
+    }
+
+    fn execute(&mut self, output: &mut Output) -> Result<()> {
+        let command_offset = self.pos;
+        let command = self.read_u8(output)?;
+
+        match command {
+            0x00..=0x7F => self.rest(output, command as u32 + 1),
+            0x80..=0xDF => {
+                let length = self.read_u8(output)? as u32 + 1;
+                self.note(output, command - 0x80, length);
+            }
+            CMD_TEMPO => output.timer_b = self.read_u8(output)?,
+            CMD_REGISTER => {
+                let addr = self.read_u8(output)?;
+                let data = self.read_u8(output)?;
+                output.write(addr, data);
+            }
+            CMD_VOICE => {
+                let number = self.read_u8(output)?;
+                self.set_voice(output, number);
+            }
+            CMD_PAN => {
+                self.pan = self.read_u8(output)? & 0x03;
+                self.write_pan(output);
+            }
+            CMD_VOLUME => {
+                let value = self.read_u8(output)?;
+                self.volume = if value & 0x80 != 0 {
+                    Volume::Direct(value & 0x7F)
+                } else {
+                    Volume::Table(value.min(15))
+                };
+                self.update_volume(output);
+            }
+            CMD_VOLUME_DOWN => {
+                self.volume = self.volume.step(-1);
+                self.update_volume(output);
+            }
+            CMD_VOLUME_UP => {
+                self.volume = self.volume.step(1);
+                self.update_volume(output);
+            }
+            CMD_GATE => self.gate = self.read_u8(output)?,
+            CMD_LEGATO => self.tie_next = true,
+            CMD_REPEAT_START => {
+                let count = self.read_u8(output)?;
+                self.read_u8(output)?;
+                self.repeat_counters.insert(self.pos - 1, count);
+            }
+            CMD_REPEAT_END => {
+                let offset = self.read_i16(output)?;
+                let start = self.jump_target(output, offset)?;
+                let counter = self.repeat_counter(output, start)?;
+                *counter = counter.saturating_sub(1);
+                if *counter > 0 {
+                    self.pos = start;
+                }
+            }
+            CMD_REPEAT_ESCAPE => {
+                let offset = self.read_i16(output)?;
+                let target = self.jump_target(output, offset)?;
+                let (repeat_end, exit) = self.find_repeat_end(output, target)?;
+                let start = self.repeat_start_of(output, repeat_end)?;
+                if *self.repeat_counter(output, start)? == 1 {
+                    self.pos = exit;
+                }
+            }
+            CMD_DETUNE => {
+                self.detune = self.read_i16(output)? as i32 * PITCH_FRACTION;
+                if self.key_on {
+                    self.update_pitch(output);
+                }
+            }
+            CMD_PORTAMENTO => self.next_portamento = self.read_i16(output)? as i32,
+            CMD_END => {
+                if output.bytes.get(self.pos) == Some(&0) {
+                    self.pos += 1;
+                    self.key_off(output);
+                    self.ended = true;
+                } else {
+                    let offset = self.read_i16(output)?;
+                    self.pos = self.jump_target(output, offset)?;
+                    self.loop_jumps += 1;
+                    self.paused = true;
+                }
+            }
+            CMD_KEY_ON_DELAY => self.key_on_delay = self.read_u8(output)? as u32,
+            CMD_SYNC_SEND => {
+                let target = self.read_u8(output)? as usize;
+                if target < MAX_CHANNELS {
+                    output.sync[target] = true;
+                }
+            }
+            CMD_SYNC_WAIT => self.waiting_sync = true,
+            CMD_NOISE => {
+                let value = self.read_u8(output)?;
+                output.write(0x0F, value);
+            }
+            CMD_PITCH_LFO | CMD_AMP_LFO => {
+                let mut lfo = if command == CMD_PITCH_LFO {
+                    self.pitch_lfo
+                } else {
+                    self.amp_lfo
+                };
+                match self.read_u8(output)? {
+                    LFO_OFF => lfo.enabled = false,
+                    LFO_ON => lfo.enabled = lfo.configured,
+                    waveform => {
+                        if waveform > 2 {
+                            output.warn(format!(
+                                "channel {}: LFO waveform {} played as a triangle",
+                                self.name(),
+                                waveform
+                            ));
+                        }
+                        let period = self.read_i16(output)? as u16;
+                        let amplitude = self.read_i16(output)?;
+                        lfo = SoftLfo {
+                            waveform,
+                            period,
+                            amplitude,
+                            configured: true,
+                            enabled: true,
+                            phase: 0,
+                        };
+                    }
+                }
+                if command == CMD_PITCH_LFO {
+                    self.pitch_lfo = lfo;
+                } else {
+                    self.amp_lfo = lfo;
+                }
+            }
+            CMD_OPM_LFO => match self.read_u8(output)? {
+                LFO_OFF => output.write(0x38 + self.ch(), 0),
+                LFO_ON => output.write(0x38 + self.ch(), self.opm_lfo_pms_ams),
+                sync_waveform => {
+                    let lfrq = self.read_u8(output)?;
+                    let pmd = self.read_u8(output)?;
+                    let amd = self.read_u8(output)?;
+                    self.opm_lfo_pms_ams = self.read_u8(output)?;
+                    self.opm_lfo_sync = sync_waveform & 0x40 != 0;
+                    output.write(0x1B, sync_waveform & 0x03);
+                    output.write(0x18, lfrq);
+                    output.write(0x19, pmd | 0x80);
+                    output.write(0x19, amd & 0x7F);
+                    output.write(0x38 + self.ch(), self.opm_lfo_pms_ams);
+                }
+            },
+            CMD_LFO_DELAY => self.lfo_delay = self.read_u8(output)? as u32,
+            CMD_PCM8 => output.warn("PCM8 mode command ignored".to_string()),
+            CMD_FADE_OUT => {
+                self.read_u8(output)?;
+                self.read_u8(output)?;
+                output.warn("fade-out command ignored".to_string());
+            }
+            _ => bail!(
+                "Unknown MDX command 0x{:02X} on channel {} (offset 0x{:X})",
+                command,
+                self.name(),
+                command_offset
+            ),
+        }
+        Ok(())
+    }
+
+    /// Counter of the repeat whose body starts at `start` (the counter byte is
+    /// the last byte of the `0xF6` command before it)
+    fn repeat_counter(&mut self, output: &Output, start: usize) -> Result<&mut u8> {
+        if start < 2 {
+            bail!(
+                "channel {}: repeat at 0x{:X} starts before its repeat command",
+                self.name(),
+                self.pos
+            );
+        }
+        let initial = output.bytes[start - 2];
+        Ok(self.repeat_counters.entry(start - 1).or_insert(initial))
+    }
+
+    /// Locate the `0xF5` command an escape points at, returning its offset and the
+    /// offset just past it. The target may be either the `0xF5` itself or the byte
+    /// after it.
+    fn find_repeat_end(&self, output: &Output, target: usize) -> Result<(usize, usize)> {
+        if target >= 3 && output.bytes[target - 3] == CMD_REPEAT_END {
+            return Ok((target - 3, target));
+        }
+        if output.bytes.get(target) == Some(&CMD_REPEAT_END) {
+            return Ok((target, target + 3));
+        }
+        bail!(
+            "channel {}: repeat escape at 0x{:X} does not lead to a repeat end",
+            self.name(),
+            self.pos
+        )
+    }
+
+    /// Body start of the repeat closed by the `0xF5` command at `repeat_end`
+    fn repeat_start_of(&self, output: &Output, repeat_end: usize) -> Result<usize> {
+        let offset = read_u16(output.bytes, repeat_end + 1)
+            .context("Truncated MDX data (repeat end)")? as i16;
+        let target = (repeat_end + 3) as i64 + offset as i64;
+        if target < 2 || target as usize >= output.bytes.len() {
+            bail!(
+                "channel {}: repeat end at 0x{:X} is invalid",
+                self.name(),
+                repeat_end
+            );
+        }
+        Ok(target as usize)
+    }
+
+    fn rest(&mut self, output: &mut Output, length: u32) {
+        self.wait = length;
+        self.tied = false;
+        self.tie_next = false;
+        self.key_off(output);
+    }
+
+    fn note(&mut self, output: &mut Output, note: u8, length: u32) {
+        self.wait = length;
+        let continues_tie = self.key_on && self.tied;
+        self.tied = std::mem::take(&mut self.tie_next);
+        self.gate_remaining = self.gate_length(length);
+        self.note_pitch = (note as i32 + NOTE_OFFSET_SEMITONES) * SEMITONE;
+        self.slide = 0;
+        self.portamento = std::mem::take(&mut self.next_portamento);
+
+        if continues_tie {
+            self.update_pitch(output);
+            return;
+        }
+
+        self.key_off(output);
+        self.ticks_since_key_on = 0;
+        self.pitch_lfo.phase = 0;
+        self.amp_lfo.phase = 0;
+        self.update_pitch(output);
+        self.update_volume(output);
+        if self.key_on_delay > 0 {
+            self.pending_key_on = self.key_on_delay;
+        } else {
+            self.write_key_on(output);
+        }
+    }
+
+    /// Ticks a note sounds for: `q1`-`q8` in eighths of its length, or `@q` (stored
+    /// as a negative byte) that many ticks before its end
+    fn gate_length(&self, length: u32) -> u32 {
+        match self.gate {
+            gate @ 1..=8 => (length * gate as u32 / 8).max(1),
+            gate @ 0x80..=0xFF => length.saturating_sub(256 - gate as u32).max(1),
+            _ => length,
+        }
+    }
+
+    fn write_key_on(&mut self, output: &mut Output) {
+        if self.opm_lfo_sync {
+            output.write(0x01, 0x02);
+            output.write(0x01, 0x00);
+        }
+        let slot_mask = self
+            .voice
+            .as_ref()
+            .map_or(DEFAULT_SLOT_MASK, |voice| voice.slot_mask);
+        output.write(0x08, (slot_mask & 0x0F) << 3 | self.ch());
+        self.key_on = true;
+    }
+
+    fn key_off(&mut self, output: &mut Output) {
+        self.pending_key_on = 0;
+        if self.key_on {
+            output.write(0x08, self.ch());
+            self.key_on = false;
+        }
+    }
+
+    fn lfo_active(&self) -> bool {
+        self.ticks_since_key_on > self.lfo_delay
+    }
+
+    /// Per-tick portamento and software LFOs of the sounding note
+    fn update_effects(&mut self, output: &mut Output) {
+        if !self.key_on && self.pending_key_on == 0 {
+            return;
+        }
+        self.slide += self.portamento;
+        self.ticks_since_key_on = self.ticks_since_key_on.saturating_add(1);
+        if self.lfo_active() {
+            self.pitch_lfo.advance();
+            self.amp_lfo.advance();
+        }
+        self.update_pitch(output);
+        self.update_volume(output);
+    }
+
+    fn update_pitch(&mut self, output: &mut Output) {
+        let lfo = if self.lfo_active() {
+            self.pitch_lfo.value()
+        } else {
+            0
+        };
+        let pitch = (self.note_pitch + self.detune + self.slide + lfo).clamp(0, MAX_PITCH);
+        let semitones = pitch / SEMITONE;
+        let key_code = key_code(semitones as u32);
+        let key_fraction = ((pitch % SEMITONE / PITCH_FRACTION) as u8) << 2;
+
+        if self.written_pitch != Some((key_code, key_fraction)) {
+            output.write(0x28 + self.ch(), key_code);
+            output.write(0x30 + self.ch(), key_fraction);
+            self.written_pitch = Some((key_code, key_fraction));
+        }
+    }
+
+    fn attenuation(&self) -> i32 {
+        let lfo = if self.lfo_active() {
+            self.amp_lfo.value()
+        } else {
+            0
+        };
+        self.volume.attenuation() + lfo
+    }
+
+    fn update_volume(&mut self, output: &mut Output) {
+        let Some(voice) = &self.voice else {
+            return;
+        };
+        let attenuation = self.attenuation();
+        if self.written_attenuation == Some(attenuation) {
+            return;
+        }
+        for op in (0..4).filter(|&op| voice.is_carrier(op)) {
+            output.write(
+                0x60 + (op * 8) as u8 + self.ch(),
+                carrier_tl(voice.operators[op].tl, attenuation),
+            );
+        }
+        self.written_attenuation = Some(attenuation);
+    }
+
+    fn write_pan(&mut self, output: &mut Output) {
+        let fb_con = self.voice.as_ref().map_or(0, OpmVoice::fb_con);
+        output.write(0x20 + self.ch(), self.pan << 6 | fb_con);
+    }
+
+    fn set_voice(&mut self, output: &mut Output, number: u8) {
+        let Some(voice) = output.voices.get(&number).cloned() else {
+            output.warn(format!(
+                "channel {}: voice @{} is not in the voice table",
+                self.name(),
+                number
+            ));
+            return;
+        };
+        self.voice = Some(voice.clone());
+        self.write_pan(output);
+
+        let attenuation = self.attenuation();
+        for (op, operator) in voice.operators.iter().enumerate() {
+            let slot = (op * 8) as u8 + self.ch();
+            let mut values = operator.register_values();
+            if voice.is_carrier(op) {
+                values[1] = carrier_tl(operator.tl, attenuation);
+            }
+            for (block, value) in values.into_iter().enumerate() {
+                output.write(0x40 + block as u8 * 0x20 + slot, value);
+            }
+        }
+        self.written_attenuation = Some(attenuation);
+    }
+}
+
+fn carrier_tl(tl: u8, attenuation: i32) -> u8 {
+    (tl as i32 + attenuation).clamp(0, MAX_TL as i32) as u8
+}
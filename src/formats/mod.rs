@@ -0,0 +1,159 @@
+//! Import and export of third-party register log formats
+//!
+//! Everything is converted into an [`EventLog`], so playback, offline rendering
+//! and the server treat imported files exactly like JSON logs. Logs can be
+//! written back out with [`save_event_log`].
+
+pub mod mdx;
+pub mod s98;
+pub mod smf;
+pub mod vgm;
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::compression::{self, Compression};
+use crate::events::{EventLog, RegisterEvent};
+
+/// Player spacing between consecutive writes, in OPM samples
+/// (address write, data 2 samples later, next address 2 samples after that)
+pub(crate) const WRITE_SPACING_SAMPLES: u64 = 4;
+
+/// File formats known by extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Json,
+    Vgm,
+    /// gzip-compressed VGM
+    Vgz,
+    S98,
+    /// X68000 MXDRV sequence (import only)
+    Mdx,
+    /// Standard MIDI File, played with the default voice on import and
+    /// transcribed one track per OPM channel on export
+    Midi,
+}
+
+impl LogFormat {
+    /// Format for a path, from its extension (JSON for anything unknown)
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("vgm") => LogFormat::Vgm,
+            Some("vgz") => LogFormat::Vgz,
+            Some("s98") => LogFormat::S98,
+            Some("mdx") => LogFormat::Mdx,
+            Some("mid") | Some("midi") => LogFormat::Midi,
+            _ => LogFormat::Json,
+        }
+    }
+}
+
+/// An event log loaded from disk, with notes about anything that was dropped
+#[derive(Debug, Clone)]
+pub struct LoadedLog {
+    pub log: EventLog,
+    pub warnings: Vec<String>,
+}
+
+/// Whether a path names a VGM file (`.vgm` or gzip-compressed `.vgz`)
+pub fn is_vgm_path<P: AsRef<Path>>(path: P) -> bool {
+    matches!(LogFormat::from_path(path), LogFormat::Vgm | LogFormat::Vgz)
+}
+
+/// Load a log file, choosing the format from its extension
+///
+/// `.vgm`/`.vgz` and `.s98` files are imported, `.mdx` and `.mid` songs are
+/// sequenced into a log (MIDI with the default options of [`smf::import`]);
+/// anything else is read as a JSON log, which may be gzip or zstd compressed.
+pub fn load_event_log<P: AsRef<Path>>(path: P) -> Result<LoadedLog> {
+    let path = path.as_ref();
+    let format = LogFormat::from_path(path);
+
+    if format == LogFormat::Json {
+        let log = EventLog::from_file(path)
+            .with_context(|| format!("Failed to load JSON file: {}", path.display()))?;
+        return Ok(LoadedLog {
+            log,
+            warnings: Vec::new(),
+        });
+    }
+
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let imported = match format {
+        LogFormat::S98 => s98::import(&bytes).map(|import| (import.warnings(), import.log)),
+        LogFormat::Mdx => mdx::import(&bytes).map(|import| (import.warnings(), import.log)),
+        LogFormat::Midi => smf::import(&bytes, &smf::SmfOptions::default())
+            .map(|import| (import.warnings(), import.log)),
+        _ => vgm::import(&bytes).map(|import| (import.warnings(), import.log)),
+    };
+    let (warnings, log) = imported
+        .with_context(|| format!("Failed to import {:?} file: {}", format, path.display()))?;
+
+    Ok(LoadedLog { log, warnings })
+}
+
+/// Save a log file, choosing the format from its extension
+///
+/// `.vgm` is written as VGM 1.71, `.vgz` as gzip-compressed VGM, `.s98` as S98 v3,
+/// `.mid` as a transcription SMF (see [`smf::export`]) and anything else as a
+/// pretty-printed JSON log, gzip or zstd compressed for `.gz`/`.zst` (e.g.
+/// `song.json.zst`). MDX cannot be written.
+pub fn save_event_log<P: AsRef<Path>>(path: P, log: &EventLog) -> Result<()> {
+    let path = path.as_ref();
+
+    let bytes = match LogFormat::from_path(path) {
+        LogFormat::Vgm => vgm::export(log)?,
+        LogFormat::Vgz => vgm::export_vgz(log)?,
+        LogFormat::S98 => s98::export(log)?,
+        LogFormat::Mdx => bail!("Saving as MDX is not supported (MDX files can only be imported)"),
+        LogFormat::Midi => smf::export(log)?,
+        LogFormat::Json => compression::compress(
+            serde_json::to_string_pretty(log)?.as_bytes(),
+            Compression::from_path(path),
+        )?,
+    };
+
+    fs::write(path, bytes).with_context(|| format!("Failed to write file: {}", path.display()))
+}
+
+/// OPM sample at which `Player` issues each event: its scheduled sample, pushed
+/// back as needed to keep the write spacing
+pub(crate) fn player_write_samples(events: &[RegisterEvent], opm_rate: u32) -> Vec<u64> {
+    let mut next_write: u64 = 0;
+    events
+        .iter()
+        .map(|event| {
+            let scheduled = (event.time * opm_rate as f64).round() as u64;
+            let sample = scheduled.max(next_write);
+            next_write = sample + WRITE_SPACING_SAMPLES;
+            sample
+        })
+        .collect()
+}
+
+/// The log's loop point as an OPM sample
+pub(crate) fn loop_opm_sample(log: &EventLog, opm_rate: u32) -> Result<Option<u64>> {
+    match log.loop_time {
+        Some(time) if time < 0.0 => bail!("loop_time {} is negative", time),
+        Some(time) => Ok(Some((time * opm_rate as f64).round() as u64)),
+        None => Ok(None),
+    }
+}
+
+/// Whether the operator at register slot `op` (0-3 = M1, M2, C1, C2) is a carrier
+/// for connection (algorithm) `connection`
+pub(crate) fn is_carrier(connection: u8, op: usize) -> bool {
+    match connection & 0x07 {
+        0..=3 => op == 3,
+        4 => op >= 2,
+        5 | 6 => op >= 1,
+        _ => true,
+    }
+}
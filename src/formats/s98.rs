@@ -0,0 +1,435 @@
+//! S98 (v3) import and export
+//!
+//! S98 logs register writes for a table of devices. Each dump command byte
+//! selects a device and port (`device * 2 + port`), and time advances in sync
+//! units of `timer_numerator / timer_denominator` seconds:
+//!
+//! - `0xFF` waits one sync, `0xFE n` waits `n + 2` syncs (`n` is a 7-bit varint)
+//! - `0xFD` ends the dump; players continue from the loop offset if there is one
+//!
+//! [`import`] takes the writes of the first OPM device in the table and skips the
+//! rest, counting them per device. [`export`] writes a single-OPM file whose sync
+//! unit is one OPM sample (`1 / (clock / 64)` seconds), so the Player's write
+//! timing is kept exactly.
+
+use anyhow::{bail, Result};
+use std::collections::BTreeMap;
+
+use super::{loop_opm_sample, player_write_samples};
+use crate::events::{EventLog, LogMetadata, RegisterEvent};
+use crate::resampler::{opm_sample_rate, YM2151_CLOCK};
+
+const S98_MAGIC: &[u8; 3] = b"S98";
+const S98_VERSION_3: u8 = b'3';
+const HEADER_SIZE: usize = 0x20;
+const DEVICE_INFO_SIZE: usize = 0x10;
+
+/// Defaults used when the timer fields are 0
+const DEFAULT_TIMER_NUMERATOR: u32 = 10;
+const DEFAULT_TIMER_DENOMINATOR: u32 = 1000;
+
+/// Device type of the YM2151 in the device table
+pub const DEVICE_OPM: u32 = 5;
+
+const CMD_SYNC: u8 = 0xFF;
+const CMD_SYNC_N: u8 = 0xFE;
+const CMD_END: u8 = 0xFD;
+
+const TAG_MAGIC: &[u8; 5] = b"[S98]";
+const UTF8_BOM: &[u8; 3] = b"\xEF\xBB\xBF";
+
+/// Name of an S98 device type, for reporting
+pub fn device_name(device_type: u32) -> &'static str {
+    match device_type {
+        0 => "NONE",
+        1 => "PSG (YM2149)",
+        2 => "OPN (YM2203)",
+        3 => "OPN2 (YM2612)",
+        4 => "OPNA (YM2608)",
+        DEVICE_OPM => "OPM (YM2151)",
+        6 => "OPLL (YM2413)",
+        7 => "OPL (YM3526)",
+        8 => "OPL2 (YM3812)",
+        9 => "OPL3 (YMF262)",
+        15 => "PSG (AY-3-8910)",
+        16 => "DCSG (SN76489)",
+        _ => "unknown device",
+    }
+}
+
+/// One entry of the device table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct S98Device {
+    pub device_type: u32,
+    pub clock: u32,
+    pub pan: u32,
+}
+
+/// Parsed S98 v3 header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct S98Header {
+    pub timer_numerator: u32,
+    pub timer_denominator: u32,
+    /// Absolute file offset of the tag, if present
+    pub tag_offset: Option<usize>,
+    /// Absolute file offset of the dump data
+    pub data_offset: usize,
+    /// Absolute file offset of the loop point, if the file loops
+    pub loop_offset: Option<usize>,
+    pub devices: Vec<S98Device>,
+}
+
+impl S98Header {
+    /// Length of one sync in seconds
+    pub fn sync_seconds(&self) -> f64 {
+        self.timer_numerator as f64 / self.timer_denominator as f64
+    }
+}
+
+/// Result of an S98 import
+#[derive(Debug, Clone)]
+pub struct S98Import {
+    pub header: S98Header,
+    /// Index of the OPM device in the device table that was imported
+    pub opm_device: usize,
+    /// Imported log, including the loop point and tag metadata
+    pub log: EventLog,
+    /// Number of skipped writes per device (e.g. "PSG (YM2149) #0")
+    pub skipped_commands: BTreeMap<String, usize>,
+}
+
+impl S98Import {
+    /// Human-readable notes about data that was not imported
+    pub fn warnings(&self) -> Vec<String> {
+        self.skipped_commands
+            .iter()
+            .map(|(device, count)| {
+                format!(
+                    "{}: {} commands skipped (unsupported device)",
+                    device, count
+                )
+            })
+            .collect()
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn non_zero_offset(value: u32) -> Option<usize> {
+    (value != 0).then_some(value as usize)
+}
+
+/// Parse the S98 header and device table
+pub fn parse_header(bytes: &[u8]) -> Result<S98Header> {
+    if bytes.len() < HEADER_SIZE || !bytes.starts_with(S98_MAGIC) {
+        bail!("Not an S98 file (missing 'S98' header)");
+    }
+    if bytes[3] != S98_VERSION_3 {
+        bail!(
+            "Unsupported S98 version '{}' (only version 3 is supported)",
+            bytes[3] as char
+        );
+    }
+
+    let field = |offset: usize| read_u32(bytes, offset).unwrap_or(0);
+    let timer_numerator = match field(0x04) {
+        0 => DEFAULT_TIMER_NUMERATOR,
+        value => value,
+    };
+    let timer_denominator = match field(0x08) {
+        0 => DEFAULT_TIMER_DENOMINATOR,
+        value => value,
+    };
+    if field(0x0C) != 0 {
+        bail!("Compressed S98 files are not supported");
+    }
+
+    let device_count = field(0x1C) as usize;
+    if device_count > (bytes.len() - HEADER_SIZE) / DEVICE_INFO_SIZE {
+        bail!(
+            "S98 device table is truncated ({} devices declared)",
+            device_count
+        );
+    }
+    let mut devices = Vec::with_capacity(device_count);
+    for index in 0..device_count {
+        let offset = HEADER_SIZE + index * DEVICE_INFO_SIZE;
+        let (Some(device_type), Some(clock), Some(pan)) = (
+            read_u32(bytes, offset),
+            read_u32(bytes, offset + 4),
+            read_u32(bytes, offset + 8),
+        ) else {
+            bail!("S98 device table is truncated (device {})", index);
+        };
+        devices.push(S98Device {
+            device_type,
+            clock,
+            pan,
+        });
+    }
+
+    let data_offset = field(0x14) as usize;
+    if data_offset == 0 || data_offset > bytes.len() {
+        bail!("S98 dump data offset 0x{:X} is invalid", data_offset);
+    }
+
+    Ok(S98Header {
+        timer_numerator,
+        timer_denominator,
+        tag_offset: non_zero_offset(field(0x10)),
+        data_offset,
+        loop_offset: non_zero_offset(field(0x18)),
+        devices,
+    })
+}
+
+/// Import an S98 v3 file from memory
+pub fn import(bytes: &[u8]) -> Result<S98Import> {
+    let header = parse_header(bytes)?;
+
+    let Some(opm_device) = header
+        .devices
+        .iter()
+        .position(|device| device.device_type == DEVICE_OPM)
+    else {
+        let names: Vec<&str> = header
+            .devices
+            .iter()
+            .map(|device| device_name(device.device_type))
+            .collect();
+        if names.is_empty() {
+            // An empty device table means a single OPNA
+            bail!("S98 file contains no OPM device (default device: OPNA)");
+        }
+        bail!(
+            "S98 file contains no OPM device (devices in file: {})",
+            names.join(", ")
+        );
+    };
+
+    let sync_seconds = header.sync_seconds();
+    let mut events = Vec::new();
+    let mut skipped_commands: BTreeMap<String, usize> = BTreeMap::new();
+    let mut syncs: u64 = 0;
+    let mut loop_time = None;
+    let mut pos = header.data_offset;
+
+    let truncated = |pos: usize| anyhow::anyhow!("S98 dump data is truncated at 0x{:X}", pos);
+
+    loop {
+        if Some(pos) == header.loop_offset {
+            loop_time = Some(syncs as f64 * sync_seconds);
+        }
+
+        let Some(&cmd) = bytes.get(pos) else {
+            break;
+        };
+
+        match cmd {
+            CMD_SYNC => {
+                syncs += 1;
+                pos += 1;
+            }
+            CMD_SYNC_N => {
+                let mut value: u64 = 0;
+                let mut shift = 0;
+                pos += 1;
+                loop {
+                    let &byte = bytes.get(pos).ok_or_else(|| truncated(pos))?;
+                    pos += 1;
+                    value |= ((byte & 0x7F) as u64) << shift;
+                    shift += 7;
+                    if byte & 0x80 == 0 {
+                        break;
+                    }
+                    if shift > 56 {
+                        bail!("S98 sync count is too long at 0x{:X}", pos);
+                    }
+                }
+                syncs += value + 2;
+            }
+            CMD_END => break,
+            _ => {
+                let device = (cmd / 2) as usize;
+                let port = cmd % 2;
+                let Some(info) = header.devices.get(device) else {
+                    bail!("S98 command 0x{:02X} at 0x{:X} has no device", cmd, pos);
+                };
+                let operands = bytes.get(pos + 1..pos + 3).ok_or_else(|| truncated(pos))?;
+
+                if device == opm_device && port == 0 {
+                    events.push(RegisterEvent {
+                        time: syncs as f64 * sync_seconds,
+                        addr: operands[0],
+                        data: operands[1],
+                        is_data: None,
+                    });
+                } else {
+                    let name = format!("{} #{}", device_name(info.device_type), device);
+                    *skipped_commands.entry(name).or_default() += 1;
+                }
+                pos += 3;
+            }
+        }
+    }
+
+    let metadata = header
+        .tag_offset
+        .and_then(|offset| parse_tag(bytes, offset));
+    let clock = header.devices[opm_device].clock;
+
+    Ok(S98Import {
+        log: EventLog {
+            events,
+            clock: (clock != 0).then_some(clock),
+            loop_time,
+            metadata,
+            markers: Vec::new(),
+        },
+        header,
+        opm_device,
+        skipped_commands,
+    })
+}
+
+/// Read a `[S98]` tag (`key=value` lines) into log metadata
+///
+/// Tags with a UTF-8 BOM are read as UTF-8; others are nominally Shift_JIS and
+/// are decoded lossily. A missing or malformed tag only means no metadata.
+fn parse_tag(bytes: &[u8], offset: usize) -> Option<LogMetadata> {
+    let tag = bytes.get(offset..)?.strip_prefix(TAG_MAGIC)?;
+    let tag = tag.strip_prefix(UTF8_BOM).unwrap_or(tag);
+    let end = tag.iter().position(|&b| b == 0).unwrap_or(tag.len());
+    let text = String::from_utf8_lossy(&tag[..end]);
+
+    let mut metadata = LogMetadata::default();
+    for line in text.split('\n') {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = Some(value.trim_end_matches('\r').to_string());
+        match key.to_ascii_lowercase().as_str() {
+            "title" => metadata.title = value,
+            "artist" => metadata.author = value,
+            "game" => metadata.game = value,
+            "system" => metadata.system = value,
+            "year" => metadata.date = value,
+            "comment" => metadata.notes = value,
+            _ => {}
+        }
+    }
+    (metadata != LogMetadata::default()).then_some(metadata)
+}
+
+fn build_tag(metadata: &LogMetadata) -> Vec<u8> {
+    let mut tag = Vec::new();
+    tag.extend_from_slice(TAG_MAGIC);
+    tag.extend_from_slice(UTF8_BOM);
+
+    let fields = [
+        ("title", &metadata.title),
+        ("artist", &metadata.author),
+        ("game", &metadata.game),
+        ("year", &metadata.date),
+        ("system", &metadata.system),
+        ("comment", &metadata.notes),
+    ];
+    for (key, value) in fields {
+        if let Some(value) = value {
+            // Values are single lines in the tag format
+            let value = value.replace('\n', " ");
+            tag.extend_from_slice(format!("{}={}\n", key, value).as_bytes());
+        }
+    }
+    tag.push(0);
+    tag
+}
+
+/// Append sync waits totalling `syncs`
+fn push_sync(data: &mut Vec<u8>, syncs: u64) {
+    match syncs {
+        0 => {}
+        1 => data.push(CMD_SYNC),
+        _ => {
+            data.push(CMD_SYNC_N);
+            let mut value = syncs - 2;
+            loop {
+                let byte = (value & 0x7F) as u8;
+                value >>= 7;
+                if value == 0 {
+                    data.push(byte);
+                    break;
+                }
+                data.push(byte | 0x80);
+            }
+        }
+    }
+}
+
+/// Export a log as an S98 v3 file with a single OPM device
+///
+/// The sync unit is one OPM sample, and writes are placed on the samples the
+/// Player issues them at. The log's loop point and metadata become the loop
+/// offset and `[S98]` tag.
+pub fn export(log: &EventLog) -> Result<Vec<u8>> {
+    log.check_playable()?;
+
+    let clock = log.clock_or(YM2151_CLOCK);
+    let opm_rate = opm_sample_rate(clock);
+    let loop_sample = loop_opm_sample(log, opm_rate)?;
+
+    let data_offset = HEADER_SIZE + DEVICE_INFO_SIZE;
+    let mut data = Vec::with_capacity(log.events.len() * 4 + 1);
+    let mut now: u64 = 0;
+    let mut loop_data_offset = None;
+
+    let write_samples = player_write_samples(&log.events, opm_rate);
+    for (event, &sample) in log.events.iter().zip(&write_samples) {
+        if let Some(loop_at) = loop_sample {
+            if loop_data_offset.is_none() && loop_at <= sample {
+                push_sync(&mut data, loop_at - now);
+                now = loop_at;
+                loop_data_offset = Some(data_offset + data.len());
+            }
+        }
+        push_sync(&mut data, sample - now);
+        now = sample;
+        // Device 0, port 0
+        data.extend_from_slice(&[0x00, event.addr, event.data]);
+    }
+    data.push(CMD_END);
+
+    if loop_sample.is_some_and(|loop_at| loop_at >= now) {
+        bail!("loop_time must be before the last event");
+    }
+
+    let tag = log.metadata.as_ref().map(build_tag);
+    let tag_offset = tag.as_ref().map(|_| data_offset + data.len());
+
+    let mut bytes = Vec::with_capacity(data_offset + data.len());
+    bytes.extend_from_slice(S98_MAGIC);
+    bytes.push(S98_VERSION_3);
+    for value in [
+        1,
+        opm_rate,
+        0,
+        tag_offset.unwrap_or(0) as u32,
+        data_offset as u32,
+        loop_data_offset.unwrap_or(0) as u32,
+        1,
+    ] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    for value in [DEVICE_OPM, clock, 0, 0] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes.extend_from_slice(&data);
+    if let Some(tag) = tag {
+        bytes.extend_from_slice(&tag);
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,916 @@
+//! Standard MIDI File (SMF type 0/1) import and export
+//!
+//! [`import`] plays MIDI notes on the eight OPM channels:
+//!
+//! - timing follows the tempo map (or the SMPTE time division)
+//! - a voice allocator gives each note-on a free OPM channel, preferring one that
+//!   already has the note's program loaded, and steals the oldest note when all
+//!   eight are busy
+//! - note number plus pitch bend become KC/KF; velocity, CC7 (volume) and CC11
+//!   (expression) become carrier TL attenuation; CC10 sets the L/R output bits
+//! - program changes select voices from a [`VoiceBank`]
+//!
+//! The percussion channel (MIDI channel 10) has no sensible FM mapping and is
+//! skipped with a warning. Marker meta events become log markers (and back on
+//! export).
+//!
+//! [`export`] goes the other way for transcription: each OPM channel becomes a
+//! track whose key-ons and key-offs are MIDI notes. KC/KF is decoded to the
+//! nearest note plus pitch bend, the carrier TL at key-on gives the velocity, and
+//! TL changes during a note are written as CC7.
+
+use anyhow::{anyhow, bail, Context, Result};
+use midly::num::{u4, u7};
+use midly::{
+    Format, Header, MetaMessage, MidiMessage, PitchBend, Smf, Timing, TrackEvent, TrackEventKind,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::is_carrier;
+use crate::events::{EventLog, LogMetadata, Marker, RegisterEvent};
+use crate::opm_tables::{
+    key_code, KEY_CODE_BASE_NOTE, KEY_CODE_NOTES, KEY_CODE_SEMITONES, KF_STEPS, MAX_TL, TL_STEP_DB,
+};
+use crate::resampler::YM2151_CLOCK;
+use crate::voice::{self, OpmOperator, OpmVoice};
+
+/// MIDI channel 10, zero-based
+pub const PERCUSSION_CHANNEL: u8 = 9;
+const MIDI_CHANNELS: usize = 16;
+const OPM_CHANNELS: usize = 8;
+
+/// 120 BPM, used until the first tempo event
+const DEFAULT_TEMPO: u32 = 500_000;
+/// Pitch bend range in semitones until changed with RPN 0
+pub const DEFAULT_BEND_RANGE: u8 = 2;
+const DEFAULT_VOLUME: u8 = 100;
+
+const CC_DATA_ENTRY: u8 = 6;
+const CC_VOLUME: u8 = 7;
+const CC_PAN: u8 = 10;
+const CC_EXPRESSION: u8 = 11;
+const CC_RPN_LSB: u8 = 100;
+const CC_RPN_MSB: u8 = 101;
+const CC_ALL_SOUND_OFF: u8 = 120;
+const CC_RESET_ALL_CONTROLLERS: u8 = 121;
+const CC_ALL_NOTES_OFF: u8 = 123;
+const RPN_NULL: (u8, u8) = (0x7F, 0x7F);
+
+/// L/R output bits of register 0x20
+const PAN_LEFT: u8 = 0x40;
+const PAN_RIGHT: u8 = 0x80;
+const PAN_CENTER: u8 = 0xC0;
+
+const MAX_PITCH: i32 = KEY_CODE_SEMITONES * KF_STEPS - 1;
+
+/// Voice used for programs missing from the bank: a plain two-carrier tone
+pub fn fallback_voice() -> OpmVoice {
+    let operator = |mul: u8, tl: u8| OpmOperator {
+        ar: 0x1F,
+        d1r: 0x05,
+        d1l: 0x01,
+        rr: 0x07,
+        tl,
+        mul,
+        ..Default::default()
+    };
+    OpmVoice {
+        name: "default".to_string(),
+        fb: 7,
+        alg: 4,
+        operators: [
+            operator(1, 0x23),
+            operator(2, 0x2D),
+            operator(1, 0),
+            operator(1, 0),
+        ],
+        ..Default::default()
+    }
+}
+
+/// Voices selected by MIDI program change, each under its voice number
+///
+/// Stored as JSON with the [`OpmVoice`] fields, e.g.
+/// `{"voices": [{"number": 0, "fb": 7, "alg": 4, "operators": [{"ar": 31, ...}, ...]}]}`,
+/// or as a VOPM `.opm` bank. The pan of a voice is not used (MIDI pan sets the
+/// output bits), nor are its LFO settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoiceBank {
+    pub voices: Vec<OpmVoice>,
+}
+
+impl VoiceBank {
+    /// Voice bank of `voices`, whose numbers must be MIDI programs (0-127)
+    pub fn new(voices: Vec<OpmVoice>) -> Result<Self> {
+        if let Some(voice) = voices.iter().find(|voice| voice.number > 127) {
+            bail!(
+                "Voice @:{} cannot be used as a MIDI program (0-127)",
+                voice.number
+            );
+        }
+        Ok(VoiceBank { voices })
+    }
+
+    /// Parse a JSON voice bank
+    pub fn from_json_str(json: &str) -> Result<Self> {
+        let bank: VoiceBank = serde_json::from_str(json).context("Invalid voice bank JSON")?;
+        Self::new(bank.voices)
+    }
+
+    /// Load a voice bank file: a VOPM bank if the extension is `.opm`, JSON otherwise
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let is_opm = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("opm"));
+        if is_opm {
+            return Self::new(voice::load_opm(path)?);
+        }
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read voice bank: {}", path.display()))?;
+        Self::from_json_str(&json)
+    }
+
+    /// Voice for a program (the first one listed if there are several)
+    pub fn voice(&self, program: u8) -> Option<&OpmVoice> {
+        self.voices
+            .iter()
+            .find(|voice| voice.number == program as u16)
+    }
+}
+
+/// Conversion options for [`import`]
+#[derive(Debug, Clone)]
+pub struct SmfOptions {
+    pub voice_bank: VoiceBank,
+    /// Chip master clock the log is made for; pitches are corrected for it
+    pub clock: u32,
+}
+
+impl Default for SmfOptions {
+    fn default() -> Self {
+        Self {
+            voice_bank: VoiceBank::default(),
+            clock: YM2151_CLOCK,
+        }
+    }
+}
+
+/// Result of an SMF import
+#[derive(Debug, Clone)]
+pub struct SmfImport {
+    /// SMF format (0 or 1)
+    pub format: u8,
+    pub track_count: usize,
+    pub log: EventLog,
+    /// Notes cut short because all OPM channels were busy
+    pub stolen_notes: usize,
+    notes: Vec<String>,
+}
+
+impl SmfImport {
+    /// Human-readable notes about what could not be played as written
+    pub fn warnings(&self) -> Vec<String> {
+        self.notes.clone()
+    }
+}
+
+/// Convert a Standard MIDI File into an event log
+pub fn import(bytes: &[u8], options: &SmfOptions) -> Result<SmfImport> {
+    let smf = Smf::parse(bytes).map_err(|e| anyhow!("Invalid MIDI file: {}", e))?;
+    let format = match smf.header.format {
+        midly::Format::SingleTrack => 0,
+        midly::Format::Parallel => 1,
+        midly::Format::Sequential => bail!("SMF format 2 (sequential tracks) is not supported"),
+    };
+
+    // Merge all tracks into one timeline; the sort is stable, so events at the
+    // same tick keep their track and in-track order
+    let mut timeline = Vec::new();
+    for (track_index, track) in smf.tracks.iter().enumerate() {
+        let mut tick: u64 = 0;
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            timeline.push((tick, track_index, event.kind));
+        }
+    }
+    timeline.sort_by_key(|&(tick, track, _)| (tick, track));
+
+    let mut converter = Converter::new(options);
+    let mut tempo = DEFAULT_TEMPO;
+    let mut last_tick: u64 = 0;
+    let mut title = None;
+    let mut markers = Vec::new();
+
+    for (tick, _, kind) in timeline {
+        converter.time += match smf.header.timing {
+            Timing::Metrical(ppq) => {
+                (tick - last_tick) as f64 * tempo as f64 / 1_000_000.0 / ppq.as_int() as f64
+            }
+            Timing::Timecode(fps, subframes) => {
+                (tick - last_tick) as f64 / (fps.as_f32() as f64 * subframes as f64)
+            }
+        };
+        last_tick = tick;
+
+        match kind {
+            TrackEventKind::Midi { channel, message } => {
+                converter.message(channel.as_int(), message)
+            }
+            TrackEventKind::Meta(MetaMessage::Tempo(value)) => tempo = value.as_int(),
+            TrackEventKind::Meta(MetaMessage::TrackName(name)) if title.is_none() => {
+                title = std::str::from_utf8(name)
+                    .ok()
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string);
+            }
+            TrackEventKind::Meta(MetaMessage::Marker(name)) => markers.push(Marker {
+                time: converter.time,
+                name: String::from_utf8_lossy(name).trim().to_string(),
+            }),
+            _ => {}
+        }
+    }
+    converter.finish();
+
+    let stolen_notes = converter.stolen_notes;
+    if stolen_notes > 0 {
+        converter.warn(format!(
+            "{} notes were cut short (more than {} notes at once)",
+            stolen_notes, OPM_CHANNELS
+        ));
+    }
+
+    let log = EventLog {
+        events: converter.events,
+        clock: Some(options.clock),
+        loop_time: None,
+        metadata: title.map(|title| LogMetadata {
+            title: Some(title),
+            ..Default::default()
+        }),
+        markers,
+    };
+
+    Ok(SmfImport {
+        format,
+        track_count: smf.tracks.len(),
+        log,
+        stolen_notes,
+        notes: converter.notes,
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MidiChannelState {
+    program: u8,
+    volume: u8,
+    expression: u8,
+    pan: u8,
+    bend: i16,
+    bend_range: u8,
+    rpn: (u8, u8),
+}
+
+impl Default for MidiChannelState {
+    fn default() -> Self {
+        Self {
+            program: 0,
+            volume: DEFAULT_VOLUME,
+            expression: 127,
+            pan: PAN_CENTER,
+            bend: 0,
+            bend_range: DEFAULT_BEND_RANGE,
+            rpn: RPN_NULL,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct OpmChannelState {
+    /// (MIDI channel, key, velocity) of the sounding note
+    note: Option<(u8, u8, u8)>,
+    /// Program whose patch is loaded
+    program: Option<u8>,
+    fb_con: u8,
+    slot_mask: u8,
+    tl: [u8; 4],
+    /// When the note started or was released, for allocation
+    order: u64,
+    written_pan: Option<u8>,
+}
+
+struct Converter<'a> {
+    options: &'a SmfOptions,
+    /// Pitch correction for the target clock, in semitones
+    tuning: f64,
+    time: f64,
+    midi: [MidiChannelState; MIDI_CHANNELS],
+    opm: [OpmChannelState; OPM_CHANNELS],
+    counter: u64,
+    stolen_notes: usize,
+    events: Vec<RegisterEvent>,
+    notes: Vec<String>,
+}
+
+impl<'a> Converter<'a> {
+    fn new(options: &'a SmfOptions) -> Self {
+        Self {
+            options,
+            tuning: clock_tuning(options.clock),
+            time: 0.0,
+            midi: [MidiChannelState::default(); MIDI_CHANNELS],
+            opm: [OpmChannelState::default(); OPM_CHANNELS],
+            counter: 0,
+            stolen_notes: 0,
+            events: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    fn write(&mut self, addr: u8, data: u8) {
+        self.events.push(RegisterEvent {
+            time: self.time,
+            addr,
+            data,
+            is_data: None,
+        });
+    }
+
+    fn warn(&mut self, note: String) {
+        if !self.notes.contains(&note) {
+            self.notes.push(note);
+        }
+    }
+
+    fn next_order(&mut self) -> u64 {
+        self.counter += 1;
+        self.counter
+    }
+
+    fn message(&mut self, channel: u8, message: MidiMessage) {
+        match message {
+            MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                self.note_on(channel, key.as_int(), vel.as_int())
+            }
+            MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                self.note_off(channel, key.as_int())
+            }
+            MidiMessage::Controller { controller, value } => {
+                self.controller(channel, controller.as_int(), value.as_int())
+            }
+            MidiMessage::ProgramChange { program } => {
+                self.midi[channel as usize].program = program.as_int()
+            }
+            MidiMessage::PitchBend { bend } => {
+                self.midi[channel as usize].bend = bend.as_int();
+                self.update_pitch(channel);
+            }
+            _ => {}
+        }
+    }
+
+    /// OPM channels currently playing notes of a MIDI channel
+    fn voices_of(&self, channel: u8) -> Vec<usize> {
+        (0..OPM_CHANNELS)
+            .filter(|&ch| matches!(self.opm[ch].note, Some((midi, _, _)) if midi == channel))
+            .collect()
+    }
+
+    /// Pick an OPM channel for a new note, stealing the oldest note if none is free
+    fn allocate(&mut self, program: u8) -> usize {
+        let free = |ch: &usize| self.opm[*ch].note.is_none();
+        let oldest = |ch: &usize| self.opm[*ch].order;
+
+        if let Some(ch) = (0..OPM_CHANNELS)
+            .filter(free)
+            .filter(|&ch| self.opm[ch].program == Some(program))
+            .min_by_key(oldest)
+        {
+            return ch;
+        }
+        if let Some(ch) = (0..OPM_CHANNELS).filter(free).min_by_key(oldest) {
+            return ch;
+        }
+
+        let ch = (0..OPM_CHANNELS).min_by_key(oldest).unwrap_or(0);
+        self.stolen_notes += 1;
+        self.key_off(ch);
+        ch
+    }
+
+    fn note_on(&mut self, channel: u8, key: u8, velocity: u8) {
+        if channel == PERCUSSION_CHANNEL {
+            self.warn("MIDI channel 10 (percussion) skipped".to_string());
+            return;
+        }
+
+        let program = self.midi[channel as usize].program;
+        let ch = match (0..OPM_CHANNELS).find(
+            |&ch| matches!(self.opm[ch].note, Some((midi, k, _)) if midi == channel && k == key),
+        ) {
+            // Retrigger the same note on its own channel
+            Some(ch) => {
+                self.key_off(ch);
+                ch
+            }
+            None => self.allocate(program),
+        };
+
+        if self.opm[ch].program != Some(program) {
+            self.load_patch(ch, program);
+        }
+        self.opm[ch].note = Some((channel, key, velocity));
+        self.opm[ch].order = self.next_order();
+
+        self.write_pan(ch, self.midi[channel as usize].pan);
+        self.write_pitch(ch);
+        self.write_volume(ch);
+        let slot_mask = self.opm[ch].slot_mask & 0x0F;
+        self.write(0x08, slot_mask << 3 | ch as u8);
+    }
+
+    fn note_off(&mut self, channel: u8, key: u8) {
+        if let Some(ch) = (0..OPM_CHANNELS).find(
+            |&ch| matches!(self.opm[ch].note, Some((midi, k, _)) if midi == channel && k == key),
+        ) {
+            self.key_off(ch);
+        }
+    }
+
+    fn key_off(&mut self, ch: usize) {
+        if self.opm[ch].note.take().is_some() {
+            self.write(0x08, ch as u8);
+            self.opm[ch].order = self.next_order();
+        }
+    }
+
+    fn controller(&mut self, channel: u8, controller: u8, value: u8) {
+        let state = &mut self.midi[channel as usize];
+        match controller {
+            CC_VOLUME => state.volume = value,
+            CC_EXPRESSION => state.expression = value,
+            CC_PAN => {
+                state.pan = match value {
+                    0..=42 => PAN_LEFT,
+                    43..=85 => PAN_CENTER,
+                    _ => PAN_RIGHT,
+                }
+            }
+            CC_RPN_MSB => state.rpn.0 = value,
+            CC_RPN_LSB => state.rpn.1 = value,
+            CC_DATA_ENTRY if state.rpn == (0, 0) => state.bend_range = value,
+            CC_RESET_ALL_CONTROLLERS => {
+                *state = MidiChannelState {
+                    program: state.program,
+                    volume: state.volume,
+                    pan: state.pan,
+                    bend_range: state.bend_range,
+                    ..Default::default()
+                }
+            }
+            CC_ALL_SOUND_OFF | CC_ALL_NOTES_OFF => {
+                for ch in self.voices_of(channel) {
+                    self.key_off(ch);
+                }
+                return;
+            }
+            _ => return,
+        }
+
+        let pan = self.midi[channel as usize].pan;
+        for ch in self.voices_of(channel) {
+            match controller {
+                CC_PAN => self.write_pan(ch, pan),
+                CC_VOLUME | CC_EXPRESSION => self.write_volume(ch),
+                CC_DATA_ENTRY => self.write_pitch(ch),
+                CC_RESET_ALL_CONTROLLERS => {
+                    self.write_pitch(ch);
+                    self.write_volume(ch);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn update_pitch(&mut self, channel: u8) {
+        for ch in self.voices_of(channel) {
+            self.write_pitch(ch);
+        }
+    }
+
+    fn load_patch(&mut self, ch: usize, program: u8) {
+        let voice = match self.options.voice_bank.voice(program) {
+            Some(voice) => voice.clone(),
+            None => {
+                self.warn(format!(
+                    "program {} is not in the voice bank (default voice used)",
+                    program
+                ));
+                fallback_voice()
+            }
+        };
+
+        let state = &mut self.opm[ch];
+        state.program = Some(program);
+        state.fb_con = voice.fb_con();
+        state.slot_mask = voice.slot_mask & 0x0F;
+        state.tl = voice.operators.map(|operator| operator.tl);
+        state.written_pan = None;
+
+        for (op, operator) in voice.operators.iter().enumerate() {
+            let slot = (op * 8 + ch) as u8;
+            let mut values = operator.register_values();
+            // Carriers start silent; write_volume sets them for each note
+            if voice.is_carrier(op) {
+                values[1] = MAX_TL;
+            }
+            for (block, value) in values.into_iter().enumerate() {
+                self.write(0x40 + block as u8 * 0x20 + slot, value);
+            }
+        }
+    }
+
+    fn write_pan(&mut self, ch: usize, pan: u8) {
+        let value = pan | self.opm[ch].fb_con;
+        if self.opm[ch].written_pan != Some(value) {
+            self.write(0x20 + ch as u8, value);
+            self.opm[ch].written_pan = Some(value);
+        }
+    }
+
+    fn write_pitch(&mut self, ch: usize) {
+        let Some((channel, key, _)) = self.opm[ch].note else {
+            return;
+        };
+        let state = self.midi[channel as usize];
+        let bend = state.bend as f64 / 8192.0 * state.bend_range as f64;
+        let semitones = key as f64 - KEY_CODE_BASE_NOTE as f64 + bend + self.tuning;
+        let unclamped = (semitones * KF_STEPS as f64).round() as i32;
+        let pitch = unclamped.clamp(0, MAX_PITCH);
+        if pitch != unclamped {
+            self.warn("notes outside the OPM range were clamped".to_string());
+        }
+
+        let key_code = key_code((pitch / KF_STEPS) as u32);
+        let key_fraction = ((pitch % KF_STEPS) as u8) << 2;
+        self.write(0x28 + ch as u8, key_code);
+        self.write(0x30 + ch as u8, key_fraction);
+    }
+
+    fn write_volume(&mut self, ch: usize) {
+        let Some((channel, _, velocity)) = self.opm[ch].note else {
+            return;
+        };
+        let state = self.midi[channel as usize];
+        let attenuation = attenuation(velocity, state.volume, state.expression);
+        let OpmChannelState { fb_con, tl, .. } = self.opm[ch];
+
+        for op in (0..4).filter(|&op| is_carrier(fb_con, op)) {
+            let level = (tl[op] as u16 + attenuation as u16).min(MAX_TL as u16) as u8;
+            self.write(0x60 + (op * 8 + ch) as u8, level);
+        }
+    }
+
+    fn finish(&mut self) {
+        for ch in 0..OPM_CHANNELS {
+            self.key_off(ch);
+        }
+    }
+}
+
+/// Pitch correction in semitones for a chip running at `clock` instead of the
+/// standard 3.579545 MHz
+fn clock_tuning(clock: u32) -> f64 {
+    12.0 * (YM2151_CLOCK as f64 / clock as f64).log2()
+}
+
+/// TL steps for the combined gain of velocity, volume and expression
+pub fn attenuation(velocity: u8, volume: u8, expression: u8) -> u8 {
+    let gain = (velocity as f64 / 127.0) * (volume as f64 / 127.0) * (expression as f64 / 127.0);
+    if gain <= 0.0 {
+        return MAX_TL;
+    }
+    let decibels = -20.0 * gain.log10();
+    (decibels / TL_STEP_DB).round().min(MAX_TL as f64) as u8
+}
+
+/// Ticks per quarter note of exported files
+const EXPORT_PPQ: u16 = 480;
+/// Pitch bend range set on every exported track, in semitones
+const EXPORT_BEND_RANGE: f64 = DEFAULT_BEND_RANGE as f64;
+const PITCH_BEND_SCALE: f64 = 8192.0;
+
+/// Track events at absolute ticks
+type ExportTrack = Vec<(u64, TrackEventKind<'static>)>;
+
+/// Convert an event log into a type 1 SMF
+///
+/// Track 0 holds the tempo (120 BPM), title and markers; tracks 1-8 are OPM channels 0-7
+/// on MIDI channels 1-8, each starting with RPN 0 set to a 2 semitone bend range.
+pub fn export(log: &EventLog) -> Result<Vec<u8>> {
+    let tuning = clock_tuning(log.clock.unwrap_or(YM2151_CLOCK));
+    let ticks_per_second = EXPORT_PPQ as f64 * 1_000_000.0 / DEFAULT_TEMPO as f64;
+
+    let mut events: Vec<&RegisterEvent> = log.events.iter().collect();
+    events.sort_by(|a, b| a.time.total_cmp(&b.time));
+    if let Some(event) = events.iter().find(|event| event.time < 0.0) {
+        bail!("event time {} is negative", event.time);
+    }
+
+    let mut channels: [ExportChannel; OPM_CHANNELS] =
+        std::array::from_fn(|ch| ExportChannel::new(ch as u8));
+    let mut output: Vec<ExportTrack> = vec![Vec::new(); OPM_CHANNELS];
+    let mut tick: u64 = 0;
+
+    for event in events {
+        let event_tick = (event.time * ticks_per_second).round() as u64;
+        if event_tick != tick {
+            // Pitch and level settle once all writes at a time are applied
+            for (ch, channel) in channels.iter_mut().enumerate() {
+                channel.flush(tick, tuning, &mut output[ch]);
+            }
+            tick = event_tick;
+        }
+
+        let ch = (event.addr & 0x07) as usize;
+        match event.addr {
+            0x08 => {
+                let ch = (event.data & 0x07) as usize;
+                if event.data & 0x78 != 0 {
+                    channels[ch].note_on(tick, tuning, &mut output[ch]);
+                } else {
+                    channels[ch].note_off(tick, &mut output[ch]);
+                }
+            }
+            0x20..=0x27 => channels[ch].connection = event.data & 0x07,
+            0x28..=0x2F => channels[ch].key_code = event.data,
+            0x30..=0x37 => channels[ch].key_fraction = event.data >> 2,
+            0x60..=0x7F => {
+                let op = ((event.addr - 0x60) / 8) as usize;
+                channels[ch].tl[op] = event.data & MAX_TL;
+            }
+            _ => {}
+        }
+    }
+    for (ch, channel) in channels.iter_mut().enumerate() {
+        channel.flush(tick, tuning, &mut output[ch]);
+        channel.note_off(tick, &mut output[ch]);
+    }
+
+    let title = log
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.title.as_deref());
+    let names: Vec<String> = (0..OPM_CHANNELS)
+        .map(|ch| format!("OPM channel {}", ch))
+        .collect();
+
+    let mut smf = Smf::new(Header::new(
+        Format::Parallel,
+        Timing::Metrical(EXPORT_PPQ.into()),
+    ));
+    let mut conductor = Vec::new();
+    if let Some(title) = title {
+        conductor.push((
+            0,
+            TrackEventKind::Meta(MetaMessage::TrackName(title.as_bytes())),
+        ));
+    }
+    conductor.push((
+        0,
+        TrackEventKind::Meta(MetaMessage::Tempo(DEFAULT_TEMPO.into())),
+    ));
+    let mut markers: Vec<&Marker> = log.markers.iter().collect();
+    markers.sort_by(|a, b| a.time.total_cmp(&b.time));
+    for marker in markers {
+        if marker.time < 0.0 {
+            bail!("marker time {} is negative", marker.time);
+        }
+        conductor.push((
+            (marker.time * ticks_per_second).round() as u64,
+            TrackEventKind::Meta(MetaMessage::Marker(marker.name.as_bytes())),
+        ));
+    }
+    smf.tracks.push(to_track(conductor));
+
+    for (ch, events) in output.into_iter().enumerate() {
+        let channel = u4::new(ch as u8);
+        let controller = |controller: u8, value: u8| TrackEventKind::Midi {
+            channel,
+            message: MidiMessage::Controller {
+                controller: u7::new(controller),
+                value: u7::new(value),
+            },
+        };
+        let mut track = vec![
+            (
+                0,
+                TrackEventKind::Meta(MetaMessage::TrackName(names[ch].as_bytes())),
+            ),
+            (0, controller(CC_RPN_MSB, 0)),
+            (0, controller(CC_RPN_LSB, 0)),
+            (0, controller(CC_DATA_ENTRY, DEFAULT_BEND_RANGE)),
+        ];
+        track.extend(events);
+        smf.tracks.push(to_track(track));
+    }
+
+    let mut bytes = Vec::new();
+    smf.write_std(&mut bytes)
+        .context("Failed to write MIDI data")?;
+    Ok(bytes)
+}
+
+/// Turn absolute-tick events into a track ending with End of Track
+fn to_track(events: Vec<(u64, TrackEventKind<'_>)>) -> Vec<TrackEvent<'_>> {
+    let mut last_tick = 0;
+    let mut track: Vec<TrackEvent> = events
+        .into_iter()
+        .map(|(tick, kind)| {
+            let delta = (tick - last_tick) as u32;
+            last_tick = tick;
+            TrackEvent {
+                delta: delta.into(),
+                kind,
+            }
+        })
+        .collect();
+    track.push(TrackEvent {
+        delta: 0.into(),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+    track
+}
+
+/// Register state of one OPM channel while exporting
+#[derive(Debug, Clone, Copy)]
+struct ExportChannel {
+    channel: u4,
+    connection: u8,
+    key_code: u8,
+    key_fraction: u8,
+    tl: [u8; 4],
+    /// Sounding MIDI note
+    note: Option<u8>,
+    velocity: u8,
+    /// Carrier TL when the note started
+    note_level: u8,
+    bend: i16,
+    volume: u8,
+}
+
+impl ExportChannel {
+    fn new(channel: u8) -> Self {
+        Self {
+            channel: u4::new(channel),
+            connection: 0,
+            key_code: 0,
+            key_fraction: 0,
+            tl: [0; 4],
+            note: None,
+            velocity: 0,
+            note_level: 0,
+            bend: 0,
+            volume: 127,
+        }
+    }
+
+    /// Pitch as a fractional MIDI note
+    fn pitch(&self, tuning: f64) -> f64 {
+        let code = self.key_code & 0x0F;
+        // Unused codes 3, 7, 11 and 15 sound like the note above
+        let semitone = KEY_CODE_NOTES
+            .iter()
+            .position(|&note| note >= code)
+            .unwrap_or(12);
+        let octave = (self.key_code >> 4) as usize;
+        (octave * 12 + semitone) as f64
+            + self.key_fraction as f64 / KF_STEPS as f64
+            + KEY_CODE_BASE_NOTE as f64
+            - tuning
+    }
+
+    /// Loudest carrier TL
+    fn level(&self) -> u8 {
+        (0..4)
+            .filter(|&op| is_carrier(self.connection, op))
+            .map(|op| self.tl[op])
+            .min()
+            .unwrap_or(MAX_TL)
+    }
+
+    fn send(&self, tick: u64, message: MidiMessage, track: &mut ExportTrack) {
+        track.push((
+            tick,
+            TrackEventKind::Midi {
+                channel: self.channel,
+                message,
+            },
+        ));
+    }
+
+    fn send_bend(&mut self, tick: u64, pitch: f64, track: &mut ExportTrack) {
+        let note = self.note.unwrap_or(0) as f64;
+        let bend = ((pitch - note) / EXPORT_BEND_RANGE * PITCH_BEND_SCALE)
+            .round()
+            .clamp(-PITCH_BEND_SCALE, PITCH_BEND_SCALE - 1.0) as i16;
+        if bend != self.bend {
+            self.bend = bend;
+            self.send(
+                tick,
+                MidiMessage::PitchBend {
+                    bend: PitchBend::from_int(bend),
+                },
+                track,
+            );
+        }
+    }
+
+    fn note_on(&mut self, tick: u64, tuning: f64, track: &mut ExportTrack) {
+        self.note_off(tick, track);
+        let level = self.level();
+        self.velocity = level_to_midi(0, level).max(1);
+        self.note_level = level;
+        self.start_note(tick, tuning, track);
+    }
+
+    fn start_note(&mut self, tick: u64, tuning: f64, track: &mut ExportTrack) {
+        let pitch = self.pitch(tuning);
+        let note = pitch.round().clamp(0.0, 127.0) as u8;
+        self.note = Some(note);
+        self.send_bend(tick, pitch, track);
+        if self.volume != 127 {
+            self.volume = 127;
+            self.send(
+                tick,
+                MidiMessage::Controller {
+                    controller: u7::new(CC_VOLUME),
+                    value: u7::new(127),
+                },
+                track,
+            );
+        }
+        self.send(
+            tick,
+            MidiMessage::NoteOn {
+                key: u7::new(note),
+                vel: u7::new(self.velocity),
+            },
+            track,
+        );
+    }
+
+    fn note_off(&mut self, tick: u64, track: &mut ExportTrack) {
+        if let Some(note) = self.note.take() {
+            self.send(
+                tick,
+                MidiMessage::NoteOff {
+                    key: u7::new(note),
+                    vel: u7::new(64),
+                },
+                track,
+            );
+        }
+    }
+
+    /// Follow pitch and level changes of the sounding note
+    fn flush(&mut self, tick: u64, tuning: f64, track: &mut ExportTrack) {
+        let Some(note) = self.note else {
+            return;
+        };
+
+        let pitch = self.pitch(tuning);
+        if (pitch - note as f64).abs() < EXPORT_BEND_RANGE {
+            self.send_bend(tick, pitch, track);
+        } else {
+            // Too far to bend: restart the note at the new pitch
+            self.note_off(tick, track);
+            self.start_note(tick, tuning, track);
+        }
+
+        let volume = level_to_midi(self.note_level, self.level());
+        if volume != self.volume {
+            self.volume = volume;
+            self.send(
+                tick,
+                MidiMessage::Controller {
+                    controller: u7::new(CC_VOLUME),
+                    value: u7::new(volume),
+                },
+                track,
+            );
+        }
+    }
+}
+
+/// MIDI value (0-127) for a TL level relative to a reference level
+fn level_to_midi(reference: u8, level: u8) -> u8 {
+    let decibels = (level as f64 - reference as f64) * TL_STEP_DB;
+    (127.0 * 10f64.powf(-decibels / 20.0))
+        .round()
+        .clamp(0.0, 127.0) as u8
+}
@@ -0,0 +1,556 @@
+//! VGM / VGZ import and export
+//!
+//! [`import`] converts the YM2151 part of a VGM command stream into an [`EventLog`]:
+//!
+//! - `0x54 aa dd` register writes become events, timed from the accumulated waits
+//! - waits `0x61 nnnn`, `0x62`, `0x63`, `0x7n` (and the wait part of `0x8n`) advance
+//!   time at the VGM rate of 44100 samples per second
+//! - the header's YM2151 clock becomes the log's `clock`, the loop offset its
+//!   `loop_time` and the GD3 tag its `metadata`
+//!
+//! Commands for other chips are skipped and counted, so callers can report them.
+//! A file without any YM2151 data is an error.
+//!
+//! [`export`] goes the other way and writes a VGM 1.71 file. Each write is placed
+//! on the OPM sample the player would issue it at, and that absolute sample time
+//! is converted to 44100 Hz on its own, so rounding never accumulates over waits.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+
+use super::{loop_opm_sample, player_write_samples};
+use crate::compression::{self, Compression};
+use crate::events::{EventLog, LogMetadata, RegisterEvent};
+use crate::resampler::{opm_sample_rate, YM2151_CLOCK};
+
+/// VGM timing base: waits are in samples at 44100 Hz
+pub const VGM_SAMPLE_RATE: u32 = 44100;
+
+const VGM_MAGIC: &[u8; 4] = b"Vgm ";
+const GD3_MAGIC: &[u8; 4] = b"Gd3 ";
+const GD3_VERSION: u32 = 0x100;
+/// GD3 holds 11 strings: track, game, system and author in English and Japanese,
+/// then release date, ripper and notes
+const GD3_STRING_COUNT: usize = 11;
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+const CMD_YM2151_WRITE: u8 = 0x54;
+const CMD_YM2151_WRITE_2ND: u8 = 0xA4;
+const CMD_WAIT: u8 = 0x61;
+const CMD_WAIT_NTSC_FRAME: u8 = 0x62;
+const CMD_WAIT_PAL_FRAME: u8 = 0x63;
+const CMD_END: u8 = 0x66;
+const CMD_DATA_BLOCK: u8 = 0x67;
+const CMD_PCM_RAM_WRITE: u8 = 0x68;
+
+/// Header offset of the YM2151 clock (VGM 1.10+)
+const YM2151_CLOCK_OFFSET: usize = 0x30;
+/// Before VGM 1.10, the YM2413 clock field was shared by YM2151 and YM2612
+const LEGACY_FM_CLOCK_OFFSET: usize = 0x10;
+/// Clock fields use bit 30 for "dual chip" and bit 31 for chip variants
+const CLOCK_MASK: u32 = 0x3FFF_FFFF;
+const DUAL_CHIP_BIT: u32 = 0x4000_0000;
+
+/// Chip clock fields in the VGM header, for reporting what a file contains
+const HEADER_CHIPS: &[(usize, &str)] = &[
+    (0x0C, "SN76489"),
+    (0x10, "YM2413"),
+    (0x2C, "YM2612"),
+    (0x38, "SegaPCM"),
+    (0x40, "RF5C68"),
+    (0x44, "YM2203"),
+    (0x48, "YM2608"),
+    (0x4C, "YM2610"),
+    (0x50, "YM3812"),
+    (0x54, "YM3526"),
+    (0x58, "Y8950"),
+    (0x5C, "YMF262"),
+    (0x60, "YMF278B"),
+    (0x64, "YMF271"),
+    (0x68, "YMZ280B"),
+    (0x6C, "RF5C164"),
+    (0x70, "PWM"),
+    (0x74, "AY8910"),
+    (0x80, "GameBoy DMG"),
+    (0x84, "NES APU"),
+    (0x88, "MultiPCM"),
+    (0x8C, "uPD7759"),
+    (0x90, "OKIM6258"),
+    (0x98, "OKIM6295"),
+    (0x9C, "K051649"),
+    (0xA0, "K054539"),
+    (0xA4, "HuC6280"),
+    (0xA8, "C140"),
+    (0xAC, "K053260"),
+    (0xB0, "Pokey"),
+    (0xB4, "QSound"),
+];
+
+/// Parsed VGM header fields relevant to YM2151 import
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VgmHeader {
+    /// BCD version, e.g. 0x171 for 1.71
+    pub version: u32,
+    /// YM2151 master clock in Hz (0 if the file has no YM2151)
+    pub ym2151_clock: u32,
+    /// Whether the header declares a second YM2151
+    pub dual_ym2151: bool,
+    /// Total length in 44100 Hz samples
+    pub total_samples: u32,
+    /// Absolute file offset of the loop point, if the file loops
+    pub loop_offset: Option<usize>,
+    /// Loop length in 44100 Hz samples
+    pub loop_samples: u32,
+    /// Absolute file offset of the GD3 tag, if present
+    pub gd3_offset: Option<usize>,
+    /// Absolute file offset of the command stream
+    pub data_offset: usize,
+    /// Other chips declared in the header (non-zero clock)
+    pub other_chips: Vec<&'static str>,
+}
+
+/// Result of a VGM import
+#[derive(Debug, Clone)]
+pub struct VgmImport {
+    pub header: VgmHeader,
+    /// Imported log, including the loop point and GD3 tags
+    pub log: EventLog,
+    /// Number of skipped commands per unsupported chip
+    pub skipped_commands: BTreeMap<&'static str, usize>,
+}
+
+impl VgmImport {
+    /// Human-readable notes about data that was not imported
+    pub fn warnings(&self) -> Vec<String> {
+        self.skipped_commands
+            .iter()
+            .map(|(chip, count)| format!("{}: {} commands skipped (unsupported chip)", chip, count))
+            .collect()
+    }
+}
+
+/// Whether the bytes look like a VGM or gzip-compressed VGZ file
+pub fn is_vgm_data(bytes: &[u8]) -> bool {
+    bytes.starts_with(VGM_MAGIC) || bytes.starts_with(&GZIP_MAGIC)
+}
+
+/// Import a `.vgm` or `.vgz` file from memory
+pub fn import(bytes: &[u8]) -> Result<VgmImport> {
+    if bytes.starts_with(&GZIP_MAGIC) {
+        let decompressed = compression::decompress(
+            bytes,
+            Compression::Gzip,
+            compression::MAX_FILE_DECOMPRESSED_SIZE,
+        )
+        .context("Failed to decompress VGZ data")?;
+        return import_uncompressed(&decompressed);
+    }
+    import_uncompressed(bytes)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .unwrap_or(0)
+}
+
+/// Resolve a header field that holds an offset relative to its own position
+fn relative_offset(bytes: &[u8], field: usize) -> Option<usize> {
+    match read_u32(bytes, field) {
+        0 => None,
+        value => Some(field + value as usize),
+    }
+}
+
+/// Parse the VGM header
+pub fn parse_header(bytes: &[u8]) -> Result<VgmHeader> {
+    if bytes.len() < 0x40 || !bytes.starts_with(VGM_MAGIC) {
+        bail!("Not a VGM file (missing 'Vgm ' header)");
+    }
+
+    let version = read_u32(bytes, 0x08);
+    let data_offset = if version >= 0x150 {
+        relative_offset(bytes, 0x34).unwrap_or(0x40)
+    } else {
+        0x40
+    };
+    if data_offset > bytes.len() {
+        bail!(
+            "VGM data offset 0x{:X} is past the end of the file",
+            data_offset
+        );
+    }
+
+    // Only fields inside the header are valid; the rest may already be command data
+    let header_field = |offset: usize| {
+        if offset + 4 <= data_offset {
+            read_u32(bytes, offset)
+        } else {
+            0
+        }
+    };
+
+    let raw_clock = if version >= 0x110 {
+        header_field(YM2151_CLOCK_OFFSET)
+    } else {
+        header_field(LEGACY_FM_CLOCK_OFFSET)
+    };
+
+    let other_chips = HEADER_CHIPS
+        .iter()
+        .filter(|&&(offset, _)| {
+            // Pre-1.10 files store the YM2151 clock in the YM2413 field
+            !(version < 0x110 && offset == LEGACY_FM_CLOCK_OFFSET)
+        })
+        .filter(|&&(offset, _)| header_field(offset) & CLOCK_MASK != 0)
+        .map(|&(_, name)| name)
+        .collect();
+
+    Ok(VgmHeader {
+        version,
+        ym2151_clock: raw_clock & CLOCK_MASK,
+        dual_ym2151: raw_clock & DUAL_CHIP_BIT != 0,
+        total_samples: read_u32(bytes, 0x18),
+        loop_offset: relative_offset(bytes, 0x1C),
+        loop_samples: read_u32(bytes, 0x20),
+        gd3_offset: relative_offset(bytes, 0x14),
+        data_offset,
+        other_chips,
+    })
+}
+
+/// Chip name for a command byte, used when counting skipped commands
+fn chip_for_command(cmd: u8) -> &'static str {
+    match cmd {
+        0x30 | 0x3F | 0x4F | 0x50 => "SN76489",
+        0x51 | 0xA1 => "YM2413",
+        0x52 | 0x53 | 0xA2 | 0xA3 | 0x80..=0x8F => "YM2612",
+        CMD_YM2151_WRITE_2ND => "YM2151 (2nd chip)",
+        0x55 | 0xA5 => "YM2203",
+        0x56 | 0x57 | 0xA6 | 0xA7 => "YM2608",
+        0x58 | 0x59 | 0xA8 | 0xA9 => "YM2610",
+        0x5A | 0xAA => "YM3812",
+        0x5B | 0xAB => "YM3526",
+        0x5C | 0xAC => "Y8950",
+        0x5D | 0xAD => "YMZ280B",
+        0x5E | 0x5F | 0xAE | 0xAF => "YMF262",
+        0xA0 => "AY8910",
+        0x90..=0x95 => "DAC stream",
+        CMD_DATA_BLOCK | CMD_PCM_RAM_WRITE | 0xE0 => "PCM data",
+        _ => "other",
+    }
+}
+
+/// Operand length of a command that is skipped, per the VGM 1.71 spec
+fn operand_len(cmd: u8) -> Option<usize> {
+    Some(match cmd {
+        0x30..=0x3F | 0x4F | 0x50 => 1,
+        0x40..=0x4E | 0x51..=0x5F | 0xA0..=0xBF => 2,
+        0xC0..=0xDF => 3,
+        0xE0..=0xFF => 4,
+        0x80..=0x8F => 0,
+        0x90 | 0x91 | 0x95 => 4,
+        0x92 => 5,
+        0x93 => 10,
+        0x94 => 1,
+        _ => return None,
+    })
+}
+
+fn import_uncompressed(bytes: &[u8]) -> Result<VgmImport> {
+    let header = parse_header(bytes)?;
+
+    let mut events = Vec::new();
+    let mut skipped_commands: BTreeMap<&'static str, usize> = BTreeMap::new();
+    let mut samples: u64 = 0;
+    let mut loop_time = None;
+    let mut pos = header.data_offset;
+
+    let time_of = |samples: u64| samples as f64 / VGM_SAMPLE_RATE as f64;
+    let truncated = |pos: usize| anyhow::anyhow!("VGM command stream is truncated at 0x{:X}", pos);
+
+    loop {
+        if Some(pos) == header.loop_offset {
+            loop_time = Some(time_of(samples));
+        }
+
+        let Some(&cmd) = bytes.get(pos) else {
+            // Some rippers omit the end marker; treat end of file as end of data
+            break;
+        };
+
+        match cmd {
+            CMD_YM2151_WRITE => {
+                let operands = bytes.get(pos + 1..pos + 3).ok_or_else(|| truncated(pos))?;
+                events.push(RegisterEvent {
+                    time: time_of(samples),
+                    addr: operands[0],
+                    data: operands[1],
+                    is_data: None,
+                });
+                pos += 3;
+            }
+            CMD_WAIT => {
+                let operands = bytes.get(pos + 1..pos + 3).ok_or_else(|| truncated(pos))?;
+                samples += u16::from_le_bytes([operands[0], operands[1]]) as u64;
+                pos += 3;
+            }
+            CMD_WAIT_NTSC_FRAME => {
+                samples += 735;
+                pos += 1;
+            }
+            CMD_WAIT_PAL_FRAME => {
+                samples += 882;
+                pos += 1;
+            }
+            0x70..=0x7F => {
+                samples += (cmd & 0x0F) as u64 + 1;
+                pos += 1;
+            }
+            CMD_END => break,
+            CMD_DATA_BLOCK => {
+                // 0x67 0x66 tt ss ss ss ss <data>
+                let size = bytes.get(pos + 3..pos + 7).ok_or_else(|| truncated(pos))?;
+                let size = u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize;
+                *skipped_commands.entry(chip_for_command(cmd)).or_default() += 1;
+                pos += 7 + (size & 0x7FFF_FFFF);
+            }
+            CMD_PCM_RAM_WRITE => {
+                // 0x68 0x66 cc oo oo oo dd dd dd ss ss ss
+                *skipped_commands.entry(chip_for_command(cmd)).or_default() += 1;
+                pos += 12;
+            }
+            _ => {
+                let Some(len) = operand_len(cmd) else {
+                    bail!("Unknown VGM command 0x{:02X} at 0x{:X}", cmd, pos);
+                };
+                if (0x80..=0x8F).contains(&cmd) {
+                    // YM2612 DAC write followed by a wait of n samples
+                    samples += (cmd & 0x0F) as u64;
+                }
+                *skipped_commands.entry(chip_for_command(cmd)).or_default() += 1;
+                pos += 1 + len;
+            }
+        }
+    }
+
+    if events.is_empty() {
+        if header.other_chips.is_empty() {
+            bail!("VGM file contains no YM2151 data");
+        }
+        bail!(
+            "VGM file contains no YM2151 data (chips in file: {})",
+            header.other_chips.join(", ")
+        );
+    }
+
+    let clock = (header.ym2151_clock != 0).then_some(header.ym2151_clock);
+    let metadata = header
+        .gd3_offset
+        .and_then(|offset| parse_gd3(bytes, offset));
+
+    Ok(VgmImport {
+        header,
+        log: EventLog {
+            events,
+            clock,
+            loop_time,
+            metadata,
+            markers: Vec::new(),
+        },
+        skipped_commands,
+    })
+}
+
+/// Read the GD3 tag into log metadata, preferring English strings
+///
+/// A missing or malformed tag is not an error; it only means no metadata.
+fn parse_gd3(bytes: &[u8], offset: usize) -> Option<LogMetadata> {
+    if bytes.get(offset..offset + 4)? != GD3_MAGIC {
+        return None;
+    }
+    let length = read_u32(bytes, offset + 8) as usize;
+    let body = bytes.get(offset + 12..offset + 12 + length)?;
+
+    let units: Vec<u16> = body
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    let strings: Vec<String> = units
+        .split(|&unit| unit == 0)
+        .take(GD3_STRING_COUNT)
+        .map(String::from_utf16_lossy)
+        .collect();
+
+    let field = |english: usize, japanese: Option<usize>| {
+        let pick = |index: usize| strings.get(index).filter(|s| !s.is_empty()).cloned();
+        pick(english).or_else(|| japanese.and_then(pick))
+    };
+
+    let metadata = LogMetadata {
+        title: field(0, Some(1)),
+        game: field(2, Some(3)),
+        system: field(4, Some(5)),
+        author: field(6, Some(7)),
+        date: field(8, None),
+        notes: field(10, None),
+    };
+    (metadata != LogMetadata::default()).then_some(metadata)
+}
+
+/// Header size written by [`export`] (the VGM 1.71 header)
+const EXPORT_HEADER_SIZE: usize = 0x100;
+const EXPORT_VERSION: u32 = 0x171;
+/// Ripper field written into the GD3 tag
+const EXPORT_RIPPER: &str = "ym2151-log-play-server";
+
+/// Convert an absolute OPM sample time to the nearest 44100 Hz sample
+fn opm_to_vgm_samples(opm_samples: u64, opm_rate: u32) -> u64 {
+    let opm_rate = opm_rate as u64;
+    (opm_samples * VGM_SAMPLE_RATE as u64 + opm_rate / 2) / opm_rate
+}
+
+/// Append wait commands totalling `samples`, using the shortest encodings
+fn push_wait(data: &mut Vec<u8>, mut samples: u64) {
+    while samples > 0 {
+        match samples {
+            1..=16 => {
+                data.push(0x70 | (samples - 1) as u8);
+                samples = 0;
+            }
+            735 => {
+                data.push(CMD_WAIT_NTSC_FRAME);
+                samples = 0;
+            }
+            882 => {
+                data.push(CMD_WAIT_PAL_FRAME);
+                samples = 0;
+            }
+            _ => {
+                let chunk = samples.min(u16::MAX as u64);
+                data.push(CMD_WAIT);
+                data.extend_from_slice(&(chunk as u16).to_le_bytes());
+                samples -= chunk;
+            }
+        }
+    }
+}
+
+fn push_gd3_string(gd3: &mut Vec<u8>, value: &str) {
+    for unit in value.encode_utf16() {
+        gd3.extend_from_slice(&unit.to_le_bytes());
+    }
+    gd3.extend_from_slice(&[0, 0]);
+}
+
+fn build_gd3(metadata: Option<&LogMetadata>) -> Vec<u8> {
+    let metadata = metadata.cloned().unwrap_or_default();
+    let text = |value: &Option<String>| value.clone().unwrap_or_default();
+    let strings = [
+        text(&metadata.title),
+        String::new(),
+        text(&metadata.game),
+        String::new(),
+        text(&metadata.system),
+        String::new(),
+        text(&metadata.author),
+        String::new(),
+        text(&metadata.date),
+        EXPORT_RIPPER.to_string(),
+        text(&metadata.notes),
+    ];
+
+    let mut body = Vec::new();
+    for value in &strings {
+        push_gd3_string(&mut body, value);
+    }
+
+    let mut gd3 = Vec::with_capacity(12 + body.len());
+    gd3.extend_from_slice(GD3_MAGIC);
+    gd3.extend_from_slice(&GD3_VERSION.to_le_bytes());
+    gd3.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    gd3.extend_from_slice(&body);
+    gd3
+}
+
+/// Export a log as a VGM 1.71 file
+///
+/// The header carries the log's clock (or the default clock), the total length
+/// and, if `loop_time` is set, the loop point. A GD3 tag is always written, with
+/// title/author/etc. from the log's metadata.
+pub fn export(log: &EventLog) -> Result<Vec<u8>> {
+    log.check_playable()?;
+
+    let clock = log.clock_or(YM2151_CLOCK);
+    let opm_rate = opm_sample_rate(clock);
+
+    let loop_vgm_samples =
+        loop_opm_sample(log, opm_rate)?.map(|opm| opm_to_vgm_samples(opm, opm_rate));
+
+    let mut data = Vec::with_capacity(log.events.len() * 4 + 1);
+    let mut vgm_now: u64 = 0;
+    let mut loop_data_offset = None;
+
+    // Wait until `target`, stopping exactly on the loop point on the way
+    let mut advance_to = |data: &mut Vec<u8>, vgm_now: &mut u64, target: u64| {
+        if let Some(loop_at) = loop_vgm_samples {
+            if loop_data_offset.is_none() && loop_at <= target {
+                push_wait(data, loop_at - *vgm_now);
+                *vgm_now = loop_at;
+                loop_data_offset = Some(data.len());
+            }
+        }
+        push_wait(data, target - *vgm_now);
+        *vgm_now = target;
+    };
+
+    let write_samples = player_write_samples(&log.events, opm_rate);
+    for (event, &opm_time) in log.events.iter().zip(&write_samples) {
+        let vgm_time = opm_to_vgm_samples(opm_time, opm_rate);
+        advance_to(&mut data, &mut vgm_now, vgm_time);
+        data.extend_from_slice(&[CMD_YM2151_WRITE, event.addr, event.data]);
+    }
+
+    data.push(CMD_END);
+
+    if vgm_now > u32::MAX as u64 {
+        bail!("log is too long for VGM ({} samples)", vgm_now);
+    }
+    let total_samples = vgm_now as u32;
+    if let Some(loop_at) = loop_vgm_samples {
+        if loop_at >= vgm_now {
+            bail!("loop_time must be before the last event");
+        }
+    }
+
+    let gd3 = build_gd3(log.metadata.as_ref());
+    let gd3_offset = EXPORT_HEADER_SIZE + data.len();
+    let file_len = gd3_offset + gd3.len();
+
+    let mut header = vec![0u8; EXPORT_HEADER_SIZE];
+    let mut put = |offset: usize, value: u32| {
+        header[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    };
+    put(0x04, (file_len - 0x04) as u32);
+    put(0x08, EXPORT_VERSION);
+    put(0x14, (gd3_offset - 0x14) as u32);
+    put(0x18, total_samples);
+    if let (Some(loop_offset), Some(loop_at)) = (loop_data_offset, loop_vgm_samples) {
+        put(0x1C, (EXPORT_HEADER_SIZE + loop_offset - 0x1C) as u32);
+        put(0x20, total_samples - loop_at as u32);
+    }
+    put(YM2151_CLOCK_OFFSET, clock);
+    put(0x34, (EXPORT_HEADER_SIZE - 0x34) as u32);
+    header[0..4].copy_from_slice(VGM_MAGIC);
+
+    let mut bytes = header;
+    bytes.extend_from_slice(&data);
+    bytes.extend_from_slice(&gd3);
+    Ok(bytes)
+}
+
+/// Export a log as a gzip-compressed VGZ file
+pub fn export_vgz(log: &EventLog) -> Result<Vec<u8>> {
+    compression::compress(&export(log)?, Compression::Gzip)
+}
@@ -1,5 +1,26 @@
+use crate::audio::markers::MarkerReached;
+use crate::compression::{self, Compression};
 use serde::{Deserialize, Serialize};
 
+/// Bits of a frame's 4-byte length prefix that hold the payload length;
+/// the bits above are flags
+pub const FRAME_LENGTH_MASK: u32 = 0x0FFF_FFFF;
+/// Frame flag: the payload is gzip compressed JSON
+pub const FRAME_FLAG_GZIP: u32 = 0x1000_0000;
+/// Frame flag: the payload is zstd compressed JSON
+pub const FRAME_FLAG_ZSTD: u32 = 0x2000_0000;
+/// Frame flag: the payload is the JSON length (4 bytes, little-endian), the
+/// JSON, then raw binary data that belongs to it (the samples of `Pcm`)
+pub const FRAME_FLAG_ATTACHMENT: u32 = 0x4000_0000;
+/// Largest size a compressed payload may expand to, against decompression bombs
+pub const MAX_DECOMPRESSED_PAYLOAD_SIZE: usize = 256 * 1024 * 1024;
+/// Largest response payload a client accepts; responses can carry rendered PCM
+pub const MAX_RESPONSE_PAYLOAD_SIZE: usize = FRAME_LENGTH_MASK as usize;
+/// Most sample bytes a `Pcm` response carries, leaving room for its JSON
+pub const MAX_PCM_RESPONSE_BYTES: usize = MAX_RESPONSE_PAYLOAD_SIZE - 4096;
+/// Sample format of `RenderPcm` when the command does not name one
+pub const DEFAULT_PCM_FORMAT: &str = "16";
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "command", rename_all = "snake_case")]
 pub enum Command {
@@ -21,45 +42,82 @@ pub enum Command {
         data: serde_json::Value,
     },
     GetServerState,
+    /// Start recording the interactive session, discarding any earlier recording.
+    /// Nothing is recorded until this is sent.
+    StartSessionRecording,
+    /// Stop recording the interactive session; the recording can still be saved
+    StopSessionRecording,
+    /// Save the interactive session recorded so far to a file on the server side.
+    /// The path is relative to the server's working directory and may not leave it;
+    /// the extension picks the format (`.vgm`/`.vgz` for VGM, `.json` for JSON, ...).
+    SaveInteractiveSession {
+        path: String,
+    },
+    /// Get the markers reached by the current playback after sequence number `after`.
+    /// Poll with the last `seq` received to follow markers as they are reached.
+    GetMarkers {
+        #[serde(default)]
+        after: u64,
+    },
+    /// Start streamed playback of a log uploaded in chunks with `AppendEvents`,
+    /// at `clock` (the server default if omitted). Playback starts with the first chunk.
+    BeginStream {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        clock: Option<u32>,
+    },
+    /// Append an event log chunk to the stream. Times count from the start of the
+    /// stream in seconds and must not go back before the previous chunk; `clock`,
+    /// `time_unit`, `driver`, `loop_time` and `markers` cannot be set. The server
+    /// answers at once with `StreamBuffer`, turning the chunk away while playback
+    /// is far behind or too many chunks are queued, so a client is paced by its
+    /// responses.
+    AppendEvents {
+        data: serde_json::Value,
+    },
+    /// End the stream; playback finishes after the last appended event
+    EndStream,
+    /// Render a log offline on a worker thread, without touching the audio device
+    /// or the current playback. `data` is a log or a server-side path as for
+    /// `PlayJson`; `sample_rate` defaults to the chip's native rate and `format` is
+    /// `16`, `24` or `32f`. The server answers with the PCM, or writes a WAV or
+    /// FLAC file (by extension) to `path` and answers `Ok`; like the path of
+    /// `SaveInteractiveSession`, it is relative to the server's working directory.
+    /// A render that could be too long for a response needs a `path`.
+    RenderPcm {
+        data: serde_json::Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sample_rate: Option<u32>,
+        #[serde(default = "default_pcm_format")]
+        format: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+    },
+}
+
+fn default_pcm_format() -> String {
+    DEFAULT_PCM_FORMAT.to_string()
 }
 
 impl Command {
     /// Parse command from binary (length-prefixed JSON) format
+    ///
+    /// Compressed frames (see [`FRAME_FLAG_GZIP`] and [`FRAME_FLAG_ZSTD`]) are
+    /// decompressed first, up to [`MAX_DECOMPRESSED_PAYLOAD_SIZE`].
     pub fn from_binary(data: &[u8]) -> Result<Self, String> {
-        if data.len() < 4 {
-            return Err("Invalid binary data: too short".to_string());
-        }
-
-        let len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
-
-        if data.len() < 4 + len {
-            return Err(format!(
-                "Invalid binary data: expected {} bytes, got {}",
-                4 + len,
-                data.len()
-            ));
-        }
-
-        let json_bytes = &data[4..4 + len];
-        let json_str =
-            std::str::from_utf8(json_bytes).map_err(|e| format!("Invalid UTF-8 in JSON: {}", e))?;
-
-        serde_json::from_str(json_str).map_err(|e| format!("Failed to parse JSON: {}", e))
+        let json_str = decode_frame(data)?;
+        serde_json::from_str(&json_str).map_err(|e| format!("Failed to parse JSON: {}", e))
     }
 
     /// Serialize command to binary (length-prefixed JSON) format
     pub fn to_binary(&self) -> Result<Vec<u8>, String> {
+        self.to_binary_compressed(Compression::None)
+    }
+
+    /// Serialize command to binary format with a compressed payload
+    pub fn to_binary_compressed(&self, compression: Compression) -> Result<Vec<u8>, String> {
         let json_str =
             serde_json::to_string(self).map_err(|e| format!("Failed to serialize JSON: {}", e))?;
-
-        let json_bytes = json_str.as_bytes();
-        let len = json_bytes.len() as u32;
-
-        let mut result = Vec::with_capacity(4 + json_bytes.len());
-        result.extend_from_slice(&len.to_le_bytes());
-        result.extend_from_slice(json_bytes);
-
-        Ok(result)
+        encode_frame(&json_str, compression)
     }
 }
 
@@ -77,45 +135,162 @@ pub enum Response {
     /// Server state response
     ServerState {
         state: String,
+        /// Name of the last marker reached by the current playback
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        marker: Option<String>,
+    },
+    /// Markers reached by the current playback, in order
+    Markers {
+        markers: Vec<MarkerReached>,
+    },
+    /// Answer to `AppendEvents`: whether the chunk was taken, and how far the
+    /// events received run ahead of playback. A chunk turned away should be sent
+    /// again once playback has caught up.
+    StreamBuffer {
+        accepted: bool,
+        buffered_sec: f64,
+    },
+    /// PCM rendered by `RenderPcm`: interleaved stereo little-endian samples
+    /// in `format`, sent raw after the JSON (see [`FRAME_FLAG_ATTACHMENT`])
+    Pcm {
+        sample_rate: u32,
+        format: String,
+        frames: usize,
+        #[serde(skip)]
+        data: Vec<u8>,
     },
 }
 
 impl Response {
     /// Parse response from binary (length-prefixed JSON) format
     pub fn from_binary(data: &[u8]) -> Result<Self, String> {
-        if data.len() < 4 {
-            return Err("Invalid binary data: too short".to_string());
+        let (json_str, attachment) = decode_frame_with_attachment(data)?;
+        let mut response: Self =
+            serde_json::from_str(&json_str).map_err(|e| format!("Failed to parse JSON: {}", e))?;
+        match (&mut response, attachment) {
+            (Response::Pcm { data, .. }, Some(attachment)) => *data = attachment.to_vec(),
+            (Response::Pcm { .. }, None) => {
+                return Err("PCM response without its samples".to_string())
+            }
+            (_, Some(_)) => return Err("Unexpected binary data in response".to_string()),
+            (_, None) => {}
         }
-
-        let len = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
-
-        if data.len() < 4 + len {
-            return Err(format!(
-                "Invalid binary data: expected {} bytes, got {}",
-                4 + len,
-                data.len()
-            ));
-        }
-
-        let json_bytes = &data[4..4 + len];
-        let json_str =
-            std::str::from_utf8(json_bytes).map_err(|e| format!("Invalid UTF-8 in JSON: {}", e))?;
-
-        serde_json::from_str(json_str).map_err(|e| format!("Failed to parse JSON: {}", e))
+        Ok(response)
     }
 
     /// Serialize response to binary (length-prefixed JSON) format
     pub fn to_binary(&self) -> Result<Vec<u8>, String> {
         let json_str =
             serde_json::to_string(self).map_err(|e| format!("Failed to serialize JSON: {}", e))?;
+        match self {
+            Response::Pcm { data, .. } => encode_frame_with_attachment(&json_str, data),
+            _ => encode_frame(&json_str, Compression::None),
+        }
+    }
+}
+
+/// Payload length of a frame from its 4-byte prefix, without the flag bits
+pub fn frame_payload_len(prefix: [u8; 4]) -> usize {
+    (u32::from_le_bytes(prefix) & FRAME_LENGTH_MASK) as usize
+}
+
+fn encode_frame(json_str: &str, compression: Compression) -> Result<Vec<u8>, String> {
+    let payload = compression::compress(json_str.as_bytes(), compression)
+        .map_err(|e| format!("Failed to compress payload: {}", e))?;
+    if payload.len() > FRAME_LENGTH_MASK as usize {
+        return Err(format!("Payload too large: {} bytes", payload.len()));
+    }
+    let flags = match compression {
+        Compression::None => 0,
+        Compression::Gzip => FRAME_FLAG_GZIP,
+        Compression::Zstd => FRAME_FLAG_ZSTD,
+    };
+    let prefix = payload.len() as u32 | flags;
+
+    let mut result = Vec::with_capacity(4 + payload.len());
+    result.extend_from_slice(&prefix.to_le_bytes());
+    result.extend_from_slice(&payload);
+    Ok(result)
+}
 
-        let json_bytes = json_str.as_bytes();
-        let len = json_bytes.len() as u32;
+fn encode_frame_with_attachment(json_str: &str, attachment: &[u8]) -> Result<Vec<u8>, String> {
+    let payload_len = 4 + json_str.len() + attachment.len();
+    if payload_len > FRAME_LENGTH_MASK as usize {
+        return Err(format!("Payload too large: {} bytes", payload_len));
+    }
+    let prefix = payload_len as u32 | FRAME_FLAG_ATTACHMENT;
 
-        let mut result = Vec::with_capacity(4 + json_bytes.len());
-        result.extend_from_slice(&len.to_le_bytes());
-        result.extend_from_slice(json_bytes);
+    let mut result = Vec::with_capacity(4 + payload_len);
+    result.extend_from_slice(&prefix.to_le_bytes());
+    result.extend_from_slice(&(json_str.len() as u32).to_le_bytes());
+    result.extend_from_slice(json_str.as_bytes());
+    result.extend_from_slice(attachment);
+    Ok(result)
+}
 
-        Ok(result)
+/// Decode a frame that may carry an attachment after its JSON
+fn decode_frame_with_attachment(data: &[u8]) -> Result<(String, Option<&[u8]>), String> {
+    if data.len() < 4 {
+        return Err("Invalid binary data: too short".to_string());
     }
+    let prefix = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    if prefix & !FRAME_LENGTH_MASK != FRAME_FLAG_ATTACHMENT {
+        return decode_frame(data).map(|json_str| (json_str, None));
+    }
+
+    let len = (prefix & FRAME_LENGTH_MASK) as usize;
+    let Some(payload) = data.get(4..4 + len) else {
+        return Err(format!(
+            "Invalid binary data: expected {} bytes, got {}",
+            4 + len,
+            data.len()
+        ));
+    };
+    let Some((json_len, rest)) = payload.split_first_chunk::<4>() else {
+        return Err("Invalid binary data: attachment frame too short".to_string());
+    };
+    let json_len = u32::from_le_bytes(*json_len) as usize;
+    if json_len > rest.len() {
+        return Err(format!(
+            "Invalid binary data: JSON of {} bytes in a payload of {}",
+            json_len,
+            rest.len()
+        ));
+    }
+    let (json_bytes, attachment) = rest.split_at(json_len);
+    let json_str = String::from_utf8(json_bytes.to_vec())
+        .map_err(|e| format!("Invalid UTF-8 in JSON: {}", e))?;
+    Ok((json_str, Some(attachment)))
+}
+
+fn decode_frame(data: &[u8]) -> Result<String, String> {
+    if data.len() < 4 {
+        return Err("Invalid binary data: too short".to_string());
+    }
+
+    let prefix = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    let len = (prefix & FRAME_LENGTH_MASK) as usize;
+
+    if data.len() < 4 + len {
+        return Err(format!(
+            "Invalid binary data: expected {} bytes, got {}",
+            4 + len,
+            data.len()
+        ));
+    }
+
+    let compression = match prefix & !FRAME_LENGTH_MASK {
+        0 => Compression::None,
+        FRAME_FLAG_GZIP => Compression::Gzip,
+        FRAME_FLAG_ZSTD => Compression::Zstd,
+        flags => return Err(format!("Unknown frame flags: 0x{:08X}", flags)),
+    };
+    let payload = &data[4..4 + len];
+    let json_bytes = match compression {
+        Compression::None => payload.to_vec(),
+        _ => compression::decompress(payload, compression, MAX_DECOMPRESSED_PAYLOAD_SIZE)
+            .map_err(|e| format!("Invalid compressed payload: {:#}", e))?,
+    };
+
+    String::from_utf8(json_bytes).map_err(|e| format!("Invalid UTF-8 in JSON: {}", e))
 }
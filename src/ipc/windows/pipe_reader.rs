@@ -51,7 +51,8 @@ impl PipeReader {
         let mut len_bytes = [0u8; 4];
         self.read_exact(&mut len_bytes)?;
 
-        let len = u32::from_le_bytes(len_bytes) as usize;
+        // The top bits of the prefix are frame flags (compression)
+        let len = crate::ipc::protocol::frame_payload_len(len_bytes);
 
         #[cfg(test)]
         log_server(&format!("📥 [SERVER] 受信データ長: {} bytes", len));
@@ -133,13 +133,14 @@ impl PipeWriter {
         let mut len_bytes = [0u8; 4];
         self.read_exact(&mut len_bytes)?;
 
-        let len = u32::from_le_bytes(len_bytes) as usize;
+        // The top bits of the prefix are frame flags (compression, attachment)
+        let len = crate::ipc::protocol::frame_payload_len(len_bytes);
 
         #[cfg(test)]
         log_client(&format!("📥 [CLIENT] レスポンス長: {} bytes", len));
 
-        // Validate reasonable length
-        if len > 10 * 1024 * 1024 {
+        // Validate reasonable length (responses can carry rendered PCM)
+        if len > crate::ipc::protocol::MAX_RESPONSE_PAYLOAD_SIZE {
             #[cfg(test)]
             log_client(&format!(
                 "❌ [CLIENT] エラー: レスポンス長が大きすぎます: {} bytes",
@@ -1,21 +1,38 @@
 pub mod audio;
 pub mod audio_config;
 pub mod client;
+pub mod compression;
 pub mod debug_wav;
 pub mod demo_client_interactive;
 pub mod demo_server_interactive;
 pub mod demo_server_non_interactive;
+pub mod diff;
+pub mod disasm;
+pub mod driver;
+pub mod event_schedule;
+pub mod event_stream;
 pub mod events;
+pub mod flac_writer;
+pub mod formats;
 pub mod ipc;
 pub mod logging;
 pub mod mmcss;
 pub mod opm;
 pub mod opm_ffi;
+pub mod opm_tables;
+pub mod optimizer;
+pub mod pcm;
 pub mod player;
+pub mod register_shadow;
+pub mod render;
 pub mod resampler;
 pub mod scheduler;
 pub mod self_update;
 pub mod server;
+pub mod session_recorder;
+pub mod stems;
+pub mod submission_ring;
+pub mod voice;
 pub mod wav_writer;
 
 #[cfg(test)]
@@ -1,11 +1,24 @@
 use clap::{Parser, Subcommand};
+use std::borrow::Cow;
 use ym2151_log_play_server::client;
 use ym2151_log_play_server::demo_client_interactive;
 use ym2151_log_play_server::demo_server_interactive;
 use ym2151_log_play_server::demo_server_non_interactive;
+use ym2151_log_play_server::diff;
+use ym2151_log_play_server::disasm;
+use ym2151_log_play_server::events::{check_clock, edit, EventLog};
+use ym2151_log_play_server::formats::{self, smf};
 use ym2151_log_play_server::logging;
+use ym2151_log_play_server::optimizer;
+use ym2151_log_play_server::pcm::{Dither, SampleFormat};
+use ym2151_log_play_server::player::SILENCE_DURATION_MS;
+use ym2151_log_play_server::render::{self, RenderOptions};
+use ym2151_log_play_server::resampler::{ResamplingQuality, YM2151_CLOCK};
 use ym2151_log_play_server::self_update as self_update_support;
 use ym2151_log_play_server::server::Server;
+use ym2151_log_play_server::stems;
+use ym2151_log_play_server::voice;
+use ym2151_log_play_server::wav_writer::{self, MAX_TAIL_SECONDS};
 
 /// YM2151 Log Player - Rust implementation
 #[derive(Parser)]
@@ -38,10 +51,14 @@ enum Commands {
         /// 非インタラクティブデモモード (output_ym2151.jsonを使用して音響テスト)
         #[arg(long)]
         demo_non_interactive: bool,
+
+        /// YM2151のマスタークロック (Hz)。X68000等は4000000。JSONのclock指定が優先
+        #[arg(long, value_name = "HZ", default_value_t = YM2151_CLOCK)]
+        clock: u32,
     },
     /// サーバーに演奏指示
     Client {
-        /// JSONファイルのパス
+        /// JSONファイルのパス (.json.gz / .json.zst / .vgm / .vgz / .s98 / .mdx / .mid も可、.ndjson / .jsonl はストリーミング送信)
         #[arg(value_name = "JSON_FILE")]
         json_file: Option<String>,
 
@@ -61,6 +78,220 @@ enum Commands {
         #[arg(long)]
         demo_interactive: bool,
     },
+    /// ログファイルを変換 (形式は拡張子で判別: .json / .json.gz / .json.zst / .vgm / .vgz / .s98 / .mid、.mdx は入力のみ)
+    Convert {
+        /// 入力ファイルのパス
+        #[arg(value_name = "INPUT")]
+        input: String,
+
+        /// 出力ファイルのパス
+        #[arg(value_name = "OUTPUT")]
+        output: String,
+    },
+    /// MIDIファイル (SMF type 0/1) をOPMのイベントログに変換
+    Midi {
+        /// 入力MIDIファイルのパス
+        #[arg(value_name = "INPUT")]
+        input: String,
+
+        /// 出力ファイルのパス (形式は拡張子で判別: .json / .vgm / .vgz / .s98)
+        #[arg(value_name = "OUTPUT")]
+        output: String,
+
+        /// プログラムチェンジで使う音色バンク (JSON または VOPM .opm)。未指定の音色はデフォルト音色
+        #[arg(long, value_name = "BANK")]
+        voices: Option<String>,
+
+        /// 出力ログのマスタークロック (Hz)。音程はこのクロックに合わせて補正
+        #[arg(long, value_name = "HZ", default_value_t = YM2151_CLOCK)]
+        clock: u32,
+    },
+    /// 冗長・無効なレジスタ書き込みを削除 (削除前後の波形が完全一致することを検証)
+    Optimize {
+        /// 入力ファイルのパス
+        #[arg(value_name = "INPUT")]
+        input: String,
+
+        /// 出力ファイルのパス (形式は拡張子で判別)
+        #[arg(value_name = "OUTPUT")]
+        output: String,
+    },
+    /// ログで使われている音色を一覧表示 (VOPM .opm 形式で保存可)
+    Voices {
+        /// 入力ファイルのパス (.json / .vgm / .vgz / .s98 / .mdx / .mid)
+        #[arg(value_name = "INPUT")]
+        input: String,
+
+        /// 抽出した音色を保存する VOPM 音色バンクのパス
+        #[arg(long, value_name = "OPM_FILE")]
+        output: Option<String>,
+    },
+    /// ログをレジスタの意味を注記したテキストに逆アセンブル
+    Disasm {
+        /// 入力ファイルのパス (.json / .vgm / .vgz / .s98 / .mdx / .mid)
+        #[arg(value_name = "INPUT")]
+        input: String,
+
+        /// 出力テキストファイルのパス (未指定時は標準出力)
+        #[arg(long, value_name = "TEXT_FILE")]
+        output: Option<String>,
+    },
+    /// 逆アセンブルしたテキストをログに戻す
+    Asm {
+        /// 入力テキストファイルのパス
+        #[arg(value_name = "INPUT")]
+        input: String,
+
+        /// 出力ファイルのパス (形式は拡張子で判別)
+        #[arg(value_name = "OUTPUT")]
+        output: String,
+    },
+    /// 指定した時間範囲を切り出す (開始時点のレジスタ状態を先頭に補う)
+    Slice {
+        /// 入力ファイルのパス
+        #[arg(value_name = "INPUT")]
+        input: String,
+
+        /// 出力ファイルのパス (形式は拡張子で判別)
+        #[arg(value_name = "OUTPUT")]
+        output: String,
+
+        /// 開始時刻 (秒)
+        #[arg(long, value_name = "SEC", default_value_t = 0.0)]
+        start: f64,
+
+        /// 終了時刻 (秒)。未指定時は最後まで
+        #[arg(long, value_name = "SEC")]
+        end: Option<f64>,
+    },
+    /// 全イベントの時刻をずらす
+    Shift {
+        /// 入力ファイルのパス
+        #[arg(value_name = "INPUT")]
+        input: String,
+
+        /// 出力ファイルのパス (形式は拡張子で判別)
+        #[arg(value_name = "OUTPUT")]
+        output: String,
+
+        /// ずらす秒数 (負の値で前に詰める)
+        #[arg(long, value_name = "SEC", allow_hyphen_values = true)]
+        by: f64,
+    },
+    /// 全イベントの時刻を倍率で伸縮 (0.5 で倍速)
+    Scale {
+        /// 入力ファイルのパス
+        #[arg(value_name = "INPUT")]
+        input: String,
+
+        /// 出力ファイルのパス (形式は拡張子で判別)
+        #[arg(value_name = "OUTPUT")]
+        output: String,
+
+        /// 時刻に掛ける倍率
+        #[arg(long, value_name = "FACTOR")]
+        factor: f64,
+    },
+    /// 複数のログを順番につなげる
+    Concat {
+        /// 入力ファイルのパス (演奏順)
+        #[arg(value_name = "INPUT", required = true, num_args = 1..)]
+        inputs: Vec<String>,
+
+        /// 出力ファイルのパス (形式は拡張子で判別)
+        #[arg(long, value_name = "OUTPUT")]
+        output: String,
+
+        /// ログの間に入れる無音 (秒)
+        #[arg(long, value_name = "SEC", default_value_t = 0.0)]
+        gap: f64,
+    },
+    /// 2つのログの違いをチャンネル・パラメータ別に表示
+    Diff {
+        /// 比較元ファイルのパス
+        #[arg(value_name = "OLD")]
+        old: String,
+
+        /// 比較先ファイルのパス
+        #[arg(value_name = "NEW")]
+        new: String,
+
+        /// 同じ書き込みとみなす時刻のずれ (秒)
+        #[arg(long, value_name = "SEC", default_value_t = diff::DEFAULT_TIME_EPSILON)]
+        epsilon: f64,
+
+        /// 両方をレンダリングし、波形が最初に異なるサンプルを表示
+        #[arg(long)]
+        render: bool,
+    },
+    /// ログをWAV/FLACファイルにレンダリング (サーバー・音声デバイス不要、実時間より高速)
+    Render {
+        /// 入力ファイルのパス (.json / .vgm / .vgz / .s98 / .mdx / .mid)
+        #[arg(value_name = "INPUT")]
+        input: String,
+
+        /// 出力ファイルのパス (.flac なら FLAC、それ以外は WAV)
+        #[arg(short, long, value_name = "FILE", default_value = wav_writer::DEFAULT_OUTPUT_FILENAME)]
+        output: String,
+
+        /// 量子化ビット数: 16 / 24 / 32f (32ビット浮動小数点、WAVのみ)
+        #[arg(long, value_name = "BITS", default_value = "16")]
+        bits: String,
+
+        /// 16ビットに落とす際に TPDF ディザを加える
+        #[arg(long)]
+        dither: bool,
+
+        /// 出力サンプルレート (未指定時は OPM のネイティブレート clock/64、例: 55930)
+        #[arg(long, value_name = "HZ")]
+        rate: Option<u32>,
+
+        /// 低品位リサンプリングを使用 (線形補間)
+        #[arg(long)]
+        low_quality_resampling: bool,
+
+        /// 最後のイベント後に生成する余韻の最大長 (秒)
+        #[arg(long, value_name = "SEC", default_value_t = MAX_TAIL_SECONDS as f64)]
+        tail: f64,
+
+        /// この長さ無音が続いたら余韻を打ち切る (ミリ秒)
+        #[arg(long, value_name = "MS", default_value_t = SILENCE_DURATION_MS)]
+        silence_ms: u32,
+
+        /// 開始時刻 (秒、ループ展開後の時刻)
+        #[arg(long, value_name = "SEC", default_value_t = 0.0)]
+        start: f64,
+
+        /// 終了時刻 (秒、未指定時は最後まで)
+        #[arg(long, value_name = "SEC")]
+        end: Option<f64>,
+
+        /// ループ区間の演奏回数 (ループ点がなければ曲全体を繰り返す)
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        loops: u32,
+
+        /// チャンネルごとのステム (<出力名>_ch0〜_ch7) もミックスと合わせて出力
+        #[arg(long)]
+        stems: bool,
+
+        /// ステムを1つの16チャンネルWAV (<出力名>_stems.wav) にまとめる
+        #[arg(long, requires = "stems")]
+        multichannel: bool,
+
+        /// ステムの合計とミックスの差の許容値 (信号レベルに対する dB)
+        #[arg(long, value_name = "DB", default_value_t = stems::DEFAULT_SUM_TOLERANCE_DB, allow_negative_numbers = true)]
+        stem_tolerance: f64,
+    },
+    /// 複数のログを同時に鳴らす (重なるチャンネルは空きチャンネルに移動)
+    Merge {
+        /// 入力ファイルのパス
+        #[arg(value_name = "INPUT", required = true, num_args = 1..)]
+        inputs: Vec<String>,
+
+        /// 出力ファイルのパス (形式は拡張子で判別)
+        #[arg(long, value_name = "OUTPUT")]
+        output: String,
+    },
     /// 最新版へ更新
     Update,
 }
@@ -72,7 +303,7 @@ fn print_usage() {
     eprintln!("使用方法:");
     eprintln!("  ym2151-log-play-server check                                                  # 更新の有無を確認");
     eprintln!(
-        "  ym2151-log-play-server server [--verbose] [--low-quality-resampling] [--demo-interactive] [--demo-non-interactive]  # サーバーとして起動"
+        "  ym2151-log-play-server server [--verbose] [--low-quality-resampling] [--clock <HZ>] [--demo-interactive] [--demo-non-interactive]  # サーバーとして起動"
     );
     eprintln!(
         "  ym2151-log-play-server client <json_file> [--verbose] [--demo-interactive]  # サーバーに演奏指示"
@@ -81,6 +312,21 @@ fn print_usage() {
     eprintln!(
         "  ym2151-log-play-server client --shutdown [--verbose]   # サーバーをシャットダウン"
     );
+    eprintln!("  ym2151-log-play-server convert <input> <output>        # ログファイルを変換 (.json/.vgm/.vgz/.s98/.mdx/.mid)");
+    eprintln!("  ym2151-log-play-server midi <input.mid> <output> [--voices <bank.json|bank.opm>] [--clock <HZ>]  # MIDIファイルを変換");
+    eprintln!(
+        "  ym2151-log-play-server voices <input> [--output <bank.opm>]  # 使われている音色を抽出"
+    );
+    eprintln!("  ym2151-log-play-server optimize <input> <output>       # 冗長な書き込みを削除");
+    eprintln!("  ym2151-log-play-server disasm <input> [--output <file.txt>]  # レジスタ注記付きテキストに逆アセンブル");
+    eprintln!("  ym2151-log-play-server asm <input.txt> <output>        # テキストをログに戻す");
+    eprintln!("  ym2151-log-play-server slice <input> <output> [--start <SEC>] [--end <SEC>]  # 時間範囲を切り出す");
+    eprintln!("  ym2151-log-play-server shift <input> <output> --by <SEC>    # 時刻をずらす");
+    eprintln!("  ym2151-log-play-server scale <input> <output> --factor <F>  # 時刻を伸縮");
+    eprintln!("  ym2151-log-play-server concat <inputs>... --output <file> [--gap <SEC>]  # ログを順番につなげる");
+    eprintln!("  ym2151-log-play-server merge <inputs>... --output <file>   # ログを同時に鳴らす");
+    eprintln!("  ym2151-log-play-server diff <old> <new> [--epsilon <SEC>] [--render]  # ログの違いを表示");
+    eprintln!("  ym2151-log-play-server render <input> [-o <out.wav|out.flac>] [--bits <16|24|32f>] [--dither] [--rate <HZ>] [--low-quality-resampling] [--tail <SEC>] [--silence-ms <MS>] [--start <SEC>] [--end <SEC>] [--loops <N>] [--stems [--multichannel] [--stem-tolerance <DB>]]  # WAV/FLACにレンダリング");
     eprintln!("  ym2151-log-play-server update                                                 # 最新版へ更新");
     eprintln!();
     eprintln!("例:");
@@ -89,22 +335,58 @@ fn print_usage() {
     eprintln!("  ym2151-log-play-server server --verbose");
     eprintln!("  ym2151-log-play-server server --low-quality-resampling");
     eprintln!("  ym2151-log-play-server server --verbose --low-quality-resampling");
+    eprintln!("  ym2151-log-play-server server --clock 4000000");
     eprintln!("  ym2151-log-play-server server --demo-interactive");
     eprintln!("  ym2151-log-play-server server --demo-non-interactive");
     eprintln!("  ym2151-log-play-server client test_input.json");
     eprintln!("  ym2151-log-play-server client test_input.json --verbose");
+    eprintln!("  ym2151-log-play-server client music.vgz");
     eprintln!("  ym2151-log-play-server client --stop");
     eprintln!("  ym2151-log-play-server client --shutdown");
     eprintln!("  ym2151-log-play-server client --demo-interactive");
+    eprintln!("  ym2151-log-play-server convert test_input.json output.vgm");
+    eprintln!("  ym2151-log-play-server convert song.mdx song.json");
+    eprintln!("  ym2151-log-play-server convert song.vgm song.mid");
+    eprintln!("  ym2151-log-play-server midi sketch.mid sketch.json --voices voices.json");
+    eprintln!("  ym2151-log-play-server voices song.vgm --output song.opm");
+    eprintln!("  ym2151-log-play-server optimize song.json song_opt.json");
+    eprintln!("  ym2151-log-play-server disasm song.vgm --output song.txt");
+    eprintln!("  ym2151-log-play-server asm song.txt song.json");
+    eprintln!("  ym2151-log-play-server slice song.vgm intro.json --start 12.5 --end 20");
+    eprintln!(
+        "  ym2151-log-play-server concat intro.json loop.json --gap 0.5 --output medley.json"
+    );
+    eprintln!("  ym2151-log-play-server merge bass.json melody.json --output band.json");
+    eprintln!("  ym2151-log-play-server diff before.json after.json --render");
+    eprintln!("  ym2151-log-play-server render song.vgm -o song.wav --rate 48000 --loops 2");
+    eprintln!("  ym2151-log-play-server render song.vgm -o song.flac --bits 24 --rate 96000");
+    eprintln!("  ym2151-log-play-server render song.vgm -o song.wav --stems --multichannel");
     eprintln!("  ym2151-log-play-server update");
     eprintln!();
     eprintln!("機能:");
     eprintln!("  - サーバー/クライアントモード (Windows)");
     eprintln!("  - GitHub からの更新確認/自己更新");
     eprintln!("  - JSONイベントログファイルを読み込み");
+    eprintln!("  - VGM/VGZ ファイルを読み込み (YM2151 部分のみ)");
+    eprintln!("  - S98 (v3) ファイルを読み込み (OPM デバイスのみ)");
+    eprintln!("  - MDX (X68000 MXDRV) ファイルを内蔵ドライバで演奏 (FM チャンネルのみ)");
+    eprintln!("  - MIDI ファイルを OPM 8 チャンネルで演奏 (音色バンク指定可)");
+    eprintln!("  - ログから音色を抽出し VOPM .opm 音色バンクに保存");
+    eprintln!("  - 冗長・無効なレジスタ書き込みを削除 (出力波形が変わらないことを検証)");
+    eprintln!("  - ログをレジスタの意味を注記したテキストに逆アセンブル (編集して再アセンブル可)");
+    eprintln!("  - ログの切り出し・時刻移動・伸縮・連結・重ね合わせ (チャンネル自動割り当て)");
+    eprintln!("  - 2つのログの差分をチャンネル・パラメータ別に表示 (波形の分岐点も検出可)");
+    eprintln!("  - JSONイベントログを VGM 1.71 / S98 v3 / MIDI ファイルに変換 (MIDI は採譜用)");
     eprintln!("  - YM2151レジスタ操作を再現");
     eprintln!("  - リアルタイム音声再生");
     eprintln!("  - WAVファイル (output.wav) を生成 (verbose時)");
+    eprintln!(
+        "  - ログをWAV/FLACファイルにオフラインレンダリング (Linux等でも音声デバイスなしで動作)"
+    );
+    eprintln!(
+        "    16/24ビット整数・32ビット浮動小数点WAV、16/24ビットFLAC (16ビット時はディザ指定可)"
+    );
+    eprintln!("    チャンネルごとのステム出力 (合計がミックスと一致するか検証、多チャンネルWAV可)");
     eprintln!();
     eprintln!("サーバーオプション:");
     eprintln!(
@@ -115,6 +397,8 @@ fn print_usage() {
     eprintln!(
         "                            デフォルトは高品位リサンプリング (Rubato FFTベース、折り返しノイズを低減)"
     );
+    eprintln!("  --clock <HZ>              YM2151のマスタークロック (デフォルト: 3579545)");
+    eprintln!("                            X68000等は4000000。JSONのclock指定が優先されます");
     eprintln!(
         "  --demo-interactive        インタラクティブデモモード (output_ym2151.jsonを使用してサーバー単体テスト)"
     );
@@ -182,10 +466,16 @@ fn main() {
             low_quality_resampling,
             demo_interactive,
             demo_non_interactive,
+            clock,
         } => {
             // Initialize logging with verbose flag
             logging::init(verbose);
 
+            if let Err(e) = check_clock(clock) {
+                logging::log_always_server(&format!("❌ エラー: --clock: {}", e));
+                std::process::exit(1);
+            }
+
             if demo_interactive && demo_non_interactive {
                 logging::log_always_server("❌ エラー: --demo-interactive と --demo-non-interactive は同時に使用できません");
                 std::process::exit(1);
@@ -222,7 +512,7 @@ fn main() {
                 }
             } else {
                 // Run normal server mode
-                let server = Server::new_with_resampling_quality(low_quality_resampling);
+                let server = Server::new_with_options(low_quality_resampling, clock);
                 match server.run() {
                     Ok(_) => {
                         std::process::exit(0);
@@ -290,29 +580,20 @@ fn main() {
                     }
                 }
             } else if let Some(json_path) = json_file {
-                // Read JSON file content
-                match std::fs::read_to_string(&json_path) {
-                    Ok(json_content) => match client::send_json(&json_content) {
-                        Ok(_) => {
-                            std::process::exit(0);
-                        }
-                        Err(e) => {
-                            logging::log_always_server(&format!(
-                                "❌ エラー: JSONファイルの送信に失敗しました: {}",
-                                e
-                            ));
-                            logging::log_always_server(
-                                "   サーバーが起動しているか確認してください",
-                            );
-                            std::process::exit(1);
-                        }
-                    },
+                // JSON is sent as-is; VGM/VGZ is converted to JSON first
+                match client::send_file(&json_path) {
+                    Ok(_) => {
+                        std::process::exit(0);
+                    }
                     Err(e) => {
                         logging::log_always_server(&format!(
-                            "❌ エラー: JSONファイルの読み込みに失敗しました: {}",
+                            "❌ エラー: ファイルの送信に失敗しました: {:#}",
                             e
                         ));
                         logging::log_always_server(&format!("   ファイルパス: {}", json_path));
+                        logging::log_always_server(
+                            "   ファイル形式と、サーバーが起動しているか確認してください",
+                        );
                         std::process::exit(1);
                     }
                 }
@@ -322,6 +603,311 @@ fn main() {
                 std::process::exit(1);
             }
         }
+        Commands::Convert { input, output } => {
+            let log = load_log_or_exit(&input);
+            save_edited_or_exit(&input, &output, Ok(log));
+        }
+        Commands::Midi {
+            input,
+            output,
+            voices,
+            clock,
+        } => {
+            if let Err(e) = check_clock(clock) {
+                eprintln!("❌ エラー: --clock: {}", e);
+                std::process::exit(1);
+            }
+            let voice_bank = match voices.as_deref().map(smf::VoiceBank::from_file) {
+                Some(Ok(bank)) => bank,
+                Some(Err(e)) => {
+                    eprintln!("❌ エラー: 音色バンクの読み込みに失敗しました: {:#}", e);
+                    std::process::exit(1);
+                }
+                None => smf::VoiceBank::default(),
+            };
+            let options = smf::SmfOptions { voice_bank, clock };
+            let imported = match std::fs::read(&input)
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| smf::import(&bytes, &options))
+            {
+                Ok(imported) => imported,
+                Err(e) => {
+                    eprintln!("❌ エラー: MIDIファイルの変換に失敗しました: {:#}", e);
+                    std::process::exit(1);
+                }
+            };
+            for warning in imported.warnings() {
+                eprintln!("⚠️  {}", warning);
+            }
+            save_edited_or_exit(&input, &output, Ok(imported.log));
+        }
+        Commands::Optimize { input, output } => {
+            let log = load_log_or_exit(&input);
+            let optimized = match optimizer::optimize(&log) {
+                Ok(optimized) => optimized,
+                Err(e) => {
+                    eprintln!("❌ エラー: 最適化に失敗しました: {:#}", e);
+                    std::process::exit(1);
+                }
+            };
+            let stats = optimized.stats;
+            if stats.dead_writes_kept {
+                eprintln!(
+                    "⚠️  同時刻の上書きを削除すると波形が変わるため、無効な書き込みは残しました"
+                );
+            }
+            if let Err(e) = formats::save_event_log(&output, &optimized.log) {
+                eprintln!("❌ エラー: ファイルの書き込みに失敗しました: {:#}", e);
+                std::process::exit(1);
+            }
+            eprintln!(
+                "✅ {} → {} ({} → {}個のイベント、冗長 {}、無効 {} を削除、波形一致を確認)",
+                input,
+                output,
+                stats.original_events,
+                stats.remaining(),
+                stats.redundant_writes,
+                stats.dead_writes
+            );
+            std::process::exit(0);
+        }
+        Commands::Voices { input, output } => {
+            let log = load_log_or_exit(&input);
+            let extracted = voice::extract_voices(&log);
+            for entry in &extracted {
+                let mut channels: Vec<u8> = entry.uses.iter().map(|u| u.channel).collect();
+                channels.sort_unstable();
+                channels.dedup();
+                let times: Vec<String> = entry
+                    .uses
+                    .iter()
+                    .map(|u| format!("{:.3}s/ch{}", u.time, u.channel))
+                    .collect();
+                println!(
+                    "@:{} ALG {} FB {} ch{:?} {}回: {}",
+                    entry.voice.number,
+                    entry.voice.alg,
+                    entry.voice.fb,
+                    channels,
+                    entry.uses.len(),
+                    times.join(" ")
+                );
+            }
+            if let Some(output) = output {
+                let voices: Vec<_> = extracted.into_iter().map(|entry| entry.voice).collect();
+                if let Err(e) = voice::save_opm(&output, &voices) {
+                    eprintln!("❌ エラー: ファイルの書き込みに失敗しました: {:#}", e);
+                    std::process::exit(1);
+                }
+                eprintln!("✅ {} → {} ({}個の音色)", input, output, voices.len());
+            } else {
+                eprintln!("✅ {}個の音色", extracted.len());
+            }
+            std::process::exit(0);
+        }
+        Commands::Disasm { input, output } => {
+            let log = load_log_or_exit(&input);
+            let text = disasm::disassemble(&log);
+            match output {
+                Some(output) => {
+                    if let Err(e) = std::fs::write(&output, text) {
+                        eprintln!("❌ エラー: ファイルの書き込みに失敗しました: {}", e);
+                        std::process::exit(1);
+                    }
+                    eprintln!(
+                        "✅ {} → {} ({}個のイベント)",
+                        input,
+                        output,
+                        log.events.len()
+                    );
+                }
+                None => print!("{}", text),
+            }
+            std::process::exit(0);
+        }
+        Commands::Asm { input, output } => {
+            let log = match std::fs::read_to_string(&input)
+                .map_err(anyhow::Error::from)
+                .and_then(|text| disasm::assemble(&text))
+            {
+                Ok(log) => log,
+                Err(e) => {
+                    eprintln!("❌ エラー: アセンブルに失敗しました: {:#}", e);
+                    std::process::exit(1);
+                }
+            };
+            save_edited_or_exit(&input, &output, Ok(log));
+        }
+        Commands::Slice {
+            input,
+            output,
+            start,
+            end,
+        } => {
+            let log = load_log_or_exit(&input);
+            let sliced = edit::slice(&log, start, end.unwrap_or(f64::INFINITY));
+            save_edited_or_exit(&input, &output, sliced);
+        }
+        Commands::Shift { input, output, by } => {
+            let log = load_log_or_exit(&input);
+            save_edited_or_exit(&input, &output, edit::shift(&log, by));
+        }
+        Commands::Scale {
+            input,
+            output,
+            factor,
+        } => {
+            let log = load_log_or_exit(&input);
+            save_edited_or_exit(&input, &output, edit::scale(&log, factor));
+        }
+        Commands::Concat {
+            inputs,
+            output,
+            gap,
+        } => {
+            let logs: Vec<EventLog> = inputs.iter().map(|path| load_log_or_exit(path)).collect();
+            save_edited_or_exit(&inputs.join(" + "), &output, edit::concat(&logs, gap));
+        }
+        Commands::Merge { inputs, output } => {
+            let logs: Vec<EventLog> = inputs.iter().map(|path| load_log_or_exit(path)).collect();
+            save_edited_or_exit(&inputs.join(" + "), &output, edit::merge(&logs));
+        }
+        Commands::Diff {
+            old,
+            new,
+            epsilon,
+            render,
+        } => {
+            let old_log = load_log_or_exit(&old);
+            let new_log = load_log_or_exit(&new);
+            let options = diff::DiffOptions {
+                time_epsilon: epsilon,
+            };
+            let result = diff::diff(&old_log, &new_log, &options);
+            print!("{}", result.report());
+            if render {
+                match diff::first_divergence(&old_log, &new_log) {
+                    Ok(Some(divergence)) => println!(
+                        "PCM: {} サンプル目 ({:.6} 秒) で波形が異なります",
+                        divergence.frame, divergence.time
+                    ),
+                    Ok(None) => println!("PCM: 波形は完全に一致します"),
+                    Err(e) => {
+                        eprintln!("❌ エラー: レンダリング比較に失敗しました: {:#}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            std::process::exit(0);
+        }
+        Commands::Render {
+            input,
+            output,
+            bits,
+            dither,
+            rate,
+            low_quality_resampling,
+            tail,
+            silence_ms,
+            start,
+            end,
+            loops,
+            stems,
+            multichannel,
+            stem_tolerance,
+        } => {
+            let format = match SampleFormat::from_name(&bits) {
+                Ok(format) => format,
+                Err(e) => {
+                    eprintln!("❌ エラー: {:#}", e);
+                    std::process::exit(1);
+                }
+            };
+            let dither = Dither::new(dither);
+            let log = load_log_or_exit(&input);
+            let options = RenderOptions {
+                sample_rate: rate,
+                quality: if low_quality_resampling {
+                    ResamplingQuality::Linear
+                } else {
+                    ResamplingQuality::HighQuality
+                },
+                max_tail_sec: tail,
+                silence_ms,
+                start,
+                end,
+                loops,
+            };
+            // Stems are written as they are rendered, before the mix
+            let stems = stems.then(|| {
+                let stem_output = stems::StemOutput {
+                    path: output.clone(),
+                    format,
+                    dither,
+                    multichannel,
+                };
+                match stems::render_stems(&log, &options, &stem_output) {
+                    Ok(stems) => stems,
+                    Err(e) => {
+                        eprintln!("❌ エラー: レンダリングに失敗しました: {:#}", e);
+                        std::process::exit(1);
+                    }
+                }
+            });
+            let rendered = match &stems {
+                Some(stems) => Cow::Borrowed(&stems.mix),
+                None => match render::render(&log, &options) {
+                    Ok(rendered) => Cow::Owned(rendered),
+                    Err(e) => {
+                        eprintln!("❌ エラー: レンダリングに失敗しました: {:#}", e);
+                        std::process::exit(1);
+                    }
+                },
+            };
+            if rendered.tail_truncated {
+                eprintln!("⚠️  余韻が{}秒を超えたため打ち切りました", tail);
+            }
+            if let Err(e) = render::write(&output, &rendered, format, dither) {
+                eprintln!("❌ エラー: ファイルの書き込みに失敗しました: {:#}", e);
+                std::process::exit(1);
+            }
+            eprintln!(
+                "✅ {} → {} ({:.2}秒, {} Hz)",
+                input,
+                output,
+                rendered.duration_sec(),
+                rendered.sample_rate
+            );
+
+            if let Some(stems) = &stems {
+                eprintln!("✅ ステム: {}", stems.paths.join(", "));
+                let check = stems::check_sum(&log, stems, stem_tolerance);
+                if check.is_ok() {
+                    eprintln!(
+                        "✅ ステムの合計はミックスと一致します (最大誤差 {:.0} LSB)",
+                        check.max_error_lsb
+                    );
+                } else {
+                    eprintln!("⚠️  ステムの合計がミックスと異なる区間があります:");
+                    for mismatch in &check.mismatches {
+                        eprintln!(
+                            "    {:.3}〜{:.3}秒: 最大誤差 {:.0} LSB ({:.1} dB)",
+                            mismatch.start_sec,
+                            mismatch.end_sec,
+                            mismatch.max_error_lsb,
+                            mismatch.max_error_db
+                        );
+                    }
+                    if !check.interactions.is_empty() {
+                        eprintln!(
+                            "    チャンネル間で共有される機能を使用: {}",
+                            check.interactions.join(", ")
+                        );
+                    }
+                }
+            }
+            std::process::exit(0);
+        }
         Commands::Update => match self_update_support::run_self_update() {
             Ok(_) => {
                 std::process::exit(0);
@@ -333,3 +919,45 @@ fn main() {
         },
     }
 }
+
+/// Load a log for the edit commands, exiting on failure
+fn load_log_or_exit(path: &str) -> EventLog {
+    match formats::load_event_log(path) {
+        Ok(loaded) => {
+            for warning in &loaded.warnings {
+                eprintln!("⚠️  {}: {}", path, warning);
+            }
+            loaded.log
+        }
+        Err(e) => {
+            eprintln!("❌ エラー: ファイルの読み込みに失敗しました: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Save the result of an edit command and exit
+fn save_edited_or_exit(input: &str, output: &str, edited: anyhow::Result<EventLog>) -> ! {
+    let log = match edited {
+        Ok(log) => log,
+        Err(e) => {
+            eprintln!("❌ エラー: 編集に失敗しました: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+    match formats::save_event_log(output, &log) {
+        Ok(()) => {
+            eprintln!(
+                "✅ {} → {} ({}個のイベント)",
+                input,
+                output,
+                log.events.len()
+            );
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("❌ エラー: ファイルの書き込みに失敗しました: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
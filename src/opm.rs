@@ -2,6 +2,44 @@ use crate::opm_ffi;
 use std::mem;
 use std::sync::atomic::{AtomicU64, Ordering};
 
+/// A chip write (port + data) applied just before the sample at `offset` is rendered
+pub type ChipWrite = opm_ffi::opm_write_t;
+
+/// Test register; its LFO reset bit restarts the LFO
+pub const REG_TEST: u8 = 0x01;
+/// Key on/off: channel in bits 0-2, operators (M1, C1, M2, C2) in bits 3-6
+pub const REG_KEY_ON: u8 = 0x08;
+/// Noise enable and frequency
+pub const REG_NOISE: u8 = 0x0F;
+/// Timer A period high bits (CLKA1, bits 9-2)
+pub const REG_CLKA1: u8 = 0x10;
+/// Timer A period low bits (CLKA2, bits 1-0)
+pub const REG_CLKA2: u8 = 0x11;
+/// Timer B period (CLKB)
+pub const REG_CLKB: u8 = 0x12;
+/// Timer control: CSM, flag reset, IRQ enable and load bits
+pub const REG_TIMER_CONTROL: u8 = 0x14;
+
+/// LFO depth: AMD, or PMD when bit 7 is set
+pub const REG_LFO_DEPTH: u8 = 0x19;
+
+/// Noise enable bit of `REG_NOISE`
+pub const NOISE_ENABLE: u8 = 0x80;
+
+/// Timer control bits for `REG_TIMER_CONTROL`
+pub const TIMER_LOAD_A: u8 = 0x01;
+pub const TIMER_LOAD_B: u8 = 0x02;
+pub const TIMER_IRQ_EN_A: u8 = 0x04;
+pub const TIMER_IRQ_EN_B: u8 = 0x08;
+pub const TIMER_RESET_A: u8 = 0x10;
+pub const TIMER_RESET_B: u8 = 0x20;
+pub const TIMER_CSM: u8 = 0x80;
+
+/// Status register bits returned by `OpmChip::read_status`
+pub const STATUS_TIMER_A: u8 = 0x01;
+pub const STATUS_TIMER_B: u8 = 0x02;
+pub const STATUS_BUSY: u8 = 0x80;
+
 static FFI_CALL_COUNTER: AtomicU64 = AtomicU64::new(0);
 
 pub struct OpmChip {
@@ -24,30 +62,114 @@ impl OpmChip {
     }
 
     pub fn generate_samples(&mut self, buffer: &mut [i16]) {
+        self.render(buffer, &[]);
+    }
+
+    /// Render a whole stereo buffer in a single FFI call
+    ///
+    /// `writes` are applied inside the C loop just before the sample at their
+    /// offset is clocked, so the per-sample hot loop never leaves C.
+    /// Offsets must be sorted; writes at or beyond the buffer end are ignored.
+    pub fn render(&mut self, buffer: &mut [i16], writes: &[ChipWrite]) {
         assert!(
             buffer.len().is_multiple_of(2),
             "Buffer length must be even for stereo output"
         );
+        debug_assert!(
+            writes.windows(2).all(|w| w[0].offset <= w[1].offset),
+            "Chip writes must be sorted by offset"
+        );
 
         let num_samples = buffer.len() / 2;
+        if num_samples == 0 {
+            return;
+        }
 
-        for i in 0..num_samples {
-            let mut output: [i32; 2] = [0; 2];
+        unsafe {
+            opm_ffi::opm_render(
+                &mut self.chip,
+                buffer.as_mut_ptr(),
+                num_samples as u32,
+                writes.as_ptr(),
+                writes.len() as u32,
+            );
+        }
 
-            unsafe {
-                opm_ffi::call_opm_clock_64times(&mut self.chip, output.as_mut_ptr());
-            }
+        FFI_CALL_COUNTER.fetch_add(1, Ordering::Relaxed);
+    }
 
-            FFI_CALL_COUNTER.fetch_add(1, Ordering::Relaxed);
+    /// Render a whole stereo buffer like [`render`](Self::render), keeping the
+    /// DAC output at full precision
+    ///
+    /// Samples are at twice the scale of the 16-bit output and are not clamped.
+    pub fn render_full(&mut self, buffer: &mut [i32], writes: &[ChipWrite]) {
+        assert!(
+            buffer.len().is_multiple_of(2),
+            "Buffer length must be even for stereo output"
+        );
+        debug_assert!(
+            writes.windows(2).all(|w| w[0].offset <= w[1].offset),
+            "Chip writes must be sorted by offset"
+        );
 
-            buffer[i * 2] = Self::convert_sample(output[0]);
-            buffer[i * 2 + 1] = Self::convert_sample(output[1]);
+        let num_samples = buffer.len() / 2;
+        if num_samples == 0 {
+            return;
+        }
+
+        unsafe {
+            opm_ffi::opm_render_full(
+                &mut self.chip,
+                buffer.as_mut_ptr(),
+                num_samples as u32,
+                writes.as_ptr(),
+                writes.len() as u32,
+            );
         }
+
+        FFI_CALL_COUNTER.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Read the status register (bit 7: busy, bit 1: timer B flag, bit 0: timer A flag)
+    pub fn read_status(&mut self) -> u8 {
+        unsafe { opm_ffi::OPM_Read(&mut self.chip, 1) }
+    }
+
+    /// Whether the chip is still processing the previous data write
+    pub fn is_busy(&mut self) -> bool {
+        self.read_status() & STATUS_BUSY != 0
+    }
+
+    /// Timer A overflow flag (only set while timer A IRQ is enabled)
+    pub fn timer_a_flag(&mut self) -> bool {
+        self.read_status() & STATUS_TIMER_A != 0
+    }
+
+    /// Timer B overflow flag (only set while timer B IRQ is enabled)
+    pub fn timer_b_flag(&mut self) -> bool {
+        self.read_status() & STATUS_TIMER_B != 0
     }
 
-    #[inline]
-    fn convert_sample(sample: i32) -> i16 {
-        (sample / 2).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+    /// State of the /IRQ output (true while either timer flag is set)
+    pub fn irq(&mut self) -> bool {
+        unsafe { opm_ffi::OPM_ReadIRQ(&mut self.chip) != 0 }
+    }
+
+    /// CT1 output pin (register 0x1B bit 6)
+    pub fn ct1(&mut self) -> bool {
+        unsafe { opm_ffi::OPM_ReadCT1(&mut self.chip) != 0 }
+    }
+
+    /// CT2 output pin (register 0x1B bit 7)
+    pub fn ct2(&mut self) -> bool {
+        unsafe { opm_ffi::OPM_ReadCT2(&mut self.chip) != 0 }
+    }
+
+    /// Drive the /IC (initial clear) input. Asserting it resets the chip while clocked.
+    pub fn set_ic(&mut self, asserted: bool) {
+        unsafe {
+            opm_ffi::OPM_SetIC(&mut self.chip, asserted as u8);
+        }
     }
 
     pub fn reset(&mut self) {
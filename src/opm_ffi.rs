@@ -3,6 +3,15 @@ pub struct opm_t {
     _private: [u8; 1396],
 }
 
+/// A register write applied at a sample offset within an `opm_render` call
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct opm_write_t {
+    pub offset: u32,
+    pub port: u8,
+    pub data: u8,
+}
+
 extern "C" {
 
     pub fn OPM_Reset(chip: *mut opm_t);
@@ -13,6 +22,22 @@ extern "C" {
 
     pub fn call_opm_clock_64times(chip: *mut opm_t, output: *mut i32);
 
+    pub fn opm_render(
+        chip: *mut opm_t,
+        buffer: *mut i16,
+        num_samples: u32,
+        writes: *const opm_write_t,
+        num_writes: u32,
+    );
+
+    pub fn opm_render_full(
+        chip: *mut opm_t,
+        buffer: *mut i32,
+        num_samples: u32,
+        writes: *const opm_write_t,
+        num_writes: u32,
+    );
+
     pub fn OPM_Read(chip: *mut opm_t, port: u32) -> u8;
 
     pub fn OPM_ReadIRQ(chip: *mut opm_t) -> u8;
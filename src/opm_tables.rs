@@ -0,0 +1,31 @@
+//! OPM pitch and level tables
+//!
+//! Shared by the importers, exporters and the disassembler, which all convert
+//! between key codes and notes, and between TL steps and decibels.
+
+/// KC note code (bits 0-3) for each semitone of an octave, counted from C#;
+/// codes 3, 7, 11 and 15 are unused
+pub const KEY_CODE_NOTES: [u8; 12] = [0, 1, 2, 4, 5, 6, 8, 9, 10, 12, 13, 14];
+/// MIDI note number of key code 0x00 (C#0) at the standard 3.579545 MHz clock
+pub const KEY_CODE_BASE_NOTE: i32 = 13;
+/// Key fraction steps per semitone (KF bits 2-7)
+pub const KF_STEPS: i32 = 64;
+/// Semitones covered by the 8 key code octaves
+pub const KEY_CODE_SEMITONES: i32 = 8 * 12;
+/// TL attenuation step in dB
+pub const TL_STEP_DB: f64 = 0.75;
+/// Largest TL value (silent)
+pub const MAX_TL: u8 = 0x7F;
+
+/// Key code for `semitone` semitones above C#0 (0 to [`KEY_CODE_SEMITONES`] - 1)
+pub fn key_code(semitone: u32) -> u8 {
+    ((semitone / 12) as u8) << 4 | KEY_CODE_NOTES[(semitone % 12) as usize]
+}
+
+/// Semitones above C#0 of a key code, or None for an unused note code
+pub fn key_code_semitone(key_code: u8) -> Option<u32> {
+    let note = KEY_CODE_NOTES
+        .iter()
+        .position(|&code| code == key_code & 0x0F)?;
+    Some((key_code >> 4 & 0x07) as u32 * 12 + note as u32)
+}
@@ -0,0 +1,282 @@
+//! Event log optimizer
+//!
+//! Removes register writes that cannot change the rendered output:
+//!
+//! - **redundant writes** set a register to the value it already holds
+//! - **dead writes** are overwritten by a later write to the same register
+//!   scheduled for the same sample, with no key-on/off write in between
+//!
+//! `Player` spaces writes 4 samples apart, so dropping a write would pull the
+//! rest of its burst earlier. Writes that were pushed back by the spacing are
+//! therefore moved to the sample they were actually issued at, and every
+//! remaining write lands exactly where it did before.
+//!
+//! A dead write still holds its value for the few samples until it is
+//! overwritten, which is only inaudible when its channel is silent. [`optimize`]
+//! renders the log before and after and keeps the dead writes if the PCM
+//! differs in any sample.
+
+use anyhow::{bail, Context, Result};
+
+use crate::events::{EventLog, RegisterEvent};
+use crate::formats::{player_write_samples, WRITE_SPACING_SAMPLES};
+use crate::opm::{REG_KEY_ON, REG_TEST, REG_TIMER_CONTROL};
+use crate::player::Player;
+use crate::register_shadow::RegisterShadow;
+use crate::resampler::{opm_sample_rate, YM2151_CLOCK};
+
+/// Registers whose writes have side effects beyond storing the value
+/// (test/LFO reset and timer load/flag reset), never removed
+const SIDE_EFFECT_REGISTERS: [u8; 2] = [REG_TEST, REG_TIMER_CONTROL];
+
+/// Rendered after the last write when comparing output, in seconds
+const VERIFY_TAIL_SEC: f64 = 0.5;
+const RENDER_CHUNK_FRAMES: usize = 4096;
+
+/// Which passes [`optimize_unverified`] runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptimizeOptions {
+    pub remove_redundant: bool,
+    pub remove_dead: bool,
+}
+
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        Self {
+            remove_redundant: true,
+            remove_dead: true,
+        }
+    }
+}
+
+/// What an optimization removed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OptimizeStats {
+    pub original_events: usize,
+    /// Writes of the value the register already held
+    pub redundant_writes: usize,
+    /// Writes overwritten at the same sample
+    pub dead_writes: usize,
+    /// Remaining writes moved to the sample the Player issued them at
+    pub retimed_events: usize,
+    /// Dead writes were found but kept because removing them changed the output
+    pub dead_writes_kept: bool,
+}
+
+impl OptimizeStats {
+    pub fn removed(&self) -> usize {
+        self.redundant_writes + self.dead_writes
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.original_events - self.removed()
+    }
+}
+
+/// An optimized log and what was removed from it
+#[derive(Debug, Clone)]
+pub struct Optimized {
+    pub log: EventLog,
+    pub stats: OptimizeStats,
+}
+
+/// Optimize a log and verify that it renders bit-exactly as before
+///
+/// Dead writes are only removed if the output stays identical; redundant
+/// writes always are.
+pub fn optimize(log: &EventLog) -> Result<Optimized> {
+    let optimized = optimize_unverified(log, OptimizeOptions::default())?;
+    let reference = Reference::render(log);
+    if optimized.stats.dead_writes > 0 && reference.compare(&optimized.log).is_ok() {
+        return Ok(optimized);
+    }
+
+    let found_dead = optimized.stats.dead_writes > 0;
+    let mut optimized = if found_dead {
+        optimize_unverified(
+            log,
+            OptimizeOptions {
+                remove_dead: false,
+                ..Default::default()
+            },
+        )?
+    } else {
+        optimized
+    };
+    reference
+        .compare(&optimized.log)
+        .context("Optimized log does not render identically")?;
+    optimized.stats.dead_writes_kept = found_dead;
+    Ok(optimized)
+}
+
+/// Run the optimization passes without rendering
+pub fn optimize_unverified(log: &EventLog, options: OptimizeOptions) -> Result<Optimized> {
+    log.check_playable()?;
+
+    let rate = opm_sample_rate(log.clock_or(YM2151_CLOCK));
+    let issued = player_write_samples(&log.events, rate);
+    let mut stats = OptimizeStats {
+        original_events: log.events.len(),
+        ..Default::default()
+    };
+
+    let dead = if options.remove_dead {
+        dead_writes(&log.events, rate)
+    } else {
+        vec![false; log.events.len()]
+    };
+
+    let mut shadow = RegisterShadow::default();
+    let mut kept = Vec::with_capacity(log.events.len());
+    for (index, event) in log.events.iter().enumerate() {
+        if dead[index] {
+            stats.dead_writes += 1;
+            continue;
+        }
+        let redundant =
+            !SIDE_EFFECT_REGISTERS.contains(&event.addr) && !shadow.store(event.addr, event.data);
+        if redundant && options.remove_redundant {
+            stats.redundant_writes += 1;
+            continue;
+        }
+        kept.push(index);
+    }
+
+    // Keep every remaining write on the sample it was issued at
+    let mut events = Vec::with_capacity(kept.len());
+    let mut next_write = 0;
+    for index in kept {
+        let mut event = log.events[index].clone();
+        let scheduled = scheduled_sample(event.time, rate);
+        if scheduled.max(next_write) != issued[index] {
+            event.time = issued[index] as f64 / rate as f64;
+            stats.retimed_events += 1;
+        }
+        next_write = issued[index] + WRITE_SPACING_SAMPLES;
+        events.push(event);
+    }
+
+    Ok(Optimized {
+        log: EventLog {
+            events,
+            ..log.clone()
+        },
+        stats,
+    })
+}
+
+fn scheduled_sample(time: f64, rate: u32) -> u64 {
+    (time * rate as f64).round() as u64
+}
+
+/// Writes overwritten by a later write to the same register at the same scheduled
+/// sample, with no key-on/off write between them
+fn dead_writes(events: &[RegisterEvent], rate: u32) -> Vec<bool> {
+    let mut dead = vec![false; events.len()];
+    let mut start = 0;
+    while start < events.len() {
+        let sample = scheduled_sample(events[start].time, rate);
+        let end = events[start..]
+            .iter()
+            .position(|event| scheduled_sample(event.time, rate) != sample)
+            .map_or(events.len(), |offset| start + offset);
+
+        for index in start..end {
+            let addr = events[index].addr;
+            if addr == REG_KEY_ON || SIDE_EFFECT_REGISTERS.contains(&addr) {
+                continue;
+            }
+            let key = RegisterShadow::key(addr, events[index].data);
+            for later in &events[index + 1..end] {
+                if later.addr == REG_KEY_ON {
+                    break;
+                }
+                if RegisterShadow::key(later.addr, later.data) == key {
+                    dead[index] = true;
+                    break;
+                }
+            }
+        }
+        start = end;
+    }
+    dead
+}
+
+/// Render a log at the native OPM rate (interleaved stereo) for `frames` samples
+pub fn render_native(log: &EventLog, frames: usize) -> Vec<i16> {
+    let mut player = Player::new(log.clone());
+    let mut output = Vec::with_capacity(frames * 2);
+    let mut buffer = vec![0i16; RENDER_CHUNK_FRAMES * 2];
+    while output.len() < frames * 2 {
+        player.generate_samples(&mut buffer);
+        output.extend_from_slice(&buffer);
+    }
+    output.truncate(frames * 2);
+    output
+}
+
+/// Check that two logs render the same PCM up to half a second after the last write
+pub fn verify_identical(a: &EventLog, b: &EventLog) -> Result<()> {
+    if a.clock_or(YM2151_CLOCK) != b.clock_or(YM2151_CLOCK) {
+        bail!("logs are for different clocks");
+    }
+    let frames = Reference::frames(a).max(Reference::frames(b));
+    Reference::render_frames(a, frames).compare(b)
+}
+
+/// First frame (at the native OPM rate) where two logs render differently, up
+/// to half a second after the last write of either
+pub fn first_divergence(a: &EventLog, b: &EventLog) -> Result<Option<usize>> {
+    if a.clock_or(YM2151_CLOCK) != b.clock_or(YM2151_CLOCK) {
+        bail!("logs are for different clocks");
+    }
+    let frames = Reference::frames(a).max(Reference::frames(b));
+    Ok(Reference::render_frames(a, frames).first_difference(b))
+}
+
+/// Rendered output of the original log, compared against candidates
+struct Reference {
+    pcm: Vec<i16>,
+    rate: u32,
+}
+
+impl Reference {
+    fn render(log: &EventLog) -> Self {
+        Self::render_frames(log, Self::frames(log))
+    }
+
+    fn render_frames(log: &EventLog, frames: usize) -> Self {
+        Self {
+            pcm: render_native(log, frames),
+            rate: opm_sample_rate(log.clock_or(YM2151_CLOCK)),
+        }
+    }
+
+    /// Samples up to the tail after the last write
+    fn frames(log: &EventLog) -> usize {
+        let rate = opm_sample_rate(log.clock_or(YM2151_CLOCK));
+        let last_write = player_write_samples(&log.events, rate)
+            .last()
+            .copied()
+            .unwrap_or(0);
+        (last_write as f64 + VERIFY_TAIL_SEC * rate as f64) as usize
+    }
+
+    fn compare(&self, log: &EventLog) -> Result<()> {
+        if let Some(frame) = self.first_difference(log) {
+            bail!(
+                "rendered output differs at sample {} ({:.6} s)",
+                frame,
+                frame as f64 / self.rate as f64
+            );
+        }
+        Ok(())
+    }
+
+    fn first_difference(&self, log: &EventLog) -> Option<usize> {
+        let pcm = render_native(log, self.pcm.len() / 2);
+        let index = self.pcm.iter().zip(&pcm).position(|(x, y)| x != y)?;
+        Some(index / 2)
+    }
+}
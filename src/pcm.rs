@@ -0,0 +1,179 @@
+//! Full-precision PCM and its conversion to output sample formats
+//!
+//! Offline rendering keeps the chip's DAC output as `f32` samples normalized so
+//! that 1.0 is 16-bit full scale. The DAC output has one bit more than the
+//! realtime 16-bit path keeps and can exceed full scale, so nothing is lost
+//! until the samples are written at the output's bit depth.
+
+use anyhow::{bail, Result};
+
+/// DAC output value of 16-bit full scale (the DAC runs at twice the 16-bit scale)
+const DAC_FULL_SCALE: f32 = 65536.0;
+const I16_SCALE: f64 = 32768.0;
+const I24_SCALE: f64 = 8_388_608.0;
+/// Seed of the dither noise, fixed so that renders are reproducible
+const DITHER_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+/// Step between the seeds of channels written separately (odd, so never 0)
+const DITHER_SEED_STEP: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Normalize a DAC output value from [`OpmChip::render_full`](crate::opm::OpmChip::render_full)
+pub fn from_dac(raw: i32) -> f32 {
+    raw as f32 / DAC_FULL_SCALE
+}
+
+/// Sample format of an output file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl SampleFormat {
+    /// Parse `16`, `24` or `32f` (also `float`)
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "16" => Ok(SampleFormat::Int16),
+            "24" => Ok(SampleFormat::Int24),
+            "32f" | "float" => Ok(SampleFormat::Float32),
+            _ => bail!("unknown sample format '{}' (use 16, 24 or 32f)", name),
+        }
+    }
+
+    /// The name [`SampleFormat::from_name`] parses back
+    pub fn name(self) -> &'static str {
+        match self {
+            SampleFormat::Int16 => "16",
+            SampleFormat::Int24 => "24",
+            SampleFormat::Float32 => "32f",
+        }
+    }
+
+    pub fn bits_per_sample(self) -> u16 {
+        match self {
+            SampleFormat::Int16 => 16,
+            SampleFormat::Int24 => 24,
+            SampleFormat::Float32 => 32,
+        }
+    }
+}
+
+/// How samples are reduced to 16 bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    /// Truncate, as the realtime path does
+    Off,
+    /// Add TPDF dither noise from a fixed seed
+    Tpdf { seed: u64 },
+}
+
+impl Dither {
+    /// TPDF dither if `enabled`, else truncation
+    pub fn new(enabled: bool) -> Self {
+        if enabled {
+            Dither::Tpdf { seed: DITHER_SEED }
+        } else {
+            Dither::Off
+        }
+    }
+
+    /// The same reduction for a chip channel written to a file of its own, with
+    /// noise independent of the other channels'
+    pub fn for_channel(self, channel: usize) -> Self {
+        match self {
+            Dither::Off => Dither::Off,
+            Dither::Tpdf { seed } => Dither::Tpdf {
+                seed: seed.wrapping_add((channel as u64 + 1).wrapping_mul(DITHER_SEED_STEP)),
+            },
+        }
+    }
+}
+
+/// Reduces samples to 16 bits, the dither noise running on from one call to the next
+pub struct I16Converter {
+    noise: Option<TpdfDither>,
+}
+
+impl I16Converter {
+    pub fn new(dither: Dither) -> Self {
+        Self {
+            noise: match dither {
+                Dither::Off => None,
+                Dither::Tpdf { seed } => Some(TpdfDither::new(seed)),
+            },
+        }
+    }
+
+    pub fn convert(&mut self, samples: &[f32]) -> Vec<i16> {
+        samples
+            .iter()
+            .map(|&sample| {
+                let scaled = sample as f64 * I16_SCALE;
+                let quantized = match &mut self.noise {
+                    Some(noise) => (scaled + noise.next()).round(),
+                    None => scaled.trunc(),
+                };
+                quantized.clamp(-I16_SCALE, I16_SCALE - 1.0) as i16
+            })
+            .collect()
+    }
+}
+
+/// Convert to 16 bits, with TPDF dither or by truncation as the realtime path does
+pub fn to_i16(samples: &[f32], dither: Dither) -> Vec<i16> {
+    I16Converter::new(dither).convert(samples)
+}
+
+/// Convert to 24 bits (rounded; the DAC output fits exactly)
+pub fn to_i24(samples: &[f32]) -> Vec<i32> {
+    samples
+        .iter()
+        .map(|&sample| {
+            (sample as f64 * I24_SCALE)
+                .round()
+                .clamp(-I24_SCALE, I24_SCALE - 1.0) as i32
+        })
+        .collect()
+}
+
+/// Convert to raw little-endian samples in `format` (24-bit samples take 3 bytes)
+pub fn to_le_bytes(samples: &[f32], format: SampleFormat, dither: Dither) -> Vec<u8> {
+    match format {
+        SampleFormat::Int16 => to_i16(samples, dither)
+            .into_iter()
+            .flat_map(i16::to_le_bytes)
+            .collect(),
+        SampleFormat::Int24 => to_i24(samples)
+            .into_iter()
+            .flat_map(|sample| {
+                let [b0, b1, b2, _] = sample.to_le_bytes();
+                [b0, b1, b2]
+            })
+            .collect(),
+        SampleFormat::Float32 => samples.iter().flat_map(|s| s.to_le_bytes()).collect(),
+    }
+}
+
+/// Triangular noise of ±1 LSB peak, the sum of two uniform random values
+struct TpdfDither {
+    state: u64,
+}
+
+impl TpdfDither {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next(&mut self) -> f64 {
+        self.uniform() - self.uniform()
+    }
+
+    /// Uniform in [0, 1) from xorshift64*
+    fn uniform(&mut self) -> f64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        let bits = self.state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (bits >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -1,18 +1,21 @@
-use crate::events::{EventLog, RegisterEvent};
-use crate::opm::OpmChip;
-use crate::resampler::OPM_SAMPLE_RATE;
-use std::collections::VecDeque;
-use std::sync::{Arc, Mutex};
-
+use crate::audio_config::buffer::GENERATION_BUFFER_SIZE;
+use crate::event_schedule::EventSchedule;
+use crate::event_stream::{event_stream, StreamReader, StreamWriter};
+use crate::events::{EventLog, Marker, RegisterEvent};
+use crate::opm::{ChipWrite, OpmChip};
+use crate::resampler::{opm_sample_rate, OPM_SAMPLE_RATE, YM2151_CLOCK};
+use crate::submission_ring::{
+    submission_ring, EventSubmitter, SubmissionOp, SubmissionReceiver, DEFAULT_RING_CAPACITY,
+};
 const OPM_ADDRESS_REGISTER: u8 = 0;
 const OPM_DATA_REGISTER: u8 = 1;
 
 const DELAY_SAMPLES: u32 = 2;
 
-const SILENCE_DURATION_MS: u32 = 100;
-const SILENCE_SAMPLES: u32 = SILENCE_DURATION_MS * OPM_SAMPLE_RATE / 1000;
+/// Silence after the last event that ends the tail, by default
+pub const SILENCE_DURATION_MS: u32 = 100;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ProcessedEvent {
     pub time: u32,
 
@@ -24,17 +27,29 @@ pub struct ProcessedEvent {
 pub struct Player {
     chip: OpmChip,
 
+    // Chip master clock in Hz and the resulting native sample rate (clock / 64)
+    clock: u32,
+    sample_rate: u32,
+
     // Static event playback (original mode)
     events: Vec<ProcessedEvent>,
     next_event_idx: usize,
 
     // Interactive mode support
     interactive_mode: bool,
-    scheduled_events: Arc<Mutex<VecDeque<ProcessedEvent>>>,
+    // Producer side of the submission ring, handed out to the IPC side
+    // (interactive mode only; static players never allocate the ring)
+    submitter: Option<EventSubmitter>,
+    // Consumer side, drained once per generate_samples() call
+    submissions: Option<SubmissionReceiver>,
+    // Generator-local schedule ordered by (time, insertion order), no lock needed
+    scheduled_events: EventSchedule,
 
     samples_played: u32,
 
     consecutive_silent_samples: u32,
+    // Silence after the last event that ends the tail
+    silence_duration_ms: u32,
 
     // Track last address register write for key on/off logging
     last_address_register: u8,
@@ -46,77 +61,115 @@ pub struct Player {
     // Track pending data write for addr-data pair processing
     // When Some, contains (data_value, scheduled_time) waiting to be written
     pending_data_write: Option<(u8, u32)>,
+
+    // Chip writes planned for the current buffer, handed to OpmChip::render in one call.
+    // At most one write is planned per sample, so with room for a whole generation
+    // buffer up front and reuse across buffers the realtime thread does not allocate.
+    planned_writes: Vec<ChipWrite>,
+
+    // Markers of a static log in time order with their sample times; markers
+    // before next_marker_idx are rendered past, and those from taken_marker_idx
+    // on are not yet handed out by take_reached_markers()
+    markers: Vec<Marker>,
+    marker_samples: Vec<u32>,
+    next_marker_idx: usize,
+    taken_marker_idx: usize,
+
+    // Streamed playback: static events arrive in chunks from the stream, and the
+    // event timeline runs stream_hold samples behind samples_played after waiting
+    // for the client
+    stream: Option<StreamReader>,
+    stream_hold: u32,
 }
 
 impl Player {
+    /// Create a Player for a static log, at the log's clock (or the default clock)
     pub fn new(log: EventLog) -> Self {
-        let events = Self::convert_events(&log.events);
-        Self {
-            chip: OpmChip::new(),
-            events,
-            next_event_idx: 0,
-            interactive_mode: false,
-            scheduled_events: Arc::new(Mutex::new(VecDeque::new())),
-            samples_played: 0,
-            consecutive_silent_samples: 0,
-            last_address_register: 0,
-            next_available_write_time: 0,
-            pending_data_write: None,
-        }
+        let clock = log.clock_or(YM2151_CLOCK);
+        let sample_rate = opm_sample_rate(clock);
+        let events = Self::convert_events_at(&log.events, sample_rate);
+        let mut player = Self::with_events(events, false, clock);
+        let mut markers: Vec<(u32, Marker)> = log
+            .markers
+            .into_iter()
+            .map(|marker| ((marker.time * sample_rate as f64).round() as u32, marker))
+            .collect();
+        markers.sort_by_key(|&(time, _)| time);
+        (player.marker_samples, player.markers) = markers.into_iter().unzip();
+        player
     }
 
     /// Create a new Player in interactive mode
     pub fn new_interactive() -> Self {
+        Self::new_interactive_with_clock(YM2151_CLOCK)
+    }
+
+    /// Create a new Player in interactive mode with the given chip master clock
+    pub fn new_interactive_with_clock(clock: u32) -> Self {
+        Self::with_events(Vec::new(), true, clock)
+    }
+
+    /// Create a Player for a log streamed in chunks through the returned writer
+    ///
+    /// Playback starts with the first chunk and finishes after the stream ends.
+    pub fn new_streaming_with_clock(clock: u32) -> (Self, StreamWriter) {
+        let mut player = Self::with_events(Vec::new(), false, clock);
+        let (writer, reader) = event_stream(player.sample_rate);
+        player.stream = Some(reader);
+        (player, writer)
+    }
+
+    fn with_events(events: Vec<ProcessedEvent>, interactive_mode: bool, clock: u32) -> Self {
+        let (submitter, submissions) = if interactive_mode {
+            let (submitter, submissions) = submission_ring(DEFAULT_RING_CAPACITY);
+            (Some(submitter), Some(submissions))
+        } else {
+            (None, None)
+        };
         Self {
             chip: OpmChip::new(),
-            events: Vec::new(),
+            clock,
+            sample_rate: opm_sample_rate(clock),
+            events,
             next_event_idx: 0,
-            interactive_mode: true,
-            scheduled_events: Arc::new(Mutex::new(VecDeque::new())),
+            interactive_mode,
+            submitter,
+            submissions,
+            scheduled_events: EventSchedule::new(),
             samples_played: 0,
             consecutive_silent_samples: 0,
+            silence_duration_ms: SILENCE_DURATION_MS,
             last_address_register: 0,
             next_available_write_time: 0,
             pending_data_write: None,
+            planned_writes: Vec::with_capacity(GENERATION_BUFFER_SIZE),
+            markers: Vec::new(),
+            marker_samples: Vec::new(),
+            next_marker_idx: 0,
+            taken_marker_idx: 0,
+            stream: None,
+            stream_hold: 0,
         }
     }
 
-    /// Get a handle to the scheduled events queue for interactive mode
-    pub fn get_event_queue(&self) -> Arc<Mutex<VecDeque<ProcessedEvent>>> {
-        self.scheduled_events.clone()
+    /// Get a producer handle to the submission ring (`None` outside interactive mode)
+    ///
+    /// The handle can be cloned and used from any thread without locking.
+    /// Submitted operations are merged into the schedule by the generator thread.
+    pub fn submitter(&self) -> Option<EventSubmitter> {
+        self.submitter.clone()
     }
 
     /// Add a register write to the interactive event queue
     pub fn schedule_register_write(&self, scheduled_time_samples: u32, addr: u8, data: u8) {
-        if !self.interactive_mode {
+        let Some(submitter) = &self.submitter else {
             return;
-        }
-
-        let mut queue = self.scheduled_events.lock().unwrap();
+        };
 
         // Store addr-data pair directly
         // The 2-sample delay between address and data writes will be applied
         // at the final stage in generate_samples()
-        queue.push_back(ProcessedEvent {
-            time: scheduled_time_samples,
-            addr,
-            data,
-        });
-
-        // Keep queue sorted by time
-        // Check if we need to sort (new event might be out of order)
-        let len = queue.len();
-        if len >= 2 {
-            // Check if the newly added event is out of order with existing events
-            let needs_sort = queue[len - 1].time < queue[len - 2].time;
-
-            if needs_sort {
-                // Need to sort - convert to vec, sort, and rebuild
-                let mut vec: Vec<_> = queue.drain(..).collect();
-                vec.sort_by_key(|e| e.time);
-                queue.extend(vec);
-            }
-        }
+        submitter.submit_write(scheduled_time_samples, addr, data);
     }
 
     /// Check if running in interactive mode
@@ -127,12 +180,9 @@ impl Player {
     /// Clear all scheduled events in interactive mode
     /// This allows seamless phrase transitions without audio gaps
     pub fn clear_schedule(&self) {
-        if !self.interactive_mode {
-            return;
+        if let Some(submitter) = &self.submitter {
+            submitter.submit(SubmissionOp::ClearAll);
         }
-
-        let mut queue = self.scheduled_events.lock().unwrap();
-        queue.clear();
     }
 
     /// Clear scheduled events from a specific sample time onwards
@@ -143,20 +193,66 @@ impl Player {
     /// # Arguments
     /// * `from_sample_time` - Sample time threshold; events at or after this time are removed
     pub fn clear_schedule_from(&self, from_sample_time: u32) {
-        if !self.interactive_mode {
+        if let Some(submitter) = &self.submitter {
+            submitter.submit(SubmissionOp::ClearFrom(from_sample_time));
+        }
+    }
+
+    /// Get a snapshot of the interactive schedule, in playback order
+    ///
+    /// Pending submissions are merged first, so the result reflects every
+    /// operation submitted before this call.
+    pub fn scheduled_events(&mut self) -> Vec<ProcessedEvent> {
+        self.drain_submissions();
+        self.scheduled_events.iter().collect()
+    }
+
+    /// Merge all ready submissions into the generator-local schedule
+    ///
+    /// Runs on the generator thread. Operations are applied in submission order,
+    /// so a clear followed by new writes behaves exactly as it did under the lock.
+    fn drain_submissions(&mut self) {
+        let Some(submissions) = &mut self.submissions else {
             return;
+        };
+        let mut changed = false;
+        while let Some(op) = submissions.pop() {
+            changed = true;
+            match op {
+                SubmissionOp::Write(event) => self.scheduled_events.push(event),
+                SubmissionOp::ClearFrom(from_sample_time) => {
+                    self.scheduled_events.clear_from(from_sample_time)
+                }
+                SubmissionOp::ClearAll => self.scheduled_events.clear(),
+            }
         }
 
-        let mut queue = self.scheduled_events.lock().unwrap();
-        queue.retain(|event| event.time < from_sample_time);
+        if changed {
+            submissions.publish_scheduled_count(self.scheduled_events.len());
+        }
     }
 
     pub fn convert_events(input: &[RegisterEvent]) -> Vec<ProcessedEvent> {
+        Self::convert_events_at(input, OPM_SAMPLE_RATE)
+    }
+
+    /// Convert events to sample times at the given OPM sample rate
+    pub fn convert_events_at(input: &[RegisterEvent], sample_rate: u32) -> Vec<ProcessedEvent> {
         let mut output = Vec::with_capacity(input.len());
+        Self::convert_events_into(input, sample_rate, &mut output);
+        output
+    }
 
+    /// Append events converted to sample times at the given OPM sample rate to `output`
+    pub fn convert_events_into(
+        input: &[RegisterEvent],
+        sample_rate: u32,
+        output: &mut Vec<ProcessedEvent>,
+    ) {
+        output.reserve(input.len());
         for event in input {
             // Convert time from f64 seconds to u32 samples
-            let time_samples = (event.time * OPM_SAMPLE_RATE as f64).round() as u32;
+            let time_samples = (event.time * sample_rate as f64).round() as u32;
 
             // Store addr-data pairs directly
             // The 2-sample delay between address and data writes will be applied
@@ -167,12 +263,53 @@ impl Player {
                 data: event.data,
             });
         }
-
-        output
     }
 
     pub fn generate_samples(&mut self, buffer: &mut [i16]) -> bool {
         let num_samples = buffer.len() / 2;
+        self.plan_buffer(num_samples);
+
+        let rendered = &mut buffer[..num_samples * 2];
+        self.chip.render(rendered, &self.planned_writes);
+        self.count_silence(
+            rendered
+                .chunks_exact(2)
+                .map(|frame| Self::is_sample_silent(frame[0], frame[1])),
+        );
+
+        self.finish_buffer()
+    }
+
+    /// Generate samples like [`generate_samples`](Self::generate_samples), keeping
+    /// the chip's DAC output at full precision
+    ///
+    /// Samples are at twice the 16-bit scale and not clamped; halving and
+    /// clamping them gives exactly what `generate_samples` would have produced.
+    pub fn generate_samples_full(&mut self, buffer: &mut [i32]) -> bool {
+        let num_samples = buffer.len() / 2;
+        self.plan_buffer(num_samples);
+
+        let rendered = &mut buffer[..num_samples * 2];
+        self.chip.render_full(rendered, &self.planned_writes);
+        // Silence as in the 16-bit output, so both end the tail at the same sample
+        self.count_silence(
+            rendered
+                .chunks_exact(2)
+                .map(|frame| frame[0] / 2 == 0 && frame[1] / 2 == 0),
+        );
+
+        self.finish_buffer()
+    }
+
+    /// Plan the chip writes of the next `num_samples` samples
+    fn plan_buffer(&mut self, num_samples: usize) {
+        // Pick up new interactive submissions once per buffer, outside the sample loop
+        if self.interactive_mode {
+            self.drain_submissions();
+        }
+
+        // Plan this buffer's chip writes at sample offsets, then render it in one FFI call
+        self.planned_writes.clear();
 
         for i in 0..num_samples {
             // First, check if we have a pending data write from a previous addr write
@@ -184,7 +321,7 @@ impl Player {
                         self.log_key_event_with_timing(data_value, scheduled_time);
                     }
 
-                    self.chip.write(OPM_DATA_REGISTER, data_value);
+                    self.plan_write(i, OPM_DATA_REGISTER, data_value);
                     self.next_available_write_time = self.samples_played + DELAY_SAMPLES;
                     self.pending_data_write = None;
                 }
@@ -192,34 +329,22 @@ impl Player {
 
             // Process events from the appropriate source
             if self.interactive_mode {
-                // Interactive mode: process from VecDeque
-                let mut queue = self.scheduled_events.lock().unwrap();
-                while let Some(event) = queue.front() {
-                    if event.time <= self.samples_played && self.pending_data_write.is_none() {
-                        let event = queue.pop_front().unwrap();
-
+                // Interactive mode: process from the generator-local schedule
+                while let Some(time) = self.scheduled_events.next_time() {
+                    if time <= self.samples_played && self.pending_data_write.is_none() {
                         // Apply 2-sample delay at final stage
                         // Ensure this write doesn't happen before next_available_write_time
                         if self.samples_played < self.next_available_write_time {
-                            // Not enough time has passed - re-queue this event for later
-                            let deferred_event = ProcessedEvent {
-                                time: self.next_available_write_time,
-                                addr: event.addr,
-                                data: event.data,
-                            };
-
-                            // Find the correct position to insert (maintain sorted order)
-                            let insert_pos = queue
-                                .iter()
-                                .position(|e| e.time > self.next_available_write_time)
-                                .unwrap_or(queue.len());
-                            queue.insert(insert_pos, deferred_event);
-                            continue;
+                            // Not enough time has passed - leave it at the front and wait,
+                            // so same-time writes keep their submission order
+                            break;
                         }
 
+                        let event = self.scheduled_events.pop_first().unwrap();
+
                         // Write address register first
                         self.last_address_register = event.addr;
-                        self.chip.write(OPM_ADDRESS_REGISTER, event.addr);
+                        self.plan_write(i, OPM_ADDRESS_REGISTER, event.addr);
                         self.next_available_write_time = self.samples_played + DELAY_SAMPLES;
 
                         // Schedule data write for later (after 2-sample delay)
@@ -229,11 +354,15 @@ impl Player {
                     }
                 }
             } else {
-                // Static mode: process from Vec
+                // Static mode: process from Vec, on the (possibly held) event timeline
+                let timeline = self.samples_played - self.stream_hold;
+                if self.next_event_idx >= self.events.len() {
+                    self.next_stream_chunk();
+                }
                 while self.next_event_idx < self.events.len() && self.pending_data_write.is_none() {
-                    let event = &self.events[self.next_event_idx];
+                    let event = self.events[self.next_event_idx].clone();
 
-                    if event.time <= self.samples_played {
+                    if event.time <= timeline {
                         // Apply 2-sample delay at final stage
                         // Ensure this write doesn't happen before next_available_write_time
                         if self.samples_played < self.next_available_write_time {
@@ -243,7 +372,7 @@ impl Player {
 
                         // Write address register first
                         self.last_address_register = event.addr;
-                        self.chip.write(OPM_ADDRESS_REGISTER, event.addr);
+                        self.plan_write(i, OPM_ADDRESS_REGISTER, event.addr);
                         self.next_available_write_time = self.samples_played + DELAY_SAMPLES;
 
                         // Schedule data write for later (after 2-sample delay)
@@ -254,31 +383,59 @@ impl Player {
                         break;
                     }
                 }
-            }
-
-            let sample_buffer = &mut buffer[i * 2..(i + 1) * 2];
-            self.chip.generate_samples(sample_buffer);
 
-            let left = sample_buffer[0];
-            let right = sample_buffer[1];
-            if Self::is_sample_silent(left, right) {
-                self.consecutive_silent_samples += 1;
-            } else {
-                self.consecutive_silent_samples = 0;
+                // Wait for the client at the last event received
+                if let Some(stream) = &mut self.stream {
+                    if !stream.is_ended()
+                        && self.next_event_idx >= self.events.len()
+                        && timeline >= stream.received_until()
+                    {
+                        self.stream_hold += 1;
+                        stream.wait_sample();
+                    }
+                }
             }
 
             self.samples_played += 1;
         }
 
+        if let Some(stream) = &self.stream {
+            stream.publish_playhead(self.samples_played - self.stream_hold);
+        }
+    }
+
+    /// Collect the markers rendered past and report whether static playback has
+    /// more to play
+    fn finish_buffer(&mut self) -> bool {
+        while let Some(&time) = self.marker_samples.get(self.next_marker_idx) {
+            if time >= self.samples_played {
+                break;
+            }
+            self.next_marker_idx += 1;
+        }
+
         // In interactive mode, always return true (continuous streaming)
         // In static mode, return whether there are more events or pending writes
         if self.interactive_mode {
+            if let Some(submissions) = &self.submissions {
+                submissions.publish_scheduled_count(self.scheduled_events.len());
+            }
             true
         } else {
             self.next_event_idx < self.events.len() || self.pending_data_write.is_some()
         }
     }
 
+    /// Plan a chip port write at `offset` samples into the current buffer
+    #[inline]
+    fn plan_write(&mut self, offset: usize, port: u8, data: u8) {
+        self.planned_writes.push(ChipWrite {
+            offset: offset as u32,
+            port,
+            data,
+        });
+    }
+
     /// Log key on/off events for debugging with timing comparison
     fn log_key_event_with_timing(&self, key_data: u8, scheduled_time: u32) {
         use crate::logging;
@@ -289,20 +446,20 @@ impl Player {
         // Key off: bit3-7 are all 0 (data value 0-7)
         // Key on: any of bit3-7 is 1 (data value 8 or higher)
 
-        let samples_sec = self.samples_played as f64 / crate::resampler::OPM_SAMPLE_RATE as f64;
+        let samples_sec = self.samples_played as f64 / self.sample_rate as f64;
         let samples_str = format!("{:.6}", samples_sec)
             .trim_end_matches('0')
             .trim_end_matches('.')
             .to_string();
 
-        let scheduled_sec = scheduled_time as f64 / crate::resampler::OPM_SAMPLE_RATE as f64;
+        let scheduled_sec = scheduled_time as f64 / self.sample_rate as f64;
         let scheduled_str = format!("{:.6}", scheduled_sec)
             .trim_end_matches('0')
             .trim_end_matches('.')
             .to_string();
 
         let delay_samples = self.samples_played.saturating_sub(scheduled_time);
-        let delay_sec = delay_samples as f64 / crate::resampler::OPM_SAMPLE_RATE as f64;
+        let delay_sec = delay_samples as f64 / self.sample_rate as f64;
         let delay_str = format!("{:.6}", delay_sec)
             .trim_end_matches('0')
             .trim_end_matches('.')
@@ -322,6 +479,28 @@ impl Player {
         }
     }
 
+    /// Replace the events played to the end with the next chunk of the stream
+    fn next_stream_chunk(&mut self) {
+        let Some(stream) = &mut self.stream else {
+            return;
+        };
+        if stream.next_chunk(&mut self.events) {
+            self.next_event_idx = 0;
+        }
+    }
+
+    /// Markers rendered past since the last call, in time order
+    pub fn take_reached_markers(&mut self) -> &[Marker] {
+        let reached = &self.markers[self.taken_marker_idx..self.next_marker_idx];
+        self.taken_marker_idx = self.next_marker_idx;
+        reached
+    }
+
+    /// The log's markers in the order playback reaches them
+    pub fn markers(&self) -> &[Marker] {
+        &self.markers
+    }
+
     pub fn total_samples(&self) -> u32 {
         self.events.last().map(|e| e.time).unwrap_or(0)
     }
@@ -343,23 +522,60 @@ impl Player {
         if self.interactive_mode {
             return false;
         }
+        // A stream is complete only once the client has ended it
+        if self
+            .stream
+            .as_ref()
+            .is_some_and(|stream| !stream.is_ended())
+        {
+            return false;
+        }
         self.next_event_idx >= self.events.len() && self.pending_data_write.is_none()
     }
 
+    /// Native OPM sample rate at the default clock
     pub const fn sample_rate() -> u32 {
         OPM_SAMPLE_RATE
     }
 
+    /// Chip master clock in Hz this player runs at
+    pub fn clock(&self) -> u32 {
+        self.clock
+    }
+
+    /// Native OPM sample rate of this player (clock / 64)
+    pub fn current_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
     fn is_sample_silent(left: i16, right: i16) -> bool {
         left == 0 && right == 0
     }
 
+    fn count_silence(&mut self, silent_frames: impl Iterator<Item = bool>) {
+        for silent in silent_frames {
+            if silent {
+                self.consecutive_silent_samples += 1;
+            } else {
+                self.consecutive_silent_samples = 0;
+            }
+        }
+    }
+
     pub fn should_continue_tail(&self) -> bool {
         if !self.is_complete() {
             return true;
         }
 
-        self.consecutive_silent_samples < SILENCE_SAMPLES
+        let silence_samples =
+            (self.silence_duration_ms as u64 * self.sample_rate as u64 / 1000) as u32;
+        self.consecutive_silent_samples < silence_samples
+    }
+
+    /// Set how long the output must stay silent after the last event before
+    /// the tail ends ([`SILENCE_DURATION_MS`] by default)
+    pub fn set_silence_duration_ms(&mut self, silence_duration_ms: u32) {
+        self.silence_duration_ms = silence_duration_ms;
     }
 
     pub fn tail_info(&self) -> Option<(u32, u32)> {
@@ -0,0 +1,66 @@
+//! Last values written to the OPM registers, as far as a log has told
+//!
+//! Register 0x19 holds two values (AMD, and PMD when bit 7 is set) and key-on
+//! writes to 0x08 address a channel through their data, so the shadow keeps
+//! them apart. [`RegisterShadow::key`] gives the slot a write lands in, which
+//! also serves to group writes that overwrite each other.
+
+use crate::opm::{REG_KEY_ON, REG_LFO_DEPTH};
+
+const CHANNELS: usize = 8;
+/// Slot of PMD, after the 256 registers
+const PMD_SLOT: usize = 0x100;
+/// Slot of channel 0's key-on state; channels 1-7 follow
+const KEY_ON_SLOT: usize = PMD_SLOT + 1;
+const SLOTS: usize = KEY_ON_SLOT + CHANNELS;
+
+/// Register values, with PMD and the key-on state of each channel kept apart
+pub struct RegisterShadow {
+    values: [Option<u8>; SLOTS],
+}
+
+impl Default for RegisterShadow {
+    fn default() -> Self {
+        Self {
+            values: [None; SLOTS],
+        }
+    }
+}
+
+impl RegisterShadow {
+    /// Slot a write is stored in: the register, PMD, or a channel's key-on state
+    pub fn key(addr: u8, data: u8) -> usize {
+        match addr {
+            REG_LFO_DEPTH if data & 0x80 != 0 => PMD_SLOT,
+            REG_KEY_ON => KEY_ON_SLOT + (data & 0x07) as usize,
+            _ => addr as usize,
+        }
+    }
+
+    /// Record a write; false if it does not change anything
+    pub fn store(&mut self, addr: u8, data: u8) -> bool {
+        let slot = &mut self.values[Self::key(addr, data)];
+        let changed = *slot != Some(data);
+        *slot = Some(data);
+        changed
+    }
+
+    /// (address, data) writes that recreate the register values, in address
+    /// order with PMD after AMD; key-on state is not included
+    pub fn writes(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        (0..=u8::MAX).flat_map(move |addr| {
+            let pmd = (addr == REG_LFO_DEPTH)
+                .then_some(self.values[PMD_SLOT])
+                .flatten();
+            self.values[addr as usize]
+                .into_iter()
+                .chain(pmd)
+                .map(move |data| (addr, data))
+        })
+    }
+
+    /// Last key-on/off data written for each channel that had one
+    pub fn keys(&self) -> impl Iterator<Item = u8> + '_ {
+        self.values[KEY_ON_SLOT..].iter().flatten().copied()
+    }
+}
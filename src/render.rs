@@ -0,0 +1,180 @@
+//! Offline rendering of event logs to PCM
+//!
+//! Renders a log as fast as the emulator runs, without an audio device or a
+//! server, for the `render` subcommand. The log can be cut to a time range and
+//! its loop repeated before rendering, and the output can stay at the native
+//! OPM rate (clock / 64) or be resampled.
+//!
+//! Samples stay at the full precision of the chip's DAC (see [`crate::pcm`])
+//! until they are written as 16/24-bit or float WAV, or FLAC.
+
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+use crate::audio_config::buffer::GENERATION_BUFFER_SIZE;
+use crate::events::{edit, EventLog};
+use crate::flac_writer;
+use crate::pcm::{self, Dither, SampleFormat};
+use crate::player::{Player, SILENCE_DURATION_MS};
+use crate::resampler::{
+    opm_sample_rate, resample_offline, resampled_frames, ResamplingQuality, YM2151_CLOCK,
+};
+use crate::wav_writer::{self, MAX_TAIL_SECONDS};
+
+/// How a log is rendered
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Output sample rate in Hz; `None` keeps the native OPM rate
+    pub sample_rate: Option<u32>,
+    pub quality: ResamplingQuality,
+    /// Longest tail rendered after the last event, in seconds
+    pub max_tail_sec: f64,
+    /// Silence after the last event that ends the tail early, in milliseconds
+    pub silence_ms: u32,
+    /// Start of the rendered range, in seconds (after unrolling the loop)
+    pub start: f64,
+    /// End of the rendered range, in seconds; `None` renders to the last event
+    pub end: Option<f64>,
+    /// Times the loop section is played (see [`edit::unroll_loop`])
+    pub loops: u32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: None,
+            quality: ResamplingQuality::HighQuality,
+            max_tail_sec: MAX_TAIL_SECONDS as f64,
+            silence_ms: SILENCE_DURATION_MS,
+            start: 0.0,
+            end: None,
+            loops: 1,
+        }
+    }
+}
+
+/// Rendered interleaved stereo samples
+#[derive(Debug, Clone)]
+pub struct RenderedAudio {
+    /// Full-precision samples, 1.0 being 16-bit full scale
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+    /// Frames rendered at the native rate, before resampling
+    pub native_frames: usize,
+    /// Whether the tail was cut at `max_tail_sec` before it fell silent
+    pub tail_truncated: bool,
+}
+
+impl RenderedAudio {
+    pub fn duration_sec(&self) -> f64 {
+        self.samples.len() as f64 / 2.0 / self.sample_rate as f64
+    }
+}
+
+/// Apply the loop count and time range of `options` to a log
+pub fn prepare(log: &EventLog, options: &RenderOptions) -> Result<EventLog> {
+    let unrolled = edit::unroll_loop(log, options.loops)?;
+    if options.start == 0.0 && options.end.is_none() {
+        return Ok(unrolled);
+    }
+    edit::slice(
+        &unrolled,
+        options.start,
+        options.end.unwrap_or(f64::INFINITY),
+    )
+}
+
+/// Render a log offline
+pub fn render(log: &EventLog, options: &RenderOptions) -> Result<RenderedAudio> {
+    check_options(options)?;
+    render_prepared(&prepare(log, options)?, options, None)
+}
+
+/// Most frames a render of `log` can have at the output rate: up to the last
+/// event and then the longest tail, without rendering it
+pub fn max_frames(log: &EventLog, options: &RenderOptions) -> Result<usize> {
+    check_options(options)?;
+    let prepared = prepare(log, options)?;
+    let native_rate = opm_sample_rate(prepared.clock_or(YM2151_CLOCK));
+    let last_event = prepared.events.last().map_or(0.0, |event| event.time);
+    let native_frames = ((last_event + options.max_tail_sec) * native_rate as f64).ceil() as usize;
+    Ok(match options.sample_rate {
+        Some(rate) if rate != native_rate => resampled_frames(native_frames, native_rate, rate),
+        _ => native_frames,
+    })
+}
+
+fn check_options(options: &RenderOptions) -> Result<()> {
+    if !(options.max_tail_sec.is_finite() && options.max_tail_sec >= 0.0) {
+        bail!("invalid tail length {}", options.max_tail_sec);
+    }
+    if options.sample_rate == Some(0) {
+        bail!("output sample rate must be greater than 0");
+    }
+    Ok(())
+}
+
+/// Render a log already passed through [`prepare`]
+///
+/// With `frames`, exactly that many frames are rendered at the native rate
+/// (before resampling) instead of stopping when the tail falls silent.
+pub fn render_prepared(
+    log: &EventLog,
+    options: &RenderOptions,
+    frames: Option<usize>,
+) -> Result<RenderedAudio> {
+    check_options(options)?;
+    let mut player = Player::new(log.clone());
+    player.set_silence_duration_ms(options.silence_ms);
+    let native_rate = player.current_sample_rate();
+    let frame_limit = frames.unwrap_or(
+        player.total_samples() as usize
+            + (options.max_tail_sec * native_rate as f64).round() as usize,
+    );
+
+    let mut samples = Vec::new();
+    let mut generation_buffer = vec![0i32; GENERATION_BUFFER_SIZE * 2];
+    let mut tail_truncated = false;
+    while frames.is_some() || player.should_continue_tail() {
+        if samples.len() / 2 >= frame_limit {
+            samples.truncate(frame_limit * 2);
+            tail_truncated = frames.is_none();
+            break;
+        }
+        player.generate_samples_full(&mut generation_buffer);
+        samples.extend(generation_buffer.iter().map(|&raw| pcm::from_dac(raw)));
+    }
+
+    let native_frames = samples.len() / 2;
+    let sample_rate = options.sample_rate.unwrap_or(native_rate);
+    if sample_rate != native_rate {
+        samples = resample_offline(&samples, native_rate, sample_rate, options.quality)
+            .context("Failed to resample rendered audio")?;
+    }
+
+    Ok(RenderedAudio {
+        samples,
+        sample_rate,
+        native_frames,
+        tail_truncated,
+    })
+}
+
+/// Write rendered audio as WAV, or as FLAC if `path` ends in `.flac`
+///
+/// `dither` says how samples are reduced to 16 bits.
+pub fn write(
+    path: &str,
+    audio: &RenderedAudio,
+    format: SampleFormat,
+    dither: Dither,
+) -> Result<()> {
+    let is_flac = Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("flac"));
+    if is_flac {
+        flac_writer::write_flac(path, &audio.samples, audio.sample_rate, format, dither)
+    } else {
+        wav_writer::write_wav_full(path, &audio.samples, audio.sample_rate, format, dither)
+    }
+}
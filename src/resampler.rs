@@ -3,12 +3,29 @@ use rubato::{
     Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction,
 };
 
+/// Default chip master clock (NTSC colorburst, most arcade boards and MSX)
 pub const YM2151_CLOCK: u32 = 3_579_545;
 
+/// 4 MHz master clock used by the X68000 and several arcade boards
+pub const X68000_CLOCK: u32 = 4_000_000;
+
+/// Accepted master clock range for logs and `--clock`
+pub const MIN_CLOCK: u32 = 1_000_000;
+pub const MAX_CLOCK: u32 = 8_000_000;
+
+/// Native OPM sample rate at the default clock
 pub const OPM_SAMPLE_RATE: u32 = YM2151_CLOCK / 64;
 
+/// Native OPM sample rate for a given master clock (one sample per 64 clocks)
+pub const fn opm_sample_rate(clock: u32) -> u32 {
+    clock / 64
+}
+
 pub const OUTPUT_SAMPLE_RATE: u32 = 48000;
 
+/// Frames per channel the offline resampler feeds the sinc filter at a time
+const OFFLINE_CHUNK_FRAMES: usize = 8192;
+
 /// Resampling quality setting
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ResamplingQuality {
@@ -22,7 +39,7 @@ enum ResamplerImpl {
     Linear {
         ratio: f64,
         position: f64,
-        last_frame: Option<(i16, i16)>,
+        last_frame: Option<(f64, f64)>,
     },
     HighQuality {
         rubato: SincFixedIn<f32>,
@@ -66,25 +83,10 @@ impl AudioResampler {
                 }
             }
             ResamplingQuality::HighQuality => {
-                // Configure high-quality sinc interpolation
-                let params = SincInterpolationParameters {
-                    sinc_len: 256,
-                    f_cutoff: 0.95,
-                    interpolation: SincInterpolationType::Linear,
-                    oversampling_factor: 256,
-                    window: WindowFunction::BlackmanHarris2,
-                };
-
-                // Choose a reasonable chunk size for processing
-                let chunk_size = crate::audio_config::buffer::RESAMPLING_CHUNK_SIZE;
-                let resample_ratio = output_rate as f64 / input_rate as f64;
-
-                let rubato = SincFixedIn::<f32>::new(
-                    resample_ratio,
-                    2.0, // max_resample_ratio_relative
-                    params,
-                    chunk_size,
-                    2, // stereo
+                let rubato = sinc_resampler(
+                    input_rate,
+                    output_rate,
+                    crate::audio_config::buffer::RESAMPLING_CHUNK_SIZE,
                 )?;
 
                 ResamplerImpl::HighQuality {
@@ -113,12 +115,61 @@ impl AudioResampler {
         }
 
         match &mut self.inner {
-            ResamplerImpl::Linear { .. } => self.resample_linear(input),
-            ResamplerImpl::HighQuality { .. } => self.resample_high_quality(input),
+            ResamplerImpl::Linear { .. } => {
+                let mut output = Vec::new();
+                self.resample_linear(
+                    input.len() / 2,
+                    |i| input[i] as f64,
+                    |sample| output.push(sample.clamp(-32768.0, 32767.0) as i16),
+                );
+                Ok(output)
+            }
+            ResamplerImpl::HighQuality { .. } => {
+                let output = self
+                    .resample_high_quality(input.iter().map(|&sample| sample as f32 / 32768.0))?;
+                Ok(output
+                    .into_iter()
+                    .map(|sample| (sample * 32768.0).clamp(-32768.0, 32767.0) as i16)
+                    .collect())
+            }
+        }
+    }
+
+    /// Resample interleaved stereo samples at full precision
+    ///
+    /// Samples are normalized so that 1.0 is 16-bit full scale; unlike
+    /// [`resample`](Self::resample), nothing is rounded or clamped.
+    pub fn resample_f32(&mut self, input: &[f32]) -> Result<Vec<f32>> {
+        if input.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if !input.len().is_multiple_of(2) {
+            anyhow::bail!("Input buffer must have even length (stereo samples)");
+        }
+
+        match &mut self.inner {
+            ResamplerImpl::Linear { .. } => {
+                let mut output = Vec::new();
+                self.resample_linear(
+                    input.len() / 2,
+                    |i| input[i] as f64 * 32768.0,
+                    |sample| output.push((sample / 32768.0) as f32),
+                );
+                Ok(output)
+            }
+            ResamplerImpl::HighQuality { .. } => self.resample_high_quality(input.iter().copied()),
         }
     }
 
-    fn resample_linear(&mut self, input: &[i16]) -> Result<Vec<i16>> {
+    /// Linear interpolation of `input_frames` frames read through `sample`
+    /// (interleaved index, in 16-bit units), passing each output sample to `emit`
+    fn resample_linear(
+        &mut self,
+        input_frames: usize,
+        sample: impl Fn(usize) -> f64,
+        mut emit: impl FnMut(f64),
+    ) {
         let (ratio, position, last_frame) = match &mut self.inner {
             ResamplerImpl::Linear {
                 ratio,
@@ -128,10 +179,6 @@ impl AudioResampler {
             _ => unreachable!(),
         };
 
-        let input_frames = input.len() / 2;
-        let output_frames = ((input_frames as f64) / *ratio).ceil() as usize;
-        let mut output = Vec::with_capacity(output_frames * 2);
-
         let mut pos = *position;
 
         while pos < input_frames as f64 {
@@ -142,16 +189,16 @@ impl AudioResampler {
             let (left0, right0, left1, right1) = if frame_idx < 0 && last_frame.is_some() {
                 // Negative position means we need the last frame from the previous chunk
                 let (last_left, last_right) = last_frame.unwrap();
-                let curr_left = input[0] as f64;
-                let curr_right = input[1] as f64;
-                (last_left as f64, last_right as f64, curr_left, curr_right)
+                let curr_left = sample(0);
+                let curr_right = sample(1);
+                (last_left, last_right, curr_left, curr_right)
             } else if frame_idx >= 0 && (frame_idx as usize) + 1 < input_frames {
                 // Normal case: interpolate between two frames in the current chunk
                 let idx = frame_idx as usize;
-                let left0 = input[idx * 2] as f64;
-                let right0 = input[idx * 2 + 1] as f64;
-                let left1 = input[(idx + 1) * 2] as f64;
-                let right1 = input[(idx + 1) * 2 + 1] as f64;
+                let left0 = sample(idx * 2);
+                let right0 = sample(idx * 2 + 1);
+                let left1 = sample((idx + 1) * 2);
+                let right1 = sample((idx + 1) * 2 + 1);
                 (left0, right0, left1, right1)
             } else {
                 // We've reached the end of the chunk
@@ -159,11 +206,8 @@ impl AudioResampler {
             };
 
             // Linear interpolation
-            let left_out = left0 + (left1 - left0) * frac;
-            let right_out = right0 + (right1 - right0) * frac;
-
-            output.push(left_out.clamp(-32768.0, 32767.0) as i16);
-            output.push(right_out.clamp(-32768.0, 32767.0) as i16);
+            emit(left0 + (left1 - left0) * frac);
+            emit(right0 + (right1 - right0) * frac);
 
             pos += *ratio;
         }
@@ -171,16 +215,15 @@ impl AudioResampler {
         // Save the last frame from this chunk for the next call
         if input_frames > 0 {
             let last_idx = (input_frames - 1) * 2;
-            *last_frame = Some((input[last_idx], input[last_idx + 1]));
+            *last_frame = Some((sample(last_idx), sample(last_idx + 1)));
         }
 
         // Update position for next chunk
         *position = pos - input_frames as f64;
-
-        Ok(output)
     }
 
-    fn resample_high_quality(&mut self, input: &[i16]) -> Result<Vec<i16>> {
+    /// Sinc resampling of normalized interleaved samples
+    fn resample_high_quality(&mut self, input: impl Iterator<Item = f32>) -> Result<Vec<f32>> {
         let (rubato, leftover_input_left, leftover_input_right) = match &mut self.inner {
             ResamplerImpl::HighQuality {
                 rubato,
@@ -190,27 +233,21 @@ impl AudioResampler {
             _ => unreachable!(),
         };
 
-        let input_frames = input.len() / 2;
-
-        // Deinterleave and convert i16 to f32, combining with leftovers
-        let mut left_channel = Vec::with_capacity(leftover_input_left.len() + input_frames);
-        let mut right_channel = Vec::with_capacity(leftover_input_right.len() + input_frames);
-
-        left_channel.extend_from_slice(leftover_input_left);
-        right_channel.extend_from_slice(leftover_input_right);
-        leftover_input_left.clear();
-        leftover_input_right.clear();
+        // Deinterleave, combining with leftovers
+        let mut left_channel = std::mem::take(leftover_input_left);
+        let mut right_channel = std::mem::take(leftover_input_right);
 
-        for i in 0..input_frames {
-            left_channel.push(input[i * 2] as f32 / 32768.0);
-            right_channel.push(input[i * 2 + 1] as f32 / 32768.0);
+        let mut input = input;
+        while let (Some(left), Some(right)) = (input.next(), input.next()) {
+            left_channel.push(left);
+            right_channel.push(right);
         }
 
         let total_frames = left_channel.len();
         let chunk_size = rubato.input_frames_next();
 
         // Process in chunks
-        let mut final_output: Vec<i16> = Vec::new();
+        let mut final_output: Vec<f32> = Vec::new();
         let mut processed_frames = 0;
 
         while processed_frames + chunk_size <= total_frames {
@@ -221,14 +258,10 @@ impl AudioResampler {
             let input_buffer = vec![chunk_left.to_vec(), chunk_right.to_vec()];
             let output = rubato.process(&input_buffer, None)?;
 
-            // Interleave and convert back to i16
-            let output_frames = output[0].len();
-            #[allow(clippy::needless_range_loop)]
-            for i in 0..output_frames {
-                let left_sample = (output[0][i] * 32768.0).clamp(-32768.0, 32767.0) as i16;
-                let right_sample = (output[1][i] * 32768.0).clamp(-32768.0, 32767.0) as i16;
-                final_output.push(left_sample);
-                final_output.push(right_sample);
+            // Interleave
+            for (&left, &right) in output[0].iter().zip(&output[1]) {
+                final_output.push(left);
+                final_output.push(right);
             }
 
             processed_frames += chunk_size;
@@ -266,3 +299,114 @@ impl AudioResampler {
         }
     }
 }
+
+/// High-quality sinc resampler for stereo, taking `chunk_size` frames at a time
+fn sinc_resampler(
+    input_rate: u32,
+    output_rate: u32,
+    chunk_size: usize,
+) -> Result<SincFixedIn<f32>> {
+    // Configure high-quality sinc interpolation
+    let params = SincInterpolationParameters {
+        sinc_len: 256,
+        f_cutoff: 0.95,
+        interpolation: SincInterpolationType::Linear,
+        oversampling_factor: 256,
+        window: WindowFunction::BlackmanHarris2,
+    };
+    let resample_ratio = output_rate as f64 / input_rate as f64;
+    Ok(SincFixedIn::<f32>::new(
+        resample_ratio,
+        2.0, // max_resample_ratio_relative
+        params,
+        chunk_size,
+        2, // stereo
+    )?)
+}
+
+/// Frames a recording of `input_frames` frames has after resampling
+pub fn resampled_frames(input_frames: usize, input_rate: u32, output_rate: u32) -> usize {
+    (input_frames as f64 * output_rate as f64 / input_rate as f64).round() as usize
+}
+
+/// Resample a whole recording of interleaved stereo samples at once
+///
+/// Unlike [`AudioResampler`], which works buffer by buffer for realtime output,
+/// this sees the whole recording: the sinc filter runs on large chunks and its
+/// tail is flushed, so the output is aligned with the input and has exactly
+/// [`resampled_frames`] frames.
+pub fn resample_offline(
+    samples: &[f32],
+    input_rate: u32,
+    output_rate: u32,
+    quality: ResamplingQuality,
+) -> Result<Vec<f32>> {
+    if !samples.len().is_multiple_of(2) {
+        anyhow::bail!("Input buffer must have even length (stereo samples)");
+    }
+    let input_frames = samples.len() / 2;
+    let output_frames = resampled_frames(input_frames, input_rate, output_rate);
+    if input_frames == 0 || output_frames == 0 {
+        return Ok(Vec::new());
+    }
+
+    match quality {
+        ResamplingQuality::Linear => {
+            let ratio = input_rate as f64 / output_rate as f64;
+            let mut output = Vec::with_capacity(output_frames * 2);
+            for frame in 0..output_frames {
+                let position = frame as f64 * ratio;
+                let index = (position.floor() as usize).min(input_frames - 1);
+                let next = (index + 1).min(input_frames - 1);
+                let frac = (position - index as f64) as f32;
+                for channel in 0..2 {
+                    let current = samples[index * 2 + channel];
+                    output.push(current + (samples[next * 2 + channel] - current) * frac);
+                }
+            }
+            Ok(output)
+        }
+        ResamplingQuality::HighQuality => {
+            let mut rubato = sinc_resampler(input_rate, output_rate, OFFLINE_CHUNK_FRAMES)?;
+            let left: Vec<f32> = samples.iter().step_by(2).copied().collect();
+            let right: Vec<f32> = samples.iter().skip(1).step_by(2).copied().collect();
+
+            // The first output frame is centred on the first input frame, so
+            // nothing needs trimming at the start (output_delay() counts the
+            // latency of a realtime stream, not an offset in the output)
+            let capacity = output_frames + rubato.output_frames_max();
+            let mut channels = [Vec::with_capacity(capacity), Vec::with_capacity(capacity)];
+            let append = |channels: &mut [Vec<f32>; 2], output: Vec<Vec<f32>>| {
+                for (channel, output) in channels.iter_mut().zip(output) {
+                    channel.extend(output);
+                }
+            };
+
+            let mut position = 0;
+            while position + OFFLINE_CHUNK_FRAMES <= input_frames {
+                let end = position + OFFLINE_CHUNK_FRAMES;
+                append(
+                    &mut channels,
+                    rubato.process(&[&left[position..end], &right[position..end]], None)?,
+                );
+                position = end;
+            }
+            if position < input_frames {
+                append(
+                    &mut channels,
+                    rubato.process_partial(Some(&[&left[position..], &right[position..]]), None)?,
+                );
+            }
+            // Flush the filter until the output covers the whole input
+            while channels[0].len() < output_frames {
+                append(&mut channels, rubato.process_partial::<&[f32]>(None, None)?);
+            }
+            let [left, right] = channels;
+            Ok(left[..output_frames]
+                .iter()
+                .zip(&right[..output_frames])
+                .flat_map(|(&left, &right)| [left, right])
+                .collect())
+        }
+    }
+}
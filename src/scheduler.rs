@@ -8,20 +8,30 @@ use crate::resampler::OPM_SAMPLE_RATE;
 /// * `time_offset_sec` - Time offset in seconds (f64) from current moment
 ///
 /// # Returns
-/// Sample time in YM2151 internal sample units (55930 Hz)
+/// Sample time in YM2151 internal sample units (55930 Hz at the default clock)
 pub fn sec_to_samples(time_offset_sec: f64) -> u32 {
-    (time_offset_sec * OPM_SAMPLE_RATE as f64).round() as u32
+    sec_to_samples_at(time_offset_sec, OPM_SAMPLE_RATE)
+}
+
+/// Converts physical time offset to sample time at the given OPM sample rate
+pub fn sec_to_samples_at(time_offset_sec: f64, sample_rate: u32) -> u32 {
+    (time_offset_sec * sample_rate as f64).round() as u32
 }
 
 /// Converts YM2151 sample time to physical time offset
 ///
 /// # Arguments
-/// * `sample_time` - Sample time in YM2151 internal sample units (55930 Hz)
+/// * `sample_time` - Sample time in YM2151 internal sample units (55930 Hz at the default clock)
 ///
 /// # Returns
 /// Time offset in seconds (f64)
 pub fn samples_to_sec(sample_time: u32) -> f64 {
-    sample_time as f64 / OPM_SAMPLE_RATE as f64
+    samples_to_sec_at(sample_time, OPM_SAMPLE_RATE)
+}
+
+/// Converts sample time at the given OPM sample rate to physical time offset
+pub fn samples_to_sec_at(sample_time: u32, sample_rate: u32) -> f64 {
+    sample_time as f64 / sample_rate as f64
 }
 
 /// Physical time tracker for interactive mode
@@ -1,9 +1,14 @@
 use crate::audio::AudioPlayer;
+use crate::audio_config::timing;
+use crate::event_stream;
 use crate::events::EventLog;
+use crate::formats;
 use crate::ipc::protocol::{Command, Response};
 use crate::logging;
 use crate::scheduler::TimeTracker;
+use crate::server::output_path::{check_output_path, SESSION_EXTENSIONS};
 use crate::server::playback::PlaybackManager;
+use crate::server::render_worker::{RenderPcmRequest, RenderWorker};
 use crate::server::state::ServerState;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
@@ -14,6 +19,7 @@ pub struct CommandHandler {
     shutdown_flag: Arc<AtomicBool>,
     time_tracker: Arc<Mutex<TimeTracker>>,
     playback_manager: PlaybackManager,
+    render_worker: RenderWorker,
 }
 
 impl CommandHandler {
@@ -27,6 +33,7 @@ impl CommandHandler {
             state,
             shutdown_flag,
             time_tracker,
+            render_worker: RenderWorker::new(playback_manager.clone()),
             playback_manager,
         }
     }
@@ -54,7 +61,23 @@ impl CommandHandler {
             Command::PlayJsonInInteractive { data } => {
                 self.handle_play_json_in_interactive(data, audio_player)
             }
-            Command::GetServerState => self.handle_get_server_state(),
+            Command::GetServerState => self.handle_get_server_state(audio_player),
+            Command::StartSessionRecording => Self::handle_session_recording(true, audio_player),
+            Command::StopSessionRecording => Self::handle_session_recording(false, audio_player),
+            Command::SaveInteractiveSession { path } => {
+                self.handle_save_interactive_session(&path, audio_player)
+            }
+            Command::GetMarkers { after } => Self::handle_get_markers(after, audio_player),
+            Command::BeginStream { clock } => self.handle_begin_stream(clock, audio_player),
+            Command::AppendEvents { data } => Self::handle_append_events(data, audio_player),
+            Command::EndStream => Self::handle_end_stream(audio_player),
+            Command::RenderPcm { .. } => {
+                // The connection loop hands RenderPcm to submit_render_pcm; rendering
+                // here would hold up every other command until the render is done
+                Response::Error {
+                    message: "RenderPcm must go through the render worker".to_string(),
+                }
+            }
             Command::Shutdown => {
                 // Shutdown is handled specially in the connection loop
                 // This should not be reached
@@ -63,7 +86,7 @@ impl CommandHandler {
         }
     }
 
-    fn handle_get_server_state(&self) -> Response {
+    fn handle_get_server_state(&self, audio_player: &Option<AudioPlayer>) -> Response {
         let state = self.state.lock().unwrap();
         let current_state = state.as_str().to_string();
 
@@ -72,9 +95,32 @@ impl CommandHandler {
 
         Response::ServerState {
             state: current_state,
+            marker: audio_player
+                .as_ref()
+                .and_then(|player| player.last_marker())
+                .map(|marker| marker.name),
         }
     }
 
+    fn handle_get_markers(after: u64, audio_player: &Option<AudioPlayer>) -> Response {
+        let markers = audio_player
+            .as_ref()
+            .map(|player| player.markers_since(after))
+            .unwrap_or_default();
+        Response::Markers { markers }
+    }
+
+    /// Render on the render worker thread, calling `reply` with the response
+    /// there when done, so that the caller can go on with other commands
+    pub fn submit_render_pcm(
+        &self,
+        request: RenderPcmRequest,
+        reply: impl FnOnce(Response) + Send + 'static,
+    ) {
+        logging::log_verbose_server("🎛️  レンダリング要求をワーカースレッドに渡しました");
+        self.render_worker.submit(request, reply);
+    }
+
     /// Check if shutdown has been requested
     pub fn is_shutdown_requested(&self) -> bool {
         self.shutdown_flag.load(Ordering::Relaxed)
@@ -97,39 +143,143 @@ impl CommandHandler {
             player.stop();
         }
 
-        // Convert JSON value to string for parsing
-        let json_result = serde_json::to_string(&data);
-
-        match json_result {
-            Ok(json_str) => match self
-                .playback_manager
-                .load_and_start_playback(&json_str, true)
-            {
-                Ok(player) => {
-                    *audio_player = Some(player);
-                    logging::log_verbose_server("✅ JSON データから音声再生を開始しました");
+        match self.playback_manager.load_and_start_playback(data) {
+            Ok(player) => {
+                *audio_player = Some(player);
+                logging::log_verbose_server("✅ JSON データから音声再生を開始しました");
 
-                    let mut state = self.state.lock().unwrap();
-                    *state = ServerState::Playing;
+                let mut state = self.state.lock().unwrap();
+                *state = ServerState::Playing;
 
-                    Response::Ok
+                Response::Ok
+            }
+            Err(e) => {
+                logging::log_always_server(&format!("❌ 音声再生の開始に失敗しました: {}", e));
+                Response::Error {
+                    message: format!("Failed to start playback: {}", e),
                 }
-                Err(e) => {
-                    logging::log_always_server(&format!("❌ 音声再生の開始に失敗しました: {}", e));
-                    Response::Error {
-                        message: format!("Failed to start playback: {}", e),
-                    }
+            }
+        }
+    }
+
+    fn handle_begin_stream(
+        &self,
+        clock: Option<u32>,
+        audio_player: &mut Option<AudioPlayer>,
+    ) -> Response {
+        logging::log_verbose_server("🌊 ストリーミング再生を準備中...");
+
+        // Stop any existing playback
+        if let Some(mut player) = audio_player.take() {
+            player.stop();
+        }
+
+        if let Some(clock) = clock {
+            if let Err(e) = crate::events::check_clock(clock) {
+                return Response::Error {
+                    message: format!("Invalid clock: {}", e),
+                };
+            }
+        }
+
+        match self.playback_manager.start_stream(clock) {
+            Ok(player) => {
+                *audio_player = Some(player);
+                logging::log_verbose_server("✅ ストリーミング再生を開始しました");
+
+                let mut state = self.state.lock().unwrap();
+                *state = ServerState::Playing;
+
+                Response::Ok
+            }
+            Err(e) => {
+                logging::log_always_server(&format!(
+                    "❌ ストリーミング再生の開始に失敗しました: {}",
+                    e
+                ));
+                Response::Error {
+                    message: format!("Failed to start stream: {}", e),
                 }
+            }
+        }
+    }
+
+    fn handle_append_events(
+        data: serde_json::Value,
+        audio_player: &mut Option<AudioPlayer>,
+    ) -> Response {
+        let Some(stream) = audio_player.as_mut().and_then(|p| p.stream_mut()) else {
+            logging::log_always_server("⚠️  ストリーミング再生中ではありません");
+            return Response::Error {
+                message: "No stream in progress".to_string(),
+            };
+        };
+
+        let events = match event_stream::parse_chunk(data) {
+            Ok(events) => events,
+            Err(e) => {
+                logging::log_always_server(&format!("❌ 無効なストリームチャンクです: {}", e));
+                return Response::Error {
+                    message: format!("Invalid stream chunk: {}", e),
+                };
+            }
+        };
+
+        // Back-pressure: turn the chunk away while the client is far ahead of
+        // playback, rather than holding the connection until it catches up
+        let buffered_sec = stream.buffered_sec();
+        if buffered_sec > timing::STREAM_MAX_BUFFERED_SEC && !stream.is_disconnected() {
+            return Response::StreamBuffer {
+                accepted: false,
+                buffered_sec,
+            };
+        }
+
+        // The generator thread has not picked up the chunks queued so far
+        match stream.append(&events) {
+            Ok(false) => Response::StreamBuffer {
+                accepted: false,
+                buffered_sec,
             },
+            Ok(true) => {
+                let buffered_sec = stream.buffered_sec();
+                logging::log_verbose_server(&format!(
+                    "🌊 {}個のイベントを追加しました (先行 {:.2}秒)",
+                    events.len(),
+                    buffered_sec
+                ));
+                Response::StreamBuffer {
+                    accepted: true,
+                    buffered_sec,
+                }
+            }
             Err(e) => {
-                logging::log_always_server(&format!("❌ JSONシリアライズに失敗しました: {}", e));
+                logging::log_always_server(&format!("❌ イベントの追加に失敗しました: {}", e));
                 Response::Error {
-                    message: format!("Failed to serialize JSON: {}", e),
+                    message: format!("Failed to append events: {}", e),
                 }
             }
         }
     }
 
+    fn handle_end_stream(audio_player: &mut Option<AudioPlayer>) -> Response {
+        let Some(stream) = audio_player.as_mut().and_then(|p| p.stream_mut()) else {
+            logging::log_always_server("⚠️  ストリーミング再生中ではありません");
+            return Response::Error {
+                message: "No stream in progress".to_string(),
+            };
+        };
+        match stream.end() {
+            Ok(()) => {
+                logging::log_verbose_server("🌊 ストリームの終端を受信しました");
+                Response::Ok
+            }
+            Err(e) => Response::Error {
+                message: format!("Failed to end stream: {}", e),
+            },
+        }
+    }
+
     fn handle_stop(&self, audio_player: &mut Option<AudioPlayer>) -> Response {
         logging::log_verbose_server("⏹️  音声再生を停止中...");
         if let Some(mut player) = audio_player.take() {
@@ -225,6 +375,76 @@ impl CommandHandler {
         Response::Ok
     }
 
+    fn handle_session_recording(start: bool, audio_player: &Option<AudioPlayer>) -> Response {
+        let Some(recorder) = audio_player.as_ref().and_then(|p| p.session_recorder()) else {
+            logging::log_always_server("⚠️  インタラクティブモードではありません");
+            return Response::Error {
+                message: "Not in interactive mode".to_string(),
+            };
+        };
+
+        if start {
+            recorder.start();
+            logging::log_verbose_server("⏺️  セッションの記録を開始しました");
+        } else {
+            recorder.stop();
+            logging::log_verbose_server(&format!(
+                "⏹️  セッションの記録を停止しました ({}個のイベント)",
+                recorder.len()
+            ));
+        }
+        Response::Ok
+    }
+
+    fn handle_save_interactive_session(
+        &self,
+        path: &str,
+        audio_player: &Option<AudioPlayer>,
+    ) -> Response {
+        let Some(recorder) = audio_player.as_ref().and_then(|p| p.session_recorder()) else {
+            logging::log_always_server("⚠️  インタラクティブモードではありません");
+            return Response::Error {
+                message: "Not in interactive mode".to_string(),
+            };
+        };
+        if let Err(e) = check_output_path(path, SESSION_EXTENSIONS) {
+            logging::log_always_server(&format!("❌ 保存先が不正です: {:#}", e));
+            return Response::Error {
+                message: format!("Invalid path: {:#}", e),
+            };
+        }
+        if recorder.is_empty() && !recorder.is_recording() {
+            logging::log_always_server("⚠️  記録されたセッションがありません");
+            return Response::Error {
+                message: "No session recorded; send StartSessionRecording first".to_string(),
+            };
+        }
+        if recorder.dropped() > 0 {
+            logging::log_always_server(&format!(
+                "⚠️  記録の上限に達したため{}個のイベントを記録していません",
+                recorder.dropped()
+            ));
+        }
+        let session = recorder.to_event_log();
+
+        match formats::save_event_log(path, &session) {
+            Ok(()) => {
+                logging::log_verbose_server(&format!(
+                    "💾 セッションを保存しました: {} ({}個のイベント)",
+                    path,
+                    session.events.len()
+                ));
+                Response::Ok
+            }
+            Err(e) => {
+                logging::log_always_server(&format!("❌ セッションの保存に失敗しました: {:#}", e));
+                Response::Error {
+                    message: format!("Failed to save session: {:#}", e),
+                }
+            }
+        }
+    }
+
     fn handle_play_json_in_interactive(
         &self,
         data: serde_json::Value,
@@ -243,21 +463,10 @@ impl CommandHandler {
         }
         drop(state);
 
-        // Early return: Serialize JSON
-        let json_str = match serde_json::to_string(&data) {
-            Ok(s) => s,
-            Err(e) => {
-                logging::log_always_server(&format!("❌ JSONシリアライズに失敗しました: {}", e));
-                return Response::Error {
-                    message: format!("Failed to serialize JSON: {}", e),
-                };
-            }
-        };
-
         logging::log_verbose_server("🎵 インタラクティブモードでJSONを処理中...");
 
         // Early return: Parse event log
-        let event_log = match EventLog::from_json_str(&json_str) {
+        let event_log = match EventLog::from_json_value(data) {
             Ok(log) => log,
             Err(e) => {
                 logging::log_always_server(&format!("❌ JSONの解析に失敗しました: {}", e));
@@ -268,11 +477,18 @@ impl CommandHandler {
         };
 
         // Early return: Validate event log
-        if !event_log.validate() {
-            logging::log_always_server("❌ 無効なイベントログです");
-            return Response::Error {
-                message: "Invalid event log: validation failed".to_string(),
-            };
+        match event_log.check_playable() {
+            Ok(warnings) => {
+                for warning in warnings {
+                    logging::log_verbose_server(&format!("⚠️  {}", warning));
+                }
+            }
+            Err(e) => {
+                logging::log_always_server(&format!("❌ 無効なイベントログです: {}", e));
+                return Response::Error {
+                    message: format!("Invalid event log: {}", e),
+                };
+            }
         }
 
         // Early return: Check audio player exists
@@ -336,8 +552,9 @@ impl CommandHandler {
 
         // Clear schedule from first event time if events exist
         if let Some(first_event) = event_log.events.first() {
-            let first_scheduled_samples = crate::scheduler::sec_to_samples(
+            let first_scheduled_samples = crate::scheduler::sec_to_samples_at(
                 audio_stream_elapsed_sec + future_offset_sec + first_event.time,
+                player_ref.native_sample_rate(),
             );
 
             player_ref.clear_schedule_from(first_scheduled_samples);
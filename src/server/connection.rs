@@ -5,7 +5,9 @@ use crate::server::command_handler::CommandHandler;
 use anyhow::Result;
 
 #[cfg(target_os = "windows")]
-use crate::ipc::pipe_windows::NamedPipe;
+use crate::ipc::pipe_windows::{NamedPipe, PipeWriter};
+#[cfg(target_os = "windows")]
+use crate::server::render_worker::RenderPcmRequest;
 
 /// Manages client connections via named pipes
 pub struct ConnectionManager {
@@ -118,6 +120,36 @@ impl ConnectionManager {
 
         self.log_command(&command);
 
+        if let Command::RenderPcm {
+            data,
+            sample_rate,
+            format,
+            path,
+        } = command
+        {
+            // レンダリングは時間がかかるのでワーカースレッドに任せ、レスポンスもそちらから送る。
+            // パイプはインスタンス数無制限で作成しているので、その間も次の接続を受け付けられる
+            drop(reader);
+            drop(writer);
+            let request = RenderPcmRequest {
+                data,
+                sample_rate,
+                format,
+                path,
+            };
+            self.command_handler
+                .submit_render_pcm(request, move |response| {
+                    match connection_pipe.open_write() {
+                        Ok(mut writer) => Self::send_response(&mut writer, &response),
+                        Err(e) => logging::log_verbose_server(&format!(
+                            "⚠️  警告: パイプの書き込みオープンに失敗しました: {}",
+                            e
+                        )),
+                    }
+                });
+            return Ok(false);
+        }
+
         let response = if matches!(command, Command::Shutdown) {
             // シャットダウン要求の処理
             logging::log_always_server("🛑 シャットダウン要求を受信しました");
@@ -138,23 +170,50 @@ impl ConnectionManager {
         };
 
         // レスポンスを送信
-        if let Ok(response_binary) = response.to_binary() {
-            if let Err(e) = writer.write_binary(&response_binary) {
-                logging::log_verbose_server(&format!(
-                    "⚠️  警告: レスポンス送信に失敗しました: {}",
-                    e
-                ));
-            }
-        } else {
-            logging::log_verbose_server("⚠️  警告: レスポンスのシリアライズに失敗しました");
-        }
+        Self::send_response(&mut writer, &response);
         // 接続が自動的にクローズされる（writerがスコープ外になったので）
 
-        logging::log_verbose_server(&format!("📤 レスポンスを送信しました: {:?}", response));
         logging::log_verbose_server("🔄 次の接続待機に進みます...");
         Ok(false)
     }
 
+    #[cfg(target_os = "windows")]
+    fn send_response(writer: &mut PipeWriter, response: &Response) {
+        // 大きすぎるレスポンス（長いレンダリング結果など）はエラーとして返す
+        let response_binary = response.to_binary().or_else(|e| {
+            logging::log_always_server(&format!(
+                "⚠️  警告: レスポンスのシリアライズに失敗しました: {}",
+                e
+            ));
+            Response::Error {
+                message: format!("Failed to serialize response: {}", e),
+            }
+            .to_binary()
+        });
+        let Ok(response_binary) = response_binary else {
+            return;
+        };
+        if let Err(e) = writer.write_binary(&response_binary) {
+            logging::log_verbose_server(&format!("⚠️  警告: レスポンス送信に失敗しました: {}", e));
+            return;
+        }
+
+        match response {
+            Response::Pcm {
+                sample_rate,
+                format,
+                frames,
+                ..
+            } => logging::log_verbose_server(&format!(
+                "📤 レスポンスを送信しました: Pcm ({}フレーム, {} Hz, {})",
+                frames, sample_rate, format
+            )),
+            other => {
+                logging::log_verbose_server(&format!("📤 レスポンスを送信しました: {:?}", other))
+            }
+        }
+    }
+
     #[cfg(not(target_os = "windows"))]
     pub fn run(&self) -> Result<()> {
         anyhow::bail!("Server is only supported on Windows")
@@ -164,30 +223,37 @@ impl ConnectionManager {
         match command {
             Command::PlayJson { data } => {
                 // JSON データの場合、末尾要素だけを表示
-                if let Ok(log_str) = serde_json::to_string(data) {
-                    match crate::events::EventLog::from_json_str(&log_str) {
-                        Ok(log) if !log.events.is_empty() => {
-                            let last_event = &log.events[log.events.len() - 1];
-                            logging::log_verbose_server(&format!(
-                                "📩 コマンドを受信しました: PlayJson (末尾要素: time:{}, addr:0x{:02X}, data:0x{:02X})",
-                                last_event.time, last_event.addr, last_event.data
-                            ));
-                        }
-                        Ok(_) => {
-                            logging::log_verbose_server(
-                                "📩 コマンドを受信しました: PlayJson (空のイベント配列)",
-                            );
-                        }
-                        Err(_) => {
-                            logging::log_verbose_server(
-                                "📩 コマンドを受信しました: PlayJson (解析エラー)",
-                            );
-                        }
+                match crate::events::EventLog::from_json_value(data.clone()) {
+                    Ok(log) if !log.events.is_empty() => {
+                        let last_event = &log.events[log.events.len() - 1];
+                        logging::log_verbose_server(&format!(
+                            "📩 コマンドを受信しました: PlayJson (末尾要素: time:{}, addr:0x{:02X}, data:0x{:02X})",
+                            last_event.time, last_event.addr, last_event.data
+                        ));
+                    }
+                    Ok(_) => {
+                        logging::log_verbose_server(
+                            "📩 コマンドを受信しました: PlayJson (空のイベント配列)",
+                        );
+                    }
+                    Err(_) => {
+                        logging::log_verbose_server(
+                            "📩 コマンドを受信しました: PlayJson (解析エラー)",
+                        );
                     }
-                } else {
-                    logging::log_verbose_server("📩 コマンドを受信しました: PlayJson");
                 }
             }
+            Command::RenderPcm {
+                sample_rate,
+                format,
+                path,
+                ..
+            } => {
+                logging::log_verbose_server(&format!(
+                    "📩 コマンドを受信しました: RenderPcm (sample_rate: {:?}, format: {}, path: {:?})",
+                    sample_rate, format, path
+                ));
+            }
             other => {
                 logging::log_verbose_server(&format!("📩 コマンドを受信しました: {:?}", other));
             }
@@ -1,15 +1,19 @@
 mod command_handler;
 mod connection;
+mod output_path;
 mod playback;
+mod render_worker;
 mod state;
 
 pub use command_handler::CommandHandler;
+pub use output_path::{check_input_path, check_output_path, RENDER_EXTENSIONS, SESSION_EXTENSIONS};
 pub use playback::PlaybackManager;
+pub use render_worker::{render_pcm, RenderPcmRequest, RenderWorker};
 pub use state::ServerState;
 
 use crate::audio::AudioPlayer;
 use crate::logging;
-use crate::resampler::ResamplingQuality;
+use crate::resampler::{opm_sample_rate, ResamplingQuality, YM2151_CLOCK};
 use crate::scheduler::TimeTracker;
 use anyhow::Result;
 use connection::ConnectionManager;
@@ -24,6 +28,7 @@ pub struct Server {
     shutdown_flag: Arc<AtomicBool>,
     resampling_quality: ResamplingQuality,
     time_tracker: Arc<Mutex<TimeTracker>>,
+    clock: u32,
 }
 
 impl Server {
@@ -32,6 +37,13 @@ impl Server {
     }
 
     pub fn new_with_resampling_quality(low_quality: bool) -> Self {
+        Self::new_with_options(low_quality, YM2151_CLOCK)
+    }
+
+    /// Create a server with resampling quality and default chip master clock
+    ///
+    /// `clock` is used for logs without a `clock` field and for interactive mode.
+    pub fn new_with_options(low_quality: bool, clock: u32) -> Self {
         let quality = if low_quality {
             ResamplingQuality::Linear
         } else {
@@ -46,11 +58,18 @@ impl Server {
             }
         ));
 
+        logging::log_always_server(&format!(
+            "🎵 チップクロック: {} Hz (サンプリングレート {} Hz)",
+            clock,
+            opm_sample_rate(clock)
+        ));
+
         Server {
             state: Arc::new(Mutex::new(ServerState::Stopped)),
             shutdown_flag: Arc::new(AtomicBool::new(false)),
             resampling_quality: quality,
             time_tracker: Arc::new(Mutex::new(TimeTracker::new())),
+            clock,
         }
     }
 
@@ -63,7 +82,7 @@ impl Server {
         }
 
         // Create managers
-        let playback_manager = PlaybackManager::new(self.resampling_quality);
+        let playback_manager = PlaybackManager::new_with_clock(self.resampling_quality, self.clock);
         let command_handler = CommandHandler::new(
             Arc::clone(&self.state),
             Arc::clone(&self.shutdown_flag),
@@ -90,7 +109,7 @@ impl Server {
     /// This is a public wrapper for the private start_interactive_mode method
     /// to be used by demo_server module for standalone testing
     pub fn start_interactive_mode_demo(&self) -> Result<AudioPlayer> {
-        let playback_manager = PlaybackManager::new(self.resampling_quality);
+        let playback_manager = PlaybackManager::new_with_clock(self.resampling_quality, self.clock);
         playback_manager.start_interactive_mode()
     }
 }
@@ -0,0 +1,65 @@
+//! Paths of files the server reads and writes for its clients
+//!
+//! `SaveInteractiveSession` and `RenderPcm` name files on the server side, and
+//! `PlayJson`/`RenderPcm` may name a log to load. Such a path must stay inside
+//! the server's working directory, and a written file's extension must be one
+//! of the kind of file being written.
+
+use anyhow::{bail, Result};
+use std::path::{Component, Path};
+
+/// Extensions `SaveInteractiveSession` writes (see `formats::save_event_log`)
+pub const SESSION_EXTENSIONS: &[&str] = &["json", "gz", "zst", "vgm", "vgz", "s98", "mid", "midi"];
+/// Extensions `RenderPcm` writes (see `render::write`)
+pub const RENDER_EXTENSIONS: &[&str] = &["wav", "flac"];
+
+/// Check a client-supplied output path
+///
+/// The path has to be relative without `..` components (nor a drive or root on
+/// Windows), and end in one of `extensions`.
+pub fn check_output_path(path: &str, extensions: &[&str]) -> Result<()> {
+    check_relative(path, "Output")?;
+
+    let checked = Path::new(path);
+    let extension = checked
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    if !extension
+        .as_deref()
+        .is_some_and(|ext| extensions.contains(&ext))
+    {
+        bail!(
+            "Output path must end in .{}: {}",
+            extensions.join(", ."),
+            path
+        );
+    }
+
+    Ok(())
+}
+
+/// Check a client-supplied path of a log to load
+///
+/// The same confinement as [`check_output_path`]; any extension is accepted, as
+/// `formats::load_event_log` reads unknown ones as JSON.
+pub fn check_input_path(path: &str) -> Result<()> {
+    check_relative(path, "Input")
+}
+
+fn check_relative(path: &str, kind: &str) -> Result<()> {
+    if path.is_empty() {
+        bail!("{} path is empty", kind);
+    }
+    if !Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        bail!(
+            "{} path must be relative to the server's working directory without '..': {}",
+            kind,
+            path
+        );
+    }
+    Ok(())
+}
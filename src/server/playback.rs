@@ -1,51 +1,89 @@
 use crate::audio::AudioPlayer;
 use crate::events::EventLog;
+use crate::formats;
 use crate::logging;
 use crate::player::Player;
-use crate::resampler::ResamplingQuality;
+use crate::resampler::{ResamplingQuality, YM2151_CLOCK};
+use crate::server::output_path::check_input_path;
 use anyhow::{Context, Result};
 
 /// Manages audio playback initialization
+#[derive(Debug, Clone)]
 pub struct PlaybackManager {
     resampling_quality: ResamplingQuality,
+    /// Chip master clock used when a log does not specify one, and for interactive mode
+    clock: u32,
 }
 
 impl PlaybackManager {
     pub fn new(resampling_quality: ResamplingQuality) -> Self {
-        Self { resampling_quality }
+        Self::new_with_clock(resampling_quality, YM2151_CLOCK)
     }
 
-    /// Load event log and start playback
-    pub fn load_and_start_playback(&self, data: &str, is_json_string: bool) -> Result<AudioPlayer> {
-        let log = if is_json_string {
-            // Parse as JSON string directly
-            EventLog::from_json_str(data).with_context(|| "Failed to parse JSON string data")?
-        } else {
-            // Load from file path
-            EventLog::from_file(data)
-                .with_context(|| format!("Failed to load JSON file: {}", data))?
-        };
-
-        if !log.validate() {
-            return Err(anyhow::anyhow!(
-                "Event log validation failed: events are not in chronological order"
-            ));
+    pub fn new_with_clock(resampling_quality: ResamplingQuality, clock: u32) -> Self {
+        Self {
+            resampling_quality,
+            clock,
         }
+    }
+
+    pub fn resampling_quality(&self) -> ResamplingQuality {
+        self.resampling_quality
+    }
+
+    /// Load event log and start playback
+    pub fn load_and_start_playback(&self, data: serde_json::Value) -> Result<AudioPlayer> {
+        let log = self.load_event_log(data)?;
 
-        let player = Player::new(log.clone());
         // Pass the event log to AudioPlayer if in verbose mode
         let event_log = if logging::is_server_verbose() {
-            Some(log)
+            Some(log.clone())
         } else {
             None
         };
+        let player = Player::new(log);
         AudioPlayer::new_with_quality(player, event_log, self.resampling_quality)
             .context("Failed to create audio player")
     }
 
+    /// Load and check an event log, defaulting its clock to the server's
+    ///
+    /// A bare string is a path to a log file on the server side (JSON, or
+    /// VGM/VGZ etc. by extension), relative to its working directory; anything
+    /// else is the log itself.
+    pub fn load_event_log(&self, data: serde_json::Value) -> Result<EventLog> {
+        let mut log = match data {
+            serde_json::Value::String(path) => {
+                check_input_path(&path)?;
+                let loaded = formats::load_event_log(&path)?;
+                for warning in &loaded.warnings {
+                    logging::log_verbose_server(&format!("⚠️  {}", warning));
+                }
+                loaded.log
+            }
+            data => EventLog::from_json_value(data).context("Failed to parse JSON data")?,
+        };
+
+        for warning in log.check_playable()? {
+            logging::log_verbose_server(&format!("⚠️  {}", warning));
+        }
+
+        // A clock in the log wins over the server default
+        if log.clock.is_none() {
+            log.clock = Some(self.clock);
+        }
+        Ok(log)
+    }
+
+    /// Start playback of a log streamed in chunks, at `clock` or the server default
+    pub fn start_stream(&self, clock: Option<u32>) -> Result<AudioPlayer> {
+        AudioPlayer::new_streaming(clock.unwrap_or(self.clock), self.resampling_quality)
+            .context("Failed to create streaming audio player")
+    }
+
     /// Start interactive mode
     pub fn start_interactive_mode(&self) -> Result<AudioPlayer> {
-        let player = Player::new_interactive();
+        let player = Player::new_interactive_with_clock(self.clock);
         // No event log in interactive mode, and no WAV output
         AudioPlayer::new_with_quality(player, None, self.resampling_quality)
             .context("Failed to create interactive audio player")
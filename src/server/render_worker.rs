@@ -0,0 +1,135 @@
+use crate::ipc::protocol::{Response, MAX_PCM_RESPONSE_BYTES};
+use crate::logging;
+use crate::pcm::{self, Dither, SampleFormat};
+use crate::render::{self, RenderOptions};
+use crate::server::output_path::{check_output_path, RENDER_EXTENSIONS};
+use crate::server::playback::PlaybackManager;
+use anyhow::{bail, Result};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+/// A `RenderPcm` command
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderPcmRequest {
+    pub data: serde_json::Value,
+    pub sample_rate: Option<u32>,
+    pub format: String,
+    pub path: Option<String>,
+}
+
+type Reply = Box<dyn FnOnce(Response) + Send>;
+
+/// Renders `RenderPcm` requests one at a time on its own thread, so that a long
+/// render holds up neither realtime playback nor the commands that follow it
+pub struct RenderWorker {
+    sender: Option<mpsc::Sender<(RenderPcmRequest, Reply)>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RenderWorker {
+    pub fn new(playback_manager: PlaybackManager) -> Self {
+        let (sender, receiver) = mpsc::channel::<(RenderPcmRequest, Reply)>();
+        let thread = thread::Builder::new()
+            .name("render-worker".to_string())
+            .spawn(move || {
+                for (request, reply) in receiver {
+                    reply(render_pcm(&request, &playback_manager));
+                }
+            })
+            .expect("Failed to spawn render worker thread");
+        Self {
+            sender: Some(sender),
+            thread: Some(thread),
+        }
+    }
+
+    /// Queue a request; `reply` is called with the response on the worker thread
+    pub fn submit(&self, request: RenderPcmRequest, reply: impl FnOnce(Response) + Send + 'static) {
+        let reply: Reply = Box::new(reply);
+        if let Some(Err(mpsc::SendError((_, reply)))) = self
+            .sender
+            .as_ref()
+            .map(|sender| sender.send((request, reply)))
+        {
+            reply(Response::Error {
+                message: "Render worker is not running".to_string(),
+            });
+        }
+    }
+}
+
+impl Drop for RenderWorker {
+    fn drop(&mut self) {
+        // Closing the channel ends the worker loop once the queue is done
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Render a request and answer with the PCM, or with `Ok` once written to its path
+pub fn render_pcm(request: &RenderPcmRequest, playback_manager: &PlaybackManager) -> Response {
+    logging::log_verbose_server("🎛️  オフラインレンダリング中...");
+    match try_render_pcm(request, playback_manager) {
+        Ok(response) => {
+            logging::log_verbose_server("✅ オフラインレンダリングが完了しました");
+            response
+        }
+        Err(e) => {
+            logging::log_always_server(&format!("❌ レンダリングに失敗しました: {:#}", e));
+            Response::Error {
+                message: format!("Failed to render: {:#}", e),
+            }
+        }
+    }
+}
+
+fn try_render_pcm(
+    request: &RenderPcmRequest,
+    playback_manager: &PlaybackManager,
+) -> Result<Response> {
+    let format = SampleFormat::from_name(&request.format)?;
+    if let Some(path) = &request.path {
+        check_output_path(path, RENDER_EXTENSIONS)?;
+    }
+
+    let log = playback_manager.load_event_log(request.data.clone())?;
+
+    let options = RenderOptions {
+        sample_rate: request.sample_rate,
+        quality: playback_manager.resampling_quality(),
+        ..Default::default()
+    };
+    if request.path.is_none() {
+        // Turn away a render too long for a response before spending time on it
+        let bytes_per_frame = 2 * format.bits_per_sample() as usize / 8;
+        let max_bytes = render::max_frames(&log, &options)?.saturating_mul(bytes_per_frame);
+        if max_bytes > MAX_PCM_RESPONSE_BYTES {
+            bail!(
+                "the PCM may take up to {} bytes, more than a response carries ({} bytes); \
+                 render to a server-side file with `path` instead",
+                max_bytes,
+                MAX_PCM_RESPONSE_BYTES
+            );
+        }
+    }
+
+    let rendered = render::render(&log, &options)?;
+    if rendered.tail_truncated {
+        logging::log_verbose_server("⚠️  テールが上限に達したため途中で打ち切りました");
+    }
+
+    if let Some(path) = &request.path {
+        render::write(path, &rendered, format, Dither::Off)?;
+        logging::log_verbose_server(&format!("💾 レンダリング結果を保存しました: {}", path));
+        return Ok(Response::Ok);
+    }
+
+    Ok(Response::Pcm {
+        sample_rate: rendered.sample_rate,
+        format: format.name().to_string(),
+        frames: rendered.samples.len() / 2,
+        data: pcm::to_le_bytes(&rendered.samples, format, Dither::Off),
+    })
+}
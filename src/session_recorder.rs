@@ -0,0 +1,129 @@
+//! Recording of interactive sessions
+//!
+//! Mirrors the writes the IPC side submits to the generator, including schedule
+//! clears, so the session can be saved afterwards as a regular [`EventLog`]
+//! (and from there as JSON or VGM). Recording happens on the submitting side,
+//! never on the audio thread.
+//!
+//! Nothing is recorded until [`SessionRecorder::start`] is called, and a
+//! recording keeps at most its limit of writes; later writes are dropped and
+//! counted.
+
+use std::sync::Mutex;
+
+use crate::event_schedule::EventSchedule;
+use crate::events::{EventLog, RegisterEvent};
+use crate::player::ProcessedEvent;
+use crate::resampler::opm_sample_rate;
+
+/// Default limit on the writes kept by a recording (about an hour of dense
+/// four-voice playing)
+pub const MAX_RECORDED_WRITES: usize = 1 << 20;
+
+/// Writes submitted during an interactive session, in sample time
+pub struct SessionRecorder {
+    clock: u32,
+    sample_rate: u32,
+    limit: usize,
+    recording: Mutex<Recording>,
+}
+
+#[derive(Default)]
+struct Recording {
+    active: bool,
+    events: EventSchedule,
+    dropped: usize,
+}
+
+impl SessionRecorder {
+    pub fn new(clock: u32) -> Self {
+        Self::with_limit(clock, MAX_RECORDED_WRITES)
+    }
+
+    /// Create a recorder that keeps at most `limit` writes
+    pub fn with_limit(clock: u32, limit: usize) -> Self {
+        Self {
+            clock,
+            sample_rate: opm_sample_rate(clock),
+            limit,
+            recording: Mutex::new(Recording::default()),
+        }
+    }
+
+    /// Start a new recording, discarding the previous one
+    pub fn start(&self) {
+        *self.recording.lock().unwrap() = Recording {
+            active: true,
+            ..Default::default()
+        };
+    }
+
+    /// Stop recording; what was recorded is kept until the next start
+    pub fn stop(&self) {
+        self.recording.lock().unwrap().active = false;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.lock().unwrap().active
+    }
+
+    /// Record a write submitted for `time` (OPM samples since the session started)
+    ///
+    /// Ignored while not recording; dropped once the recording is full.
+    pub fn record_write(&self, time: u32, addr: u8, data: u8) {
+        let mut recording = self.recording.lock().unwrap();
+        if !recording.active {
+            return;
+        }
+        if recording.events.len() >= self.limit {
+            recording.dropped += 1;
+            return;
+        }
+        recording.events.push(ProcessedEvent { time, addr, data });
+    }
+
+    /// Drop recorded writes with time >= `from_sample_time`, matching a schedule clear
+    pub fn clear_from(&self, from_sample_time: u32) {
+        self.recording
+            .lock()
+            .unwrap()
+            .events
+            .clear_from(from_sample_time);
+    }
+
+    /// Number of recorded writes
+    pub fn len(&self) -> usize {
+        self.recording.lock().unwrap().events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recording.lock().unwrap().events.is_empty()
+    }
+
+    /// Number of writes dropped because the recording was full
+    pub fn dropped(&self) -> usize {
+        self.recording.lock().unwrap().dropped
+    }
+
+    /// The session so far as an event log, starting at the first recorded write
+    pub fn to_event_log(&self) -> EventLog {
+        let recording = self.recording.lock().unwrap();
+        let start = recording.events.next_time().unwrap_or(0);
+        let events = recording
+            .events
+            .iter()
+            .map(|event| RegisterEvent {
+                time: (event.time - start) as f64 / self.sample_rate as f64,
+                addr: event.addr,
+                data: event.data,
+                is_data: None,
+            })
+            .collect();
+
+        EventLog {
+            events,
+            clock: Some(self.clock),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//! Per-channel stem rendering
+//!
+//! Each stem is the log replayed with every other channel masked (see
+//! [`edit::mask_channels`]) and rendered for exactly as long as the full mix,
+//! so the stems line up with it sample for sample.
+//!
+//! The stems do not always add up to the mix exactly. The chip's DAC quantizes
+//! the sum of all channels with a floating-point mantissa, so each stem carries
+//! its own rounding, and shared state such as CSM key-ons, the noise generator
+//! and the LFO can make a channel sound different on its own. [`check_sum`]
+//! reports where the difference exceeds a tolerance.
+
+use anyhow::{bail, Context, Result};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::events::{edit, EventLog};
+use crate::opm::{NOISE_ENABLE, REG_LFO_DEPTH, REG_NOISE, REG_TIMER_CONTROL, TIMER_CSM};
+use crate::pcm::{Dither, SampleFormat};
+use crate::render::{self, RenderOptions, RenderedAudio};
+use crate::wav_writer;
+
+/// Number of stems: one per OPM channel
+pub const STEM_COUNT: usize = 8;
+/// Default tolerance of [`check_sum`], in dB relative to the level of the signal
+pub const DEFAULT_SUM_TOLERANCE_DB: f64 = -24.0;
+/// Differences up to this many 16-bit LSBs are always within tolerance
+const MIN_TOLERANCE_LSB: f32 = 2.0;
+/// Length of the windows [`check_sum`] reports on, in seconds
+const CHECK_WINDOW_SEC: f64 = 0.01;
+/// Frames read from each stem at a time when merging the multichannel WAV
+const MERGE_BLOCK_FRAMES: usize = 4096;
+
+/// How the stems are written
+#[derive(Debug, Clone)]
+pub struct StemOutput {
+    /// Path of the mix; the stems are written next to it (see [`stem_path`]
+    /// and [`multichannel_path`])
+    pub path: String,
+    pub format: SampleFormat,
+    /// Reduction to 16 bits; each stem file gets dither noise of its own
+    pub dither: Dither,
+    /// One multichannel WAV instead of a file per channel
+    pub multichannel: bool,
+}
+
+/// The full mix and what is left of the stems once they are written
+#[derive(Debug, Clone)]
+pub struct Stems {
+    pub mix: RenderedAudio,
+    /// Sum of the stems of all channels, sample for sample with the mix
+    pub sum: Vec<f32>,
+    /// Files written: one per channel, or the multichannel WAV
+    pub paths: Vec<String>,
+}
+
+/// Render the mix and the stem of every channel, writing each stem as soon as
+/// it is rendered so that only one is held in memory at a time
+pub fn render_stems(log: &EventLog, options: &RenderOptions, output: &StemOutput) -> Result<Stems> {
+    let prepared = render::prepare(log, options)?;
+    let mix = render::render_prepared(&prepared, options, None)?;
+    let mut sum = vec![0.0f32; mix.samples.len()];
+    let mut paths = Vec::new();
+    let mut channel_files = ChannelFiles::default();
+
+    for channel in 0..STEM_COUNT {
+        let solo = edit::mask_channels(&prepared, 1 << channel);
+        let stem = render::render_prepared(&solo, options, Some(mix.native_frames))?;
+        if stem.samples.len() != sum.len() {
+            bail!(
+                "stem of ch{} has {} samples, the mix {}",
+                channel,
+                stem.samples.len(),
+                sum.len()
+            );
+        }
+        for (total, sample) in sum.iter_mut().zip(&stem.samples) {
+            *total += sample;
+        }
+
+        if output.multichannel {
+            channel_files.write(&multichannel_path(&output.path), channel, &stem.samples)?;
+        } else {
+            let path = stem_path(&output.path, channel);
+            render::write(
+                &path,
+                &stem,
+                output.format,
+                output.dither.for_channel(channel),
+            )
+            .with_context(|| format!("Failed to write {}", path))?;
+            paths.push(path);
+        }
+    }
+
+    if output.multichannel {
+        let path = multichannel_path(&output.path);
+        channel_files
+            .merge(&path, mix.samples.len() / 2, mix.sample_rate, output)
+            .with_context(|| format!("Failed to write {}", path))?;
+        paths.push(path);
+    }
+
+    Ok(Stems { mix, sum, paths })
+}
+
+/// Stems kept in raw 32-bit float files while the other channels render, to be
+/// merged into the multichannel WAV; the files are removed when dropped
+#[derive(Default)]
+struct ChannelFiles {
+    paths: Vec<String>,
+}
+
+impl ChannelFiles {
+    fn write(&mut self, output: &str, channel: usize, samples: &[f32]) -> Result<()> {
+        let path = format!("{}.ch{}.tmp", output, channel);
+        self.paths.push(path.clone());
+        let mut file = BufWriter::new(
+            File::create(&path).with_context(|| format!("Failed to create {}", path))?,
+        );
+        for sample in samples {
+            file.write_all(&sample.to_le_bytes())?;
+        }
+        file.flush()
+            .with_context(|| format!("Failed to write {}", path))
+    }
+
+    /// Write the stems interleaved, left and right of channel 0, then of
+    /// channel 1, ..., reading a block of frames from each file at a time
+    fn merge(
+        &self,
+        path: &str,
+        frames: usize,
+        sample_rate: u32,
+        output: &StemOutput,
+    ) -> Result<()> {
+        let mut readers = self
+            .paths
+            .iter()
+            .map(|path| File::open(path).map(BufReader::new))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let channels = 2 * readers.len();
+        let mut bytes = Vec::new();
+        let blocks = (0..frames).step_by(MERGE_BLOCK_FRAMES).map(|start| {
+            let block_frames = MERGE_BLOCK_FRAMES.min(frames - start);
+            let mut block = vec![0.0f32; block_frames * channels];
+            bytes.resize(block_frames * 2 * size_of::<f32>(), 0);
+            for (stem, reader) in readers.iter_mut().enumerate() {
+                reader.read_exact(&mut bytes)?;
+                for (i, sample) in bytes.chunks_exact(size_of::<f32>()).enumerate() {
+                    let (frame, side) = (i / 2, i % 2);
+                    block[frame * channels + stem * 2 + side] =
+                        f32::from_le_bytes(sample.try_into().unwrap());
+                }
+            }
+            Ok(block)
+        });
+        wav_writer::write_wav_blocks(
+            path,
+            blocks,
+            channels as u16,
+            sample_rate,
+            output.format,
+            output.dither,
+        )
+    }
+}
+
+impl Drop for ChannelFiles {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Path of the stem of `channel` (0-7) next to `output`: `song.wav` gives
+/// `song_ch0.wav` for channel 0, numbered like `disasm` and `diff` do
+pub fn stem_path(output: &str, channel: usize) -> String {
+    sibling_path(output, &format!("_ch{}", channel), None)
+}
+
+/// Path of the multichannel stem file next to `output`: `song.wav` gives
+/// `song_stems.wav`
+pub fn multichannel_path(output: &str) -> String {
+    sibling_path(output, "_stems", Some("wav"))
+}
+
+fn sibling_path(output: &str, suffix: &str, extension: Option<&str>) -> String {
+    let path = Path::new(output);
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output");
+    let extension = extension
+        .or_else(|| path.extension().and_then(|e| e.to_str()))
+        .unwrap_or("wav");
+    path.with_file_name(format!("{}{}.{}", stem, suffix, extension))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// A stretch of time where the stems do not add up to the mix
+#[derive(Debug, Clone, PartialEq)]
+pub struct SumMismatch {
+    pub start_sec: f64,
+    pub end_sec: f64,
+    /// Largest difference in the stretch, in 16-bit LSBs
+    pub max_error_lsb: f32,
+    /// Largest difference relative to the signal level, in dB
+    pub max_error_db: f64,
+}
+
+/// Result of comparing the sum of the stems with the mix
+#[derive(Debug, Clone, PartialEq)]
+pub struct SumCheck {
+    /// Largest difference over the whole render, in 16-bit LSBs
+    pub max_error_lsb: f32,
+    pub mismatches: Vec<SumMismatch>,
+    /// Features of the log that can keep the stems from adding up
+    pub interactions: Vec<&'static str>,
+}
+
+impl SumCheck {
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Compare the sum of the stems with the mix, reporting every stretch where
+/// they differ by more than `tolerance_db` relative to the signal level
+///
+/// The level is the peak of the mix or of the sum of the stems, whichever is
+/// higher, in windows of 10 ms; adjacent windows over the tolerance are merged.
+pub fn check_sum(log: &EventLog, stems: &Stems, tolerance_db: f64) -> SumCheck {
+    let rate = stems.mix.sample_rate as f64;
+    let window_frames = ((CHECK_WINDOW_SEC * rate).round() as usize).max(1);
+    let frames = stems.mix.samples.len().min(stems.sum.len()) / 2;
+    let tolerance_ratio = 10f64.powf(tolerance_db / 20.0) as f32;
+
+    let mut max_error_lsb = 0.0f32;
+    let mut mismatches: Vec<SumMismatch> = Vec::new();
+    for window_start in (0..frames).step_by(window_frames) {
+        let window_end = (window_start + window_frames).min(frames);
+        let (error, level) = (window_start * 2..window_end * 2)
+            .map(|i| {
+                let (mix, sum) = (stems.mix.samples[i], stems.sum[i]);
+                ((sum - mix).abs(), mix.abs().max(sum.abs()))
+            })
+            .fold((0.0f32, 0.0f32), |(error, level), (e, l)| {
+                (error.max(e), level.max(l))
+            });
+        let error_lsb = error * 32768.0;
+        max_error_lsb = max_error_lsb.max(error_lsb);
+        if error_lsb <= MIN_TOLERANCE_LSB || error <= level * tolerance_ratio {
+            continue;
+        }
+
+        let start_sec = window_start as f64 / rate;
+        let end_sec = window_end as f64 / rate;
+        let error_db = 20.0 * (error as f64 / level as f64).log10();
+        match mismatches.last_mut() {
+            Some(last) if last.end_sec == start_sec => {
+                last.end_sec = end_sec;
+                last.max_error_lsb = last.max_error_lsb.max(error_lsb);
+                last.max_error_db = last.max_error_db.max(error_db);
+            }
+            _ => mismatches.push(SumMismatch {
+                start_sec,
+                end_sec,
+                max_error_lsb: error_lsb,
+                max_error_db: error_db,
+            }),
+        }
+    }
+
+    SumCheck {
+        max_error_lsb,
+        mismatches,
+        interactions: interactions(log),
+    }
+}
+
+/// Chip features used by a log that are shared between channels
+fn interactions(log: &EventLog) -> Vec<&'static str> {
+    let written = |addr: u8, mask: u8| {
+        log.events
+            .iter()
+            .any(|event| event.addr == addr && event.data & mask != 0)
+    };
+    let mut found = Vec::new();
+    if written(REG_NOISE, NOISE_ENABLE) {
+        found.push("noise (channel 8 operator C2)");
+    }
+    if written(REG_TIMER_CONTROL, TIMER_CSM) {
+        found.push("CSM key-on by timer A");
+    }
+    if written(REG_LFO_DEPTH, 0x7F) {
+        found.push("LFO");
+    }
+    found
+}
@@ -0,0 +1,218 @@
+//! Lock-free submission ring between the IPC side and the generator thread
+//!
+//! Interactive mode receives register writes on the IPC/command thread while the
+//! MMCSS-boosted generator thread renders audio. Sharing a `Mutex<VecDeque>` between
+//! them meant the generator could stall behind a lower-priority thread that was
+//! sorting the whole queue. This module replaces that with a bounded ring:
+//!
+//! - **Producers** ([`EventSubmitter`], cloneable, any number of threads) claim a slot
+//!   with a single CAS and never touch the schedule itself.
+//! - **Consumer** ([`SubmissionReceiver`], exactly one, owned by `Player`) drains the
+//!   ring without ever spinning or blocking. Ordering and merging of the drained
+//!   operations happen on the generator side.
+//!
+//! The slot layout follows Dmitry Vyukov's bounded MPMC queue, restricted to a
+//! single consumer so that `pop` is wait-free.
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::player::ProcessedEvent;
+
+/// Default ring capacity (number of in-flight operations)
+///
+/// The generator drains the ring once per generation buffer (~18ms), so this only
+/// needs to hold what the IPC side can submit within a few buffers.
+pub const DEFAULT_RING_CAPACITY: usize = 1 << 16;
+
+/// An operation submitted from the IPC side to the generator thread
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubmissionOp {
+    /// Schedule a register write (addr-data pair) at the given sample time
+    Write(ProcessedEvent),
+    /// Remove all scheduled events with time >= the given sample time
+    ClearFrom(u32),
+    /// Remove all scheduled events
+    ClearAll,
+}
+
+struct Slot {
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<SubmissionOp>>,
+}
+
+struct Ring {
+    slots: Box<[Slot]>,
+    mask: usize,
+    enqueue_pos: AtomicUsize,
+    dequeue_pos: AtomicUsize,
+    /// Number of events in the generator-side schedule, published by the consumer
+    scheduled: AtomicUsize,
+}
+
+// SAFETY: a slot's value is only written by the producer that won the CAS for that
+// position and only read by the single consumer after the producer's Release store
+// to `sequence`, so no slot is ever accessed concurrently.
+unsafe impl Sync for Ring {}
+unsafe impl Send for Ring {}
+
+impl Ring {
+    fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+        let slots = (0..capacity)
+            .map(|i| Slot {
+                sequence: AtomicUsize::new(i),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
+
+        Self {
+            slots,
+            mask: capacity - 1,
+            enqueue_pos: AtomicUsize::new(0),
+            dequeue_pos: AtomicUsize::new(0),
+            scheduled: AtomicUsize::new(0),
+        }
+    }
+
+    fn try_push(&self, op: SubmissionOp) -> Result<(), SubmissionOp> {
+        let mut pos = self.enqueue_pos.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let diff = sequence.wrapping_sub(pos) as isize;
+
+            if diff == 0 {
+                match self.enqueue_pos.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: winning the CAS gives this producer exclusive access
+                        // to the slot until it publishes the new sequence below.
+                        unsafe { (*slot.value.get()).write(op) };
+                        slot.sequence.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if diff < 0 {
+                // The consumer has not yet freed this slot: the ring is full
+                return Err(op);
+            } else {
+                pos = self.enqueue_pos.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Pop one operation. Must only be called by the single consumer.
+    fn pop(&self) -> Option<SubmissionOp> {
+        let pos = self.dequeue_pos.load(Ordering::Relaxed);
+        let slot = &self.slots[pos & self.mask];
+        let sequence = slot.sequence.load(Ordering::Acquire);
+
+        if sequence != pos.wrapping_add(1) {
+            // Empty, or the producer for this slot has not published yet.
+            // Either way the generator moves on and picks it up on the next drain.
+            return None;
+        }
+
+        self.dequeue_pos
+            .store(pos.wrapping_add(1), Ordering::Relaxed);
+        // SAFETY: the Acquire load above observed the producer's Release store,
+        // so the value is initialized and no producer will touch it until we
+        // hand the slot back below.
+        let op = unsafe { (*slot.value.get()).assume_init_read() };
+        slot.sequence
+            .store(pos.wrapping_add(self.mask + 1), Ordering::Release);
+        Some(op)
+    }
+
+    fn len(&self) -> usize {
+        let tail = self.enqueue_pos.load(Ordering::Relaxed);
+        let head = self.dequeue_pos.load(Ordering::Relaxed);
+        tail.wrapping_sub(head).min(self.mask + 1)
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+/// Create a connected submitter/receiver pair
+pub fn submission_ring(capacity: usize) -> (EventSubmitter, SubmissionReceiver) {
+    let ring = Arc::new(Ring::with_capacity(capacity));
+    (
+        EventSubmitter { ring: ring.clone() },
+        SubmissionReceiver { ring },
+    )
+}
+
+/// Producer handle used by the IPC side (cloneable, lock-free)
+#[derive(Clone)]
+pub struct EventSubmitter {
+    ring: Arc<Ring>,
+}
+
+impl EventSubmitter {
+    /// Try to submit an operation without waiting
+    ///
+    /// Returns the operation back if the ring is full.
+    pub fn try_submit(&self, op: SubmissionOp) -> Result<(), SubmissionOp> {
+        self.ring.try_push(op)
+    }
+
+    /// Submit an operation, yielding while the ring is full
+    ///
+    /// Back-pressure is applied to the producer only; the generator thread is
+    /// never blocked by a full ring.
+    pub fn submit(&self, op: SubmissionOp) {
+        let mut op = op;
+        while let Err(returned) = self.ring.try_push(op) {
+            op = returned;
+            std::thread::yield_now();
+        }
+    }
+
+    /// Schedule a register write at the given sample time
+    pub fn submit_write(&self, time: u32, addr: u8, data: u8) {
+        self.submit(SubmissionOp::Write(ProcessedEvent { time, addr, data }));
+    }
+
+    /// Number of operations submitted but not yet drained by the generator
+    pub fn in_flight(&self) -> usize {
+        self.ring.len()
+    }
+
+    /// Approximate number of scheduled events
+    ///
+    /// This is the generator-side schedule size as of its last drain, plus the
+    /// operations still in flight. It is intended for diagnostics only.
+    pub fn scheduled_event_count(&self) -> usize {
+        self.ring.scheduled.load(Ordering::Relaxed) + self.ring.len()
+    }
+}
+
+/// Consumer handle owned by the generator side (exactly one per ring)
+pub struct SubmissionReceiver {
+    ring: Arc<Ring>,
+}
+
+impl SubmissionReceiver {
+    /// Pop the next ready operation, if any. Never blocks.
+    pub fn pop(&mut self) -> Option<SubmissionOp> {
+        self.ring.pop()
+    }
+
+    /// Publish the generator-side schedule size for [`EventSubmitter::scheduled_event_count`]
+    pub fn publish_scheduled_count(&self, count: usize) {
+        self.ring.scheduled.store(count, Ordering::Relaxed);
+    }
+}
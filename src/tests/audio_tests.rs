@@ -11,6 +11,7 @@ fn test_audio_player_creation() {
             data: 0x00,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let player = Player::new(log);
@@ -45,6 +46,7 @@ fn test_audio_player_short_playback() {
                 is_data: None,
             },
         ],
+        ..Default::default()
     };
 
     let player = Player::new(log);
@@ -59,3 +61,31 @@ fn test_audio_player_short_playback() {
         }
     }
 }
+
+#[test]
+fn test_marker_feed_sequence() {
+    use crate::audio::MarkerFeed;
+    use crate::events::Marker;
+
+    let marker = |name: &str| Marker {
+        time: 1.0,
+        name: name.to_string(),
+    };
+    let feed = MarkerFeed::new(vec![marker("a"), marker("b"), marker("c")]);
+    assert!(feed.latest().is_none());
+    assert!(feed.since(0).is_empty());
+
+    feed.publish(2);
+    feed.clone().publish(1);
+
+    let all = feed.since(0);
+    assert_eq!(all.iter().map(|m| m.seq).collect::<Vec<_>>(), [1, 2, 3]);
+    let newer: Vec<String> = feed.since(2).into_iter().map(|m| m.name).collect();
+    assert_eq!(newer, ["c"]);
+    assert_eq!(feed.latest().unwrap().name, "c");
+    assert!(feed.since(3).is_empty());
+
+    // Publishing past the end of the log is clamped
+    feed.publish(1);
+    assert_eq!(feed.since(0).len(), 3);
+}
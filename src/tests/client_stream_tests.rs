@@ -0,0 +1,35 @@
+use crate::client::stream::{NdjsonChunks, STREAM_CHUNK_EVENTS};
+
+#[test]
+fn test_ndjson_chunks_split_by_time() {
+    let input = r#"{"time": 0.0, "addr": "0x08", "data": "0x00"}
+
+{"time": 0.5, "addr": 32, "data": 199}
+{"time": 1.0, "addr": "0x08", "data": "0x78"}
+{"time": 2.5, "addr": "0x08", "data": "0x00"}
+"#;
+    let chunks: Vec<Vec<f64>> = NdjsonChunks::new(input.as_bytes())
+        .map(|chunk| chunk.unwrap().iter().map(|e| e.time).collect())
+        .collect();
+    assert_eq!(chunks, vec![vec![0.0, 0.5], vec![1.0], vec![2.5]]);
+}
+
+#[test]
+fn test_ndjson_chunks_split_by_count() {
+    let input: String = (0..STREAM_CHUNK_EVENTS + 1)
+        .map(|_| "{\"time\": 0, \"addr\": \"0x08\", \"data\": \"0x00\"}\n")
+        .collect();
+    let sizes: Vec<usize> = NdjsonChunks::new(input.as_bytes())
+        .map(|chunk| chunk.unwrap().len())
+        .collect();
+    assert_eq!(sizes, vec![STREAM_CHUNK_EVENTS, 1]);
+}
+
+#[test]
+fn test_ndjson_reports_bad_line() {
+    let input = "{\"time\": 0, \"addr\": \"0x08\", \"data\": \"0x00\"}\nnot json\n";
+    let error = NdjsonChunks::new(input.as_bytes())
+        .find_map(Result::err)
+        .unwrap();
+    assert!(format!("{:#}", error).starts_with("line 2"), "{:#}", error);
+}
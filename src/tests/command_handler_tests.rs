@@ -150,7 +150,9 @@ fn test_handler_state_transitions() {
     let response = handler.handle_command(get_state_cmd, &mut audio_player);
 
     match response {
-        Response::ServerState { state: state_str } => {
+        Response::ServerState {
+            state: state_str, ..
+        } => {
             assert_eq!(state_str, "Stopped");
         }
         _ => panic!("Expected ServerState response"),
@@ -190,3 +192,48 @@ fn test_early_return_pattern() {
         _ => panic!("Expected immediate error response for wrong state"),
     }
 }
+
+/// Test that stream chunks are rejected when no stream was begun
+#[test]
+fn test_append_events_error_without_stream() {
+    let state = Arc::new(Mutex::new(ServerState::Stopped));
+    let shutdown_flag = Arc::new(AtomicBool::new(false));
+    let time_tracker = Arc::new(Mutex::new(TimeTracker::new()));
+    let playback_manager = PlaybackManager::new(ResamplingQuality::Linear);
+
+    let handler = CommandHandler::new(state, shutdown_flag, time_tracker, playback_manager);
+
+    let mut audio_player = None;
+    let command = Command::AppendEvents {
+        data: serde_json::json!({"events": []}),
+    };
+    for command in [command, Command::EndStream] {
+        match handler.handle_command(command, &mut audio_player) {
+            Response::Error { message } => assert!(message.contains("No stream in progress")),
+            other => panic!("Expected error response without a stream, got {:?}", other),
+        }
+    }
+}
+
+/// Test that RenderPcm is not rendered on the command thread
+#[test]
+fn test_render_pcm_is_left_to_the_render_worker() {
+    let state = Arc::new(Mutex::new(ServerState::Stopped));
+    let shutdown_flag = Arc::new(AtomicBool::new(false));
+    let time_tracker = Arc::new(Mutex::new(TimeTracker::new()));
+    let playback_manager = PlaybackManager::new(ResamplingQuality::Linear);
+
+    let handler = CommandHandler::new(state, shutdown_flag, time_tracker, playback_manager);
+
+    let mut audio_player = None;
+    let command = Command::RenderPcm {
+        data: serde_json::json!({"events": [{"time": 0.0, "addr": "0x08", "data": "0x00"}]}),
+        sample_rate: None,
+        format: "16".to_string(),
+        path: None,
+    };
+    match handler.handle_command(command, &mut audio_player) {
+        Response::Error { message } => assert!(message.contains("render worker")),
+        other => panic!("Expected error response for RenderPcm, got {:?}", other),
+    }
+}
@@ -0,0 +1,60 @@
+use crate::compression::{self, Compression};
+use crate::events::EventLog;
+use crate::formats;
+
+const JSON: &str = r#"{"events": [{"time": 0.5, "addr": "0x08", "data": "0x78"}]}"#;
+
+#[test]
+fn test_round_trip_and_detect() {
+    for compression in [Compression::Gzip, Compression::Zstd] {
+        let packed = compression::compress(JSON.as_bytes(), compression).unwrap();
+        assert_eq!(Compression::detect(&packed), compression);
+        let unpacked = compression::decompress_detected(&packed, 1024).unwrap();
+        assert_eq!(unpacked, JSON.as_bytes());
+    }
+    assert_eq!(Compression::detect(JSON.as_bytes()), Compression::None);
+}
+
+#[test]
+fn test_decompression_is_bounded() {
+    // Megabytes of zeros compress to almost nothing
+    let zeros = vec![0u8; 4 * 1024 * 1024];
+    for compression in [Compression::Gzip, Compression::Zstd] {
+        let packed = compression::compress(&zeros, compression).unwrap();
+        assert!(packed.len() < 64 * 1024);
+        let error = compression::decompress(&packed, compression, 1024 * 1024).unwrap_err();
+        assert!(
+            error.to_string().contains("more than 1048576 bytes"),
+            "{}",
+            error
+        );
+        // An exact fit is fine
+        assert!(compression::decompress(&packed, compression, zeros.len()).is_ok());
+    }
+}
+
+#[test]
+fn test_compression_from_path() {
+    assert_eq!(Compression::from_path("song.json.gz"), Compression::Gzip);
+    assert_eq!(Compression::from_path("song.JSON.ZST"), Compression::Zstd);
+    assert_eq!(Compression::from_path("song.vgz"), Compression::Gzip);
+    assert_eq!(Compression::from_path("song.json"), Compression::None);
+}
+
+#[test]
+fn test_compressed_log_files() {
+    let dir = std::env::temp_dir();
+    let log = EventLog::from_json_str(JSON).unwrap();
+    for name in ["compression_test.json.gz", "compression_test.json.zst"] {
+        let path = dir.join(name);
+        formats::save_event_log(&path, &log).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        assert_ne!(Compression::detect(&bytes), Compression::None);
+
+        let loaded = EventLog::from_file(&path).unwrap();
+        assert_eq!(loaded.events.len(), 1);
+        assert_eq!(loaded.events[0].data, 0x78);
+        assert_eq!(formats::load_event_log(&path).unwrap().log.events.len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
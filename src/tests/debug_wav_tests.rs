@@ -35,6 +35,7 @@ fn test_generate_post_playback_buffers() {
                 is_data: None,
             },
         ],
+        ..Default::default()
     };
 
     let result = generate_post_playback_buffers(&log, ResamplingQuality::Linear);
@@ -65,6 +66,7 @@ fn test_generate_post_playback_buffers_high_quality() {
                 is_data: None,
             },
         ],
+        ..Default::default()
     };
 
     let result = generate_post_playback_buffers(&log, ResamplingQuality::HighQuality);
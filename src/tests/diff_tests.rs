@@ -0,0 +1,97 @@
+use super::{event, log};
+use crate::diff::{self, ChangeKind, DiffOptions};
+use crate::events::{EventLog, RegisterEvent};
+
+fn note() -> Vec<RegisterEvent> {
+    vec![
+        event(0.0, 0x20, 0xC7),
+        event(0.0, 0x28, 0x4A),
+        event(0.0, 0x60, 0x10),
+        event(0.0, 0x08, 0x78),
+        event(0.5, 0x08, 0x00),
+    ]
+}
+
+#[test]
+fn test_identical_logs() {
+    let result = diff::diff(&log(note()), &log(note()), &DiffOptions::default());
+    assert!(result.is_empty());
+    assert_eq!(result.unchanged, 5);
+}
+
+#[test]
+fn test_changed_added_and_removed() {
+    let mut new = note();
+    new[1].data = 0x4E; // KC A4 -> C5
+    new.remove(2); // TL
+    new.insert(3, event(0.0, 0x18, 0x80)); // LFRQ
+    let result = diff::diff(&log(note()), &log(new), &DiffOptions::default());
+
+    assert_eq!(result.count(ChangeKind::Changed), 1);
+    assert_eq!(result.count(ChangeKind::Added), 1);
+    assert_eq!(result.count(ChangeKind::Removed), 1);
+
+    let groups = result.grouped();
+    let ch0 = &groups[&Some(0)];
+    assert_eq!(ch0["KC"][0].kind, ChangeKind::Changed);
+    assert_eq!(ch0["M1 TL"][0].kind, ChangeKind::Removed);
+    assert_eq!(groups[&None]["LFRQ"][0].kind, ChangeKind::Added);
+
+    let report = result.report();
+    assert!(
+        report.contains("1 changed, 1 added, 1 removed, 3 unchanged"),
+        "{}",
+        report
+    );
+    assert!(report.contains("ch0 KC=A4 → ch0 KC=C5"), "{}", report);
+    assert!(report.contains("- ch0 M1 TL=0x10"), "{}", report);
+}
+
+#[test]
+fn test_time_epsilon() {
+    let mut new = note();
+    new[4].time = 0.5004;
+
+    let result = diff::diff(&log(note()), &log(new.clone()), &DiffOptions::default());
+    assert!(result.is_empty());
+
+    let strict = DiffOptions { time_epsilon: 0.0 };
+    let result = diff::diff(&log(note()), &log(new), &strict);
+    assert_eq!(result.count(ChangeKind::Removed), 1);
+    assert_eq!(result.count(ChangeKind::Added), 1);
+    assert_eq!(result.changes[0].parameter, "KEY");
+    assert_eq!(result.changes[0].channel, Some(0));
+}
+
+#[test]
+fn test_key_on_aligned_per_channel() {
+    let old = log(vec![event(0.0, 0x08, 0x78), event(0.0, 0x08, 0x79)]);
+    let new = log(vec![event(0.0, 0x08, 0x79), event(0.0, 0x08, 0x78)]);
+    assert!(diff::diff(&old, &new, &DiffOptions::default()).is_empty());
+}
+
+#[test]
+fn test_clock_difference() {
+    let new = EventLog {
+        clock: Some(4_000_000),
+        ..log(note())
+    };
+    let result = diff::diff(&log(note()), &new, &DiffOptions::default());
+    assert_eq!(result.clock, Some((3_579_545, 4_000_000)));
+    assert!(!result.is_empty());
+}
+
+#[test]
+fn test_first_divergence() {
+    // Full attack rate so the note is audible
+    let mut events = note();
+    events.splice(0..0, (0..4).map(|slot| event(0.0, 0x80 + slot * 8, 0x1F)));
+    let old = log(events.clone());
+    assert_eq!(diff::first_divergence(&old, &old).unwrap(), None);
+
+    events[7].time = 0.1;
+    let divergence = diff::first_divergence(&old, &log(events))
+        .unwrap()
+        .expect("the delayed key-on changes the output");
+    assert!(divergence.time > 0.0 && divergence.time < 0.1);
+}
@@ -0,0 +1,175 @@
+use super::{event, log};
+use crate::disasm::{assemble, disassemble};
+use crate::events::{EventLog, LogMetadata, Marker};
+
+/// Disassembly of a single write, without the time and header
+fn line(addr: u8, data: u8) -> String {
+    let text = disassemble(&log(vec![event(0.0, addr, data)]));
+    let line = text.lines().last().unwrap().trim();
+    line.strip_prefix("0s").unwrap().trim().to_string()
+}
+
+#[test]
+fn test_key_on_and_off() {
+    assert_eq!(line(0x08, 0x78), "KEY ON ch0 ops=M1,C1,M2,C2");
+    assert_eq!(line(0x08, 0x53), "KEY ON ch3 ops=C1,C2");
+    assert_eq!(line(0x08, 0x05), "KEY OFF ch5");
+}
+
+#[test]
+fn test_channel_registers() {
+    assert_eq!(
+        line(0x20, 0xC7),
+        "ch0 RL=LR FB=0 CON=7             ; carriers M1,M2,C1,C2"
+    );
+    assert_eq!(
+        line(0x23, 0x44),
+        "ch3 RL=L FB=0 CON=4              ; carriers C1,C2"
+    );
+    assert_eq!(line(0x2B, 0x4A), "ch3 KC=A4                        ; A4+0c");
+    assert_eq!(line(0x3B, 0x21), "ch3 PMS=2 AMS=1");
+}
+
+#[test]
+fn test_slot_registers() {
+    // Slot 1 of channel 1 is M2
+    assert_eq!(
+        line(0x69, 0x1F),
+        "ch1 M2 TL=0x1F                   ; -23.25 dB"
+    );
+    assert_eq!(line(0x40, 0x31), "ch0 M1 DT1=3 MUL=1");
+    assert_eq!(line(0x60, 0x00), "ch0 M1 TL=0x00                   ; 0 dB");
+    assert_eq!(line(0xB2, 0x85), "ch2 C1 AMS-EN=1 D1R=5");
+    assert_eq!(
+        line(0xFF, 0xF7),
+        "ch7 C2 D1L=15 RR=7               ; D1L -93 dB"
+    );
+}
+
+#[test]
+fn test_global_registers() {
+    assert_eq!(line(0x19, 0x7F), "AMD=127");
+    assert_eq!(line(0x19, 0x90), "PMD=16");
+    assert_eq!(
+        line(0x1B, 0x02),
+        "CT=0 W=2                         ; LFO triangle"
+    );
+    assert_eq!(line(0x14, 0x15), "CSM=0 RESET=A IRQ=A LOAD=A");
+    assert_eq!(
+        line(0x0F, 0x9F),
+        "NE=1 NFRQ=31                     ; noise on"
+    );
+}
+
+#[test]
+fn test_unmapped_writes_are_raw() {
+    assert!(line(0x02, 0x12).starts_with("REG 0x02=0x12"));
+    // KF only uses bits 2-7
+    assert!(line(0x30, 0x01).starts_with("REG 0x30=0x01"));
+    // Invalid key code note
+    assert!(line(0x28, 0x4F).starts_with("ch0 KC=0x4F"));
+}
+
+#[test]
+fn test_pitch_annotation_follows_key_fraction_and_clock() {
+    let mut input = log(vec![event(0.0, 0x28, 0x4A), event(0.1, 0x30, 0x20)]);
+    let text = disassemble(&input);
+    assert!(
+        text.lines().last().unwrap().ends_with("; A4+13c"),
+        "{}",
+        text
+    );
+
+    // One octave up from the standard clock
+    input.clock = Some(3_579_545 * 2);
+    let text = disassemble(&input);
+    assert!(
+        text.lines().last().unwrap().ends_with("; A5+13c"),
+        "{}",
+        text
+    );
+}
+
+#[test]
+fn test_round_trip_every_register_value() {
+    let mut events = Vec::new();
+    for addr in 0..=255u8 {
+        for data in [0x00, 0x01, 0x3C, 0x4A, 0x80, 0xC7, 0xFF] {
+            events.push(event(events.len() as f64 / 1000.0, addr, data));
+        }
+    }
+    let input = EventLog {
+        events,
+        clock: Some(4_000_000),
+        loop_time: Some(0.123),
+        metadata: Some(LogMetadata {
+            title: Some("Test \"song\"".to_string()),
+            notes: Some("line 1\nline 2 ; not a comment".to_string()),
+            ..Default::default()
+        }),
+        markers: vec![
+            Marker {
+                time: 0.0015,
+                name: "intro".to_string(),
+            },
+            Marker {
+                time: 100.0,
+                name: "end ; \"quoted\"".to_string(),
+            },
+        ],
+    };
+
+    let text = disassemble(&input);
+    assert!(text.contains(".marker 0.0015s \"intro\""), "{}", text);
+    let output = assemble(&text).unwrap();
+
+    assert_eq!(output.clock, input.clock);
+    assert_eq!(output.loop_time, input.loop_time);
+    assert_eq!(output.metadata, input.metadata);
+    assert_eq!(output.markers, input.markers);
+    assert_eq!(output.events.len(), input.events.len());
+    for (a, b) in input.events.iter().zip(&output.events) {
+        assert_eq!((a.time, a.addr, a.data), (b.time, b.addr, b.data));
+    }
+}
+
+#[test]
+fn test_assemble_hand_written_text() {
+    let text = "\
+; a hand-written phrase
+.clock 3579545
+0s     ch0 CON=7 FB=0 RL=LR   ; fields in any order
+0s     ch0 KC=C#5
+0.5s   ch0 C2 TL=20
+0.5s   KEY ON ch0 ops=C2
+1s     KEY OFF ch0
+";
+    let output = assemble(text).unwrap();
+    let writes: Vec<(f64, u8, u8)> = output
+        .events
+        .iter()
+        .map(|e| (e.time, e.addr, e.data))
+        .collect();
+    assert_eq!(
+        writes,
+        vec![
+            (0.0, 0x20, 0xC7),
+            (0.0, 0x28, 0x50),
+            (0.5, 0x78, 20),
+            (0.5, 0x08, 0x40),
+            (1.0, 0x08, 0x00),
+        ]
+    );
+}
+
+#[test]
+fn test_assemble_errors_name_the_line() {
+    let error = assemble("0s KEY OFF ch0\n0.1s ch0 M1 TL=200\n").unwrap_err();
+    assert!(format!("{:#}", error).contains("line 2"), "{:#}", error);
+
+    let error = assemble("0s ch0 FOO=1\n").unwrap_err();
+    assert!(format!("{:#}", error).contains("line 1"), "{:#}", error);
+
+    assert!(assemble("soon KEY OFF ch0\n").is_err());
+    assert!(assemble("0s KEY OFF ch8\n").is_err());
+}
@@ -0,0 +1,139 @@
+use crate::driver::{DriverStep, TimerDrivenLog};
+use crate::events::{EventLog, Marker};
+use crate::resampler::OPM_SAMPLE_RATE;
+
+/// CLKA = 0x3C0, load + IRQ enable A
+const TIMER_SETUP: &str = r#"
+    {"addr": "0x10", "data": "0xF0"},
+    {"addr": "0x11", "data": "0x00"},
+    {"addr": "0x14", "data": "0x15"}
+"#;
+
+fn to_samples(time: f64) -> u32 {
+    (time * OPM_SAMPLE_RATE as f64).round() as u32
+}
+
+#[test]
+fn test_parse_timer_driven_log() {
+    let json = r#"{"driver": "timer_a", "events": [
+        {"addr": "0x08", "data": "0x78"},
+        {"wait_timer_a": 3}
+    ]}"#;
+    let log = TimerDrivenLog::from_json_str(json).unwrap();
+
+    assert_eq!(
+        log.events,
+        vec![
+            DriverStep::Write {
+                addr: 0x08,
+                data: 0x78
+            },
+            DriverStep::WaitTimerA { wait_timer_a: 3 },
+        ]
+    );
+}
+
+#[test]
+fn test_unknown_driver_is_rejected() {
+    let json = r#"{"driver": "vsync", "events": []}"#;
+    let err = EventLog::from_json_str(json).unwrap_err();
+    assert!(err.to_string().contains("unsupported driver"));
+}
+
+#[test]
+fn test_writes_are_spaced_like_player() {
+    let json = r#"{"driver": "timer_a", "events": [
+        {"addr": "0x20", "data": "0xC7"},
+        {"addr": "0x28", "data": "0x4A"},
+        {"addr": "0x08", "data": "0x78"}
+    ]}"#;
+    let log = EventLog::from_json_str(json).unwrap();
+
+    let samples: Vec<u32> = log.events.iter().map(|e| to_samples(e.time)).collect();
+    assert_eq!(samples, vec![0, 4, 8]);
+    assert!(log.validate());
+}
+
+#[test]
+fn test_wait_timer_a_advances_by_timer_period() {
+    let json = format!(
+        r#"{{"driver": "timer_a", "events": [{},
+            {{"wait_timer_a": 1}},
+            {{"addr": "0x08", "data": "0x78"}},
+            {{"wait_timer_a": 4}},
+            {{"addr": "0x08", "data": "0x00"}}
+        ]}}"#,
+        TIMER_SETUP
+    );
+    let log = EventLog::from_json_str(&json).unwrap();
+
+    let key_events: Vec<u32> = log
+        .events
+        .iter()
+        .filter(|e| e.addr == 0x08)
+        .map(|e| to_samples(e.time))
+        .collect();
+    assert_eq!(key_events.len(), 2);
+
+    // Overflows are evenly spaced: acknowledging does not disturb the timer
+    let acks: Vec<u32> = log
+        .events
+        .iter()
+        .filter(|e| e.addr == 0x14 && e.data == 0x15)
+        .map(|e| to_samples(e.time))
+        .collect();
+    let period = acks[2] - acks[1];
+    assert!(period > 0);
+    assert!(acks.windows(2).skip(1).all(|w| w[1] - w[0] == period));
+
+    // Four overflows, regardless of the writes in between
+    assert_eq!(key_events[1] - key_events[0], 4 * period);
+
+    // Every overflow is acknowledged with a flag reset on 0x14 (plus the setup write)
+    assert_eq!(acks.len(), 1 + 1 + 4);
+}
+
+#[test]
+fn test_loop_time_and_markers_are_carried_over() {
+    let json = r#"{"driver": "timer_a", "loop_time": 0.5,
+        "markers": [{"time": 0.25, "name": "A"}],
+        "events": [{"addr": "0x08", "data": "0x78"}]}"#;
+    let log = EventLog::from_json_str(json).unwrap();
+    assert_eq!(log.loop_time, Some(0.5));
+    assert_eq!(
+        log.markers,
+        vec![Marker {
+            time: 0.25,
+            name: "A".to_string()
+        }]
+    );
+}
+
+#[test]
+fn test_wait_without_running_timer_is_an_error() {
+    let json = r#"{"driver": "timer_a", "events": [
+        {"addr": "0x08", "data": "0x78"},
+        {"wait_timer_a": 1}
+    ]}"#;
+    let err = EventLog::from_json_str(json).unwrap_err();
+    assert!(err.to_string().contains("event 1"));
+}
+
+#[test]
+fn test_waits_past_the_length_limit_are_rejected() {
+    // Rejected up front, not after billions of overflows
+    let json = format!(
+        r#"{{"driver": "timer_a", "events": [{}, {{"wait_timer_a": 4294967295}}]}}"#,
+        TIMER_SETUP
+    );
+    let err = EventLog::from_json_str(&json).unwrap_err();
+    assert!(err.to_string().contains("3600 seconds"), "{}", err);
+}
+
+#[test]
+fn test_timed_logs_are_unaffected() {
+    let json = r#"{"events": [{"time": 0.5, "addr": "0x08", "data": "0x00"}]}"#;
+    let log = EventLog::from_json_str(json).unwrap();
+    assert_eq!(log.events.len(), 1);
+    assert_eq!(log.events[0].time, 0.5);
+}
@@ -0,0 +1,77 @@
+use crate::event_schedule::EventSchedule;
+use crate::player::ProcessedEvent;
+
+fn event(time: u32, addr: u8, data: u8) -> ProcessedEvent {
+    ProcessedEvent { time, addr, data }
+}
+
+#[test]
+fn test_events_are_ordered_by_time() {
+    let mut schedule = EventSchedule::new();
+    schedule.push(event(300, 0x28, 0x3E));
+    schedule.push(event(100, 0x08, 0x78));
+    schedule.push(event(200, 0x30, 0x4F));
+
+    let times: Vec<u32> = schedule.iter().map(|e| e.time).collect();
+    assert_eq!(times, vec![100, 200, 300]);
+    assert_eq!(schedule.next_time(), Some(100));
+}
+
+#[test]
+fn test_same_time_events_keep_insertion_order() {
+    let mut schedule = EventSchedule::new();
+    schedule.push(event(100, 0x20, 0xC7));
+    schedule.push(event(50, 0x01, 0x00));
+    schedule.push(event(100, 0x08, 0x78));
+    schedule.push(event(100, 0x28, 0x4A));
+
+    assert_eq!(schedule.pop_first(), Some(event(50, 0x01, 0x00)));
+    assert_eq!(schedule.pop_first(), Some(event(100, 0x20, 0xC7)));
+    assert_eq!(schedule.pop_first(), Some(event(100, 0x08, 0x78)));
+    assert_eq!(schedule.pop_first(), Some(event(100, 0x28, 0x4A)));
+    assert_eq!(schedule.pop_first(), None);
+}
+
+#[test]
+fn test_clear_from_removes_events_at_and_after_time() {
+    let mut schedule = EventSchedule::new();
+    for time in [100, 199, 200, 200, 300] {
+        schedule.push(event(time, 0x08, 0x00));
+    }
+
+    schedule.clear_from(200);
+
+    let times: Vec<u32> = schedule.iter().map(|e| e.time).collect();
+    assert_eq!(times, vec![100, 199]);
+
+    // Events added after the clear are scheduled normally
+    schedule.push(event(250, 0x08, 0x01));
+    assert_eq!(schedule.len(), 3);
+}
+
+#[test]
+fn test_clear_removes_everything() {
+    let mut schedule = EventSchedule::new();
+    schedule.push(event(100, 0x08, 0x00));
+    schedule.push(event(200, 0x08, 0x00));
+
+    schedule.clear();
+
+    assert!(schedule.is_empty());
+    assert_eq!(schedule.next_time(), None);
+}
+
+#[test]
+fn test_large_reverse_order_submission_is_sorted() {
+    let mut schedule = EventSchedule::new();
+    for time in (0..100_000u32).rev() {
+        schedule.push(event(time, 0x08, (time & 0xFF) as u8));
+    }
+
+    assert_eq!(schedule.len(), 100_000);
+    let mut last = 0;
+    while let Some(e) = schedule.pop_first() {
+        assert!(e.time >= last);
+        last = e.time;
+    }
+}
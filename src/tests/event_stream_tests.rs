@@ -0,0 +1,207 @@
+use super::event;
+use crate::audio_config::timing::STREAM_MAX_QUEUED_CHUNKS;
+use crate::event_stream::{event_stream, parse_chunk};
+use crate::player::{Player, ProcessedEvent};
+use crate::resampler::YM2151_CLOCK;
+
+#[test]
+fn test_chunks_arrive_in_order() {
+    let (mut writer, mut reader) = event_stream(1000);
+    writer.append(&[event(0.0, 0x08, 0x00)]).unwrap();
+    writer
+        .append(&[event(0.5, 0x20, 0xC7), event(1.0, 0x08, 0x78)])
+        .unwrap();
+
+    let mut events = Vec::new();
+    assert!(reader.next_chunk(&mut events));
+    assert_eq!(
+        events,
+        vec![ProcessedEvent {
+            time: 0,
+            addr: 0x08,
+            data: 0x00
+        }]
+    );
+    assert_eq!(reader.received_until(), 0);
+
+    assert!(reader.next_chunk(&mut events));
+    assert_eq!(
+        events,
+        vec![
+            ProcessedEvent {
+                time: 500,
+                addr: 0x20,
+                data: 0xC7
+            },
+            ProcessedEvent {
+                time: 1000,
+                addr: 0x08,
+                data: 0x78
+            },
+        ]
+    );
+    assert_eq!(reader.received_until(), 1000);
+    assert!(!reader.is_ended());
+    assert!(!reader.next_chunk(&mut events));
+}
+
+#[test]
+fn test_played_buffers_are_reused() {
+    let (mut writer, mut reader) = event_stream(1000);
+    writer.append(&[event(0.0, 0x08, 0x00)]).unwrap();
+
+    // The buffer swapped out is handed back and filled with the next chunk
+    let mut events = Vec::with_capacity(100);
+    assert!(reader.next_chunk(&mut events));
+    writer.append(&[event(1.0, 0x08, 0x78)]).unwrap();
+    assert!(reader.next_chunk(&mut events));
+    assert_eq!(events.capacity(), 100);
+    assert_eq!(
+        events,
+        vec![ProcessedEvent {
+            time: 1000,
+            addr: 0x08,
+            data: 0x78
+        }]
+    );
+}
+
+#[test]
+fn test_writer_rejects_going_back_and_appending_after_end() {
+    let (mut writer, mut reader) = event_stream(1000);
+    writer.append(&[event(1.0, 0x08, 0x00)]).unwrap();
+    assert!(writer.append(&[event(0.5, 0x08, 0x00)]).is_err());
+    // Same time as the previous chunk is fine
+    writer.append(&[event(1.0, 0x08, 0x78)]).unwrap();
+
+    writer.end().unwrap();
+    assert!(writer.append(&[event(2.0, 0x08, 0x00)]).is_err());
+    assert!(writer.end().is_err());
+
+    let mut events = Vec::new();
+    while reader.next_chunk(&mut events) {}
+    assert!(reader.is_ended());
+}
+
+#[test]
+fn test_full_queue_turns_chunks_away() {
+    let (mut writer, mut reader) = event_stream(1000);
+    for i in 0..STREAM_MAX_QUEUED_CHUNKS {
+        assert!(writer.append(&[event(i as f64, 0x08, 0x00)]).unwrap());
+    }
+    let next = event(STREAM_MAX_QUEUED_CHUNKS as f64, 0x08, 0x00);
+    assert!(!writer.append(&[next]).unwrap());
+    // The end does not need room in the queue
+    writer.end().unwrap();
+
+    let mut chunks = 0;
+    while reader.next_chunk(&mut Vec::new()) {
+        chunks += 1;
+    }
+    assert_eq!(chunks, STREAM_MAX_QUEUED_CHUNKS);
+    assert!(reader.is_ended());
+}
+
+#[test]
+fn test_buffered_time_and_disconnect() {
+    let (mut writer, reader) = event_stream(1000);
+    writer.append(&[event(3.0, 0x08, 0x00)]).unwrap();
+    assert_eq!(writer.buffered_sec(), 3.0);
+
+    reader.publish_playhead(1000);
+    assert_eq!(writer.playhead_sec(), 1.0);
+    assert_eq!(writer.buffered_sec(), 2.0);
+
+    assert!(!writer.is_disconnected());
+    drop(reader);
+    assert!(writer.is_disconnected());
+    assert!(writer.append(&[event(4.0, 0x08, 0x00)]).is_err());
+}
+
+#[test]
+fn test_idle_stream_times_out() {
+    let (mut writer, mut reader) = event_stream(1000);
+    writer.append(&[event(0.0, 0x08, 0x00)]).unwrap();
+    reader.next_chunk(&mut Vec::new());
+
+    // 10 s at 1000 Hz; new events start the count again
+    for _ in 0..9999 {
+        reader.wait_sample();
+    }
+    writer.append(&[event(0.5, 0x08, 0x00)]).unwrap();
+    reader.next_chunk(&mut Vec::new());
+    for _ in 0..9999 {
+        reader.wait_sample();
+    }
+    assert!(!reader.is_ended());
+    assert!(!writer.is_timed_out());
+
+    reader.wait_sample();
+    assert!(reader.is_ended());
+    assert!(writer.is_timed_out());
+    assert!(writer.append(&[event(1.0, 0x08, 0x00)]).is_err());
+}
+
+#[test]
+fn test_parse_chunk() {
+    let events = parse_chunk(serde_json::json!({
+        "events": [{"time": 0.5, "addr": "0x08", "data": "0x78"}]
+    }))
+    .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].time, 0.5);
+
+    for field in ["clock", "time_unit", "driver", "loop_time", "markers"] {
+        let mut chunk = serde_json::json!({"events": []});
+        chunk[field] = serde_json::json!(1);
+        let error = parse_chunk(chunk).unwrap_err().to_string();
+        assert!(error.contains(field), "{}", error);
+    }
+    assert!(parse_chunk(serde_json::json!({"invalid": "data"})).is_err());
+}
+
+#[test]
+fn test_dropped_writer_ends_stream() {
+    let (writer, mut reader) = event_stream(1000);
+    drop(writer);
+    reader.next_chunk(&mut Vec::new());
+    assert!(reader.is_ended());
+}
+
+#[test]
+fn test_streaming_player_holds_for_the_client() {
+    let (mut player, mut writer) = Player::new_streaming_with_clock(YM2151_CLOCK);
+    let mut buffer = vec![0i16; 2000];
+
+    // Nothing received yet: playback waits at the start
+    player.generate_samples(&mut buffer);
+    assert!(!player.is_complete());
+    assert_eq!(writer.playhead_sec(), 0.0);
+
+    // 1000 samples at 55930 Hz
+    let one_thousand = 0.017881603406326504;
+    writer
+        .append(&[event(0.0, 0x20, 0xC7), event(one_thousand, 0x08, 0x00)])
+        .unwrap();
+    player.generate_samples(&mut buffer);
+    player.generate_samples(&mut buffer);
+    assert_eq!(player.events_processed(), 2);
+    // Held at the last event received
+    let held = 1000.0 / player.current_sample_rate() as f64;
+    assert_eq!(writer.playhead_sec(), held);
+    assert!(!player.is_complete());
+
+    writer.append(&[event(0.5, 0x08, 0x78)]).unwrap();
+    writer.end().unwrap();
+    player.generate_samples(&mut buffer);
+    assert!(writer.playhead_sec() > held);
+    // Events played before the new chunk are dropped
+    assert_eq!(player.total_events(), 1);
+
+    let mut guard = 0;
+    while !player.is_complete() {
+        player.generate_samples(&mut buffer);
+        guard += 1;
+        assert!(guard < 100);
+    }
+}
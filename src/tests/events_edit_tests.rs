@@ -0,0 +1,290 @@
+use super::{event, log};
+use crate::events::edit;
+use crate::events::{EventLog, Marker, RegisterEvent};
+
+fn writes(log: &EventLog) -> Vec<(f64, u8, u8)> {
+    log.events
+        .iter()
+        .map(|e| (e.time, e.addr, e.data))
+        .collect()
+}
+
+/// A note on `channel` from `on` to `off`
+fn note(channel: u8, on: f64, off: f64) -> Vec<RegisterEvent> {
+    vec![
+        event(on, 0x20 + channel, 0xC7),
+        event(on, 0x28 + channel, 0x4A),
+        event(on, 0x60 + channel, 0x10),
+        event(on, 0x08, 0x78 | channel),
+        event(off, 0x08, channel),
+    ]
+}
+
+#[test]
+fn test_slice_restores_state_and_keys() {
+    let mut events = note(0, 0.0, 2.0);
+    events.insert(4, event(0.5, 0x19, 0x90));
+    events.insert(5, event(1.2, 0x28, 0x4E));
+    events.insert(6, event(1.8, 0x01, 0x02));
+    let input = EventLog {
+        loop_time: Some(1.5),
+        ..log(events)
+    };
+
+    let sliced = edit::slice(&input, 1.0, 1.6).unwrap();
+
+    assert_eq!(
+        writes(&sliced),
+        vec![
+            (0.0, 0x19, 0x90),
+            (0.0, 0x20, 0xC7),
+            (0.0, 0x28, 0x4A),
+            (0.0, 0x60, 0x10),
+            (0.0, 0x08, 0x78),
+            (0.19999999999999996, 0x28, 0x4E),
+            (0.6000000000000001, 0x08, 0x00),
+        ]
+    );
+    assert_eq!(sliced.loop_time, Some(0.5));
+    assert!(sliced.validate());
+}
+
+#[test]
+fn test_slice_skips_side_effect_registers_in_preamble() {
+    let input = log(vec![
+        event(0.0, 0x01, 0x02),
+        event(0.0, 0x14, 0x15),
+        event(0.0, 0x18, 0x80),
+        event(1.0, 0x18, 0x40),
+    ]);
+    let sliced = edit::slice(&input, 0.5, f64::INFINITY).unwrap();
+    assert_eq!(writes(&sliced), vec![(0.0, 0x18, 0x80), (0.5, 0x18, 0x40)]);
+    assert_eq!(sliced.loop_time, None);
+}
+
+#[test]
+fn test_slice_rejects_empty_range() {
+    let input = log(note(0, 0.0, 1.0));
+    assert!(edit::slice(&input, 1.0, 1.0).is_err());
+    assert!(edit::slice(&input, -1.0, 1.0).is_err());
+}
+
+#[test]
+fn test_shift_and_scale() {
+    let input = EventLog {
+        loop_time: Some(0.5),
+        ..log(note(0, 0.5, 1.0))
+    };
+
+    let shifted = edit::shift(&input, 0.25).unwrap();
+    assert_eq!(shifted.events[0].time, 0.75);
+    assert_eq!(shifted.events[4].time, 1.25);
+    assert_eq!(shifted.loop_time, Some(0.75));
+
+    let scaled = edit::scale(&input, 2.0).unwrap();
+    assert_eq!(scaled.events[4].time, 2.0);
+    assert_eq!(scaled.loop_time, Some(1.0));
+    assert!(scaled.validate());
+
+    assert!(edit::shift(&input, -0.6).is_err());
+    assert!(edit::scale(&input, 0.0).is_err());
+    assert!(edit::scale(&input, f64::NAN).is_err());
+}
+
+#[test]
+fn test_concat_with_gap() {
+    let mut first = note(0, 0.0, 1.0);
+    // Still sounding at the end of the first log
+    first.push(event(1.0, 0x08, 0x79));
+    let second = note(1, 0.0, 0.5);
+
+    let joined = edit::concat(&[log(first), log(second)], 0.25).unwrap();
+
+    let times: Vec<f64> = joined.events.iter().map(|e| e.time).collect();
+    assert_eq!(
+        times,
+        [0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.25, 1.25, 1.25, 1.25, 1.75]
+    );
+    assert_eq!((joined.events[6].addr, joined.events[6].data), (0x08, 0x01));
+    assert_eq!(joined.events[7].addr, 0x21);
+    assert!(joined.validate());
+}
+
+#[test]
+fn test_merge_remaps_colliding_channels() {
+    let a = log(note(0, 0.0, 1.0));
+    let b = log(note(0, 0.5, 1.5));
+    let mut c_events = note(2, 0.25, 0.75);
+    c_events.insert(0, event(0.25, 0x18, 0x80));
+    let c = log(c_events);
+
+    let merged = edit::merge(&[a, b, c]).unwrap();
+
+    assert!(merged.validate());
+    assert_eq!(merged.events.len(), 16);
+    // b moves to the lowest free channel, c keeps channel 2
+    assert!(merged
+        .events
+        .iter()
+        .any(|e| e.addr == 0x21 && e.time == 0.5));
+    assert!(merged
+        .events
+        .iter()
+        .any(|e| (e.addr, e.data, e.time) == (0x08, 0x79, 0.5)));
+    assert!(merged
+        .events
+        .iter()
+        .any(|e| e.addr == 0x62 && e.time == 0.25));
+    assert!(merged.events.iter().any(|e| e.addr == 0x18));
+}
+
+#[test]
+fn test_merge_rejects_too_many_channels_and_clock_mismatch() {
+    let logs: Vec<EventLog> = (0..9).map(|_| log(note(0, 0.0, 1.0))).collect();
+    assert!(edit::merge(&logs).is_err());
+    assert!(edit::merge(&logs[..8]).is_ok());
+
+    let other_clock = EventLog {
+        clock: Some(4_000_000),
+        ..log(note(0, 0.0, 1.0))
+    };
+    assert!(edit::merge(&[log(note(0, 0.0, 1.0)), other_clock.clone()]).is_err());
+    assert!(edit::concat(&[log(note(0, 0.0, 1.0)), other_clock], 0.0).is_err());
+}
+
+#[test]
+fn test_merge_ignores_initialization_key_offs() {
+    let with_reset = |channel: u8| {
+        let mut events: Vec<RegisterEvent> = (0..8).map(|c| event(0.0, 0x08, c)).collect();
+        events.extend(note(channel, 0.0, 1.0));
+        log(events)
+    };
+
+    let merged = edit::merge(&[with_reset(0), with_reset(0)]).unwrap();
+
+    // Only the key-off of the channel each log plays is kept
+    let key_offs_at_start = merged
+        .events
+        .iter()
+        .filter(|e| e.addr == 0x08 && e.data & 0x78 == 0 && e.time == 0.0)
+        .count();
+    assert_eq!(key_offs_at_start, 2);
+    assert!(merged.events.iter().any(|e| e.addr == 0x21));
+}
+
+#[test]
+fn test_markers_follow_edits() {
+    let marker = |time: f64, name: &str| Marker {
+        time,
+        name: name.to_string(),
+    };
+    let input = EventLog {
+        markers: vec![marker(0.25, "intro"), marker(0.75, "chorus")],
+        ..log(note(0, 0.0, 1.0))
+    };
+
+    let sliced = edit::slice(&input, 0.5, 1.0).unwrap();
+    assert_eq!(sliced.markers, [marker(0.25, "chorus")]);
+
+    let shifted = edit::shift(&input, 1.0).unwrap();
+    assert_eq!(shifted.markers[0].time, 1.25);
+
+    let joined = edit::concat(&[input.clone(), input.clone()], 0.5).unwrap();
+    assert_eq!(joined.markers.len(), 4);
+    assert_eq!(joined.markers[3].time, 2.25);
+
+    let other = EventLog {
+        markers: vec![marker(0.5, "drop")],
+        ..log(note(1, 0.0, 1.0))
+    };
+    let merged = edit::merge(&[input, other]).unwrap();
+    let names: Vec<&str> = merged.markers.iter().map(|m| m.name.as_str()).collect();
+    assert_eq!(names, ["intro", "drop", "chorus"]);
+}
+
+#[test]
+fn test_unroll_loop_repeats_loop_section() {
+    let mut events = vec![event(0.0, 0x20, 0xC7)];
+    events.extend(note(0, 1.0, 2.0));
+    let input = EventLog {
+        loop_time: Some(1.0),
+        markers: vec![
+            Marker {
+                time: 0.0,
+                name: "intro".to_string(),
+            },
+            Marker {
+                time: 1.0,
+                name: "loop".to_string(),
+            },
+        ],
+        ..log(events)
+    };
+
+    let unrolled = edit::unroll_loop(&input, 3).unwrap();
+
+    assert_eq!(unrolled.loop_time, None);
+    assert_eq!(unrolled.events.len(), 1 + 5 * 3);
+    assert!(unrolled.validate());
+    let key_ons: Vec<f64> = unrolled
+        .events
+        .iter()
+        .filter(|e| e.addr == 0x08 && e.data & 0x78 != 0)
+        .map(|e| e.time)
+        .collect();
+    assert_eq!(key_ons, vec![1.0, 2.0, 3.0]);
+    assert_eq!(unrolled.events.last().unwrap().time, 4.0);
+    let markers: Vec<(f64, &str)> = unrolled
+        .markers
+        .iter()
+        .map(|m| (m.time, m.name.as_str()))
+        .collect();
+    assert_eq!(
+        markers,
+        vec![(0.0, "intro"), (1.0, "loop"), (2.0, "loop"), (3.0, "loop")]
+    );
+}
+
+#[test]
+fn test_unroll_loop_without_loop_point_repeats_whole_log() {
+    let input = log(note(0, 0.0, 1.0));
+
+    assert_eq!(
+        writes(&edit::unroll_loop(&input, 1).unwrap()),
+        writes(&input)
+    );
+    let twice = edit::unroll_loop(&input, 2).unwrap();
+    assert_eq!(twice.events.len(), 10);
+    assert_eq!(twice.events.last().unwrap().time, 2.0);
+
+    assert!(edit::unroll_loop(&input, 0).is_err());
+    assert!(edit::unroll_loop(&log(vec![event(0.0, 0x20, 0xC7)]), 2).is_err());
+}
+
+#[test]
+fn test_mask_channels_keys_off_and_silences_other_channels() {
+    let mut events = note(0, 0.0, 1.0);
+    events.extend(note(3, 0.5, 1.5));
+    events.push(event(0.0, 0x0F, 0x85));
+    let input = log(events);
+
+    let solo = edit::mask_channels(&input, 1 << 3);
+
+    assert_eq!(
+        writes(&solo),
+        vec![
+            (0.0, 0x20, 0xC7),
+            (0.0, 0x28, 0x4A),
+            (0.0, 0x60, 0x7F),
+            (0.0, 0x08, 0x00),
+            (1.0, 0x08, 0x00),
+            (0.5, 0x23, 0xC7),
+            (0.5, 0x2B, 0x4A),
+            (0.5, 0x63, 0x10),
+            (0.5, 0x08, 0x7B),
+            (1.5, 0x08, 0x03),
+            (0.0, 0x0F, 0x85),
+        ]
+    );
+    assert_eq!(writes(&edit::mask_channels(&input, 0xFF)), writes(&input));
+}
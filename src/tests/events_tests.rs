@@ -1,4 +1,5 @@
-use crate::events::EventLog;
+use super::event;
+use crate::events::{EventLog, Marker, ValidationIssueKind};
 
 #[test]
 fn test_parse_simple_json() {
@@ -174,6 +175,24 @@ fn test_from_json_str_validates() {
     assert!(log.validate());
 }
 
+#[test]
+fn test_from_json_value_matches_from_json_str() {
+    let json = r#"{
+        "time_unit": "ticks",
+        "bpm": 120,
+        "ppq": 480,
+        "events": [{"time": 480, "addr": "0x08", "data": "0x00"}]
+    }"#;
+
+    let value: serde_json::Value = serde_json::from_str(json).unwrap();
+    let log = EventLog::from_json_value(value).unwrap();
+    assert_eq!(log.events.len(), 1);
+    assert_eq!(log.events[0].time, 0.5);
+
+    let invalid = serde_json::json!({"events": "none"});
+    assert!(EventLog::from_json_value(invalid).is_err());
+}
+
 #[test]
 fn test_from_json_str_invalid_json() {
     let json = r#"{"events": [}"#;
@@ -205,3 +224,245 @@ fn test_json_string_vs_file_workflow() {
     assert_eq!(log_from_string.events[1].addr, 0x20);
     assert_eq!(log_from_string.events[1].data, 0xC7);
 }
+
+#[test]
+fn test_clock_field_is_optional() {
+    let json = r#"{"events": [{"time": 0.0, "addr": "0x08", "data": "0x00"}]}"#;
+    let log = EventLog::from_json_str(json).unwrap();
+    assert_eq!(log.clock, None);
+    assert_eq!(log.clock_or(3_579_545), 3_579_545);
+}
+
+#[test]
+fn test_parse_clock_field() {
+    let json = r#"{"clock": 4000000, "events": [{"time": 0.0, "addr": "0x08", "data": "0x00"}]}"#;
+    let log = EventLog::from_json_str(json).unwrap();
+    assert_eq!(log.clock, Some(4_000_000));
+    assert_eq!(log.clock_or(3_579_545), 4_000_000);
+}
+
+#[test]
+fn test_out_of_range_clock_is_rejected() {
+    let json = r#"{"clock": 44100, "events": []}"#;
+    let err = EventLog::from_json_str(json).unwrap_err();
+    assert!(err.to_string().contains("out of range"));
+}
+
+#[test]
+fn test_integer_addr_and_data() {
+    let json = r#"{
+        "events": [
+            {"time": 0.0, "addr": 32, "data": 199},
+            {"time": 0.1, "addr": "0x08", "data": 120.0}
+        ]
+    }"#;
+
+    let log = EventLog::from_json_str(json).unwrap();
+    assert_eq!((log.events[0].addr, log.events[0].data), (0x20, 0xC7));
+    assert_eq!(log.events[1].data, 0x78);
+
+    // Written back in the usual hex form
+    let written = log.to_json_string().unwrap();
+    assert!(written.contains(r#""addr":"0x20""#));
+
+    for bad in ["256", "-1", "1.5"] {
+        let json = format!(
+            r#"{{"events": [{{"time": 0.0, "addr": {}, "data": 0}}]}}"#,
+            bad
+        );
+        assert!(EventLog::from_json_str(&json).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn test_time_unit_samples() {
+    let json = r#"{
+        "time_unit": "samples",
+        "loop_time": 27965,
+        "events": [
+            {"time": 0, "addr": "0x08", "data": "0x00"},
+            {"time": 55930, "addr": "0x08", "data": "0x78"}
+        ]
+    }"#;
+    let log = EventLog::from_json_str(json).unwrap();
+    assert_eq!(log.events[1].time, 1.0);
+    assert_eq!(log.loop_time, Some(0.5));
+
+    // Samples follow the log's clock: 62500 Hz at 4 MHz
+    let json = r#"{
+        "time_unit": "samples",
+        "clock": 4000000,
+        "events": [{"time": 62500, "addr": "0x08", "data": "0x00"}]
+    }"#;
+    assert_eq!(EventLog::from_json_str(json).unwrap().events[0].time, 1.0);
+}
+
+#[test]
+fn test_time_unit_ticks() {
+    let json = r#"{
+        "time_unit": "ticks",
+        "bpm": 150,
+        "ppq": 96,
+        "events": [{"time": 192, "addr": "0x08", "data": "0x00"}]
+    }"#;
+    let log = EventLog::from_json_str(json).unwrap();
+    // Two beats at 150 BPM
+    assert!((log.events[0].time - 0.8).abs() < 1e-12);
+    // The converted log is plain seconds
+    assert!(!log.to_json_string().unwrap().contains("time_unit"));
+
+    let missing = r#"{"time_unit": "ticks", "bpm": 120, "events": []}"#;
+    assert!(EventLog::from_json_str(missing).is_err());
+    let zero = r#"{"time_unit": "ticks", "bpm": 0, "ppq": 480, "events": []}"#;
+    assert!(EventLog::from_json_str(zero).is_err());
+    let unknown = r#"{"time_unit": "beats", "events": []}"#;
+    assert!(EventLog::from_json_str(unknown).is_err());
+}
+
+#[test]
+fn test_time_unit_seconds_is_default() {
+    let json =
+        r#"{"time_unit": "seconds", "events": [{"time": 0.25, "addr": "0x08", "data": "0x00"}]}"#;
+    assert_eq!(EventLog::from_json_str(json).unwrap().events[0].time, 0.25);
+}
+
+fn kinds(log: &EventLog) -> Vec<(usize, ValidationIssueKind)> {
+    log.validate_detailed()
+        .iter()
+        .map(|issue| (issue.index, issue.kind))
+        .collect()
+}
+
+#[test]
+fn test_validate_detailed_clean_log() {
+    let log = EventLog::from_file("output_ym2151.json").unwrap();
+    assert!(log.validate_detailed().is_empty());
+    assert!(log.check_playable().unwrap().is_empty());
+}
+
+#[test]
+fn test_validate_detailed_times() {
+    let log = EventLog {
+        events: vec![
+            event(0.5, 0x20, 0xC7),
+            event(0.25, 0x20, 0xC7),
+            event(f64::NAN, 0x20, 0xC7),
+            event(-1.0, 0x20, 0xC7),
+            event(f64::INFINITY, 0x20, 0xC7),
+            event(4000.0, 0x20, 0xC7),
+            event(5000.0, 0x20, 0xC7),
+        ],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        kinds(&log),
+        vec![
+            (1, ValidationIssueKind::OutOfOrder),
+            (2, ValidationIssueKind::InvalidTime),
+            (3, ValidationIssueKind::InvalidTime),
+            (4, ValidationIssueKind::InvalidTime),
+            (5, ValidationIssueKind::TooLong),
+        ]
+    );
+    assert!(!log.validate());
+
+    let issues = log.validate_detailed();
+    assert_eq!(issues[0].time, 0.25);
+    assert!(issues[0].is_error());
+    assert!(!issues[4].is_error());
+    assert_eq!(
+        issues[0].to_string(),
+        "event 1 (time 0.25): time goes back from 0.5 to 0.25"
+    );
+}
+
+#[test]
+fn test_validate_detailed_registers() {
+    let log = EventLog {
+        events: vec![
+            event(0.0, 0x01, 0x02),
+            event(0.0, 0x01, 0x80),
+            event(0.0, 0x02, 0x00),
+            event(0.0, 0x1F, 0x00),
+            event(0.0, 0x08, 0x79),
+            event(0.0, 0x08, 0x79),
+            event(0.0, 0x60, 0x00),
+            event(0.0, 0x08, 0x78),
+            event(0.0, 0x08, 0x01),
+        ],
+        ..Default::default()
+    };
+
+    // LFO reset is fine; channel 1 is reported once; channel 0 got a voice first
+    assert_eq!(
+        kinds(&log),
+        vec![
+            (1, ValidationIssueKind::TestRegister),
+            (2, ValidationIssueKind::UnusedRegister),
+            (3, ValidationIssueKind::UnusedRegister),
+            (4, ValidationIssueKind::KeyOnWithoutVoice),
+        ]
+    );
+    // Warnings only: still playable
+    assert!(log.validate());
+    assert_eq!(log.check_playable().unwrap().len(), 4);
+}
+
+#[test]
+fn test_check_playable_reports_errors() {
+    let mut events = vec![event(1.0, 0x20, 0xC7), event(0.5, 0x20, 0xC7)];
+    events.extend((1..15).map(|i| event(-(i as f64), 0x20, 0xC7)));
+    let log = EventLog {
+        events,
+        ..Default::default()
+    };
+
+    let message = log.check_playable().unwrap_err().to_string();
+    assert!(message.starts_with("Event log validation failed: event 1 (time 0.5): time goes back"));
+    assert!(message.contains("event 2 (time -1): time -1 is not a finite"));
+    assert!(message.ends_with("and 5 more"));
+}
+
+#[test]
+fn test_markers_and_metadata_aliases() {
+    let json = r#"{
+        "time_unit": "samples",
+        "metadata": {"title": "Stage 1", "composer": "someone", "source": "a game"},
+        "markers": [{"time": 27965, "name": "chorus"}],
+        "events": [{"time": 0, "addr": "0x08", "data": "0x00"}]
+    }"#;
+    let log = EventLog::from_json_str(json).unwrap();
+    assert_eq!(log.markers[0].name, "chorus");
+    assert_eq!(log.markers[0].time, 0.5);
+    let metadata = log.metadata.as_ref().unwrap();
+    assert_eq!(metadata.author.as_deref(), Some("someone"));
+    assert_eq!(metadata.game.as_deref(), Some("a game"));
+
+    // Logs without markers are written as before
+    let plain = EventLog::from_json_str(r#"{"events": []}"#).unwrap();
+    assert!(!plain.to_json_string().unwrap().contains("markers"));
+}
+
+#[test]
+fn test_validate_detailed_markers() {
+    let log = EventLog {
+        events: vec![event(0.0, 0x08, 0x00)],
+        markers: vec![
+            Marker {
+                time: 0.5,
+                name: "intro".to_string(),
+            },
+            Marker {
+                time: -1.0,
+                name: "bad".to_string(),
+            },
+        ],
+        ..Default::default()
+    };
+    let issues = log.validate_detailed();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].kind, ValidationIssueKind::InvalidMarker);
+    assert_eq!(issues[0].index, 1);
+    assert!(log.check_playable().is_err());
+}
@@ -0,0 +1,229 @@
+use crate::flac_writer::{encode, write_flac};
+use crate::pcm::{Dither, SampleFormat};
+
+/// MSB-first CRC with a zero initial value, as FLAC uses for frame headers
+/// (8 bits, polynomial 0x07) and whole frames (16 bits, polynomial 0x8005)
+fn crc(bytes: &[u8], width: u32, polynomial: u32) -> u32 {
+    let top = 1 << (width - 1);
+    let mask = (1u64 << width) as u32 - 1;
+    bytes.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ (u32::from(byte) << (width - 8)), |crc, _| {
+            if crc & top != 0 {
+                ((crc << 1) ^ polynomial) & mask
+            } else {
+                (crc << 1) & mask
+            }
+        })
+    })
+}
+
+#[test]
+fn test_crc_check_values() {
+    // Standard check values of CRC-8 and CRC-16/UMTS
+    assert_eq!(crc(b"123456789", 8, 0x07), 0xF4);
+    assert_eq!(crc(b"123456789", 16, 0x8005), 0xFEE8);
+}
+
+/// Minimal FLAC reader for what the encoder writes (independent stereo,
+/// CONSTANT/VERBATIM/FIXED subframes), returning (sample rate, bits, interleaved)
+///
+/// Checks the CRCs of every frame and the block and frame sizes in STREAMINFO.
+fn decode(bytes: &[u8]) -> (u32, u32, Vec<i32>) {
+    struct Reader<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+    }
+    impl Reader<'_> {
+        fn bits(&mut self, n: u32) -> u64 {
+            (0..n).fold(0, |value, _| {
+                let bit = (self.bytes[self.pos / 8] >> (7 - self.pos % 8)) & 1;
+                self.pos += 1;
+                (value << 1) | bit as u64
+            })
+        }
+        fn signed(&mut self, n: u32) -> i64 {
+            let value = self.bits(n) as i64;
+            if value >> (n - 1) != 0 {
+                value - (1 << n)
+            } else {
+                value
+            }
+        }
+    }
+
+    assert_eq!(&bytes[..4], b"fLaC");
+    let mut r = Reader { bytes, pos: 32 };
+    assert_eq!(r.bits(32), (1 << 31) | 34); // last block, STREAMINFO, 34 bytes
+    let min_block_size = r.bits(16) as usize;
+    let max_block_size = r.bits(16) as usize;
+    let min_frame_size = r.bits(24) as usize;
+    let max_frame_size = r.bits(24) as usize;
+    let sample_rate = r.bits(20) as u32;
+    assert_eq!(r.bits(3), 1); // stereo
+    let bits = r.bits(5) as u32 + 1;
+    let total = r.bits(36) as usize;
+    r.bits(128);
+
+    let mut channels = [Vec::new(), Vec::new()];
+    let mut block_sizes = Vec::new();
+    let mut frame_sizes = Vec::new();
+    while r.pos / 8 < bytes.len() {
+        let frame_start = r.pos / 8;
+        assert_eq!(r.bits(16), 0xFFF8);
+        let block_size_code = r.bits(4);
+        r.bits(4 + 4 + 3 + 1);
+        let lead = r.bits(8);
+        for _ in 0..(lead as u8).leading_ones().saturating_sub(1) {
+            r.bits(8);
+        }
+        let block_size = match block_size_code {
+            0b1100 => 4096,
+            0b0111 => r.bits(16) as usize + 1,
+            code => panic!("unexpected block size code {}", code),
+        };
+        let header_crc = crc(&bytes[frame_start..r.pos / 8], 8, 0x07);
+        assert_eq!(r.bits(8) as u32, header_crc, "frame header CRC-8");
+
+        for channel in &mut channels {
+            let header = r.bits(8);
+            let kind = header >> 1;
+            match kind {
+                0 => {
+                    let value = r.signed(bits);
+                    channel.extend(std::iter::repeat_n(value, block_size));
+                }
+                1 => channel.extend((0..block_size).map(|_| r.signed(bits))),
+                8..=12 => {
+                    let order = (kind - 8) as usize;
+                    let mut x: Vec<i64> = (0..order).map(|_| r.signed(bits)).collect();
+                    let parameter_bits = if r.bits(2) == 1 { 5 } else { 4 };
+                    let partition_order = r.bits(4);
+                    for partition in 0..1usize << partition_order {
+                        let k = r.bits(parameter_bits) as u32;
+                        let count = (block_size >> partition_order)
+                            - if partition == 0 { order } else { 0 };
+                        for _ in 0..count {
+                            let mut q = 0;
+                            while r.bits(1) == 0 {
+                                q += 1;
+                            }
+                            let u = (q << k) | r.bits(k);
+                            let residual = (u >> 1) as i64 ^ -((u & 1) as i64);
+                            let n = x.len();
+                            let prediction = match order {
+                                0 => 0,
+                                1 => x[n - 1],
+                                2 => 2 * x[n - 1] - x[n - 2],
+                                3 => 3 * x[n - 1] - 3 * x[n - 2] + x[n - 3],
+                                _ => 4 * x[n - 1] - 6 * x[n - 2] + 4 * x[n - 3] - x[n - 4],
+                            };
+                            x.push(prediction + residual);
+                        }
+                    }
+                    channel.extend(x);
+                }
+                _ => panic!("unexpected subframe type {}", kind),
+            }
+        }
+        r.pos = r.pos.div_ceil(8) * 8; // padding
+        let frame_crc = crc(&bytes[frame_start..r.pos / 8], 16, 0x8005);
+        assert_eq!(r.bits(16) as u32, frame_crc, "frame CRC-16");
+        block_sizes.push(block_size);
+        frame_sizes.push(r.pos / 8 - frame_start);
+    }
+
+    assert_eq!(channels[0].len(), total);
+    // Fixed-size blocks: every block but the last is exactly the maximum
+    assert_eq!(min_block_size, max_block_size);
+    assert!((16..=65535).contains(&max_block_size));
+    if let Some((last, blocks)) = block_sizes.split_last() {
+        assert!(blocks.iter().all(|&size| size == max_block_size));
+        assert!(*last <= max_block_size);
+    }
+    assert_eq!(
+        (min_frame_size, max_frame_size),
+        (
+            frame_sizes.iter().copied().min().unwrap_or(0),
+            frame_sizes.iter().copied().max().unwrap_or(0)
+        )
+    );
+    let interleaved = channels[0]
+        .iter()
+        .zip(&channels[1])
+        .flat_map(|(&left, &right)| [left as i32, right as i32])
+        .collect();
+    (sample_rate, bits, interleaved)
+}
+
+#[test]
+fn test_encode_round_trip() {
+    let mut noise = 1u32;
+    let samples: Vec<i32> = (0..10_000)
+        .flat_map(|i| {
+            noise = noise.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let tone = ((i as f64 * 0.05).sin() * 30000.0) as i32;
+            [tone, (noise >> 16) as i32 - 32768]
+        })
+        .collect();
+
+    let bytes = encode(&samples, 55930, 16).unwrap();
+
+    assert_eq!(decode(&bytes), (55930, 16, samples.clone()));
+    // The tone compresses, the noise channel falls back to verbatim
+    assert!(bytes.len() < samples.len() * 2);
+}
+
+#[test]
+fn test_encode_round_trip_24_bit_and_short_blocks() {
+    let samples: Vec<i32> = (0..4200)
+        .flat_map(|i| {
+            let value = ((i as f64 * 0.01).sin() * 8_000_000.0) as i32;
+            if i < 4096 {
+                [0, 0]
+            } else {
+                [value, 8_388_607 - (i % 2) * 16_777_215]
+            }
+        })
+        .collect();
+    let bytes = encode(&samples, 96000, 24).unwrap();
+    assert_eq!(decode(&bytes), (96000, 24, samples));
+
+    for samples in [vec![], vec![7, -7], vec![1, 2, 3, 4, 5, 6]] {
+        let bytes = encode(&samples, 44100, 16).unwrap();
+        assert_eq!(decode(&bytes).2, samples);
+    }
+}
+
+#[test]
+#[should_panic(expected = "frame CRC-16")]
+fn test_decode_checks_frame_crc() {
+    let mut bytes = encode(&[1, 2, 3, 4], 44100, 16).unwrap();
+    *bytes.last_mut().unwrap() ^= 1;
+    decode(&bytes);
+}
+
+#[test]
+fn test_encode_rejects_invalid_input() {
+    assert!(encode(&[0, 0, 0], 44100, 16).is_err());
+    assert!(encode(&[0, 0], 0, 16).is_err());
+    assert!(encode(&[0, 0], 44100, 32).is_err());
+    assert!(encode(&[32768, 0], 44100, 16).is_err());
+}
+
+#[test]
+fn test_write_flac_formats() {
+    let path = std::env::temp_dir().join("test_write_flac.flac");
+    let path = path.to_str().unwrap();
+    let samples = vec![0.75 / 32768.0, -0.5, 0.25, 0.0];
+
+    write_flac(path, &samples, 48000, SampleFormat::Int24, Dither::Off).unwrap();
+    let (_, bits, written) = decode(&std::fs::read(path).unwrap());
+    assert_eq!((bits, written), (24, vec![192, -4194304, 2097152, 0]));
+
+    write_flac(path, &samples, 48000, SampleFormat::Int16, Dither::Off).unwrap();
+    let (_, bits, written) = decode(&std::fs::read(path).unwrap());
+    assert_eq!((bits, written), (16, vec![0, -16384, 8192, 0]));
+
+    assert!(write_flac(path, &samples, 48000, SampleFormat::Float32, Dither::Off).is_err());
+    let _ = std::fs::remove_file(path);
+}
@@ -1,4 +1,9 @@
-use crate::ipc::protocol::{Command, Response};
+use crate::audio::MarkerReached;
+use crate::compression::Compression;
+use crate::ipc::protocol::{
+    frame_payload_len, Command, Response, FRAME_FLAG_ATTACHMENT, FRAME_FLAG_GZIP, FRAME_FLAG_ZSTD,
+    FRAME_LENGTH_MASK, MAX_DECOMPRESSED_PAYLOAD_SIZE,
+};
 
 // Binary protocol tests
 
@@ -174,3 +179,225 @@ fn test_binary_play_json_in_interactive_roundtrip() {
     let parsed = Command::from_binary(&binary).unwrap();
     assert_eq!(original, parsed);
 }
+
+#[test]
+fn test_binary_save_interactive_session_roundtrip() {
+    let original = Command::SaveInteractiveSession {
+        path: "session.vgm".to_string(),
+    };
+    let binary = original.to_binary().unwrap();
+    let parsed = Command::from_binary(&binary).unwrap();
+    assert_eq!(original, parsed);
+}
+
+#[test]
+fn test_binary_get_markers_roundtrip() {
+    let original = Command::GetMarkers { after: 3 };
+    let binary = original.to_binary().unwrap();
+    let parsed = Command::from_binary(&binary).unwrap();
+    assert_eq!(original, parsed);
+
+    // `after` may be omitted
+    let json = r#"{"command":"get_markers"}"#;
+    let parsed: Command = serde_json::from_str(json).unwrap();
+    assert_eq!(parsed, Command::GetMarkers { after: 0 });
+}
+
+#[test]
+fn test_binary_markers_response_roundtrip() {
+    let original = Response::Markers {
+        markers: vec![MarkerReached {
+            seq: 1,
+            name: "chorus".to_string(),
+            time: 12.5,
+        }],
+    };
+    let binary = original.to_binary().unwrap();
+    let parsed = Response::from_binary(&binary).unwrap();
+    assert_eq!(original, parsed);
+}
+
+#[test]
+fn test_server_state_marker_is_optional() {
+    let json = r#"{"status":"serverstate","state":"playing"}"#;
+    let parsed: Response = serde_json::from_str(json).unwrap();
+    assert_eq!(
+        parsed,
+        Response::ServerState {
+            state: "playing".to_string(),
+            marker: None,
+        }
+    );
+    assert!(!serde_json::to_string(&parsed).unwrap().contains("marker"));
+}
+
+#[test]
+fn test_binary_stream_commands_roundtrip() {
+    let commands = [
+        Command::BeginStream {
+            clock: Some(4_000_000),
+        },
+        Command::AppendEvents {
+            data: serde_json::json!({
+                "events": [{"time": 0.5, "addr": "0x08", "data": "0x78"}]
+            }),
+        },
+        Command::EndStream,
+    ];
+    for original in commands {
+        let binary = original.to_binary().unwrap();
+        let parsed = Command::from_binary(&binary).unwrap();
+        assert_eq!(original, parsed);
+    }
+
+    let parsed: Command = serde_json::from_str(r#"{"command":"begin_stream"}"#).unwrap();
+    assert_eq!(parsed, Command::BeginStream { clock: None });
+}
+
+#[test]
+fn test_binary_stream_buffer_response_roundtrip() {
+    let original = Response::StreamBuffer {
+        accepted: false,
+        buffered_sec: 5.25,
+    };
+    let binary = original.to_binary().unwrap();
+    assert_eq!(Response::from_binary(&binary).unwrap(), original);
+}
+
+#[test]
+fn test_compressed_frames_roundtrip() {
+    let original = Command::PlayJson {
+        data: serde_json::json!({
+            "events": [{"time": 0, "addr": "0x08", "data": "0x00"}]
+        }),
+    };
+    for (compression, flag) in [
+        (Compression::Gzip, FRAME_FLAG_GZIP),
+        (Compression::Zstd, FRAME_FLAG_ZSTD),
+    ] {
+        let binary = original.to_binary_compressed(compression).unwrap();
+        let prefix = [binary[0], binary[1], binary[2], binary[3]];
+        assert_eq!(u32::from_le_bytes(prefix) & flag, flag);
+        assert_eq!(frame_payload_len(prefix), binary.len() - 4);
+        assert_eq!(Command::from_binary(&binary).unwrap(), original);
+    }
+}
+
+#[test]
+fn test_compressed_frame_errors() {
+    let command = Command::Stop;
+
+    // Unknown flag bits
+    let mut binary = command.to_binary().unwrap();
+    binary[3] |= 0x40;
+    assert!(Command::from_binary(&binary)
+        .unwrap_err()
+        .contains("Unknown frame flags"));
+
+    // Flagged as compressed but not
+    let mut binary = command.to_binary().unwrap();
+    binary[3] |= (FRAME_FLAG_ZSTD >> 24) as u8;
+    assert!(Command::from_binary(&binary)
+        .unwrap_err()
+        .contains("Invalid compressed payload"));
+}
+
+#[test]
+fn test_compressed_frame_size_is_bounded() {
+    let json = format!(
+        "{{\"command\":\"play_json\",\"data\":\"{}\"}}",
+        "a".repeat(MAX_DECOMPRESSED_PAYLOAD_SIZE)
+    );
+    let payload = crate::compression::compress(json.as_bytes(), Compression::Zstd).unwrap();
+    let mut binary = (payload.len() as u32 | FRAME_FLAG_ZSTD)
+        .to_le_bytes()
+        .to_vec();
+    binary.extend_from_slice(&payload);
+    assert!(Command::from_binary(&binary)
+        .unwrap_err()
+        .contains("expands to more than"));
+}
+
+#[test]
+fn test_binary_render_pcm_roundtrip() {
+    let original = Command::RenderPcm {
+        data: serde_json::json!({
+            "events": [{"time": 0, "addr": "0x08", "data": "0x00"}]
+        }),
+        sample_rate: Some(48000),
+        format: "24".to_string(),
+        path: Some("out.flac".to_string()),
+    };
+    let binary = original.to_binary().unwrap();
+    assert_eq!(Command::from_binary(&binary).unwrap(), original);
+
+    let parsed: Command =
+        serde_json::from_str(r#"{"command":"render_pcm","data":"song.vgm"}"#).unwrap();
+    assert_eq!(
+        parsed,
+        Command::RenderPcm {
+            data: serde_json::json!("song.vgm"),
+            sample_rate: None,
+            format: "16".to_string(),
+            path: None,
+        }
+    );
+}
+
+#[test]
+fn test_binary_pcm_response_roundtrip() {
+    for len in 0..7 {
+        let original = Response::Pcm {
+            sample_rate: 55930,
+            format: "16".to_string(),
+            frames: len / 4,
+            data: (0..len as u8).map(|b| b.wrapping_mul(97)).collect(),
+        };
+        let binary = original.to_binary().unwrap();
+        assert_eq!(Response::from_binary(&binary).unwrap(), original);
+    }
+
+    // The samples follow the JSON raw, not inside it
+    let binary = Response::Pcm {
+        sample_rate: 48000,
+        format: "16".to_string(),
+        frames: 1,
+        data: vec![0xFB, 0xFF, 0x01, 0x02],
+    }
+    .to_binary()
+    .unwrap();
+    let prefix = u32::from_le_bytes([binary[0], binary[1], binary[2], binary[3]]);
+    assert_eq!(prefix & !FRAME_LENGTH_MASK, FRAME_FLAG_ATTACHMENT);
+    assert_eq!(&binary[binary.len() - 4..], [0xFB, 0xFF, 0x01, 0x02]);
+    let json_len = u32::from_le_bytes([binary[4], binary[5], binary[6], binary[7]]) as usize;
+    let json = std::str::from_utf8(&binary[8..8 + json_len]).unwrap();
+    assert!(!json.contains("data"), "{}", json);
+
+    // Truncated frames, a JSON length past the end, PCM without its samples
+    // and samples after another response
+    let frame = |flags: u32, payload: &[u8]| {
+        let mut frame = (payload.len() as u32 | flags).to_le_bytes().to_vec();
+        frame.extend_from_slice(payload);
+        frame
+    };
+    let attached = |json: &str, json_len: u32| {
+        let mut payload = json_len.to_le_bytes().to_vec();
+        payload.extend_from_slice(json.as_bytes());
+        payload.extend_from_slice(&[1, 2]);
+        frame(FRAME_FLAG_ATTACHMENT, &payload)
+    };
+    let pcm = r#"{"status":"pcm","sample_rate":1,"format":"16","frames":0}"#;
+    let ok = r#"{"status":"ok"}"#;
+    for data in [
+        binary[..binary.len() - 1].to_vec(),
+        attached(pcm, pcm.len() as u32 + 3),
+        frame(0, pcm.as_bytes()),
+        attached(ok, ok.len() as u32),
+    ] {
+        assert!(Response::from_binary(&data).is_err(), "{:?}", data);
+    }
+    assert!(matches!(
+        Response::from_binary(&attached(pcm, pcm.len() as u32)).unwrap(),
+        Response::Pcm { data, .. } if data == [1, 2]
+    ));
+}
@@ -0,0 +1,365 @@
+use super::{key_ons, writes};
+use crate::formats::mdx::{self, MdxOptions, DEFAULT_TIMER_B};
+use crate::formats::{self, LogFormat};
+use crate::resampler::X68000_CLOCK;
+
+/// FB 7 / CON 4 voice: C1 and C2 are carriers with TL 0x10, M1 and M2 have TL 0x20
+const VOICE: [u8; 27] = [
+    0x00, 0x3C, 0x0F, // number, FL/CON, slot mask
+    0x01, 0x01, 0x01, 0x01, // DT1/MUL
+    0x20, 0x20, 0x10, 0x10, // TL
+    0x1F, 0x1F, 0x1F, 0x1F, // KS/AR
+    0x00, 0x00, 0x00, 0x00, // AME/D1R
+    0x00, 0x00, 0x00, 0x00, // DT2/D2R
+    0x0F, 0x0F, 0x0F, 0x0F, // D1L/RR
+];
+
+const END: &[u8] = &[0xF1, 0x00];
+
+/// Build an MDX file with channels A-H and P (missing channels are empty)
+fn build_mdx(title: &[u8], pdx: &str, channels: &[&[u8]]) -> Vec<u8> {
+    let table_len = 2 + 2 * 9;
+    let mut body = Vec::new();
+    let mut offsets = Vec::new();
+    for index in 0..9 {
+        offsets.push((table_len + body.len()) as u16);
+        body.extend_from_slice(channels.get(index).copied().unwrap_or(END));
+    }
+    let voice_offset = (table_len + body.len()) as u16;
+    body.extend_from_slice(&VOICE);
+
+    let mut bytes = title.to_vec();
+    bytes.extend_from_slice(b"\r\n\x1A");
+    bytes.extend_from_slice(pdx.as_bytes());
+    bytes.push(0);
+    bytes.extend_from_slice(&voice_offset.to_be_bytes());
+    for offset in offsets {
+        bytes.extend_from_slice(&offset.to_be_bytes());
+    }
+    bytes.extend_from_slice(&body);
+    bytes
+}
+
+fn import_channel(data: &[u8]) -> mdx::MdxImport {
+    mdx::import(&build_mdx(b"test", "", &[data])).unwrap()
+}
+
+/// Seconds per sequencer tick at timer B value `timer_b`
+fn tick(timer_b: u8) -> f64 {
+    1024.0 * (256 - timer_b as u32) as f64 / X68000_CLOCK as f64
+}
+
+/// (time, data) of every write to `addr`
+fn assert_time(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-9,
+        "{} != {}",
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn test_parse_header_and_voices() {
+    let bytes = build_mdx(b"title", "BANK", &[]);
+    let header = mdx::parse_header(&bytes).unwrap();
+
+    assert_eq!(header.title, b"title");
+    assert_eq!(header.pdx_name.as_deref(), Some("BANK"));
+    assert_eq!(header.data_offset, 13);
+    assert_eq!(header.channel_offsets.len(), 9);
+    assert_eq!(header.channel_offsets[0], 13 + 20);
+
+    let voices = mdx::parse_voices(&bytes, &header);
+    let voice = &voices[&0];
+    assert_eq!(voice.alg, 4);
+    assert_eq!(voice.slot_mask, 0x0F);
+    let tl = voice.operators.map(|operator| operator.tl);
+    assert_eq!(tl, [0x20, 0x20, 0x10, 0x10]);
+    assert!(voice
+        .operators
+        .iter()
+        .all(|operator| (operator.d1l, operator.rr) == (0, 0x0F)));
+    assert!(!voice.is_carrier(1));
+    assert!(voice.is_carrier(2));
+}
+
+#[test]
+fn test_rejects_non_mdx_data() {
+    let err = mdx::import(b"not an mdx file").unwrap_err();
+    assert!(err.to_string().contains("Not an MDX file"));
+}
+
+#[test]
+fn test_note_writes_pitch_and_keys_on_and_off() {
+    // @0, o4a for 48 ticks
+    let import = import_channel(&[0xFD, 0x00, 0xB6, 0x2F, 0xF1, 0x00]);
+    let log = &import.log;
+
+    assert_eq!(log.clock, Some(X68000_CLOCK));
+    assert_eq!(writes(log, 0x28), vec![(0.0, 0x4A)]);
+    assert_eq!(writes(log, 0x30), vec![(0.0, 0x00)]);
+    let keys = writes(log, 0x08);
+    assert_eq!(keys.len(), 2);
+    assert_eq!(keys[0], (0.0, 0x78));
+    assert_eq!(keys[1].1, 0x00);
+    assert_time(keys[1].0, 48.0 * tick(DEFAULT_TIMER_B));
+    assert_eq!(import.ticks, 48);
+}
+
+#[test]
+fn test_note_to_key_code_mapping() {
+    // o4c is key code 0x3E (C belongs to the octave below on the OPM)
+    let import = import_channel(&[0xFD, 0x00, 0xAD, 0x00, 0xF1, 0x00]);
+    assert_eq!(writes(&import.log, 0x28), vec![(0.0, 0x3E)]);
+}
+
+#[test]
+fn test_voice_and_volume_set_carrier_tl() {
+    // @0 (default v8), then v15, then @v127
+    let import = import_channel(&[0xFD, 0x00, 0xFB, 0x0F, 0xFB, 0xFF, 0xF1, 0x00]);
+    let log = &import.log;
+
+    assert_eq!(writes(log, 0x20), vec![(0.0, 0xFC)]);
+    // Modulators keep the voice TL
+    assert_eq!(writes(log, 0x60), vec![(0.0, 0x20)]);
+    assert_eq!(writes(log, 0x68), vec![(0.0, 0x20)]);
+    // Carriers: v8 adds 0x15, v15 adds 0x02, @v127 adds nothing
+    let c2: Vec<u8> = writes(log, 0x78).iter().map(|w| w.1).collect();
+    assert_eq!(c2, vec![0x25, 0x12, 0x10]);
+}
+
+#[test]
+fn test_tempo_sets_tick_length() {
+    // @t240, @0, 12-tick note
+    let import = import_channel(&[0xFF, 0xF0, 0xFD, 0x00, 0xB6, 0x0B, 0xF1, 0x00]);
+    let keys = writes(&import.log, 0x08);
+
+    assert_time(keys[1].0, 12.0 * tick(0xF0));
+}
+
+#[test]
+fn test_gate_time_keys_off_early() {
+    // q4: key off after half of a 48-tick note
+    let import = import_channel(&[0xF8, 0x04, 0xFD, 0x00, 0xB6, 0x2F, 0xF1, 0x00]);
+    let keys = writes(&import.log, 0x08);
+    assert_time(keys[1].0, 24.0 * tick(DEFAULT_TIMER_B));
+
+    // @q4 (0xFC): key off 4 ticks before the end
+    let import = import_channel(&[0xF8, 0xFC, 0xFD, 0x00, 0xB6, 0x2F, 0xF1, 0x00]);
+    let keys = writes(&import.log, 0x08);
+    assert_time(keys[1].0, 44.0 * tick(DEFAULT_TIMER_B));
+}
+
+#[test]
+fn test_legato_ties_notes() {
+    // &, o4a 12 ticks, o4b 12 ticks: one key-on, pitch changes in place
+    let import = import_channel(&[0xFD, 0x00, 0xF7, 0xB6, 0x0B, 0xB8, 0x0B, 0xF1, 0x00]);
+    let log = &import.log;
+
+    assert_eq!(key_ons(log).len(), 1);
+    let key_codes: Vec<u8> = writes(log, 0x28).iter().map(|w| w.1).collect();
+    assert_eq!(key_codes, vec![0x4A, 0x4D]);
+    assert_time(writes(log, 0x08)[1].0, 24.0 * tick(DEFAULT_TIMER_B));
+}
+
+#[test]
+fn test_repeat() {
+    // [ o4a ]3
+    let import = import_channel(&[
+        0xFD, 0x00, 0xF6, 0x03, 0x00, 0xB6, 0x0B, 0xF5, 0xFF, 0xFB, 0xF1, 0x00,
+    ]);
+    assert_eq!(key_ons(&import.log).len(), 3);
+    assert_eq!(import.ticks, 36);
+}
+
+#[test]
+fn test_repeat_escape_skips_the_rest_of_the_last_pass() {
+    // [ o4a / o4b ]3 plays a b a b a
+    let import = import_channel(&[
+        0xFD, 0x00, 0xF6, 0x03, 0x00, // @0, repeat 3
+        0xB6, 0x0B, // o4a
+        0xF4, 0x00, 0x05, // escape to after the repeat end
+        0xB8, 0x0B, // o4b
+        0xF5, 0xFF, 0xF6, // back to the repeat body
+        0xF1, 0x00,
+    ]);
+    let key_codes: Vec<u8> = writes(&import.log, 0x28).iter().map(|w| w.1).collect();
+    assert_eq!(key_codes, vec![0x4A, 0x4D, 0x4A, 0x4D, 0x4A]);
+}
+
+#[test]
+fn test_repeat_end_jumping_to_the_file_start_is_an_error() {
+    // Channel 0 starts after the title line, PDX name and offset table
+    let channel_start = b"test\r\n\x1A\0".len() + 2 + 2 * 9;
+    for target in [0, 1] {
+        let offset = target as i16 - (channel_start + 3) as i16;
+        let [high, low] = offset.to_be_bytes();
+        let bytes = build_mdx(b"test", "", &[&[0xF5, high, low, 0xF1, 0x00]]);
+        let err = mdx::import(&bytes).unwrap_err();
+        assert!(err.to_string().contains("starts before"), "{}", err);
+    }
+}
+
+#[test]
+fn test_detune() {
+    // Detune +64 is one semitone, +32 half a semitone
+    let import = import_channel(&[0xFD, 0x00, 0xF3, 0x00, 0x40, 0xB6, 0x0B, 0xF1, 0x00]);
+    assert_eq!(writes(&import.log, 0x28), vec![(0.0, 0x4C)]);
+
+    let import = import_channel(&[0xFD, 0x00, 0xF3, 0x00, 0x20, 0xB6, 0x0B, 0xF1, 0x00]);
+    assert_eq!(writes(&import.log, 0x28), vec![(0.0, 0x4A)]);
+    assert_eq!(writes(&import.log, 0x30), vec![(0.0, 0x80)]);
+}
+
+#[test]
+fn test_portamento_slides_one_kf_step_per_tick() {
+    let import = import_channel(&[0xFD, 0x00, 0xF2, 0x01, 0x00, 0xB6, 0x0B, 0xF1, 0x00]);
+    let fractions = writes(&import.log, 0x30);
+
+    assert_eq!(fractions.len(), 12);
+    assert_eq!(fractions[11].1, 11 << 2);
+    assert_time(fractions[11].0, 11.0 * tick(DEFAULT_TIMER_B));
+}
+
+#[test]
+fn test_pitch_lfo_moves_key_fraction() {
+    // MP triangle, half cycle 4 ticks, amplitude 8 KF steps
+    let import = import_channel(&[
+        0xFD, 0x00, 0xEC, 0x02, 0x00, 0x04, 0x08, 0x00, 0xB6, 0x2F, 0xF1, 0x00,
+    ]);
+    assert!(writes(&import.log, 0x30).len() > 8);
+}
+
+#[test]
+fn test_opm_lfo_writes_lfo_registers() {
+    // MH: waveform 2, LFRQ 0xC0, PMD 0x10, AMD 0x00, PMS/AMS 0x40
+    let import = import_channel(&[0xEA, 0x02, 0xC0, 0x10, 0x00, 0x40, 0xEA, 0x80, 0xF1, 0x00]);
+    let log = &import.log;
+
+    assert_eq!(writes(log, 0x1B), vec![(0.0, 0x02)]);
+    assert_eq!(writes(log, 0x18), vec![(0.0, 0xC0)]);
+    assert_eq!(writes(log, 0x19), vec![(0.0, 0x90), (0.0, 0x00)]);
+    assert_eq!(writes(log, 0x38), vec![(0.0, 0x40), (0.0, 0x00)]);
+}
+
+#[test]
+fn test_loop_count() {
+    // @0, L o4a 12 ticks, jump back to L
+    let channel: &[u8] = &[0xFD, 0x00, 0xB6, 0x0B, 0xF1, 0xFF, 0xFB];
+
+    let import = import_channel(channel);
+    assert_eq!(key_ons(&import.log).len(), 1);
+    assert_eq!(import.ticks, 12);
+
+    let bytes = build_mdx(b"test", "", &[channel]);
+    let import = mdx::import_with_options(&bytes, &MdxOptions { loop_count: 3 }).unwrap();
+    assert_eq!(key_ons(&import.log).len(), 3);
+    assert_eq!(import.ticks, 36);
+}
+
+#[test]
+fn test_looping_channel_plays_until_the_song_ends() {
+    let bytes = build_mdx(
+        b"test",
+        "",
+        &[
+            &[0xFD, 0x00, 0xB6, 0x0B, 0xF1, 0xFF, 0xFB],
+            &[0xFD, 0x00, 0xB6, 0x2F, 0xF1, 0x00],
+        ],
+    );
+    let import = mdx::import(&bytes).unwrap();
+
+    assert_eq!(import.ticks, 48);
+    // Channel A keeps looping while channel B plays its 48-tick note
+    let channel_a_key_ons = writes(&import.log, 0x08)
+        .iter()
+        .filter(|(_, data)| *data == 0x78)
+        .count();
+    assert_eq!(channel_a_key_ons, 4);
+}
+
+#[test]
+fn test_sync_wait_holds_channel_until_signalled() {
+    let bytes = build_mdx(
+        b"test",
+        "",
+        &[
+            // Channel A waits, then plays
+            &[0xEE, 0xFD, 0x00, 0xB6, 0x0B, 0xF1, 0x00],
+            // Channel B rests 6 ticks, then signals channel A
+            &[0x05, 0xEF, 0x00, 0x05, 0xF1, 0x00],
+        ],
+    );
+    let import = mdx::import(&bytes).unwrap();
+    let keys = writes(&import.log, 0x08);
+
+    assert_eq!(keys[0].1, 0x78);
+    assert_time(keys[0].0, 7.0 * tick(DEFAULT_TIMER_B));
+}
+
+#[test]
+fn test_adpcm_channel_and_pdx_are_reported() {
+    let bytes = build_mdx(
+        b"test",
+        "BANK",
+        &[
+            END,
+            END,
+            END,
+            END,
+            END,
+            END,
+            END,
+            END,
+            &[0x80, 0x0F, 0xF1, 0x00],
+        ],
+    );
+    let import = mdx::import(&bytes).unwrap();
+    let warnings = import.warnings();
+
+    assert!(import.log.events.is_empty());
+    assert_eq!(warnings.len(), 2, "{:?}", warnings);
+    assert!(warnings[0].contains("channel P"));
+    assert!(warnings[1].contains("BANK"));
+}
+
+#[test]
+fn test_title_metadata() {
+    let import = mdx::import(&build_mdx(b"my song ", "", &[])).unwrap();
+    let metadata = import.log.metadata.unwrap();
+    assert_eq!(metadata.title.as_deref(), Some("my song"));
+    assert_eq!(metadata.system.as_deref(), Some("X68000"));
+
+    // Shift_JIS titles are reported rather than decoded
+    let import = mdx::import(&build_mdx(b"\x83\x65\x83\x58\x83\x67", "", &[])).unwrap();
+    assert!(import.log.metadata.is_none());
+    assert_eq!(import.warnings().len(), 1);
+}
+
+#[test]
+fn test_unknown_command_is_an_error() {
+    let bytes = build_mdx(b"test", "", &[&[0xE0, 0xF1, 0x00]]);
+    let err = mdx::import(&bytes).unwrap_err();
+    assert!(err.to_string().contains("Unknown MDX command 0xE0"));
+}
+
+#[test]
+fn test_loop_without_notes_ends_the_song() {
+    // A loop that jumps back without playing anything counts as looped
+    let bytes = build_mdx(b"test", "", &[&[0xF9, 0xF1, 0xFF, 0xFC]]);
+    let import = mdx::import(&bytes).unwrap();
+
+    assert_eq!(import.ticks, 0);
+}
+
+#[test]
+fn test_mdx_format_is_import_only() {
+    assert_eq!(LogFormat::from_path("song.MDX"), LogFormat::Mdx);
+
+    let loaded = formats::load_event_log("tests/fixtures/simple.mdx").unwrap();
+    assert!(!loaded.log.events.is_empty());
+
+    let path = std::env::temp_dir().join("ym2151_mdx_tests_output.mdx");
+    assert!(formats::save_event_log(&path, &loaded.log).is_err());
+}
@@ -2,22 +2,92 @@
 // These tests have access to private functions and types
 
 mod audio_tests;
+mod client_stream_tests;
 mod client_tests;
 mod command_handler_tests;
+mod compression_tests;
 mod debug_wav_tests;
 mod demo_server_interactive_tests;
 mod demo_server_non_interactive_tests;
+mod diff_tests;
+mod disasm_tests;
+mod driver_tests;
+mod event_schedule_tests;
+mod event_stream_tests;
+mod events_edit_tests;
 mod events_tests;
+mod flac_writer_tests;
 mod ipc_pipe_windows_tests;
 mod ipc_protocol_tests;
 mod logging_tests;
+mod mdx_tests;
 mod mmcss_tests;
 mod opm_ffi_tests;
 mod opm_tests;
+mod optimizer_tests;
+mod output_path_tests;
+mod pcm_tests;
 mod play_json_interactive_tests;
 mod player_tests;
+mod register_shadow_tests;
+mod render_tests;
+mod render_worker_tests;
 mod resampler_tests;
+mod s98_tests;
 mod scheduler_tests;
 mod self_update_tests;
 mod server_tests;
+mod session_recorder_tests;
+mod smf_tests;
+mod stems_tests;
+mod submission_ring_tests;
+mod vgm_tests;
+mod voice_tests;
 mod wav_writer_tests;
+
+use crate::events::{EventLog, RegisterEvent};
+
+/// A register write at `time` seconds
+fn event(time: f64, addr: u8, data: u8) -> RegisterEvent {
+    RegisterEvent {
+        time,
+        addr,
+        data,
+        is_data: None,
+    }
+}
+
+/// A log of `events` with the default header
+fn log(events: Vec<RegisterEvent>) -> EventLog {
+    EventLog {
+        events,
+        ..Default::default()
+    }
+}
+
+/// `(time, data)` of every write to `addr`
+fn writes(log: &EventLog, addr: u8) -> Vec<(f64, u8)> {
+    log.events
+        .iter()
+        .filter(|e| e.addr == addr)
+        .map(|e| (e.time, e.data))
+        .collect()
+}
+
+/// `(time, data)` of every key-on write (0x08 with a slot bit set)
+fn key_ons(log: &EventLog) -> Vec<(f64, u8)> {
+    writes(log, 0x08)
+        .into_iter()
+        .filter(|(_, data)| data & 0x78 != 0)
+        .collect()
+}
+
+/// The little-endian u32 at `offset` of a file image
+fn read_u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Overwrite the little-endian u32 at `offset` of a file image
+fn write_u32_at(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
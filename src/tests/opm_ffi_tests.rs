@@ -24,3 +24,32 @@ fn test_call_opm_clock_64times() {
         call_opm_clock_64times(&mut chip, output.as_mut_ptr());
     }
 }
+
+#[test]
+fn test_opm_write_t_layout() {
+    // Must match the C struct: uint32_t offset, uint8_t port, uint8_t data (+ padding)
+    assert_eq!(mem::size_of::<opm_write_t>(), 8);
+    assert_eq!(mem::align_of::<opm_write_t>(), 4);
+}
+
+#[test]
+fn test_opm_render() {
+    unsafe {
+        let mut chip: opm_t = mem::zeroed();
+        let mut buffer = [0i16; 32];
+        let writes = [opm_write_t {
+            offset: 0,
+            port: 0,
+            data: 0x08,
+        }];
+
+        OPM_Reset(&mut chip);
+        opm_render(
+            &mut chip,
+            buffer.as_mut_ptr(),
+            16,
+            writes.as_ptr(),
+            writes.len() as u32,
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use super::{event, log};
+use crate::events::{EventLog, RegisterEvent};
+use crate::formats::player_write_samples;
+use crate::optimizer::{self, OptimizeOptions};
+use crate::resampler::OPM_SAMPLE_RATE;
+
+/// A sine-like voice on channel 0 (algorithm 7, all carriers loud)
+fn voice() -> Vec<RegisterEvent> {
+    let mut events = vec![event(0.0, 0x20, 0xC7), event(0.0, 0x28, 0x4A)];
+    for slot in 0..4u8 {
+        let offset = slot * 8;
+        events.push(event(0.0, 0x40 + offset, 0x01));
+        events.push(event(0.0, 0x60 + offset, 0x10));
+        events.push(event(0.0, 0x80 + offset, 0x1F));
+        events.push(event(0.0, 0xE0 + offset, 0x0F));
+    }
+    events
+}
+
+#[test]
+fn test_removes_redundant_writes() {
+    let mut events = voice();
+    events.push(event(0.0, 0x08, 0x78));
+    events.push(event(0.1, 0x28, 0x4A));
+    events.push(event(0.1, 0x20, 0xC7));
+    events.push(event(0.2, 0x08, 0x00));
+    events.push(event(0.3, 0x08, 0x00));
+    let original = log(events);
+
+    let optimized = optimizer::optimize(&original).unwrap();
+    let stats = optimized.stats;
+    assert_eq!(stats.redundant_writes, 3);
+    assert_eq!(stats.dead_writes, 0);
+    assert_eq!(stats.remaining(), original.events.len() - 3);
+    assert_eq!(optimized.log.events.len(), stats.remaining());
+    assert!(optimized
+        .log
+        .events
+        .iter()
+        .all(|e| e.time != 0.1 && e.time != 0.3));
+}
+
+#[test]
+fn test_kept_writes_stay_on_their_samples() {
+    // The second 0x20 write is dropped; the writes behind it must not move up
+    let original = log(vec![
+        event(0.0, 0x20, 0xC7),
+        event(0.0, 0x08, 0x00),
+        event(0.0, 0x20, 0xC7),
+        event(0.0, 0x28, 0x4A),
+        event(0.0, 0x30, 0x00),
+    ]);
+    let optimized = optimizer::optimize_unverified(&original, OptimizeOptions::default()).unwrap();
+
+    assert_eq!(optimized.stats.redundant_writes, 1);
+    assert_eq!(optimized.stats.retimed_events, 1);
+    let before = player_write_samples(&original.events, OPM_SAMPLE_RATE);
+    let after = player_write_samples(&optimized.log.events, OPM_SAMPLE_RATE);
+    assert_eq!(after, vec![before[0], before[1], before[3], before[4]]);
+    optimizer::verify_identical(&original, &optimized.log).unwrap();
+}
+
+#[test]
+fn test_removes_dead_writes_on_silent_channel() {
+    let mut events = vec![event(0.0, 0x28, 0x30), event(0.0, 0x30, 0x10)];
+    events.extend(voice());
+    events.push(event(0.0, 0x08, 0x78));
+    events.push(event(0.5, 0x08, 0x00));
+    let original = log(events);
+
+    let optimized = optimizer::optimize(&original).unwrap();
+    // 0x28 is overwritten by the voice; 0x30 is not
+    assert_eq!(optimized.stats.dead_writes, 1);
+    assert!(!optimized.stats.dead_writes_kept);
+    assert_eq!(
+        optimized
+            .log
+            .events
+            .iter()
+            .filter(|e| e.addr == 0x28)
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn test_keeps_audible_dead_writes() {
+    let mut events = voice();
+    events.push(event(0.0, 0x08, 0x78));
+    // A dip in level while the note sounds is overwritten at the same time
+    events.push(event(0.1, 0x78, 0x7F));
+    events.push(event(0.1, 0x78, 0x10));
+    events.push(event(0.2, 0x08, 0x00));
+    let original = log(events);
+
+    let unverified = optimizer::optimize_unverified(&original, OptimizeOptions::default()).unwrap();
+    assert_eq!(unverified.stats.dead_writes, 1);
+
+    let optimized = optimizer::optimize(&original).unwrap();
+    assert_eq!(optimized.stats.dead_writes, 0);
+    assert!(optimized.stats.dead_writes_kept);
+    assert_eq!(optimized.log.events.len(), original.events.len());
+}
+
+#[test]
+fn test_side_effect_and_key_writes_are_not_dead() {
+    let original = log(vec![
+        event(0.0, 0x14, 0x15),
+        event(0.0, 0x14, 0x15),
+        event(0.0, 0x01, 0x02),
+        event(0.0, 0x01, 0x00),
+        event(0.0, 0x19, 0x10),
+        event(0.0, 0x19, 0x90),
+        event(0.0, 0x08, 0x00),
+        event(0.0, 0x08, 0x01),
+    ]);
+    let optimized = optimizer::optimize_unverified(&original, OptimizeOptions::default()).unwrap();
+
+    // AMD and PMD share 0x19 but are different registers; key-off per channel
+    assert_eq!(optimized.stats.removed(), 0);
+}
+
+#[test]
+fn test_rejects_invalid_log() {
+    let original = log(vec![event(0.5, 0x20, 0xC7), event(0.1, 0x20, 0xC7)]);
+    assert!(optimizer::optimize(&original).is_err());
+}
+
+#[test]
+fn test_sample_log_renders_identically() {
+    // The first half second keeps the test fast in debug builds
+    let mut original = EventLog::from_file("output_ym2151.json").unwrap();
+    original.events.retain(|e| e.time < 0.5);
+
+    // optimize() renders and compares before returning
+    let optimized = optimizer::optimize(&original).unwrap();
+    assert_eq!(optimized.stats.original_events, original.events.len());
+    assert!(optimized.log.validate());
+}
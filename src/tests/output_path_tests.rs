@@ -0,0 +1,57 @@
+use crate::server::{check_input_path, check_output_path, RENDER_EXTENSIONS, SESSION_EXTENSIONS};
+
+#[test]
+fn test_relative_paths_with_known_extensions_are_accepted() {
+    for path in [
+        "session.vgm",
+        "out/session.JSON",
+        "./take1.json.gz",
+        "a/b.mid",
+    ] {
+        assert!(
+            check_output_path(path, SESSION_EXTENSIONS).is_ok(),
+            "{}",
+            path
+        );
+    }
+    assert!(check_output_path("renders/song.flac", RENDER_EXTENSIONS).is_ok());
+}
+
+#[test]
+fn test_paths_leaving_the_working_directory_are_rejected() {
+    for path in [
+        "",
+        "/tmp/session.json",
+        "../session.json",
+        "out/../../session.json",
+        "out/..",
+    ] {
+        assert!(
+            check_output_path(path, SESSION_EXTENSIONS).is_err(),
+            "{}",
+            path
+        );
+    }
+}
+
+#[test]
+fn test_other_extensions_are_rejected() {
+    for path in ["session", "session.exe", "song.mdx"] {
+        assert!(
+            check_output_path(path, SESSION_EXTENSIONS).is_err(),
+            "{}",
+            path
+        );
+    }
+    assert!(check_output_path("song.json", RENDER_EXTENSIONS).is_err());
+}
+
+#[test]
+fn test_input_paths_are_confined_like_output_paths() {
+    for path in ["song.json", "logs/song.vgz", "./song"] {
+        assert!(check_input_path(path).is_ok(), "{}", path);
+    }
+    for path in ["", "/etc/passwd", "../song.json", "logs/../../song.json"] {
+        assert!(check_input_path(path).is_err(), "{}", path);
+    }
+}
@@ -0,0 +1,103 @@
+use crate::pcm::{from_dac, to_i16, to_i24, to_le_bytes, Dither, I16Converter, SampleFormat};
+
+#[test]
+fn test_from_dac_scale() {
+    assert_eq!(from_dac(0), 0.0);
+    assert_eq!(from_dac(65536), 1.0);
+    assert_eq!(from_dac(-32768), -0.5);
+}
+
+#[test]
+fn test_to_i16_truncates_like_realtime_path() {
+    let raw = [0, 1, -1, 3, -3, 65534, 70000, -70000];
+    let samples: Vec<f32> = raw.iter().map(|&r| from_dac(r)).collect();
+    let expected: Vec<i16> = raw
+        .iter()
+        .map(|&r| (r / 2).clamp(-32768, 32767) as i16)
+        .collect();
+    assert_eq!(to_i16(&samples, Dither::Off), expected);
+}
+
+#[test]
+fn test_to_i24_keeps_dac_bit() {
+    let raw = [0, 1, -1, 12345, -65536, 70000];
+    let samples: Vec<f32> = raw.iter().map(|&r| from_dac(r)).collect();
+    assert_eq!(
+        to_i24(&samples),
+        vec![0, 128, -128, 1580160, -8388608, 8388607]
+    );
+}
+
+#[test]
+fn test_dither_is_reproducible_and_small() {
+    // A constant a quarter LSB above zero: truncation loses it, dither keeps it on average
+    let samples = vec![0.25 / 32768.0; 20000];
+    let dithered = to_i16(&samples, Dither::new(true));
+
+    assert_eq!(dithered, to_i16(&samples, Dither::new(true)));
+    assert!(dithered.iter().all(|&s| (-1..=2).contains(&s)));
+    let mean = dithered.iter().map(|&s| s as f64).sum::<f64>() / dithered.len() as f64;
+    assert!((mean - 0.25).abs() < 0.05, "mean {}", mean);
+    assert!(to_i16(&samples, Dither::Off).iter().all(|&s| s == 0));
+}
+
+#[test]
+fn test_dither_noise_differs_per_channel_and_runs_on_across_calls() {
+    let samples = vec![0.25 / 32768.0; 2000];
+    let dither = Dither::new(true);
+    let whole = to_i16(&samples, dither);
+    assert_ne!(whole, to_i16(&samples, dither.for_channel(0)));
+    assert_ne!(
+        to_i16(&samples, dither.for_channel(0)),
+        to_i16(&samples, dither.for_channel(1))
+    );
+    assert_eq!(Dither::Off.for_channel(3), Dither::Off);
+
+    let mut converter = I16Converter::new(dither);
+    let mut halves = converter.convert(&samples[..1000]);
+    halves.extend(converter.convert(&samples[1000..]));
+    assert_eq!(halves, whole);
+}
+
+#[test]
+fn test_sample_format_names() {
+    assert_eq!(SampleFormat::from_name("16").unwrap(), SampleFormat::Int16);
+    assert_eq!(SampleFormat::from_name("24").unwrap(), SampleFormat::Int24);
+    assert_eq!(
+        SampleFormat::from_name("32F").unwrap(),
+        SampleFormat::Float32
+    );
+    assert_eq!(
+        SampleFormat::from_name("float").unwrap(),
+        SampleFormat::Float32
+    );
+    assert!(SampleFormat::from_name("8").is_err());
+    assert_eq!(SampleFormat::Int24.bits_per_sample(), 24);
+    for format in [
+        SampleFormat::Int16,
+        SampleFormat::Int24,
+        SampleFormat::Float32,
+    ] {
+        assert_eq!(SampleFormat::from_name(format.name()).unwrap(), format);
+    }
+}
+
+#[test]
+fn test_to_le_bytes() {
+    let samples = [0.5, -1.0 / 32768.0];
+
+    assert_eq!(
+        to_le_bytes(&samples, SampleFormat::Int16, Dither::Off),
+        vec![0x00, 0x40, 0xFF, 0xFF]
+    );
+    assert_eq!(
+        to_le_bytes(&samples, SampleFormat::Int24, Dither::Off),
+        vec![0x00, 0x00, 0x40, 0x00, 0xFF, 0xFF]
+    );
+    let bytes = to_le_bytes(&samples, SampleFormat::Float32, Dither::Off);
+    assert_eq!(bytes.len(), 8);
+    assert_eq!(
+        f32::from_le_bytes(bytes[4..].try_into().unwrap()),
+        samples[1]
+    );
+}
@@ -140,3 +140,25 @@ fn test_play_json_interactive_integration() -> Result<()> {
         }
     }
 }
+
+#[test]
+fn test_sample_times_are_sent_in_seconds() {
+    use crate::client::interactive::seconds_json;
+
+    let input_json = r#"{
+        "time_unit": "samples",
+        "events": [
+            {"time": 0, "addr": "0x08", "data": "0x00"},
+            {"time": 27965, "addr": 32, "data": 199}
+        ]
+    }"#;
+
+    let converted = seconds_json(input_json).unwrap();
+    let events = converted["events"].as_array().unwrap();
+    assert_eq!(events[1]["time"], 0.5);
+    assert_eq!(events[1]["addr"], "0x20");
+    assert_eq!(events[1]["data"], "0xC7");
+    assert!(converted.get("time_unit").is_none());
+
+    assert!(seconds_json(r#"{"time_unit": "ticks", "events": []}"#).is_err());
+}
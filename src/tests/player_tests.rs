@@ -1,5 +1,6 @@
-use crate::events::{EventLog, RegisterEvent};
+use crate::events::{EventLog, Marker, RegisterEvent};
 use crate::player::Player;
+use crate::resampler::YM2151_CLOCK;
 
 #[test]
 fn test_convert_events_empty() {
@@ -137,6 +138,7 @@ fn test_player_creation() {
             data: 0x00,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let player = Player::new(log);
@@ -155,6 +157,7 @@ fn test_generate_samples_basic() {
             data: 0x00,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let mut player = Player::new(log);
@@ -182,6 +185,7 @@ fn test_generate_samples_timing() {
                 is_data: None,
             },
         ],
+        ..Default::default()
     };
 
     let mut player = Player::new(log);
@@ -210,6 +214,7 @@ fn test_total_samples() {
             data: 0x00,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let player = Player::new(log);
@@ -221,7 +226,10 @@ fn test_total_samples() {
 
 #[test]
 fn test_empty_event_log() {
-    let log = EventLog { events: vec![] };
+    let log = EventLog {
+        events: vec![],
+        ..Default::default()
+    };
 
     let player = Player::new(log);
 
@@ -239,6 +247,7 @@ fn test_playback_completion() {
             data: 0x00,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let mut player = Player::new(log);
@@ -269,14 +278,13 @@ fn test_interactive_mode_creation() {
 
 #[test]
 fn test_schedule_register_write() {
-    let player = Player::new_interactive();
+    let mut player = Player::new_interactive();
 
     // Schedule a register write
     player.schedule_register_write(100, 0x08, 0x78);
 
     // Check that event was added to the queue
-    let queue = player.get_event_queue();
-    let q = queue.lock().unwrap();
+    let q = player.scheduled_events();
     assert_eq!(q.len(), 1); // One addr-data pair event
 
     // Check addr-data pair
@@ -287,7 +295,7 @@ fn test_schedule_register_write() {
 
 #[test]
 fn test_clear_schedule() {
-    let player = Player::new_interactive();
+    let mut player = Player::new_interactive();
 
     // Schedule some events
     player.schedule_register_write(100, 0x08, 0x78);
@@ -295,8 +303,7 @@ fn test_clear_schedule() {
 
     // Verify events were added
     {
-        let queue = player.get_event_queue();
-        let q = queue.lock().unwrap();
+        let q = player.scheduled_events();
         assert_eq!(q.len(), 2); // 2 register writes = 2 addr-data pair events
     }
 
@@ -305,15 +312,14 @@ fn test_clear_schedule() {
 
     // Verify queue is empty
     {
-        let queue = player.get_event_queue();
-        let q = queue.lock().unwrap();
+        let q = player.scheduled_events();
         assert_eq!(q.len(), 0);
     }
 }
 
 #[test]
 fn test_clear_schedule_from() {
-    let player = Player::new_interactive();
+    let mut player = Player::new_interactive();
 
     // Schedule some events at different times
     player.schedule_register_write(100, 0x08, 0x78);
@@ -323,8 +329,7 @@ fn test_clear_schedule_from() {
 
     // Verify all events were added
     {
-        let queue = player.get_event_queue();
-        let q = queue.lock().unwrap();
+        let q = player.scheduled_events();
         assert_eq!(q.len(), 4);
     }
 
@@ -333,8 +338,7 @@ fn test_clear_schedule_from() {
 
     // Verify only events before time 250 remain
     {
-        let queue = player.get_event_queue();
-        let q = queue.lock().unwrap();
+        let q = player.scheduled_events();
         assert_eq!(q.len(), 2);
         assert_eq!(q[0].time, 100);
         assert_eq!(q[1].time, 200);
@@ -343,7 +347,7 @@ fn test_clear_schedule_from() {
 
 #[test]
 fn test_clear_schedule_from_boundary() {
-    let player = Player::new_interactive();
+    let mut player = Player::new_interactive();
 
     // Schedule events
     player.schedule_register_write(100, 0x08, 0x78);
@@ -354,8 +358,7 @@ fn test_clear_schedule_from_boundary() {
     player.clear_schedule_from(200);
 
     {
-        let queue = player.get_event_queue();
-        let q = queue.lock().unwrap();
+        let q = player.scheduled_events();
         assert_eq!(q.len(), 1);
         assert_eq!(q[0].time, 100);
     }
@@ -363,17 +366,27 @@ fn test_clear_schedule_from_boundary() {
 
 #[test]
 fn test_clear_schedule_non_interactive_mode() {
-    let log = EventLog { events: vec![] };
+    let log = EventLog {
+        events: vec![],
+        ..Default::default()
+    };
     let player = Player::new(log);
 
     // clear_schedule should do nothing in non-interactive mode
     player.clear_schedule(); // Should not panic
     assert!(!player.is_interactive());
+    // Static and streaming players do not allocate a submission ring
+    assert!(player.submitter().is_none());
+    assert!(Player::new_streaming_with_clock(YM2151_CLOCK)
+        .0
+        .submitter()
+        .is_none());
+    assert!(Player::new_interactive().submitter().is_some());
 }
 
 #[test]
 fn test_schedule_events_are_sorted() {
-    let player = Player::new_interactive();
+    let mut player = Player::new_interactive();
 
     // Schedule events out of order
     player.schedule_register_write(200, 0x20, 0xC7);
@@ -381,8 +394,7 @@ fn test_schedule_events_are_sorted() {
     player.schedule_register_write(150, 0x28, 0x3E);
 
     // Check that events are sorted by time
-    let queue = player.get_event_queue();
-    let q = queue.lock().unwrap();
+    let q = player.scheduled_events();
 
     // Should have 3 events (3 register writes as addr-data pairs)
     assert_eq!(q.len(), 3);
@@ -392,3 +404,98 @@ fn test_schedule_events_are_sorted() {
         assert!(q[i].time >= q[i - 1].time);
     }
 }
+
+#[test]
+fn test_player_follows_log_clock() {
+    let event = |time: f64| RegisterEvent {
+        time,
+        addr: 0x08,
+        data: 0x00,
+        is_data: None,
+    };
+
+    let default_player = Player::new(EventLog {
+        events: vec![event(1.0)],
+        ..Default::default()
+    });
+    assert_eq!(default_player.clock(), 3_579_545);
+    assert_eq!(default_player.current_sample_rate(), 55930);
+    assert_eq!(default_player.total_samples(), 55930);
+
+    // At 4 MHz the chip produces 62500 samples per second
+    let x68k_player = Player::new(EventLog {
+        events: vec![event(1.0)],
+        clock: Some(4_000_000),
+        ..Default::default()
+    });
+    assert_eq!(x68k_player.clock(), 4_000_000);
+    assert_eq!(x68k_player.current_sample_rate(), 62500);
+    assert_eq!(x68k_player.total_samples(), 62500);
+}
+
+#[test]
+fn test_interactive_player_with_clock() {
+    let player = Player::new_interactive_with_clock(4_000_000);
+    assert!(player.is_interactive());
+    assert_eq!(player.current_sample_rate(), 62500);
+}
+
+#[test]
+fn test_reached_markers() {
+    let marker = |time: f64, name: &str| Marker {
+        time,
+        name: name.to_string(),
+    };
+    let log = EventLog {
+        events: vec![RegisterEvent {
+            time: 0.0,
+            addr: 0x08,
+            data: 0x00,
+            is_data: None,
+        }],
+        // Out of order on purpose; 1000 samples is ~0.0179 s
+        markers: vec![marker(0.03, "b"), marker(0.0, "a"), marker(0.03, "c")],
+        ..Default::default()
+    };
+    let mut player = Player::new(log);
+
+    let mut buffer = vec![0i16; 2000];
+    player.generate_samples(&mut buffer);
+    let names: Vec<&str> = player
+        .take_reached_markers()
+        .iter()
+        .map(|m| m.name.as_str())
+        .collect();
+    assert_eq!(names, ["a"]);
+    assert!(player.take_reached_markers().is_empty());
+
+    player.generate_samples(&mut buffer);
+    let names: Vec<&str> = player
+        .take_reached_markers()
+        .iter()
+        .map(|m| m.name.as_str())
+        .collect();
+    assert_eq!(names, ["b", "c"]);
+    let names: Vec<&str> = player.markers().iter().map(|m| m.name.as_str()).collect();
+    assert_eq!(names, ["a", "b", "c"]);
+}
+
+#[test]
+fn test_generate_samples_full_keeps_dac_precision() {
+    let log = EventLog::from_file("output_ym2151.json").unwrap();
+    let mut player = Player::new(log.clone());
+    let mut full_player = Player::new(log);
+
+    let mut buffer = vec![0i16; 4096];
+    let mut full_buffer = vec![0i32; 4096];
+    let mut odd_values = 0;
+    for _ in 0..20 {
+        player.generate_samples(&mut buffer);
+        full_player.generate_samples_full(&mut full_buffer);
+        for (&sample, &full) in buffer.iter().zip(&full_buffer) {
+            assert_eq!(sample as i32, (full / 2).clamp(-32768, 32767));
+            odd_values += (full % 2 != 0) as usize;
+        }
+    }
+    assert!(odd_values > 0, "the extra DAC bit should be kept");
+}
@@ -0,0 +1,50 @@
+use crate::register_shadow::RegisterShadow;
+
+#[test]
+fn test_store_reports_changes() {
+    let mut shadow = RegisterShadow::default();
+
+    assert!(shadow.store(0x20, 0xC7));
+    assert!(!shadow.store(0x20, 0xC7));
+    assert!(shadow.store(0x20, 0xC0));
+}
+
+#[test]
+fn test_amd_pmd_and_key_ons_are_kept_apart() {
+    let mut shadow = RegisterShadow::default();
+
+    shadow.store(0x19, 0x10);
+    assert!(shadow.store(0x19, 0x90));
+    assert!(!shadow.store(0x19, 0x10));
+    shadow.store(0x08, 0x78);
+    assert!(shadow.store(0x08, 0x01));
+    assert!(!shadow.store(0x08, 0x78));
+
+    assert_ne!(
+        RegisterShadow::key(0x19, 0x10),
+        RegisterShadow::key(0x19, 0x90)
+    );
+    assert_ne!(
+        RegisterShadow::key(0x08, 0x78),
+        RegisterShadow::key(0x08, 0x79)
+    );
+    assert_eq!(
+        RegisterShadow::key(0x08, 0x78),
+        RegisterShadow::key(0x08, 0x00)
+    );
+}
+
+#[test]
+fn test_writes_and_keys_recreate_the_state() {
+    let mut shadow = RegisterShadow::default();
+    shadow.store(0x28, 0x4A);
+    shadow.store(0x19, 0x90);
+    shadow.store(0x19, 0x10);
+    shadow.store(0x08, 0x79);
+    shadow.store(0x08, 0x00);
+
+    let writes: Vec<_> = shadow.writes().collect();
+    assert_eq!(writes, vec![(0x19, 0x10), (0x19, 0x90), (0x28, 0x4A)]);
+    let keys: Vec<_> = shadow.keys().collect();
+    assert_eq!(keys, vec![0x00, 0x79]);
+}
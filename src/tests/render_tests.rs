@@ -0,0 +1,156 @@
+use super::event;
+use crate::events::EventLog;
+use crate::render::{max_frames, prepare, render, RenderOptions};
+use crate::resampler::{resampled_frames, ResamplingQuality, OPM_SAMPLE_RATE};
+
+/// A sine-like note on channel 0 (operator M1 only), keyed off at `off` if given
+fn note_log(off: Option<f64>) -> EventLog {
+    let mut events = vec![
+        event(0.0, 0x20, 0xC7),
+        event(0.0, 0x28, 0x4A),
+        event(0.0, 0x60, 0x00),
+        event(0.0, 0x80, 0x1F),
+        event(0.0, 0xE0, 0x0F),
+        event(0.0, 0x08, 0x08),
+    ];
+    if let Some(off) = off {
+        events.push(event(off, 0x08, 0x00));
+    }
+    EventLog {
+        events,
+        ..Default::default()
+    }
+}
+
+fn frames(samples: &[f32]) -> usize {
+    samples.len() / 2
+}
+
+#[test]
+fn test_render_native_rate_ends_after_silence() {
+    let rendered = render(&note_log(Some(0.2)), &RenderOptions::default()).unwrap();
+
+    assert_eq!(rendered.sample_rate, OPM_SAMPLE_RATE);
+    assert!(!rendered.tail_truncated);
+    assert!(rendered.samples.iter().any(|&s| s != 0.0));
+    // The note, its release and 100 ms of silence, well short of the tail limit
+    assert!(rendered.duration_sec() > 0.3);
+    assert!(rendered.duration_sec() < 1.0);
+}
+
+#[test]
+fn test_render_silence_duration_extends_tail() {
+    let short = render(&note_log(Some(0.2)), &RenderOptions::default()).unwrap();
+    let options = RenderOptions {
+        silence_ms: 500,
+        ..Default::default()
+    };
+    let long = render(&note_log(Some(0.2)), &options).unwrap();
+
+    let extra = long.duration_sec() - short.duration_sec();
+    assert!((0.35..0.45).contains(&extra), "extra tail {}", extra);
+}
+
+#[test]
+fn test_render_truncates_endless_tail() {
+    let options = RenderOptions {
+        max_tail_sec: 0.5,
+        ..Default::default()
+    };
+    let rendered = render(&note_log(None), &options).unwrap();
+
+    assert!(rendered.tail_truncated);
+    assert_eq!(
+        frames(&rendered.samples),
+        (0.5 * OPM_SAMPLE_RATE as f64).round() as usize
+    );
+}
+
+#[test]
+fn test_render_resamples_to_output_rate() {
+    for quality in [ResamplingQuality::Linear, ResamplingQuality::HighQuality] {
+        let native = render(&note_log(Some(0.2)), &RenderOptions::default()).unwrap();
+        let options = RenderOptions {
+            sample_rate: Some(48000),
+            quality,
+            ..Default::default()
+        };
+        let resampled = render(&note_log(Some(0.2)), &options).unwrap();
+
+        assert_eq!(resampled.sample_rate, 48000);
+        assert_eq!(resampled.native_frames, native.native_frames);
+        assert_eq!(
+            frames(&resampled.samples),
+            resampled_frames(native.native_frames, OPM_SAMPLE_RATE, 48000),
+            "{:?}",
+            quality
+        );
+        assert!(frames(&resampled.samples) <= max_frames(&note_log(Some(0.2)), &options).unwrap());
+    }
+}
+
+#[test]
+fn test_render_range_and_loops() {
+    let mut log = note_log(Some(1.0));
+    log.loop_time = Some(0.0);
+
+    let looped = prepare(
+        &log,
+        &RenderOptions {
+            loops: 3,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(looped.events.last().unwrap().time, 3.0);
+
+    let ranged = prepare(
+        &log,
+        &RenderOptions {
+            loops: 3,
+            start: 1.5,
+            end: Some(2.5),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(ranged.events.iter().all(|e| e.time <= 1.0));
+    let rendered = render(
+        &log,
+        &RenderOptions {
+            loops: 3,
+            start: 1.5,
+            end: Some(2.5),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(rendered.duration_sec() > 1.0);
+    assert!(rendered.duration_sec() < 2.0);
+}
+
+#[test]
+fn test_render_rejects_invalid_options() {
+    let log = note_log(Some(0.2));
+    for options in [
+        RenderOptions {
+            loops: 0,
+            ..Default::default()
+        },
+        RenderOptions {
+            sample_rate: Some(0),
+            ..Default::default()
+        },
+        RenderOptions {
+            max_tail_sec: -1.0,
+            ..Default::default()
+        },
+        RenderOptions {
+            start: 1.0,
+            end: Some(0.5),
+            ..Default::default()
+        },
+    ] {
+        assert!(render(&log, &options).is_err(), "{:?}", options);
+    }
+}
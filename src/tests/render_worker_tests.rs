@@ -0,0 +1,141 @@
+use crate::ipc::protocol::Response;
+use crate::resampler::ResamplingQuality;
+use crate::server::{render_pcm, PlaybackManager, RenderPcmRequest, RenderWorker};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// A short note on channel 0
+fn note_json() -> serde_json::Value {
+    serde_json::json!({
+        "events": [
+            {"time": 0.0, "addr": "0x20", "data": "0xC7"},
+            {"time": 0.0, "addr": "0x28", "data": "0x4A"},
+            {"time": 0.0, "addr": "0x60", "data": "0x00"},
+            {"time": 0.0, "addr": "0x80", "data": "0x1F"},
+            {"time": 0.0, "addr": "0xE0", "data": "0x0F"},
+            {"time": 0.0, "addr": "0x08", "data": "0x08"},
+            {"time": 0.05, "addr": "0x08", "data": "0x00"}
+        ]
+    })
+}
+
+fn request(format: &str) -> RenderPcmRequest {
+    RenderPcmRequest {
+        data: note_json(),
+        sample_rate: None,
+        format: format.to_string(),
+        path: None,
+    }
+}
+
+fn render(request: &RenderPcmRequest) -> Response {
+    let manager = PlaybackManager::new(ResamplingQuality::Linear);
+    render_pcm(request, &manager)
+}
+
+#[test]
+fn test_render_pcm_returns_samples_in_format() {
+    let Response::Pcm {
+        sample_rate,
+        format,
+        frames,
+        data,
+    } = render(&request("24"))
+    else {
+        panic!("expected PCM");
+    };
+
+    assert_eq!(sample_rate, 55930);
+    assert_eq!(format, "24");
+    assert!(frames > 0);
+    assert_eq!(data.len(), frames * 2 * 3);
+    assert!(data.iter().any(|&b| b != 0));
+
+    let Response::Pcm {
+        frames: resampled, ..
+    } = render(&RenderPcmRequest {
+        sample_rate: Some(44100),
+        ..request("16")
+    })
+    else {
+        panic!("expected PCM");
+    };
+    assert!((resampled as f64 / 44100.0 - frames as f64 / 55930.0).abs() < 0.01);
+}
+
+#[test]
+fn test_render_pcm_writes_server_side_file() {
+    // Relative to the working directory, as the server requires
+    let path = "test_render_pcm.wav";
+
+    let response = render(&RenderPcmRequest {
+        path: Some(path.to_string()),
+        ..request("32f")
+    });
+
+    assert_eq!(response, Response::Ok);
+    let reader = hound::WavReader::open(path).unwrap();
+    assert_eq!(reader.spec().sample_format, hound::SampleFormat::Float);
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn test_render_pcm_errors() {
+    for request in [
+        request("8"),
+        RenderPcmRequest {
+            data: serde_json::json!({"invalid": "data"}),
+            ..request("16")
+        },
+        RenderPcmRequest {
+            sample_rate: Some(0),
+            ..request("16")
+        },
+        RenderPcmRequest {
+            path: Some("../escape.wav".to_string()),
+            ..request("16")
+        },
+    ] {
+        assert!(
+            matches!(render(&request), Response::Error { .. }),
+            "{:?}",
+            request
+        );
+    }
+}
+
+#[test]
+fn test_render_pcm_too_long_for_a_response() {
+    // An hour at 55930 Hz in 32-bit float is well over the response limit
+    let data = serde_json::json!({
+        "events": [{"time": 3600.0, "addr": "0x08", "data": "0x00"}]
+    });
+    let Response::Error { message } = render(&RenderPcmRequest {
+        data,
+        ..request("32f")
+    }) else {
+        panic!("expected an error");
+    };
+    assert!(message.contains("`path`"), "{}", message);
+}
+
+#[test]
+fn test_render_worker_replies_from_its_thread() {
+    let worker = RenderWorker::new(PlaybackManager::new(ResamplingQuality::Linear));
+    let (sender, receiver) = mpsc::channel();
+
+    for format in ["16", "8"] {
+        let sender = sender.clone();
+        worker.submit(request(format), move |response| {
+            let thread = std::thread::current().name().map(str::to_string);
+            sender.send((response, thread)).unwrap();
+        });
+    }
+
+    let timeout = Duration::from_secs(60);
+    let (first, thread) = receiver.recv_timeout(timeout).unwrap();
+    assert!(matches!(first, Response::Pcm { .. }));
+    assert_eq!(thread.as_deref(), Some("render-worker"));
+    let (second, _) = receiver.recv_timeout(timeout).unwrap();
+    assert!(matches!(second, Response::Error { .. }));
+}
@@ -1,4 +1,6 @@
-use crate::resampler::{AudioResampler, ResamplingQuality, OPM_SAMPLE_RATE};
+use crate::resampler::{
+    resample_offline, resampled_frames, AudioResampler, ResamplingQuality, OPM_SAMPLE_RATE,
+};
 
 #[test]
 fn test_resampler_creation() {
@@ -307,3 +309,79 @@ fn test_high_quality_vs_linear_quality() {
     let hq_max = hq_output.iter().map(|&s| s.abs()).max().unwrap_or(0);
     assert!(hq_max > 100, "High quality output should have signal");
 }
+
+#[test]
+fn test_opm_sample_rate_follows_clock() {
+    use crate::resampler::{opm_sample_rate, X68000_CLOCK, YM2151_CLOCK};
+
+    assert_eq!(opm_sample_rate(YM2151_CLOCK), OPM_SAMPLE_RATE);
+    assert_eq!(opm_sample_rate(X68000_CLOCK), 62500);
+
+    let resampler =
+        AudioResampler::with_rates_and_quality(62500, 48000, ResamplingQuality::Linear).unwrap();
+    assert_eq!(resampler.input_rate(), 62500);
+}
+
+#[test]
+fn test_resample_f32_matches_i16_path() {
+    let input: Vec<i16> = (0..4000)
+        .map(|i| ((i as f32 * 0.01).sin() * 20000.0) as i16)
+        .collect();
+    let input_f32: Vec<f32> = input.iter().map(|&s| s as f32 / 32768.0).collect();
+
+    for quality in [ResamplingQuality::Linear, ResamplingQuality::HighQuality] {
+        let mut resampler = AudioResampler::with_quality(quality).unwrap();
+        let mut resampler_f32 = AudioResampler::with_quality(quality).unwrap();
+        let mut output = Vec::new();
+        let mut output_f32 = Vec::new();
+        for (chunk, chunk_f32) in input.chunks(512).zip(input_f32.chunks(512)) {
+            output.extend(resampler.resample(chunk).unwrap());
+            output_f32.extend(resampler_f32.resample_f32(chunk_f32).unwrap());
+        }
+
+        assert_eq!(output.len(), output_f32.len(), "{:?}", quality);
+        for (&sample, &full) in output.iter().zip(&output_f32) {
+            assert!(
+                (sample as f32 - full * 32768.0).abs() <= 1.0,
+                "{:?}: {} vs {}",
+                quality,
+                sample,
+                full * 32768.0
+            );
+        }
+    }
+}
+
+#[test]
+fn test_resample_offline_length_and_alignment() {
+    // Silence, then a step to 0.5 at frame 10000; longer than one chunk
+    let input: Vec<f32> = (0..20000)
+        .flat_map(|frame| {
+            let sample = if frame < 10000 { 0.0 } else { 0.5 };
+            [sample, -sample]
+        })
+        .collect();
+    assert_eq!(resampled_frames(20000, OPM_SAMPLE_RATE, 48000), 17164);
+
+    for quality in [ResamplingQuality::Linear, ResamplingQuality::HighQuality] {
+        let output = resample_offline(&input, OPM_SAMPLE_RATE, 48000, quality).unwrap();
+        assert_eq!(output.len(), 17164 * 2, "{:?}", quality);
+
+        // The step stays at 10000 / 55930 s: no filter delay is left in
+        let step = (10000.0 * 48000.0 / OPM_SAMPLE_RATE as f64).round() as usize;
+        for (frame, expected) in [(step - 20, 0.0), (step + 20, 0.5), (17000, 0.5)] {
+            let (left, right) = (output[frame * 2], output[frame * 2 + 1]);
+            assert!((left - expected).abs() < 0.01, "{:?}: {}", quality, left);
+            assert!((right + expected).abs() < 0.01, "{:?}: {}", quality, right);
+        }
+    }
+
+    assert!(
+        resample_offline(&[], OPM_SAMPLE_RATE, 48000, ResamplingQuality::HighQuality)
+            .unwrap()
+            .is_empty()
+    );
+    assert!(
+        resample_offline(&[0.0; 3], OPM_SAMPLE_RATE, 48000, ResamplingQuality::Linear).is_err()
+    );
+}
@@ -0,0 +1,278 @@
+use super::{event, read_u32_at, write_u32_at};
+use crate::events::{EventLog, LogMetadata};
+use crate::formats::s98::{self, DEVICE_OPM};
+use crate::formats::{self, LogFormat};
+use crate::resampler::{opm_sample_rate, X68000_CLOCK, YM2151_CLOCK};
+
+const DEVICE_PSG: u32 = 1;
+
+/// Build an S98 v3 file with the given timer, device table and dump data
+fn build_s98(timer: (u32, u32), devices: &[(u32, u32)], dump: &[u8]) -> Vec<u8> {
+    let data_offset = 0x20 + devices.len() * 0x10;
+    let mut bytes = b"S983".to_vec();
+    for value in [
+        timer.0,
+        timer.1,
+        0,
+        0,
+        data_offset as u32,
+        0,
+        devices.len() as u32,
+    ] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    for &(device_type, clock) in devices {
+        for value in [device_type, clock, 0, 0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    bytes.extend_from_slice(dump);
+    bytes
+}
+
+fn set_loop(bytes: &mut [u8], dump_offset: usize) {
+    let data_offset = read_u32_at(bytes, 0x14) as usize;
+    write_u32_at(bytes, 0x18, (data_offset + dump_offset) as u32);
+}
+
+#[test]
+fn test_parse_header() {
+    let bytes = build_s98((1, 100), &[(DEVICE_OPM, X68000_CLOCK)], &[0xFD]);
+    let header = s98::parse_header(&bytes).unwrap();
+
+    assert_eq!(header.timer_numerator, 1);
+    assert_eq!(header.timer_denominator, 100);
+    assert_eq!(header.data_offset, 0x30);
+    assert_eq!(header.loop_offset, None);
+    assert_eq!(header.devices.len(), 1);
+    assert_eq!(header.devices[0].device_type, DEVICE_OPM);
+    assert_eq!(header.devices[0].clock, X68000_CLOCK);
+    assert_eq!(header.sync_seconds(), 0.01);
+}
+
+#[test]
+fn test_zero_timer_uses_defaults() {
+    let bytes = build_s98((0, 0), &[(DEVICE_OPM, X68000_CLOCK)], &[0xFD]);
+    let header = s98::parse_header(&bytes).unwrap();
+
+    assert_eq!(header.timer_numerator, 10);
+    assert_eq!(header.timer_denominator, 1000);
+}
+
+#[test]
+fn test_rejects_other_versions() {
+    let mut bytes = build_s98((10, 1000), &[(DEVICE_OPM, X68000_CLOCK)], &[0xFD]);
+    bytes[3] = b'1';
+    let err = s98::import(&bytes).unwrap_err();
+    assert!(err.to_string().contains("version"));
+
+    let err = s98::import(b"Vgm not an s98 file at all.......").unwrap_err();
+    assert!(err.to_string().contains("Not an S98 file"));
+}
+
+#[test]
+fn test_rejects_device_count_beyond_file() {
+    let mut bytes = build_s98((0, 0), &[(DEVICE_OPM, X68000_CLOCK)], &[0xFD]);
+    write_u32_at(&mut bytes, 0x1C, u32::MAX);
+    let err = s98::parse_header(&bytes).unwrap_err();
+    assert!(err.to_string().contains("truncated"), "{}", err);
+}
+
+#[test]
+fn test_sync_waits_advance_time() {
+    let bytes = build_s98(
+        (10, 1000),
+        &[(DEVICE_OPM, X68000_CLOCK)],
+        &[
+            0x00, 0x20, 0xC7, // at 0
+            0xFF, // +1 sync
+            0x00, 0x08, 0x78, // at 10 ms
+            0xFE, 0x00, // +2 syncs
+            0x00, 0x08, 0x00, // at 30 ms
+            0xFE, 0x80, 0x01, // +130 syncs (two-byte varint)
+            0x00, 0x08, 0x01, // at 1330 ms
+            0xFD,
+        ],
+    );
+    let import = s98::import(&bytes).unwrap();
+    let times: Vec<f64> = import.log.events.iter().map(|e| e.time).collect();
+
+    assert_eq!(times.len(), 4);
+    for (time, expected) in times.iter().zip([0.0, 0.01, 0.03, 1.33]) {
+        assert!((time - expected).abs() < 1e-9, "{} != {}", time, expected);
+    }
+    assert_eq!(import.log.clock, Some(X68000_CLOCK));
+}
+
+#[test]
+fn test_selects_opm_device_and_skips_others() {
+    let bytes = build_s98(
+        (10, 1000),
+        &[(DEVICE_PSG, 2_000_000), (DEVICE_OPM, X68000_CLOCK)],
+        &[
+            0x00, 0x07, 0x38, // PSG
+            0x02, 0x20, 0xC7, // OPM
+            0x03, 0x20, 0xC7, // OPM port 1 does not exist
+            0x00, 0x08, 0x0F, // PSG
+            0xFD,
+        ],
+    );
+    let import = s98::import(&bytes).unwrap();
+
+    assert_eq!(import.opm_device, 1);
+    assert_eq!(import.log.events.len(), 1);
+    assert_eq!(import.skipped_commands.get("PSG (YM2149) #0"), Some(&2));
+    assert_eq!(import.skipped_commands.get("OPM (YM2151) #1"), Some(&1));
+    assert_eq!(import.warnings().len(), 2);
+}
+
+#[test]
+fn test_file_without_opm_is_an_error() {
+    let bytes = build_s98(
+        (10, 1000),
+        &[(DEVICE_PSG, 2_000_000)],
+        &[0x00, 0x07, 0x38, 0xFD],
+    );
+    let err = s98::import(&bytes).unwrap_err();
+    let message = err.to_string();
+
+    assert!(message.contains("no OPM device"), "{}", message);
+    assert!(message.contains("PSG"), "{}", message);
+
+    let bytes = build_s98((10, 1000), &[], &[0xFD]);
+    let err = s98::import(&bytes).unwrap_err();
+    assert!(err.to_string().contains("OPNA"));
+}
+
+#[test]
+fn test_command_for_missing_device_is_an_error() {
+    let bytes = build_s98(
+        (10, 1000),
+        &[(DEVICE_OPM, X68000_CLOCK)],
+        &[0x04, 0x08, 0x00, 0xFD],
+    );
+    assert!(s98::import(&bytes).is_err());
+}
+
+#[test]
+fn test_loop_offset_is_converted_to_time() {
+    let mut bytes = build_s98(
+        (10, 1000),
+        &[(DEVICE_OPM, X68000_CLOCK)],
+        &[
+            0x00, 0x08, 0x78, // offset 0
+            0xFE, 0x08, // offset 3: 10 syncs
+            0x00, 0x08, 0x00, // offset 5: loop start at 100 ms
+            0xFF, 0xFD,
+        ],
+    );
+    set_loop(&mut bytes, 5);
+    let import = s98::import(&bytes).unwrap();
+
+    assert!((import.log.loop_time.unwrap() - 0.1).abs() < 1e-9);
+}
+
+#[test]
+fn test_export_header_and_device_table() {
+    let log = EventLog {
+        events: vec![event(0.0, 0x20, 0xC7), event(1.0, 0x08, 0x00)],
+        clock: Some(X68000_CLOCK),
+        ..Default::default()
+    };
+    let bytes = s98::export(&log).unwrap();
+
+    assert_eq!(&bytes[0..4], b"S983");
+    assert_eq!(read_u32_at(&bytes, 0x04), 1);
+    assert_eq!(read_u32_at(&bytes, 0x08), opm_sample_rate(X68000_CLOCK));
+    assert_eq!(read_u32_at(&bytes, 0x10), 0, "no tag without metadata");
+    assert_eq!(read_u32_at(&bytes, 0x14), 0x30);
+    assert_eq!(read_u32_at(&bytes, 0x18), 0, "no loop");
+    assert_eq!(read_u32_at(&bytes, 0x1C), 1);
+    assert_eq!(read_u32_at(&bytes, 0x20), DEVICE_OPM);
+    assert_eq!(read_u32_at(&bytes, 0x24), X68000_CLOCK);
+
+    // 62500 syncs = 0xFE with 62498 as a varint
+    assert_eq!(
+        &bytes[0x30..],
+        &[0x00, 0x20, 0xC7, 0xFE, 0xA2, 0xE8, 0x03, 0x00, 0x08, 0x00, 0xFD][..]
+    );
+}
+
+#[test]
+fn test_export_round_trip_keeps_player_timing() {
+    let log = EventLog {
+        events: vec![
+            event(0.0, 0x20, 0xC7),
+            event(0.0, 0x28, 0x4A),
+            event(0.0, 0x08, 0x78),
+            event(0.5, 0x08, 0x00),
+            event(2.0, 0x08, 0x78),
+        ],
+        loop_time: Some(0.5),
+        metadata: Some(LogMetadata {
+            title: Some("テスト".to_string()),
+            author: Some("cat2151".to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let import = s98::import(&s98::export(&log).unwrap()).unwrap();
+    let rate = opm_sample_rate(YM2151_CLOCK) as f64;
+
+    assert_eq!(import.log.clock, Some(YM2151_CLOCK));
+    let samples: Vec<u64> = import
+        .log
+        .events
+        .iter()
+        .map(|e| (e.time * rate).round() as u64)
+        .collect();
+    // Same-time writes come out spaced the way Player issues them
+    assert_eq!(samples, vec![0, 4, 8, 27965, 111860]);
+    assert_eq!(
+        import.log.loop_time.map(|t| (t * rate).round() as u64),
+        Some(27965)
+    );
+
+    let metadata = import.log.metadata.unwrap();
+    assert_eq!(metadata.title.as_deref(), Some("テスト"));
+    assert_eq!(metadata.author.as_deref(), Some("cat2151"));
+}
+
+#[test]
+fn test_export_is_stable_after_round_trip() {
+    let log = EventLog {
+        events: vec![event(0.0, 0x20, 0xC7), event(0.25, 0x08, 0x78)],
+        clock: Some(X68000_CLOCK),
+        ..Default::default()
+    };
+    let exported = s98::export(&log).unwrap();
+    let reexported = s98::export(&s98::import(&exported).unwrap().log).unwrap();
+
+    assert_eq!(exported, reexported);
+}
+
+#[test]
+fn test_export_rejects_bad_loop_time() {
+    let log = EventLog {
+        events: vec![event(0.0, 0x08, 0x00), event(1.0, 0x08, 0x01)],
+        loop_time: Some(2.0),
+        ..Default::default()
+    };
+    assert!(s98::export(&log).is_err());
+}
+
+#[test]
+fn test_log_format_from_path() {
+    assert_eq!(LogFormat::from_path("song.S98"), LogFormat::S98);
+    assert_eq!(LogFormat::from_path("song.vgz"), LogFormat::Vgz);
+    assert_eq!(LogFormat::from_path("song.json"), LogFormat::Json);
+    assert_eq!(LogFormat::from_path("song"), LogFormat::Json);
+}
+
+#[test]
+fn test_load_event_log_reads_s98() {
+    let loaded = formats::load_event_log("tests/fixtures/simple.s98").unwrap();
+
+    assert_eq!(loaded.log.events.len(), 4);
+    assert_eq!(loaded.warnings.len(), 1);
+}
@@ -43,3 +43,17 @@ fn test_time_tracker_reset() {
     let elapsed_after = tracker.elapsed_sec();
     assert!(elapsed_after < 0.005); // Should be very small after reset
 }
+
+#[test]
+fn test_sec_to_samples_at_custom_rate() {
+    // 4 MHz clock: 4000000 / 64 = 62500 Hz
+    assert_eq!(sec_to_samples_at(1.0, 62500), 62500);
+    assert_eq!(sec_to_samples_at(0.5, 62500), 31250);
+    assert_eq!(samples_to_sec_at(62500, 62500), 1.0);
+
+    // The default-rate functions match the explicit form
+    assert_eq!(
+        sec_to_samples(0.25),
+        sec_to_samples_at(0.25, OPM_SAMPLE_RATE)
+    );
+}
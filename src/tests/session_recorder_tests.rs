@@ -0,0 +1,95 @@
+use crate::resampler::{opm_sample_rate, X68000_CLOCK};
+use crate::session_recorder::SessionRecorder;
+
+fn recording() -> SessionRecorder {
+    let recorder = SessionRecorder::new(X68000_CLOCK);
+    recorder.start();
+    recorder
+}
+
+#[test]
+fn test_recording_starts_at_first_write() {
+    let recorder = recording();
+    let rate = opm_sample_rate(X68000_CLOCK) as f64;
+
+    recorder.record_write(1000, 0x20, 0xC7);
+    recorder.record_write(1000 + 625, 0x08, 0x78);
+
+    let log = recorder.to_event_log();
+    assert_eq!(log.clock, Some(X68000_CLOCK));
+    assert_eq!(log.events.len(), 2);
+    assert_eq!(log.events[0].time, 0.0);
+    assert_eq!(log.events[1].time, 625.0 / rate);
+    assert_eq!((log.events[1].addr, log.events[1].data), (0x08, 0x78));
+}
+
+#[test]
+fn test_recording_is_time_ordered() {
+    let recorder = recording();
+
+    recorder.record_write(300, 0x08, 0x01);
+    recorder.record_write(100, 0x08, 0x00);
+    recorder.record_write(300, 0x08, 0x02);
+
+    let log = recorder.to_event_log();
+    let data: Vec<u8> = log.events.iter().map(|e| e.data).collect();
+    assert_eq!(data, vec![0x00, 0x01, 0x02]);
+    assert!(log.validate());
+}
+
+#[test]
+fn test_clear_from_drops_cancelled_writes() {
+    let recorder = recording();
+
+    recorder.record_write(100, 0x08, 0x00);
+    recorder.record_write(200, 0x08, 0x01);
+    recorder.record_write(300, 0x08, 0x02);
+    recorder.clear_from(200);
+    recorder.record_write(250, 0x08, 0x03);
+
+    let log = recorder.to_event_log();
+    let data: Vec<u8> = log.events.iter().map(|e| e.data).collect();
+    assert_eq!(data, vec![0x00, 0x03]);
+    assert_eq!(recorder.len(), 2);
+}
+
+#[test]
+fn test_empty_recording() {
+    let recorder = SessionRecorder::new(X68000_CLOCK);
+
+    assert!(recorder.is_empty());
+    assert!(recorder.to_event_log().events.is_empty());
+}
+
+#[test]
+fn test_records_only_while_recording() {
+    let recorder = SessionRecorder::new(X68000_CLOCK);
+    recorder.record_write(100, 0x08, 0x00);
+    assert!(!recorder.is_recording());
+    assert!(recorder.is_empty());
+
+    recorder.start();
+    recorder.record_write(200, 0x08, 0x01);
+    recorder.stop();
+    recorder.record_write(300, 0x08, 0x02);
+    assert_eq!(recorder.len(), 1);
+
+    // Starting again discards the previous recording
+    recorder.start();
+    assert!(recorder.is_recording());
+    assert!(recorder.is_empty());
+}
+
+#[test]
+fn test_recording_is_capped() {
+    let recorder = SessionRecorder::with_limit(X68000_CLOCK, 2);
+    recorder.start();
+
+    for time in 0..5 {
+        recorder.record_write(time, 0x08, 0x00);
+    }
+
+    assert_eq!(recorder.len(), 2);
+    assert_eq!(recorder.dropped(), 3);
+    assert_eq!(recorder.to_event_log().events.len(), 2);
+}
@@ -0,0 +1,583 @@
+use midly::num::{u4, u7};
+use midly::{
+    Format, Header, MetaMessage, MidiMessage, PitchBend, Smf, Timing, TrackEvent, TrackEventKind,
+};
+
+use super::{event, key_ons, writes};
+use crate::events::{EventLog, LogMetadata, RegisterEvent};
+use crate::formats::smf::{self, SmfOptions, VoiceBank};
+use crate::formats::{self, LogFormat};
+use crate::resampler::X68000_CLOCK;
+use crate::voice::OpmVoice;
+
+const PPQ: u16 = 480;
+
+fn midi(delta: u32, channel: u8, message: MidiMessage) -> TrackEvent<'static> {
+    TrackEvent {
+        delta: delta.into(),
+        kind: TrackEventKind::Midi {
+            channel: u4::new(channel),
+            message,
+        },
+    }
+}
+
+fn note_on(delta: u32, channel: u8, key: u8, velocity: u8) -> TrackEvent<'static> {
+    midi(
+        delta,
+        channel,
+        MidiMessage::NoteOn {
+            key: u7::new(key),
+            vel: u7::new(velocity),
+        },
+    )
+}
+
+fn note_off(delta: u32, channel: u8, key: u8) -> TrackEvent<'static> {
+    midi(
+        delta,
+        channel,
+        MidiMessage::NoteOff {
+            key: u7::new(key),
+            vel: u7::new(64),
+        },
+    )
+}
+
+fn tempo(delta: u32, microseconds_per_quarter: u32) -> TrackEvent<'static> {
+    TrackEvent {
+        delta: delta.into(),
+        kind: TrackEventKind::Meta(MetaMessage::Tempo(microseconds_per_quarter.into())),
+    }
+}
+
+/// Write an SMF with 480 ticks per quarter note
+fn build_smf(format: Format, tracks: Vec<Vec<TrackEvent<'static>>>) -> Vec<u8> {
+    let mut smf = Smf::new(Header::new(format, Timing::Metrical(PPQ.into())));
+    for mut track in tracks {
+        track.push(TrackEvent {
+            delta: 0.into(),
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        });
+        smf.tracks.push(track);
+    }
+    let mut bytes = Vec::new();
+    smf.write_std(&mut bytes).unwrap();
+    bytes
+}
+
+fn import_track(track: Vec<TrackEvent<'static>>) -> smf::SmfImport {
+    let bytes = build_smf(Format::SingleTrack, vec![track]);
+    smf::import(&bytes, &SmfOptions::default()).unwrap()
+}
+
+/// (time, data) of every write to `addr`
+/// Channel 0 with all four operators as carriers at TL 0, playing A4 from 0.0 to 0.5 s
+fn a4_events() -> Vec<RegisterEvent> {
+    vec![
+        event(0.0, 0x20, 0xC7),
+        event(0.0, 0x28, 0x4A),
+        event(0.0, 0x08, 0x78),
+        event(0.5, 0x08, 0x00),
+    ]
+}
+
+/// (absolute tick, message) of the MIDI events in track `index` of an exported file
+fn exported_messages(bytes: &[u8], index: usize) -> Vec<(u32, MidiMessage)> {
+    let smf = Smf::parse(bytes).unwrap();
+    let mut tick = 0;
+    smf.tracks[index]
+        .iter()
+        .filter_map(|event| {
+            tick += event.delta.as_int();
+            match event.kind {
+                TrackEventKind::Midi { message, .. } => Some((tick, message)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn notes(messages: &[(u32, MidiMessage)]) -> Vec<(u32, u8, u8)> {
+    messages
+        .iter()
+        .filter_map(|(tick, message)| match message {
+            MidiMessage::NoteOn { key, vel } => Some((*tick, key.as_int(), vel.as_int())),
+            MidiMessage::NoteOff { key, .. } => Some((*tick, key.as_int(), 0)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn bends(messages: &[(u32, MidiMessage)]) -> Vec<(u32, i16)> {
+    messages
+        .iter()
+        .filter_map(|(tick, message)| match message {
+            MidiMessage::PitchBend { bend } => Some((*tick, bend.as_int())),
+            _ => None,
+        })
+        .collect()
+}
+
+fn volumes(messages: &[(u32, MidiMessage)]) -> Vec<(u32, u8)> {
+    messages
+        .iter()
+        .filter_map(|(tick, message)| match message {
+            MidiMessage::Controller { controller, value } if controller.as_int() == 7 => {
+                Some((*tick, value.as_int()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn voice(program: u8, carrier_tl: u8) -> OpmVoice {
+    let mut voice = OpmVoice {
+        number: program as u16,
+        ..smf::fallback_voice()
+    };
+    for (operator, tl) in voice
+        .operators
+        .iter_mut()
+        .zip([0x20, 0x20, carrier_tl, carrier_tl])
+    {
+        operator.tl = tl;
+    }
+    voice
+}
+
+#[test]
+fn test_attenuation() {
+    assert_eq!(smf::attenuation(127, 127, 127), 0);
+    // Half velocity is about -6 dB, 8 TL steps of 0.75 dB
+    assert_eq!(smf::attenuation(64, 127, 127), 8);
+    assert_eq!(smf::attenuation(0, 127, 127), 0x7F);
+    assert_eq!(smf::attenuation(1, 1, 1), 0x7F);
+}
+
+#[test]
+fn test_note_to_key_code() {
+    let import = import_track(vec![
+        note_on(0, 0, 69, 127),
+        note_off(480, 0, 69),
+        note_on(0, 0, 60, 127),
+        note_off(480, 0, 60),
+    ]);
+    let log = &import.log;
+
+    let key_codes: Vec<u8> = writes(log, 0x28).iter().map(|w| w.1).collect();
+    assert_eq!(key_codes, vec![0x4A, 0x3E]);
+    assert!(writes(log, 0x30).iter().all(|w| w.1 == 0));
+    assert_eq!(import.format, 0);
+    assert!(import.warnings().iter().all(|w| !w.contains("clamped")));
+}
+
+#[test]
+fn test_pitch_bend_sets_key_fraction() {
+    // +2048 of 8192 with the default 2 semitone range is half a semitone
+    let import = import_track(vec![
+        note_on(0, 0, 69, 127),
+        midi(
+            240,
+            0,
+            MidiMessage::PitchBend {
+                bend: PitchBend::from_int(2048),
+            },
+        ),
+        note_off(240, 0, 69),
+    ]);
+    let fractions = writes(&import.log, 0x30);
+
+    assert_eq!(fractions.len(), 2);
+    assert_eq!(fractions[1], (0.25, 32 << 2));
+}
+
+#[test]
+fn test_bend_range_rpn() {
+    let controller = |delta: u32, controller: u8, value: u8| {
+        midi(
+            delta,
+            0,
+            MidiMessage::Controller {
+                controller: u7::new(controller),
+                value: u7::new(value),
+            },
+        )
+    };
+    // RPN 0 = 12 semitones, then full bend up: one octave higher
+    let import = import_track(vec![
+        controller(0, 101, 0),
+        controller(0, 100, 0),
+        controller(0, 6, 12),
+        midi(
+            0,
+            0,
+            MidiMessage::PitchBend {
+                bend: PitchBend::from_int(8191),
+            },
+        ),
+        note_on(0, 0, 57, 127),
+        note_off(480, 0, 57),
+    ]);
+    assert_eq!(writes(&import.log, 0x28), vec![(0.0, 0x4A)]);
+}
+
+#[test]
+fn test_tempo_map_timing() {
+    // 120 BPM, then 240 BPM from the second beat
+    let bytes = build_smf(
+        Format::Parallel,
+        vec![
+            vec![tempo(0, 500_000), tempo(480, 250_000)],
+            vec![
+                note_on(0, 0, 69, 100),
+                note_off(480, 0, 69),
+                note_on(0, 0, 69, 100),
+                note_off(480, 0, 69),
+            ],
+        ],
+    );
+    let import = smf::import(&bytes, &SmfOptions::default()).unwrap();
+    let keys = writes(&import.log, 0x08);
+    let times: Vec<f64> = keys.iter().map(|k| k.0).collect();
+
+    assert_eq!(import.format, 1);
+    assert_eq!(import.track_count, 2);
+    assert_eq!(times, vec![0.0, 0.5, 0.5, 0.75]);
+}
+
+#[test]
+fn test_velocity_and_volume_scale_carrier_tl() {
+    let bank = VoiceBank {
+        voices: vec![voice(0, 0x04)],
+    };
+    let bytes = build_smf(
+        Format::SingleTrack,
+        vec![vec![
+            note_on(0, 0, 69, 127),
+            midi(
+                240,
+                0,
+                MidiMessage::Controller {
+                    controller: u7::new(7),
+                    value: u7::new(64),
+                },
+            ),
+            note_off(240, 0, 69),
+        ]],
+    );
+    let options = SmfOptions {
+        voice_bank: bank,
+        ..Default::default()
+    };
+    let import = smf::import(&bytes, &options).unwrap();
+    let c2: Vec<u8> = writes(&import.log, 0x78).iter().map(|w| w.1).collect();
+
+    // Silenced on load, then TL 4 + default CC7 100 (3 steps), then CC7 64 (8 steps)
+    assert_eq!(c2, vec![0x7F, 0x04 + 3, 0x04 + 8]);
+    // Modulators keep the patch TL
+    assert_eq!(writes(&import.log, 0x60), vec![(0.0, 0x20)]);
+}
+
+#[test]
+fn test_program_change_uses_voice_bank() {
+    let bank = VoiceBank {
+        voices: vec![voice(5, 0x00)],
+    };
+    let bytes = build_smf(
+        Format::SingleTrack,
+        vec![vec![
+            midi(
+                0,
+                0,
+                MidiMessage::ProgramChange {
+                    program: u7::new(5),
+                },
+            ),
+            note_on(0, 0, 69, 127),
+            note_off(480, 0, 69),
+            note_on(0, 0, 71, 127),
+            note_off(480, 0, 71),
+            midi(
+                0,
+                0,
+                MidiMessage::ProgramChange {
+                    program: u7::new(6),
+                },
+            ),
+            note_on(0, 0, 69, 127),
+            note_off(480, 0, 69),
+        ]],
+    );
+    let options = SmfOptions {
+        voice_bank: bank,
+        ..Default::default()
+    };
+    let import = smf::import(&bytes, &options).unwrap();
+
+    // Program 5 is loaded once on channel 0 and reused; program 6 goes to a
+    // fresh channel so channel 0 keeps its patch
+    assert_eq!(writes(&import.log, 0x40).len(), 1);
+    assert_eq!(writes(&import.log, 0x41).len(), 1);
+    let channels: Vec<u8> = key_ons(&import.log).iter().map(|k| k.1 & 0x07).collect();
+    assert_eq!(channels, vec![0, 0, 1]);
+    let warnings = import.warnings();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].contains("program 6"));
+}
+
+#[test]
+fn test_voice_stealing() {
+    // Nine notes at once: the ninth steals the oldest
+    let mut track: Vec<TrackEvent> = (0..9).map(|i| note_on(0, 0, 60 + i, 100)).collect();
+    track.extend((0..9).map(|i| note_off(if i == 0 { 480 } else { 0 }, 0, 60 + i)));
+    let import = import_track(track);
+    let log = &import.log;
+
+    assert_eq!(import.stolen_notes, 1);
+    let channels: Vec<u8> = key_ons(log).iter().map(|k| k.1 & 0x07).collect();
+    assert_eq!(channels, vec![0, 1, 2, 3, 4, 5, 6, 7, 0]);
+    assert!(import.warnings().iter().any(|w| w.contains("cut short")));
+    // Every channel is keyed off by the end
+    assert_eq!(writes(log, 0x08).len(), 9 + 9);
+}
+
+#[test]
+fn test_percussion_channel_is_skipped() {
+    let import = import_track(vec![note_on(0, 9, 36, 100), note_off(480, 9, 36)]);
+
+    assert!(import.log.events.is_empty());
+    assert_eq!(import.warnings().len(), 1);
+}
+
+#[test]
+fn test_clock_correction() {
+    let bytes = build_smf(
+        Format::SingleTrack,
+        vec![vec![note_on(0, 0, 69, 127), note_off(480, 0, 69)]],
+    );
+    let options = SmfOptions {
+        clock: X68000_CLOCK,
+        ..Default::default()
+    };
+    let import = smf::import(&bytes, &options).unwrap();
+
+    // At 4 MHz A4 is 1.92 semitones lower in key code terms
+    assert_eq!(import.log.clock, Some(X68000_CLOCK));
+    assert_eq!(writes(&import.log, 0x28), vec![(0.0, 0x48)]);
+    assert_eq!(writes(&import.log, 0x30), vec![(0.0, 5 << 2)]);
+}
+
+#[test]
+fn test_rejects_invalid_files() {
+    assert!(smf::import(b"not midi", &SmfOptions::default()).is_err());
+
+    let bytes = build_smf(Format::Sequential, vec![vec![]]);
+    let err = smf::import(&bytes, &SmfOptions::default()).unwrap_err();
+    assert!(err.to_string().contains("format 2"));
+}
+
+#[test]
+fn test_voice_bank_opm() {
+    let bank = VoiceBank::from_file("tests/fixtures/voices.opm").unwrap();
+    let piano = bank.voice(1).unwrap();
+
+    assert_eq!(bank.voices.len(), 2);
+    assert_eq!(piano.name, "E.Piano");
+    assert_eq!(piano.fb_con(), 5 << 3 | 4);
+    assert_eq!(piano.slot_mask, 0x0F);
+    assert_eq!(piano.operators.map(|operator| operator.tl), [30, 38, 0, 0]);
+    assert_eq!(piano.operators[3].register_values()[3], 0x80 | 14);
+    assert_eq!(bank.voice(2).unwrap().operators[2].dt2, 1);
+}
+
+#[test]
+fn test_voice_bank_json() {
+    let bank = VoiceBank::from_file("tests/fixtures/voice_bank.json").unwrap();
+    let voice = bank.voice(1).unwrap();
+    assert_eq!(voice.name, "electric piano");
+    assert_eq!(voice.slot_mask, 0x0F);
+    assert_eq!(
+        voice.operators[1].register_values(),
+        [0x02, 40, 31, 8, 0, 0x26]
+    );
+    // Fields left out take the voice defaults
+    assert_eq!(voice.pan, 64);
+    assert!(bank.voice(0).is_none());
+
+    let json = serde_json::to_string(&bank).unwrap();
+    assert_eq!(VoiceBank::from_json_str(&json).unwrap(), bank);
+
+    let mut bad = bank.clone();
+    bad.voices[0].number = 200;
+    let json = serde_json::to_string(&bad).unwrap();
+    assert!(VoiceBank::from_json_str(&json).is_err());
+}
+
+#[test]
+fn test_midi_format_loads_with_default_voice() {
+    assert_eq!(LogFormat::from_path("sketch.mid"), LogFormat::Midi);
+    assert_eq!(LogFormat::from_path("sketch.MIDI"), LogFormat::Midi);
+
+    let loaded = formats::load_event_log("tests/fixtures/simple.mid").unwrap();
+    assert_eq!(key_ons(&loaded.log).len(), 3);
+    // Percussion and the program missing from the (empty) default bank
+    assert_eq!(loaded.warnings.len(), 2);
+}
+
+#[test]
+fn test_export_one_track_per_channel() {
+    let log = EventLog {
+        events: a4_events(),
+        metadata: Some(LogMetadata {
+            title: Some("chip tune".to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let bytes = smf::export(&log).unwrap();
+    let smf = Smf::parse(&bytes).unwrap();
+
+    assert_eq!(smf.header.format, Format::Parallel);
+    assert_eq!(smf.header.timing, Timing::Metrical(PPQ.into()));
+    assert_eq!(smf.tracks.len(), 9);
+    assert!(smf.tracks[0]
+        .iter()
+        .any(|e| e.kind == TrackEventKind::Meta(MetaMessage::TrackName(b"chip tune"))));
+
+    // 120 BPM: 0.5 s is one quarter note
+    let messages = exported_messages(&bytes, 1);
+    assert_eq!(notes(&messages), vec![(0, 69, 127), (480, 69, 0)]);
+    assert!(bends(&messages).is_empty());
+    for index in 2..9 {
+        assert!(notes(&exported_messages(&bytes, index)).is_empty());
+    }
+}
+
+#[test]
+fn test_export_key_fraction_as_pitch_bend() {
+    let mut events = a4_events();
+    // Half a semitone up a quarter of the way into the note
+    events.insert(3, event(0.125, 0x30, 32 << 2));
+    let bytes = smf::export(&EventLog {
+        events,
+        ..Default::default()
+    })
+    .unwrap();
+    let messages = exported_messages(&bytes, 1);
+
+    assert_eq!(bends(&messages), vec![(120, 2048)]);
+    assert_eq!(notes(&messages), vec![(0, 69, 127), (480, 69, 0)]);
+}
+
+#[test]
+fn test_export_large_pitch_change_restarts_note() {
+    let mut events = a4_events();
+    events.insert(3, event(0.25, 0x28, 0x5A));
+    let bytes = smf::export(&EventLog {
+        events,
+        ..Default::default()
+    })
+    .unwrap();
+    let messages = exported_messages(&bytes, 1);
+
+    assert_eq!(
+        notes(&messages),
+        vec![(0, 69, 127), (240, 69, 0), (240, 81, 127), (480, 81, 0)]
+    );
+}
+
+#[test]
+fn test_export_carrier_tl_as_velocity_and_volume() {
+    // Connection 0: only operator C2 (0x78) is a carrier
+    let events = vec![
+        event(0.0, 0x20, 0xC0),
+        event(0.0, 0x60, 0x00),
+        event(0.0, 0x78, 0x08),
+        event(0.0, 0x28, 0x4A),
+        event(0.0, 0x08, 0x78),
+        event(0.25, 0x78, 0x10),
+        event(0.25, 0x60, 0x30),
+        event(0.5, 0x08, 0x00),
+        event(0.5, 0x78, 0x08),
+        event(0.75, 0x08, 0x78),
+        event(1.0, 0x08, 0x00),
+    ];
+    let bytes = smf::export(&EventLog {
+        events,
+        ..Default::default()
+    })
+    .unwrap();
+    let messages = exported_messages(&bytes, 1);
+
+    // 8 TL steps are 6 dB: about half the velocity, then half the volume.
+    // The modulator TL change is ignored and the next note resets CC7
+    assert_eq!(
+        notes(&messages),
+        vec![(0, 69, 64), (480, 69, 0), (720, 69, 64), (960, 69, 0)]
+    );
+    assert_eq!(volumes(&messages), vec![(240, 64), (720, 127)]);
+}
+
+#[test]
+fn test_export_clock_correction() {
+    // At 4 MHz KC 0x48 + KF 5 is A4 again
+    let log = EventLog {
+        events: vec![
+            event(0.0, 0x20, 0xC7),
+            event(0.0, 0x28, 0x48),
+            event(0.0, 0x30, 5 << 2),
+            event(0.0, 0x08, 0x78),
+            event(0.5, 0x08, 0x00),
+        ],
+        clock: Some(X68000_CLOCK),
+        ..Default::default()
+    };
+    let messages = exported_messages(&smf::export(&log).unwrap(), 1);
+
+    assert_eq!(notes(&messages)[0].1, 69);
+    assert!(bends(&messages)[0].1.abs() < 100);
+}
+
+#[test]
+fn test_export_round_trip_through_import() {
+    let original = import_track(vec![
+        note_on(0, 0, 60, 127),
+        note_off(480, 0, 60),
+        note_on(0, 0, 64, 127),
+        note_off(480, 0, 64),
+    ]);
+    let bytes = smf::export(&original.log).unwrap();
+    let reimported = smf::import(&bytes, &SmfOptions::default()).unwrap();
+
+    assert_eq!(reimported.track_count, 9);
+    assert_eq!(key_ons(&reimported.log), key_ons(&original.log));
+    assert_eq!(writes(&reimported.log, 0x28), writes(&original.log, 0x28));
+}
+
+#[test]
+fn test_export_rejects_negative_times() {
+    let log = EventLog {
+        events: vec![event(-1.0, 0x08, 0x00)],
+        ..Default::default()
+    };
+    assert!(smf::export(&log).is_err());
+}
+
+#[test]
+fn test_markers_round_trip() {
+    let mut track = vec![TrackEvent {
+        delta: 480.into(),
+        kind: TrackEventKind::Meta(MetaMessage::Marker(b"chorus")),
+    }];
+    track.extend([note_on(0, 0, 60, 127), note_off(480, 0, 60)]);
+    let imported = import_track(track);
+    assert_eq!(imported.log.markers.len(), 1);
+    assert_eq!(imported.log.markers[0].name, "chorus");
+    assert!((imported.log.markers[0].time - 0.5).abs() < 1e-9);
+
+    let bytes = smf::export(&imported.log).unwrap();
+    let reimported = smf::import(&bytes, &SmfOptions::default()).unwrap();
+    assert_eq!(reimported.log.markers[0].name, "chorus");
+    assert!((reimported.log.markers[0].time - 0.5).abs() < 1e-6);
+}
@@ -0,0 +1,149 @@
+use super::event;
+use crate::events::EventLog;
+use crate::pcm::{Dither, SampleFormat};
+use crate::render::{RenderOptions, RenderedAudio};
+use crate::stems::{
+    check_sum, multichannel_path, render_stems, stem_path, StemOutput, Stems, STEM_COUNT,
+};
+
+/// Notes on channels 0 and 5 (operator M1 only), the second one starting later
+fn two_channel_log() -> EventLog {
+    let mut events = Vec::new();
+    for (channel, on, off) in [(0u8, 0.0, 0.1), (5, 0.05, 0.15)] {
+        events.extend([
+            event(0.0, 0x20 + channel, 0xC7),
+            event(0.0, 0x28 + channel, 0x4A + channel),
+            event(0.0, 0x60 + channel, 0x10),
+            event(0.0, 0x80 + channel, 0x1F),
+            event(0.0, 0xE0 + channel, 0x0F),
+            event(on, 0x08, 0x08 | channel),
+            event(off, 0x08, channel),
+        ]);
+    }
+    events.sort_by(|a, b| a.time.total_cmp(&b.time));
+    EventLog {
+        events,
+        ..Default::default()
+    }
+}
+
+fn audio(samples: Vec<f32>) -> RenderedAudio {
+    RenderedAudio {
+        native_frames: samples.len() / 2,
+        samples,
+        sample_rate: 1000,
+        tail_truncated: false,
+    }
+}
+
+fn stem_output(name: &str, multichannel: bool) -> StemOutput {
+    StemOutput {
+        path: std::env::temp_dir()
+            .join(name)
+            .to_string_lossy()
+            .into_owned(),
+        format: SampleFormat::Float32,
+        dither: Dither::Off,
+        multichannel,
+    }
+}
+
+/// Channels of a float WAV with any non-zero sample, and its length in frames
+fn audible_channels(path: &str) -> (Vec<usize>, u32) {
+    let mut reader = hound::WavReader::open(path).unwrap();
+    let channels = reader.spec().channels as usize;
+    let frames = reader.duration();
+    let mut audible = vec![false; channels];
+    for (i, sample) in reader.samples::<f32>().enumerate() {
+        audible[i % channels] |= sample.unwrap() != 0.0;
+    }
+    let audible = (0..channels).filter(|&channel| audible[channel]).collect();
+    (audible, frames)
+}
+
+#[test]
+fn test_render_stems_add_up_to_mix() {
+    let log = two_channel_log();
+    let options = RenderOptions {
+        max_tail_sec: 0.5,
+        ..Default::default()
+    };
+    let output = stem_output("test_render_stems.wav", false);
+    let stems = render_stems(&log, &options, &output).unwrap();
+    let mix_frames = (stems.mix.samples.len() / 2) as u32;
+
+    assert_eq!(stems.sum.len(), stems.mix.samples.len());
+    assert_eq!(stems.paths.len(), STEM_COUNT);
+    for (channel, path) in stems.paths.iter().enumerate() {
+        assert_eq!(path, &stem_path(&output.path, channel));
+        let (audible, frames) = audible_channels(path);
+        assert_eq!(frames, mix_frames);
+        let expected = if channel == 0 || channel == 5 {
+            vec![0, 1]
+        } else {
+            vec![]
+        };
+        assert_eq!(audible, expected, "channel {}", channel);
+        let _ = std::fs::remove_file(path);
+    }
+
+    let check = check_sum(&log, &stems, -24.0);
+    assert!(check.is_ok(), "{:?}", check);
+    assert!(check.interactions.is_empty());
+}
+
+#[test]
+fn test_render_stems_multichannel() {
+    let log = two_channel_log();
+    let options = RenderOptions {
+        max_tail_sec: 0.5,
+        ..Default::default()
+    };
+    let output = stem_output("test_render_stems_multichannel.wav", true);
+    let stems = render_stems(&log, &options, &output).unwrap();
+
+    let path = multichannel_path(&output.path);
+    assert_eq!(stems.paths, std::slice::from_ref(&path));
+    let (audible, frames) = audible_channels(&path);
+    assert_eq!(frames as usize, stems.mix.samples.len() / 2);
+    // Left and right of channels 0 and 5
+    assert_eq!(audible, [0, 1, 10, 11]);
+    // The per-channel files are gone once merged
+    assert!(!std::path::Path::new(&format!("{}.ch0.tmp", path)).exists());
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_check_sum_reports_mismatching_ranges() {
+    // 10 ms windows of 10 frames at 1000 Hz; the second and third are off
+    let mix: Vec<f32> = vec![0.5; 80];
+    let mut stem = mix.clone();
+    for sample in &mut stem[20..60] {
+        *sample = 0.25;
+    }
+    let stems = Stems {
+        mix: audio(mix),
+        sum: stem,
+        paths: Vec::new(),
+    };
+    let mut log = two_channel_log();
+    log.events.push(event(0.0, 0x0F, 0x80));
+
+    let check = check_sum(&log, &stems, -24.0);
+
+    assert_eq!(check.max_error_lsb, 0.25 * 32768.0);
+    assert_eq!(check.mismatches.len(), 1);
+    assert_eq!(check.mismatches[0].start_sec, 0.01);
+    assert_eq!(check.mismatches[0].end_sec, 0.03);
+    assert!((check.mismatches[0].max_error_db + 6.02).abs() < 0.01);
+    assert_eq!(check.interactions.len(), 1);
+    // The same difference is within a tolerance above it
+    assert!(check_sum(&log, &stems, -3.0).is_ok());
+}
+
+#[test]
+fn test_stem_paths() {
+    assert_eq!(stem_path("song.wav", 0), "song_ch0.wav");
+    assert_eq!(stem_path("out/song.flac", 7), "out/song_ch7.flac");
+    assert_eq!(multichannel_path("out/song.flac"), "out/song_stems.wav");
+}
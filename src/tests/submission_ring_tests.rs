@@ -0,0 +1,139 @@
+use crate::player::{Player, ProcessedEvent};
+use crate::submission_ring::{submission_ring, SubmissionOp};
+use std::sync::Arc;
+use std::thread;
+
+fn write(time: u32, addr: u8, data: u8) -> SubmissionOp {
+    SubmissionOp::Write(ProcessedEvent { time, addr, data })
+}
+
+#[test]
+fn test_pop_returns_operations_in_submission_order() {
+    let (submitter, mut receiver) = submission_ring(8);
+
+    submitter.submit(write(100, 0x08, 0x00));
+    submitter.submit(SubmissionOp::ClearFrom(50));
+    submitter.submit(write(10, 0x20, 0xC7));
+
+    assert_eq!(receiver.pop(), Some(write(100, 0x08, 0x00)));
+    assert_eq!(receiver.pop(), Some(SubmissionOp::ClearFrom(50)));
+    assert_eq!(receiver.pop(), Some(write(10, 0x20, 0xC7)));
+    assert_eq!(receiver.pop(), None);
+}
+
+#[test]
+fn test_try_submit_reports_full_ring() {
+    let (submitter, mut receiver) = submission_ring(4);
+
+    for i in 0..4 {
+        assert!(submitter.try_submit(write(i, 0x08, 0x00)).is_ok());
+    }
+    assert_eq!(submitter.in_flight(), 4);
+
+    // Ring is full: the operation is handed back untouched
+    let rejected = submitter.try_submit(write(99, 0x08, 0x00));
+    assert_eq!(rejected, Err(write(99, 0x08, 0x00)));
+
+    // Popping one frees a slot again
+    assert!(receiver.pop().is_some());
+    assert!(submitter.try_submit(write(99, 0x08, 0x00)).is_ok());
+}
+
+#[test]
+fn test_ring_wraps_around() {
+    let (submitter, mut receiver) = submission_ring(4);
+
+    for round in 0..100u32 {
+        submitter.submit(write(round, 0x08, round as u8));
+        assert_eq!(receiver.pop(), Some(write(round, 0x08, round as u8)));
+    }
+    assert_eq!(receiver.pop(), None);
+}
+
+#[test]
+fn test_multiple_producers_deliver_every_operation() {
+    const PRODUCERS: u32 = 4;
+    const PER_PRODUCER: u32 = 10_000;
+
+    let (submitter, mut receiver) = submission_ring(256);
+    let submitter = Arc::new(submitter);
+
+    let handles: Vec<_> = (0..PRODUCERS)
+        .map(|producer| {
+            let submitter = submitter.clone();
+            thread::spawn(move || {
+                for i in 0..PER_PRODUCER {
+                    submitter.submit(write(i, producer as u8, 0));
+                }
+            })
+        })
+        .collect();
+
+    // Consume concurrently while producers are running
+    let mut next_expected = [0u32; PRODUCERS as usize];
+    let mut received = 0;
+    while received < PRODUCERS * PER_PRODUCER {
+        match receiver.pop() {
+            Some(SubmissionOp::Write(event)) => {
+                // Each producer's own operations must arrive in order
+                let producer = event.addr as usize;
+                assert_eq!(event.time, next_expected[producer]);
+                next_expected[producer] += 1;
+                received += 1;
+            }
+            Some(other) => panic!("unexpected operation: {:?}", other),
+            None => thread::yield_now(),
+        }
+    }
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(receiver.pop(), None);
+}
+
+#[test]
+fn test_scheduled_event_count_includes_in_flight_operations() {
+    let mut player = Player::new_interactive();
+    let submitter = player.submitter().unwrap();
+
+    submitter.submit_write(100, 0x08, 0x00);
+    submitter.submit_write(200, 0x20, 0xC7);
+    assert_eq!(submitter.scheduled_event_count(), 2);
+
+    // After the generator side merges them, the count is published
+    assert_eq!(player.scheduled_events().len(), 2);
+    assert_eq!(submitter.in_flight(), 0);
+    assert_eq!(submitter.scheduled_event_count(), 2);
+}
+
+#[test]
+fn test_clear_then_write_is_applied_in_order() {
+    let mut player = Player::new_interactive();
+
+    player.schedule_register_write(100, 0x08, 0x78);
+    player.schedule_register_write(300, 0x28, 0x3E);
+    player.clear_schedule_from(200);
+    player.schedule_register_write(250, 0x30, 0x4F);
+
+    // The write submitted after the clear must survive it
+    let q = player.scheduled_events();
+    assert_eq!(q.len(), 2);
+    assert_eq!((q[0].time, q[0].addr), (100, 0x08));
+    assert_eq!((q[1].time, q[1].addr), (250, 0x30));
+}
+
+#[test]
+fn test_same_time_writes_keep_submission_order() {
+    let mut player = Player::new_interactive();
+
+    player.schedule_register_write(200, 0x28, 0x4A);
+    player.schedule_register_write(100, 0x20, 0xC7);
+    player.schedule_register_write(100, 0x08, 0x78);
+
+    let q = player.scheduled_events();
+    assert_eq!(q.len(), 3);
+    assert_eq!(q[0].addr, 0x20);
+    assert_eq!(q[1].addr, 0x08);
+    assert_eq!(q[2].addr, 0x28);
+}
@@ -0,0 +1,430 @@
+use super::{event, read_u32_at, write_u32_at};
+use crate::events::{EventLog, LogMetadata, RegisterEvent};
+use crate::formats::vgm::{self, VGM_SAMPLE_RATE};
+use crate::formats::{self, is_vgm_path};
+use crate::resampler::{opm_sample_rate, X68000_CLOCK, YM2151_CLOCK};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::Write;
+
+const DATA_OFFSET: usize = 0x80;
+
+/// Build a VGM 1.71 file with the given YM2151 clock and command stream
+fn build_vgm(ym2151_clock: u32, commands: &[u8]) -> Vec<u8> {
+    build_vgm_with(
+        0x171,
+        |header| {
+            header[0x30..0x34].copy_from_slice(&ym2151_clock.to_le_bytes());
+        },
+        commands,
+    )
+}
+
+fn build_vgm_with(version: u32, setup: impl FnOnce(&mut Vec<u8>), commands: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![0u8; DATA_OFFSET];
+    bytes[0..4].copy_from_slice(b"Vgm ");
+    bytes[0x08..0x0C].copy_from_slice(&version.to_le_bytes());
+    if version >= 0x150 {
+        bytes[0x34..0x38].copy_from_slice(&((DATA_OFFSET - 0x34) as u32).to_le_bytes());
+    }
+    setup(&mut bytes);
+    bytes.extend_from_slice(commands);
+    let eof = (bytes.len() - 4) as u32;
+    bytes[0x04..0x08].copy_from_slice(&eof.to_le_bytes());
+    bytes
+}
+
+fn set_loop(bytes: &mut [u8], command_offset: usize) {
+    let relative = (DATA_OFFSET + command_offset - 0x1C) as u32;
+    write_u32_at(bytes, 0x1C, relative);
+}
+
+fn samples(time: f64) -> u32 {
+    (time * VGM_SAMPLE_RATE as f64).round() as u32
+}
+
+#[test]
+fn test_parse_header() {
+    let bytes = build_vgm(4_000_000, &[0x54, 0x08, 0x00, 0x66]);
+    let header = vgm::parse_header(&bytes).unwrap();
+
+    assert_eq!(header.version, 0x171);
+    assert_eq!(header.ym2151_clock, 4_000_000);
+    assert!(!header.dual_ym2151);
+    assert_eq!(header.data_offset, DATA_OFFSET);
+    assert_eq!(header.loop_offset, None);
+    assert!(header.other_chips.is_empty());
+}
+
+#[test]
+fn test_rejects_non_vgm_data() {
+    let err = vgm::import(b"{\"events\": []}").unwrap_err();
+    assert!(err.to_string().contains("Not a VGM file"));
+}
+
+#[test]
+fn test_writes_and_waits_become_timed_events() {
+    let bytes = build_vgm(
+        3_579_545,
+        &[
+            0x54, 0x20, 0xC7, // at 0
+            0x61, 0x44, 0xAC, // +44100
+            0x54, 0x08, 0x78, // at 1s
+            0x62, // +735
+            0x63, // +882
+            0x7F, // +16
+            0x54, 0x08, 0x00, // at 44100 + 1633
+            0x66,
+        ],
+    );
+    let import = vgm::import(&bytes).unwrap();
+    let events = &import.log.events;
+
+    assert_eq!(events.len(), 3);
+    assert_eq!((events[0].addr, events[0].data), (0x20, 0xC7));
+    assert_eq!(samples(events[0].time), 0);
+    assert_eq!(samples(events[1].time), 44100);
+    assert_eq!((events[2].addr, events[2].data), (0x08, 0x00));
+    assert_eq!(samples(events[2].time), 44100 + 735 + 882 + 16);
+    assert!(import.log.validate());
+    assert_eq!(import.log.clock, Some(3_579_545));
+}
+
+#[test]
+fn test_stops_at_end_of_data() {
+    let bytes = build_vgm(4_000_000, &[0x54, 0x08, 0x00, 0x66, 0x54, 0x08, 0x01]);
+    let import = vgm::import(&bytes).unwrap();
+    assert_eq!(import.log.events.len(), 1);
+}
+
+#[test]
+fn test_loop_offset_is_converted_to_time() {
+    let mut bytes = build_vgm(
+        4_000_000,
+        &[
+            0x54, 0x08, 0x78, // offset 0
+            0x62, // offset 3
+            0x54, 0x08, 0x00, // offset 4: loop start, after 735 samples
+            0x62, 0x66,
+        ],
+    );
+    set_loop(&mut bytes, 4);
+    let import = vgm::import(&bytes).unwrap();
+
+    assert_eq!(import.header.loop_offset, Some(DATA_OFFSET + 4));
+    assert_eq!(import.log.loop_time.map(samples), Some(735));
+}
+
+#[test]
+fn test_unsupported_chips_are_skipped_and_reported() {
+    let bytes = build_vgm_with(
+        0x171,
+        |header| {
+            header[0x0C..0x10].copy_from_slice(&3_579_545u32.to_le_bytes());
+            header[0x2C..0x30].copy_from_slice(&7_670_453u32.to_le_bytes());
+            header[0x30..0x34].copy_from_slice(&4_000_000u32.to_le_bytes());
+        },
+        &[
+            0x50, 0x9F, // SN76489
+            0x52, 0x28, 0xF0, // YM2612 port 0
+            0x54, 0x08, 0x78, // YM2151
+            0x67, 0x66, 0x00, 0x03, 0x00, 0x00, 0x00, 0xAA, 0xBB, 0xCC, // data block
+            0x81, // YM2612 DAC + wait 1
+            0xA4, 0x08, 0x00, // second YM2151
+            0x54, 0x08, 0x00, 0x66,
+        ],
+    );
+    let import = vgm::import(&bytes).unwrap();
+
+    assert_eq!(import.header.other_chips, vec!["SN76489", "YM2612"]);
+    assert_eq!(import.log.events.len(), 2);
+    assert_eq!(samples(import.log.events[1].time), 1);
+    assert_eq!(import.skipped_commands.get("SN76489"), Some(&1));
+    assert_eq!(import.skipped_commands.get("YM2612"), Some(&2));
+    assert_eq!(import.skipped_commands.get("PCM data"), Some(&1));
+    assert_eq!(import.skipped_commands.get("YM2151 (2nd chip)"), Some(&1));
+
+    let warnings = import.warnings();
+    assert_eq!(warnings.len(), 4);
+    assert!(warnings.iter().any(|w| w.starts_with("SN76489: 1 ")));
+}
+
+#[test]
+fn test_file_without_ym2151_is_an_error() {
+    let bytes = build_vgm_with(
+        0x171,
+        |header| {
+            header[0x0C..0x10].copy_from_slice(&3_579_545u32.to_le_bytes());
+        },
+        &[0x50, 0x9F, 0x62, 0x66],
+    );
+    let err = vgm::import(&bytes).unwrap_err();
+    let message = err.to_string();
+
+    assert!(message.contains("no YM2151 data"), "{}", message);
+    assert!(message.contains("SN76489"), "{}", message);
+}
+
+#[test]
+fn test_unknown_command_is_an_error() {
+    let bytes = build_vgm(4_000_000, &[0x54, 0x08, 0x00, 0x01, 0x66]);
+    let err = vgm::import(&bytes).unwrap_err();
+    assert!(err.to_string().contains("0x01"));
+}
+
+#[test]
+fn test_truncated_stream_is_an_error() {
+    let bytes = build_vgm(4_000_000, &[0x54, 0x08]);
+    let err = vgm::import(&bytes).unwrap_err();
+    assert!(err.to_string().contains("truncated"));
+}
+
+#[test]
+fn test_pre_110_files_use_shared_fm_clock() {
+    let bytes = build_vgm_with(
+        0x101,
+        |header| {
+            header[0x10..0x14].copy_from_slice(&3_579_545u32.to_le_bytes());
+        },
+        &[0x54, 0x08, 0x00, 0x66],
+    );
+    let header = vgm::parse_header(&bytes).unwrap();
+
+    // Data always starts at 0x40 before 1.50, regardless of the field at 0x34
+    assert_eq!(header.data_offset, 0x40);
+    assert_eq!(header.ym2151_clock, 3_579_545);
+    assert!(header.other_chips.is_empty());
+}
+
+#[test]
+fn test_dual_chip_bit_is_masked_from_clock() {
+    let bytes = build_vgm(4_000_000 | 0x4000_0000, &[0x54, 0x08, 0x00, 0x66]);
+    let header = vgm::parse_header(&bytes).unwrap();
+
+    assert_eq!(header.ym2151_clock, 4_000_000);
+    assert!(header.dual_ym2151);
+}
+
+#[test]
+fn test_vgz_is_decompressed() {
+    let bytes = build_vgm(4_000_000, &[0x54, 0x08, 0x78, 0x62, 0x54, 0x08, 0x00, 0x66]);
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&bytes).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    assert!(vgm::is_vgm_data(&compressed));
+    let import = vgm::import(&compressed).unwrap();
+    assert_eq!(import.log.events.len(), 2);
+}
+
+#[test]
+fn test_is_vgm_path() {
+    assert!(is_vgm_path("music.vgm"));
+    assert!(is_vgm_path("dir/music.VGZ"));
+    assert!(!is_vgm_path("music.json"));
+    assert!(!is_vgm_path("vgm"));
+}
+
+#[test]
+fn test_imported_log_round_trips_through_json() {
+    let bytes = build_vgm(4_000_000, &[0x54, 0x20, 0xC7, 0x62, 0x54, 0x08, 0x00, 0x66]);
+    let import = vgm::import(&bytes).unwrap();
+
+    let json = import.log.to_json_string().unwrap();
+    assert!(json.contains("\"addr\":\"0x20\""));
+    assert!(json.contains("\"data\":\"0xC7\""));
+    assert!(!json.contains("is_data"));
+
+    let parsed = EventLog::from_json_str(&json).unwrap();
+    assert_eq!(parsed.clock, Some(4_000_000));
+    assert_eq!(parsed.events.len(), 2);
+    assert_eq!(parsed.events[1].time, import.log.events[1].time);
+}
+
+#[test]
+fn test_load_event_log_dispatches_on_extension() {
+    let loaded = formats::load_event_log("tests/fixtures/simple.vgm").unwrap();
+    assert_eq!(loaded.log.clock, Some(4_000_000));
+    assert_eq!(loaded.warnings.len(), 1);
+
+    let loaded = formats::load_event_log("tests/fixtures/simple.json").unwrap();
+    assert!(loaded.warnings.is_empty());
+}
+
+#[test]
+fn test_export_header() {
+    let log = EventLog {
+        events: vec![event(0.0, 0x20, 0xC7), event(1.0, 0x08, 0x00)],
+        clock: Some(X68000_CLOCK),
+        ..Default::default()
+    };
+    let bytes = vgm::export(&log).unwrap();
+
+    assert_eq!(&bytes[0..4], b"Vgm ");
+    assert_eq!(read_u32_at(&bytes, 0x04) as usize, bytes.len() - 4);
+    assert_eq!(read_u32_at(&bytes, 0x08), 0x171);
+    assert_eq!(read_u32_at(&bytes, 0x18), 44100);
+    assert_eq!(read_u32_at(&bytes, 0x1C), 0, "no loop");
+    assert_eq!(read_u32_at(&bytes, 0x30), X68000_CLOCK);
+    assert_eq!(0x34 + read_u32_at(&bytes, 0x34) as usize, 0x100);
+
+    let gd3 = 0x14 + read_u32_at(&bytes, 0x14) as usize;
+    assert_eq!(&bytes[gd3..gd3 + 4], b"Gd3 ");
+    assert_eq!(read_u32_at(&bytes, gd3 + 4), 0x100);
+    assert_eq!(
+        gd3 + 12 + read_u32_at(&bytes, gd3 + 8) as usize,
+        bytes.len()
+    );
+}
+
+#[test]
+fn test_export_uses_default_clock() {
+    let log = EventLog {
+        events: vec![event(0.0, 0x08, 0x00)],
+        ..Default::default()
+    };
+    let bytes = vgm::export(&log).unwrap();
+    assert_eq!(read_u32_at(&bytes, 0x30), YM2151_CLOCK);
+}
+
+#[test]
+fn test_export_round_trips_through_import() {
+    let log = EventLog {
+        events: vec![
+            event(0.0, 0x20, 0xC7),
+            event(0.25, 0x08, 0x78),
+            event(0.5, 0x08, 0x00),
+            event(3.0, 0x08, 0x78),
+        ],
+        clock: Some(X68000_CLOCK),
+        loop_time: Some(0.5),
+        metadata: Some(LogMetadata {
+            title: Some("Test Song".to_string()),
+            author: Some("作曲者".to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let import = vgm::import(&vgm::export(&log).unwrap()).unwrap();
+
+    assert_eq!(import.log.clock, Some(X68000_CLOCK));
+    assert_eq!(import.log.events.len(), 4);
+    for (exported, original) in import.log.events.iter().zip(&log.events) {
+        assert_eq!(
+            (exported.addr, exported.data),
+            (original.addr, original.data)
+        );
+        assert!((exported.time - original.time).abs() <= 0.5 / VGM_SAMPLE_RATE as f64);
+    }
+    assert_eq!(import.log.loop_time.map(samples), Some(22050));
+    assert_eq!(import.header.loop_samples, 3 * 44100 - 22050);
+    assert_eq!(import.header.total_samples, 3 * 44100);
+
+    let metadata = import.log.metadata.unwrap();
+    assert_eq!(metadata.title.as_deref(), Some("Test Song"));
+    assert_eq!(metadata.author.as_deref(), Some("作曲者"));
+}
+
+#[test]
+fn test_export_quantizes_from_absolute_opm_time() {
+    // One write per OPM sample pair for a second: per-delta rounding would drift,
+    // absolute conversion stays within half a VGM sample
+    let rate = opm_sample_rate(YM2151_CLOCK);
+    let events: Vec<RegisterEvent> = (0..rate / 7)
+        .map(|i| event((i * 7) as f64 / rate as f64, 0x08, 0x00))
+        .collect();
+    let log = EventLog {
+        events,
+        ..Default::default()
+    };
+    let import = vgm::import(&vgm::export(&log).unwrap()).unwrap();
+
+    for (i, exported) in import.log.events.iter().enumerate() {
+        let expected = ((i as u64 * 7 * 44100) as f64 / rate as f64).round() as u32;
+        assert_eq!(samples(exported.time), expected, "event {}", i);
+    }
+}
+
+#[test]
+fn test_export_applies_player_write_spacing() {
+    // Three writes at the same time are issued 4 OPM samples apart by Player
+    let log = EventLog {
+        events: vec![
+            event(0.0, 0x20, 0xC7),
+            event(0.0, 0x28, 0x4A),
+            event(0.0, 0x08, 0x78),
+        ],
+        ..Default::default()
+    };
+    let import = vgm::import(&vgm::export(&log).unwrap()).unwrap();
+    let rate = opm_sample_rate(YM2151_CLOCK) as f64;
+
+    let times: Vec<u32> = import.log.events.iter().map(|e| samples(e.time)).collect();
+    let expected: Vec<u32> = [0.0, 4.0, 8.0]
+        .iter()
+        .map(|opm: &f64| (opm * 44100.0 / rate).round() as u32)
+        .collect();
+    assert_eq!(times, expected);
+}
+
+#[test]
+fn test_export_wait_encoding() {
+    let log = EventLog {
+        events: vec![
+            event(0.0, 0x08, 0x00),
+            event(1.0, 0x08, 0x01),
+            event(3.0, 0x08, 0x02),
+        ],
+        ..Default::default()
+    };
+    let bytes = vgm::export(&log).unwrap();
+    let gd3 = 0x14 + read_u32_at(&bytes, 0x14) as usize;
+    let data = &bytes[0x100..gd3];
+
+    assert_eq!(
+        data,
+        &[
+            0x54, 0x08, 0x00, // 0s
+            0x61, 0x44, 0xAC, // 44100
+            0x54, 0x08, 0x01, // 1s
+            0x61, 0xFF, 0xFF, // 65535
+            0x61, 0x89, 0x58, // 22665
+            0x54, 0x08, 0x02, // 3s
+            0x66,
+        ][..]
+    );
+}
+
+#[test]
+fn test_export_rejects_bad_loop_time() {
+    let mut log = EventLog {
+        events: vec![event(0.0, 0x08, 0x00), event(1.0, 0x08, 0x01)],
+        loop_time: Some(1.0),
+        ..Default::default()
+    };
+    assert!(vgm::export(&log).is_err());
+
+    log.loop_time = Some(-1.0);
+    assert!(vgm::export(&log).is_err());
+}
+
+#[test]
+fn test_export_rejects_unsorted_log() {
+    let log = EventLog {
+        events: vec![event(1.0, 0x08, 0x00), event(0.0, 0x08, 0x01)],
+        ..Default::default()
+    };
+    assert!(vgm::export(&log).is_err());
+}
+
+#[test]
+fn test_export_vgz_round_trip() {
+    let log = EventLog {
+        events: vec![event(0.0, 0x08, 0x00), event(0.5, 0x08, 0x01)],
+        ..Default::default()
+    };
+    let compressed = vgm::export_vgz(&log).unwrap();
+
+    assert_eq!(&compressed[0..2], &[0x1F, 0x8B]);
+    assert_eq!(vgm::import(&compressed).unwrap().log.events.len(), 2);
+}
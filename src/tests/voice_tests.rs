@@ -0,0 +1,208 @@
+use crate::events::{EventLog, RegisterEvent};
+use crate::voice::{self, OpmOperator, OpmVoice};
+
+fn load_fixture() -> Vec<OpmVoice> {
+    voice::load_opm("tests/fixtures/voices.opm").unwrap()
+}
+
+/// Events that load `voice` onto `channel` and key it on at `time`
+fn play(voice: &OpmVoice, channel: u8, time: f64) -> Vec<RegisterEvent> {
+    voice
+        .lfo_writes()
+        .into_iter()
+        .chain(voice.register_writes(channel))
+        .chain([(0x08, voice.key_on_data(channel))])
+        .map(|(addr, data)| RegisterEvent {
+            time,
+            addr,
+            data,
+            is_data: None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_opm_fixture() {
+    let voices = load_fixture();
+    assert_eq!(voices.len(), 2);
+
+    let piano = &voices[0];
+    assert_eq!((piano.number, piano.name.as_str()), (1, "E.Piano"));
+    assert_eq!(
+        (piano.lfo.lfrq, piano.lfo.amd, piano.lfo.pmd),
+        (200, 10, 20)
+    );
+    assert_eq!((piano.fb, piano.alg, piano.ams, piano.pms), (5, 4, 1, 2));
+    assert_eq!(piano.slot_mask, 0x0F);
+    // File order is M1, C1, M2, C2; operators are kept in slot order M1, M2, C1, C2
+    assert_eq!(piano.operators[0].tl, 30);
+    assert_eq!(piano.operators[1].tl, 38);
+    assert_eq!(piano.operators[2].d1r, 12);
+    assert!(piano.operators[3].ams_en);
+    assert!(!piano.operators[2].ams_en);
+
+    // Comments after the name are dropped
+    assert_eq!(voices[1].name, "Bass");
+}
+
+#[test]
+fn test_write_opm_round_trip() {
+    let voices = load_fixture();
+    let text = voice::write_opm(&voices);
+
+    assert!(text.starts_with("//MiOPMdrv sound bank"));
+    assert!(text.contains("@:1 E.Piano\n"));
+    assert!(text.contains("C2:  31  14   4   8   3   0   1   1   0   0 128\n"));
+    assert_eq!(voice::parse_opm(&text).unwrap(), voices);
+}
+
+#[test]
+fn test_register_writes() {
+    let piano = &load_fixture()[0];
+    let writes = piano.register_writes(3);
+
+    assert_eq!(writes.len(), 2 + 6 * 4);
+    assert!(writes.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    let data = |addr: u8| writes.iter().find(|w| w.0 == addr).unwrap().1;
+    assert_eq!(data(0x23), 0xC0 | 5 << 3 | 4);
+    assert_eq!(data(0x3B), 2 << 4 | 1);
+    // DT1/MUL of M1, M2 (slot 1) and C1 (slot 2)
+    assert_eq!(data(0x43), 0x31);
+    assert_eq!(data(0x4B), 0x74);
+    assert_eq!(data(0x53), 0x01);
+    // AMS-EN and D1R of C2
+    assert_eq!(data(0xBB), 0x8E);
+    assert_eq!(data(0xFB), 0x38);
+    assert_eq!(piano.key_on_data(3), 0x7B);
+
+    assert_eq!(
+        piano.lfo_writes(),
+        vec![
+            (0x18, 200),
+            (0x19, 10),
+            (0x19, 0x80 | 20),
+            (0x1B, 2),
+            (0x0F, 0)
+        ]
+    );
+}
+
+#[test]
+fn test_pan_to_output_bits() {
+    let pan = |pan: u8| {
+        OpmVoice {
+            pan,
+            ..Default::default()
+        }
+        .pan_fb_con()
+    };
+    assert_eq!(pan(0), 0x40);
+    assert_eq!(pan(64), 0xC0);
+    assert_eq!(pan(127), 0x80);
+}
+
+#[test]
+fn test_operator_register_values_round_trip() {
+    let operator = OpmOperator {
+        ar: 31,
+        d1r: 17,
+        d2r: 9,
+        rr: 15,
+        d1l: 12,
+        tl: 127,
+        ks: 3,
+        mul: 15,
+        dt1: 7,
+        dt2: 3,
+        ams_en: true,
+    };
+    let values = operator.register_values();
+    assert_eq!(values, [0x7F, 0x7F, 0xDF, 0x91, 0xC9, 0xCF]);
+    assert_eq!(OpmOperator::from_register_values(values), operator);
+}
+
+#[test]
+fn test_parse_opm_errors() {
+    let voice = "@:0 test\nLFO: 0 0 0 0 0\nCH: 64 0 0 0 0 120 0\n";
+    let op = |name: &str, ar: u32| format!("{}: {} 0 0 0 0 0 0 0 0 0 0\n", name, ar);
+    let complete = format!(
+        "{}{}{}{}{}",
+        voice,
+        op("M1", 31),
+        op("C1", 31),
+        op("M2", 31),
+        op("C2", 31)
+    );
+    assert_eq!(voice::parse_opm(&complete).unwrap().len(), 1);
+
+    let out_of_range = format!(
+        "{}{}{}{}{}",
+        voice,
+        op("M1", 32),
+        op("C1", 31),
+        op("M2", 31),
+        op("C2", 31)
+    );
+    let err = voice::parse_opm(&out_of_range).unwrap_err().to_string();
+    assert!(err.contains("line 4") && err.contains("AR 32"), "{}", err);
+
+    let missing = format!("{}{}", voice, op("M1", 31));
+    let err = voice::parse_opm(&missing).unwrap_err().to_string();
+    assert!(err.contains("no C1 line"), "{}", err);
+
+    assert!(voice::parse_opm("CH: 64 0 0 0 0 120 0").is_err());
+    assert!(voice::parse_opm(&complete.replace("M2:", "X2:")).is_err());
+    assert!(voice::parse_opm("@:zero\n").is_err());
+    assert!(voice::parse_opm("// only comments\n\n").unwrap().is_empty());
+}
+
+#[test]
+fn test_extract_voices() {
+    let voices = load_fixture();
+    let mut events = play(&voices[0], 0, 0.0);
+    events.extend(play(&voices[1], 1, 0.5));
+    events.extend(play(&voices[0], 3, 1.0));
+    // Reloading the same voice on channel 0 is not a new voice
+    events.extend(play(&voices[0], 0, 1.5));
+    let log = EventLog {
+        events,
+        ..Default::default()
+    };
+
+    let extracted = voice::extract_voices(&log);
+    assert_eq!(extracted.len(), 2);
+    assert!(extracted[0].voice.same_sound(&voices[0]));
+    assert!(extracted[1].voice.same_sound(&voices[1]));
+    assert_eq!(
+        (extracted[1].voice.number, extracted[1].voice.name.as_str()),
+        (1, "Voice 1")
+    );
+    let uses: Vec<(f64, u8)> = extracted[0]
+        .uses
+        .iter()
+        .map(|u| (u.time, u.channel))
+        .collect();
+    assert_eq!(uses, vec![(0.0, 0), (1.0, 3), (1.5, 0)]);
+    assert_eq!(extracted[1].uses.len(), 1);
+}
+
+#[test]
+fn test_extract_voices_uses_key_on_slots() {
+    let voices = load_fixture();
+    let mut events = play(&voices[0], 2, 0.0);
+    // Same registers, but only the carriers keyed on
+    events.push(RegisterEvent {
+        time: 0.5,
+        addr: 0x08,
+        data: 0x50 | 2,
+        is_data: None,
+    });
+    let log = EventLog {
+        events,
+        ..Default::default()
+    };
+
+    let extracted = voice::extract_voices(&log);
+    assert_eq!(extracted.len(), 2);
+    assert_eq!(extracted[1].voice.slot_mask, 0x0A);
+}
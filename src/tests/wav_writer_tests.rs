@@ -77,6 +77,7 @@ fn test_generate_wav_with_player() {
                 is_data: None,
             },
         ],
+        ..Default::default()
     };
 
     let player = Player::new(log);
@@ -166,3 +167,98 @@ fn test_wav_format_verification() {
 fn test_default_output_filename() {
     assert_eq!(DEFAULT_OUTPUT_FILENAME, "output.wav");
 }
+
+#[test]
+fn test_generate_wav_uses_log_clock_sample_rate() {
+    let temp_path = std::env::temp_dir().join("test_generate_wav_4mhz.wav");
+    let temp_path_str = temp_path.to_str().unwrap();
+
+    let log = EventLog {
+        events: vec![RegisterEvent {
+            time: 0.01,
+            addr: 0x08,
+            data: 0x00,
+            is_data: None,
+        }],
+        clock: Some(4_000_000),
+        ..Default::default()
+    };
+
+    generate_wav(Player::new(log), temp_path_str).unwrap();
+
+    let reader = hound::WavReader::open(temp_path_str).unwrap();
+    assert_eq!(reader.spec().sample_rate, 62500);
+
+    let _ = std::fs::remove_file(temp_path_str);
+}
+
+#[test]
+fn test_write_wav_full_formats() {
+    use crate::pcm::{Dither, SampleFormat};
+    use crate::wav_writer::write_wav_full;
+
+    // 0.75 LSB of 16 bits is kept by 24-bit and float output
+    let samples = vec![0.75 / 32768.0, -0.5, 1.5, -1.0];
+    let temp_dir = std::env::temp_dir();
+
+    let path = temp_dir.join("test_write_wav_full_24.wav");
+    let path = path.to_str().unwrap();
+    write_wav_full(path, &samples, 55930, SampleFormat::Int24, Dither::Off).unwrap();
+    let mut reader = hound::WavReader::open(path).unwrap();
+    assert_eq!(reader.spec().bits_per_sample, 24);
+    let written: Vec<i32> = reader.samples::<i32>().map(|s| s.unwrap()).collect();
+    assert_eq!(written, vec![192, -4194304, 8388607, -8388608]);
+    let _ = std::fs::remove_file(path);
+
+    let path = temp_dir.join("test_write_wav_full_float.wav");
+    let path = path.to_str().unwrap();
+    write_wav_full(path, &samples, 48000, SampleFormat::Float32, Dither::Off).unwrap();
+    let mut reader = hound::WavReader::open(path).unwrap();
+    assert_eq!(reader.spec().sample_format, hound::SampleFormat::Float);
+    let written: Vec<f32> = reader.samples::<f32>().map(|s| s.unwrap()).collect();
+    assert_eq!(written, samples);
+    let _ = std::fs::remove_file(path);
+
+    let path = temp_dir.join("test_write_wav_full_16.wav");
+    let path = path.to_str().unwrap();
+    write_wav_full(path, &samples, 48000, SampleFormat::Int16, Dither::Off).unwrap();
+    let mut reader = hound::WavReader::open(path).unwrap();
+    let written: Vec<i16> = reader.samples::<i16>().map(|s| s.unwrap()).collect();
+    assert_eq!(written, vec![0, -16384, 32767, -32768]);
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn test_write_wav_channels() {
+    use crate::pcm::{Dither, SampleFormat};
+    use crate::wav_writer::{write_wav_blocks, write_wav_channels};
+
+    let samples: Vec<f32> = (0..32).map(|i| i as f32 / 32768.0).collect();
+    let path = std::env::temp_dir().join("test_write_wav_channels.wav");
+    let path = path.to_str().unwrap();
+
+    write_wav_channels(path, &samples, 16, 48000, SampleFormat::Int16, Dither::Off).unwrap();
+    let mut reader = hound::WavReader::open(path).unwrap();
+    assert_eq!(reader.spec().channels, 16);
+    assert_eq!(reader.duration(), 2);
+    let written: Vec<i16> = reader.samples::<i16>().map(|s| s.unwrap()).collect();
+    assert_eq!(written, (0..32).collect::<Vec<i16>>());
+
+    assert!(write_wav_channels(
+        path,
+        &samples[..31],
+        16,
+        48000,
+        SampleFormat::Int16,
+        Dither::Off
+    )
+    .is_err());
+
+    // The same samples in blocks of one frame each
+    let blocks = samples.chunks(16).map(anyhow::Ok);
+    write_wav_blocks(path, blocks, 16, 48000, SampleFormat::Int16, Dither::Off).unwrap();
+    let mut reader = hound::WavReader::open(path).unwrap();
+    let written: Vec<i16> = reader.samples::<i16>().map(|s| s.unwrap()).collect();
+    assert_eq!(written, (0..32).collect::<Vec<i16>>());
+    let _ = std::fs::remove_file(path);
+}
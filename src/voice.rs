@@ -0,0 +1,524 @@
+//! OPM voices and VOPM `.opm` voice banks
+//!
+//! [`OpmVoice`] holds a patch as VOPM parameters, can be read from and written
+//! to the VOPM text bank format, and produces the register writes that load it
+//! onto a channel. [`extract_voices`] goes the other way and collects the
+//! voices an event log plays.
+//!
+//! A VOPM bank is a list of entries like this (`//` starts a comment):
+//!
+//! ```text
+//! @:0 Instrument 0
+//! LFO:  0   0   0   0   0
+//! CH: 64   7   4   0   0 120   0
+//! M1: 31   0   0  15   0  33   0   1   0   0   0
+//! C1: 31   0   0  15   0   0   0   1   0   0   0
+//! M2: 31   0   0  15   0  33   0   1   0   0   0
+//! C2: 31   0   0  15   0   0   0   1   0   0   0
+//! ```
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::events::EventLog;
+use crate::formats::is_carrier;
+
+/// Operator names in VOPM file order
+const FILE_OPERATORS: [&str; 4] = ["M1", "C1", "M2", "C2"];
+/// Register slot (M1, M2, C1, C2) of each operator in VOPM file order
+const FILE_TO_SLOT: [usize; 4] = [0, 2, 1, 3];
+
+const PAN_LEFT: u8 = 0x40;
+const PAN_RIGHT: u8 = 0x80;
+const PAN_CENTER: u8 = 0xC0;
+/// VOPM pan is a MIDI-style 0-127 value; the chip can only switch each side on
+/// or off, so the outer thirds are hard left and right
+const PAN_LEFT_BELOW: u8 = 43;
+const PAN_RIGHT_ABOVE: u8 = 84;
+
+/// Value VOPM writes for an enabled AMS-EN
+const AMS_ENABLED: u8 = 128;
+
+const HEADER: &str = "//MiOPMdrv sound bank Paramer Ver2002.04.22
+//LFO: LFRQ AMD PMD WF NFRQ
+//@:[Num] [Name]
+//CH: PAN\tFL CON AMS PMS SLOT NE
+//[OPname]\tAR D1R D2R\tRR D1L\tTL\tKS MUL DT1 DT2 AMS-EN
+";
+
+/// One FM operator, with each parameter in its register range
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpmOperator {
+    /// Attack rate (0-31)
+    pub ar: u8,
+    /// First decay rate (0-31)
+    pub d1r: u8,
+    /// Second decay rate (0-31)
+    pub d2r: u8,
+    /// Release rate (0-15)
+    pub rr: u8,
+    /// First decay level (0-15)
+    pub d1l: u8,
+    /// Total level (0-127, 0 is loudest)
+    pub tl: u8,
+    /// Key scaling (0-3)
+    pub ks: u8,
+    /// Frequency multiplier (0-15)
+    pub mul: u8,
+    /// Detune 1 (0-7)
+    pub dt1: u8,
+    /// Detune 2 (0-3)
+    pub dt2: u8,
+    /// Amplitude modulation enable
+    pub ams_en: bool,
+}
+
+impl OpmOperator {
+    /// Register values for the 0x40, 0x60, 0x80, 0xA0, 0xC0 and 0xE0 blocks
+    pub fn register_values(&self) -> [u8; 6] {
+        [
+            (self.dt1 & 0x07) << 4 | (self.mul & 0x0F),
+            self.tl & 0x7F,
+            (self.ks & 0x03) << 6 | (self.ar & 0x1F),
+            (self.ams_en as u8) << 7 | (self.d1r & 0x1F),
+            (self.dt2 & 0x03) << 6 | (self.d2r & 0x1F),
+            (self.d1l & 0x0F) << 4 | (self.rr & 0x0F),
+        ]
+    }
+
+    /// Operator from the values of [`register_values`](Self::register_values)
+    pub fn from_register_values(values: [u8; 6]) -> Self {
+        Self {
+            dt1: values[0] >> 4 & 0x07,
+            mul: values[0] & 0x0F,
+            tl: values[1] & 0x7F,
+            ks: values[2] >> 6,
+            ar: values[2] & 0x1F,
+            ams_en: values[3] & 0x80 != 0,
+            d1r: values[3] & 0x1F,
+            dt2: values[4] >> 6,
+            d2r: values[4] & 0x1F,
+            d1l: values[5] >> 4,
+            rr: values[5] & 0x0F,
+        }
+    }
+}
+
+/// Chip-wide LFO and noise settings stored with a voice
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpmLfo {
+    /// LFO frequency (0-255)
+    pub lfrq: u8,
+    /// Amplitude modulation depth (0-127)
+    pub amd: u8,
+    /// Phase modulation depth (0-127)
+    pub pmd: u8,
+    /// Waveform: 0 saw, 1 square, 2 triangle, 3 noise
+    pub wf: u8,
+    /// Noise frequency (0-31)
+    pub nfrq: u8,
+}
+
+/// An OPM voice (patch) as VOPM parameters
+///
+/// This is the one voice type of the crate: VOPM banks, JSON voice banks, MIDI
+/// import and the MDX voice table all read into it. Missing fields take their
+/// defaults when it is read from JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpmVoice {
+    /// Bank number (`@:` in VOPM)
+    pub number: u16,
+    pub name: String,
+    pub lfo: OpmLfo,
+    /// VOPM pan, 0-127 with 64 as both speakers
+    pub pan: u8,
+    /// Feedback (0-7)
+    pub fb: u8,
+    /// Algorithm (0-7, CON in VOPM)
+    pub alg: u8,
+    /// Amplitude modulation sensitivity (0-3)
+    pub ams: u8,
+    /// Phase modulation sensitivity (0-7)
+    pub pms: u8,
+    /// Operators keyed on, as written to bits 3-6 of register 0x08 (M1, C1, M2, C2)
+    pub slot_mask: u8,
+    /// Noise enable (only channel 7 can play noise)
+    pub noise: bool,
+    /// Operators in register slot order (M1, M2, C1, C2)
+    pub operators: [OpmOperator; 4],
+}
+
+impl Default for OpmVoice {
+    fn default() -> Self {
+        Self {
+            number: 0,
+            name: String::new(),
+            lfo: OpmLfo::default(),
+            pan: 64,
+            fb: 0,
+            alg: 0,
+            ams: 0,
+            pms: 0,
+            slot_mask: 0x0F,
+            noise: false,
+            operators: [OpmOperator::default(); 4],
+        }
+    }
+}
+
+impl OpmVoice {
+    /// Register 0x20 value: output bits, feedback and algorithm
+    pub fn pan_fb_con(&self) -> u8 {
+        let output = if self.pan < PAN_LEFT_BELOW {
+            PAN_LEFT
+        } else if self.pan > PAN_RIGHT_ABOVE {
+            PAN_RIGHT
+        } else {
+            PAN_CENTER
+        };
+        output | self.fb_con()
+    }
+
+    /// Feedback and algorithm bits of register 0x20, without the output bits
+    pub fn fb_con(&self) -> u8 {
+        (self.fb & 0x07) << 3 | (self.alg & 0x07)
+    }
+
+    /// Whether the operator at register slot `slot` (M1, M2, C1, C2) is a
+    /// carrier, i.e. its TL sets the channel volume
+    pub fn is_carrier(&self, slot: usize) -> bool {
+        is_carrier(self.alg, slot)
+    }
+
+    /// Register 0x08 value that keys the voice on for `channel`
+    pub fn key_on_data(&self, channel: u8) -> u8 {
+        (self.slot_mask & 0x0F) << 3 | (channel & 0x07)
+    }
+
+    /// (address, data) writes that load the voice onto `channel`, in address order
+    ///
+    /// Only channel registers are written; the LFO and noise settings are shared
+    /// by all channels and come from [`lfo_writes`](Self::lfo_writes).
+    pub fn register_writes(&self, channel: u8) -> Vec<(u8, u8)> {
+        let channel = channel & 0x07;
+        let mut writes = vec![
+            (0x20 + channel, self.pan_fb_con()),
+            (0x38 + channel, (self.pms & 0x07) << 4 | (self.ams & 0x03)),
+        ];
+        for (block, base) in [0x40u8, 0x60, 0x80, 0xA0, 0xC0, 0xE0].iter().enumerate() {
+            for (slot, operator) in self.operators.iter().enumerate() {
+                let addr = base + slot as u8 * 8 + channel;
+                writes.push((addr, operator.register_values()[block]));
+            }
+        }
+        writes
+    }
+
+    /// (address, data) writes for the chip-wide LFO and noise settings
+    pub fn lfo_writes(&self) -> Vec<(u8, u8)> {
+        vec![
+            (0x18, self.lfo.lfrq),
+            (0x19, self.lfo.amd & 0x7F),
+            (0x19, 0x80 | (self.lfo.pmd & 0x7F)),
+            (0x1B, self.lfo.wf & 0x03),
+            (0x0F, (self.noise as u8) << 7 | (self.lfo.nfrq & 0x1F)),
+        ]
+    }
+
+    /// Whether two voices sound the same, ignoring number and name
+    pub fn same_sound(&self, other: &OpmVoice) -> bool {
+        self.lfo == other.lfo
+            && self.pan_fb_con() == other.pan_fb_con()
+            && (self.ams, self.pms, self.slot_mask, self.noise)
+                == (other.ams, other.pms, other.slot_mask, other.noise)
+            && self.operators == other.operators
+    }
+}
+
+/// Parse a VOPM `.opm` bank
+pub fn parse_opm(text: &str) -> Result<Vec<OpmVoice>> {
+    let mut voices = Vec::new();
+    let mut current: Option<(OpmVoice, [bool; 6])> = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.split("//").next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (key, rest) = line
+            .split_once(':')
+            .ok_or_else(|| anyhow!("line {}: expected `NAME: values`", line_number))?;
+        let key = key.trim();
+
+        if key == "@" {
+            if let Some(voice) = current.take() {
+                voices.push(finish_voice(voice)?);
+            }
+            let rest = rest.trim();
+            let (number, name) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let number = number
+                .parse()
+                .map_err(|_| anyhow!("line {}: invalid voice number `{}`", line_number, number))?;
+            let voice = OpmVoice {
+                number,
+                name: name.trim().to_string(),
+                ..Default::default()
+            };
+            current = Some((voice, [false; 6]));
+            continue;
+        }
+
+        let (voice, seen) = current
+            .as_mut()
+            .ok_or_else(|| anyhow!("line {}: `{}` before the first `@:`", line_number, key))?;
+        let values = parse_values(rest, line_number)?;
+        let field = |index: usize, name: &str, max: u8| -> Result<u8> {
+            match values.get(index) {
+                Some(&value) if value <= max as u32 => Ok(value as u8),
+                Some(value) => bail!(
+                    "line {}: {} {} is out of range (0-{})",
+                    line_number,
+                    name,
+                    value,
+                    max
+                ),
+                None => bail!("line {}: {} is missing", line_number, name),
+            }
+        };
+
+        let line_index = match key {
+            "LFO" => {
+                voice.lfo = OpmLfo {
+                    lfrq: field(0, "LFRQ", 255)?,
+                    amd: field(1, "AMD", 127)?,
+                    pmd: field(2, "PMD", 127)?,
+                    wf: field(3, "WF", 3)?,
+                    nfrq: field(4, "NFRQ", 31)?,
+                };
+                0
+            }
+            "CH" => {
+                voice.pan = field(0, "PAN", 127)?;
+                voice.fb = field(1, "FL", 7)?;
+                voice.alg = field(2, "CON", 7)?;
+                voice.ams = field(3, "AMS", 3)?;
+                voice.pms = field(4, "PMS", 7)?;
+                voice.slot_mask = field(5, "SLOT", 120)? >> 3;
+                voice.noise = field(6, "NE", 1)? != 0;
+                1
+            }
+            _ => {
+                let position = FILE_OPERATORS
+                    .iter()
+                    .position(|&name| name == key)
+                    .ok_or_else(|| anyhow!("line {}: unknown line `{}`", line_number, key))?;
+                voice.operators[FILE_TO_SLOT[position]] = OpmOperator {
+                    ar: field(0, "AR", 31)?,
+                    d1r: field(1, "D1R", 31)?,
+                    d2r: field(2, "D2R", 31)?,
+                    rr: field(3, "RR", 15)?,
+                    d1l: field(4, "D1L", 15)?,
+                    tl: field(5, "TL", 127)?,
+                    ks: field(6, "KS", 3)?,
+                    mul: field(7, "MUL", 15)?,
+                    dt1: field(8, "DT1", 7)?,
+                    dt2: field(9, "DT2", 3)?,
+                    ams_en: field(10, "AMS-EN", 255)? != 0,
+                };
+                2 + position
+            }
+        };
+        seen[line_index] = true;
+    }
+
+    if let Some(voice) = current {
+        voices.push(finish_voice(voice)?);
+    }
+    Ok(voices)
+}
+
+fn parse_values(text: &str, line_number: usize) -> Result<Vec<u32>> {
+    text.split_whitespace()
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| anyhow!("line {}: invalid value `{}`", line_number, value))
+        })
+        .collect()
+}
+
+fn finish_voice((voice, seen): (OpmVoice, [bool; 6])) -> Result<OpmVoice> {
+    let names = ["LFO", "CH", "M1", "C1", "M2", "C2"];
+    if let Some(missing) = seen.iter().position(|&seen| !seen) {
+        bail!("voice @:{} has no {} line", voice.number, names[missing]);
+    }
+    Ok(voice)
+}
+
+/// Write voices as a VOPM `.opm` bank
+pub fn write_opm(voices: &[OpmVoice]) -> String {
+    let mut text = HEADER.to_string();
+    for voice in voices {
+        let lfo = &voice.lfo;
+        let _ = writeln!(text);
+        let _ = writeln!(text, "@:{} {}", voice.number, voice.name);
+        let _ = writeln!(
+            text,
+            "LFO:{}",
+            columns(&[lfo.lfrq, lfo.amd, lfo.pmd, lfo.wf, lfo.nfrq])
+        );
+        let _ = writeln!(
+            text,
+            "CH:{}",
+            columns(&[
+                voice.pan,
+                voice.fb,
+                voice.alg,
+                voice.ams,
+                voice.pms,
+                (voice.slot_mask & 0x0F) << 3,
+                voice.noise as u8,
+            ])
+        );
+        for (name, slot) in FILE_OPERATORS.iter().zip(FILE_TO_SLOT) {
+            let op = &voice.operators[slot];
+            let ams_en = if op.ams_en { AMS_ENABLED } else { 0 };
+            let _ = writeln!(
+                text,
+                "{}:{}",
+                name,
+                columns(&[
+                    op.ar, op.d1r, op.d2r, op.rr, op.d1l, op.tl, op.ks, op.mul, op.dt1, op.dt2,
+                    ams_en,
+                ])
+            );
+        }
+    }
+    text
+}
+
+fn columns(values: &[u8]) -> String {
+    values.iter().map(|value| format!(" {:3}", value)).collect()
+}
+
+/// Load a VOPM `.opm` bank file
+pub fn load_opm<P: AsRef<Path>>(path: P) -> Result<Vec<OpmVoice>> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read voice bank: {}", path.display()))?;
+    parse_opm(&text).with_context(|| format!("Invalid VOPM bank: {}", path.display()))
+}
+
+/// Save voices as a VOPM `.opm` bank file
+pub fn save_opm<P: AsRef<Path>>(path: P, voices: &[OpmVoice]) -> Result<()> {
+    let path = path.as_ref();
+    std::fs::write(path, write_opm(voices))
+        .with_context(|| format!("Failed to write file: {}", path.display()))
+}
+
+/// A key-on that played an extracted voice
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoiceUse {
+    pub time: f64,
+    pub channel: u8,
+}
+
+/// A distinct voice found in an event log and where it was played
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractedVoice {
+    pub voice: OpmVoice,
+    pub uses: Vec<VoiceUse>,
+}
+
+/// Collect every distinct voice keyed on in a log, in order of first use
+///
+/// The voice of a channel is read from the register values at each key-on, so
+/// a patch whose TL is changed for volume counts as a new voice. Voices are
+/// numbered from 0 in the order found.
+pub fn extract_voices(log: &EventLog) -> Vec<ExtractedVoice> {
+    let mut events: Vec<_> = log.events.iter().collect();
+    events.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    let mut registers = [0u8; 256];
+    let mut amd = 0;
+    let mut pmd = 0;
+    let mut found: Vec<ExtractedVoice> = Vec::new();
+
+    for event in events {
+        match event.addr {
+            0x19 if event.data & 0x80 != 0 => pmd = event.data & 0x7F,
+            0x19 => amd = event.data,
+            0x08 if event.data & 0x78 != 0 => {
+                let channel = event.data & 0x07;
+                let mut voice = voice_from_registers(&registers, channel, event.data >> 3);
+                voice.lfo.amd = amd;
+                voice.lfo.pmd = pmd;
+
+                let usage = VoiceUse {
+                    time: event.time,
+                    channel,
+                };
+                match found.iter_mut().find(|e| e.voice.same_sound(&voice)) {
+                    Some(existing) => existing.uses.push(usage),
+                    None => {
+                        voice.number = found.len() as u16;
+                        voice.name = format!("Voice {}", voice.number);
+                        found.push(ExtractedVoice {
+                            voice,
+                            uses: vec![usage],
+                        });
+                    }
+                }
+            }
+            addr => registers[addr as usize] = event.data,
+        }
+    }
+    found
+}
+
+fn voice_from_registers(registers: &[u8; 256], channel: u8, slot_mask: u8) -> OpmVoice {
+    let channel = channel as usize;
+    let pan_fb_con = registers[0x20 + channel];
+    let pms_ams = registers[0x38 + channel];
+    let pan = match pan_fb_con & 0xC0 {
+        PAN_LEFT => 0,
+        PAN_RIGHT => 127,
+        _ => 64,
+    };
+
+    let mut operators = [OpmOperator::default(); 4];
+    for (slot, operator) in operators.iter_mut().enumerate() {
+        let offset = slot * 8 + channel;
+        let mut values = [0; 6];
+        for (block, value) in values.iter_mut().enumerate() {
+            *value = registers[0x40 + block * 0x20 + offset];
+        }
+        *operator = OpmOperator::from_register_values(values);
+    }
+
+    OpmVoice {
+        number: 0,
+        name: String::new(),
+        lfo: OpmLfo {
+            lfrq: registers[0x18],
+            amd: 0,
+            pmd: 0,
+            wf: registers[0x1B] & 0x03,
+            nfrq: registers[0x0F] & 0x1F,
+        },
+        pan,
+        fb: pan_fb_con >> 3 & 0x07,
+        alg: pan_fb_con & 0x07,
+        ams: pms_ams & 0x03,
+        pms: pms_ams >> 4 & 0x07,
+        slot_mask: slot_mask & 0x0F,
+        noise: registers[0x0F] & 0x80 != 0,
+        operators,
+    }
+}
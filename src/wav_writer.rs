@@ -1,12 +1,14 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use hound::{SampleFormat, WavSpec, WavWriter};
 
 use crate::audio_config::buffer::GENERATION_BUFFER_SIZE;
+use crate::pcm::{self, Dither, SampleFormat as PcmFormat};
 use crate::player::Player;
 
 pub const DEFAULT_OUTPUT_FILENAME: &str = "output.wav";
 
-const MAX_TAIL_SECONDS: u32 = 10;
+/// Longest tail rendered after the last event, by default
+pub const MAX_TAIL_SECONDS: u32 = 10;
 
 const TAIL_DURATION_MULTIPLIER: u32 = 10;
 
@@ -31,15 +33,97 @@ pub fn write_wav(path: &str, samples: &[i16], sample_rate: u32) -> Result<()> {
     Ok(())
 }
 
+/// Write full-precision stereo samples (see [`crate::pcm`]) as 16-bit or
+/// 24-bit int or 32-bit float WAV, reduced to 16 bits as `dither` says
+pub fn write_wav_full(
+    path: &str,
+    samples: &[f32],
+    sample_rate: u32,
+    format: PcmFormat,
+    dither: Dither,
+) -> Result<()> {
+    write_wav_channels(path, samples, 2, sample_rate, format, dither)
+}
+
+/// Write full-precision interleaved samples of any number of channels, as
+/// [`write_wav_full`] does for stereo
+pub fn write_wav_channels(
+    path: &str,
+    samples: &[f32],
+    channels: u16,
+    sample_rate: u32,
+    format: PcmFormat,
+    dither: Dither,
+) -> Result<()> {
+    write_wav_blocks(path, [Ok(samples)], channels, sample_rate, format, dither)
+}
+
+/// Write interleaved samples arriving in blocks of whole frames, as
+/// [`write_wav_channels`] does for samples all in memory
+pub fn write_wav_blocks<B: AsRef<[f32]>>(
+    path: &str,
+    blocks: impl IntoIterator<Item = Result<B>>,
+    channels: u16,
+    sample_rate: u32,
+    format: PcmFormat,
+    dither: Dither,
+) -> Result<()> {
+    if channels == 0 {
+        bail!("a WAV file needs at least one channel");
+    }
+    let spec = WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: format.bits_per_sample(),
+        sample_format: match format {
+            PcmFormat::Float32 => SampleFormat::Float,
+            PcmFormat::Int16 | PcmFormat::Int24 => SampleFormat::Int,
+        },
+    };
+
+    let mut writer = WavWriter::create(path, spec)
+        .with_context(|| format!("Failed to create WAV file: {}", path))?;
+
+    let mut to_i16 = pcm::I16Converter::new(dither);
+    for block in blocks {
+        let block = block?;
+        let samples = block.as_ref();
+        if !samples.len().is_multiple_of(channels as usize) {
+            bail!(
+                "{} samples do not make whole frames of {} channels",
+                samples.len(),
+                channels
+            );
+        }
+        let written = match format {
+            PcmFormat::Int16 => to_i16
+                .convert(samples)
+                .into_iter()
+                .try_for_each(|sample| writer.write_sample(sample)),
+            PcmFormat::Int24 => pcm::to_i24(samples)
+                .into_iter()
+                .try_for_each(|sample| writer.write_sample(sample)),
+            PcmFormat::Float32 => samples
+                .iter()
+                .try_for_each(|&sample| writer.write_sample(sample)),
+        };
+        written.context("Failed to write sample to WAV file")?;
+    }
+
+    writer.finalize().context("Failed to finalize WAV file")?;
+    Ok(())
+}
+
 pub fn generate_wav(mut player: Player, output_path: &str) -> Result<()> {
     println!("Generating WAV file: {}", output_path);
 
     let total_samples = player.total_samples();
-    let total_duration = total_samples as f64 / Player::sample_rate() as f64;
+    let sample_rate = player.current_sample_rate();
+    let total_duration = total_samples as f64 / sample_rate as f64;
     println!("  Total duration: {:.2} seconds", total_duration);
     println!(
         "  Sample rate: {} Hz (native OPM rate, no resampling)",
-        Player::sample_rate()
+        sample_rate
     );
 
     let mut output_samples = Vec::new();
@@ -70,20 +154,20 @@ pub fn generate_wav(mut player: Player, output_path: &str) -> Result<()> {
 
         if !player.should_continue_tail() {
             if let Some((tail_samples, _)) = player.tail_info() {
-                let tail_ms = tail_samples as f64 / Player::sample_rate() as f64 * 1000.0;
+                let tail_ms = tail_samples as f64 / sample_rate as f64 * 1000.0;
                 println!("  演奏データの余韻{}ms 波形生成 OK", tail_ms as u32);
             }
             break;
         }
 
         let max_tail_samples = std::cmp::max(
-            Player::sample_rate() * MAX_TAIL_SECONDS,
+            sample_rate * MAX_TAIL_SECONDS,
             total_samples * TAIL_DURATION_MULTIPLIER,
         );
         if processed_samples > (total_samples as usize + max_tail_samples as usize) {
             println!("  Warning: Tail generation exceeded safety limit");
             if let Some((tail_samples, _)) = player.tail_info() {
-                let tail_ms = tail_samples as f64 / Player::sample_rate() as f64 * 1000.0;
+                let tail_ms = tail_samples as f64 / sample_rate as f64 * 1000.0;
                 println!(
                     "  演奏データの余韻{}ms 波形生成 OK (safety limit)",
                     tail_ms as u32
@@ -96,12 +180,12 @@ pub fn generate_wav(mut player: Player, output_path: &str) -> Result<()> {
     println!(
         "  Generated {} samples ({:.2}s at {} Hz)",
         output_samples.len() / 2,
-        output_samples.len() as f64 / 2.0 / Player::sample_rate() as f64,
-        Player::sample_rate()
+        output_samples.len() as f64 / 2.0 / sample_rate as f64,
+        sample_rate
     );
 
     println!("  Writing to file...");
-    write_wav(output_path, &output_samples, Player::sample_rate())
+    write_wav(output_path, &output_samples, sample_rate)
         .with_context(|| format!("Failed to write WAV file: {}", output_path))?;
 
     println!("✅ WAV file created successfully!");
@@ -18,6 +18,7 @@ fn test_audio_player_creation() {
             data: 0x00,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let player = Player::new(log);
@@ -71,6 +72,7 @@ fn test_audio_player_with_multiple_events() {
                 is_data: None,
             },
         ],
+        ..Default::default()
     };
 
     let player = Player::new(log);
@@ -102,6 +104,7 @@ fn test_audio_player_early_stop() {
 
     let log = EventLog {
         events,
+        ..Default::default()
     };
 
     let player = Player::new(log);
@@ -127,6 +130,7 @@ fn test_audio_player_drop() {
             data: 0x00,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let player = Player::new(log);
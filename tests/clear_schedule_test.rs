@@ -7,7 +7,7 @@ use ym2151_log_play_server::player::Player;
 
 #[test]
 fn test_clear_schedule_removes_all_events() {
-    let player = Player::new_interactive();
+    let mut player = Player::new_interactive();
 
     // Schedule phrase 1 events
     player.schedule_register_write(100, 0x08, 0x78);
@@ -16,8 +16,7 @@ fn test_clear_schedule_removes_all_events() {
 
     // Verify events were scheduled
     {
-        let queue = player.get_event_queue();
-        let q = queue.lock().unwrap();
+        let q = player.scheduled_events();
         assert_eq!(q.len(), 3); // 3 addr-data pair events
     }
 
@@ -26,8 +25,7 @@ fn test_clear_schedule_removes_all_events() {
 
     // Verify all events were removed
     {
-        let queue = player.get_event_queue();
-        let q = queue.lock().unwrap();
+        let q = player.scheduled_events();
         assert_eq!(q.len(), 0);
     }
 
@@ -37,8 +35,7 @@ fn test_clear_schedule_removes_all_events() {
 
     // Verify only phrase 2 events are in the queue
     {
-        let queue = player.get_event_queue();
-        let q = queue.lock().unwrap();
+        let q = player.scheduled_events();
         assert_eq!(q.len(), 2); // 2 addr-data pair events
 
         // Verify first event is from phrase 2
@@ -67,8 +64,7 @@ fn test_clear_schedule_after_partial_playback() {
 
     // Verify queue is empty
     {
-        let queue = player.get_event_queue();
-        let q = queue.lock().unwrap();
+        let q = player.scheduled_events();
         assert_eq!(q.len(), 0);
     }
 
@@ -76,23 +72,21 @@ fn test_clear_schedule_after_partial_playback() {
     player.schedule_register_write(400, 0x40, 0xFF);
 
     {
-        let queue = player.get_event_queue();
-        let q = queue.lock().unwrap();
+        let q = player.scheduled_events();
         assert_eq!(q.len(), 1); // 1 addr-data pair event
     }
 }
 
 #[test]
 fn test_clear_schedule_on_empty_queue() {
-    let player = Player::new_interactive();
+    let mut player = Player::new_interactive();
 
     // Clear schedule when queue is already empty (should not panic)
     player.clear_schedule();
 
     // Verify queue is still empty
     {
-        let queue = player.get_event_queue();
-        let q = queue.lock().unwrap();
+        let q = player.scheduled_events();
         assert_eq!(q.len(), 0);
     }
 
@@ -100,15 +94,14 @@ fn test_clear_schedule_on_empty_queue() {
     player.schedule_register_write(100, 0x08, 0x78);
 
     {
-        let queue = player.get_event_queue();
-        let q = queue.lock().unwrap();
+        let q = player.scheduled_events();
         assert_eq!(q.len(), 1); // 1 addr-data pair event
     }
 }
 
 #[test]
 fn test_clear_schedule_multiple_times() {
-    let player = Player::new_interactive();
+    let mut player = Player::new_interactive();
 
     // Schedule some events
     player.schedule_register_write(100, 0x08, 0x78);
@@ -125,8 +118,7 @@ fn test_clear_schedule_multiple_times() {
 
     // Verify queue is empty
     {
-        let queue = player.get_event_queue();
-        let q = queue.lock().unwrap();
+        let q = player.scheduled_events();
         assert_eq!(q.len(), 0);
     }
 }
@@ -41,6 +41,7 @@ fn test_post_playback_buffer_generation() {
                 is_data: None,
             },
         ],
+        ..Default::default()
     };
 
     let result = debug_wav::generate_post_playback_buffers(&log, ResamplingQuality::Linear);
@@ -128,6 +129,7 @@ fn test_complete_debug_workflow() {
                 is_data: None,
             },
         ],
+        ..Default::default()
     };
 
     // Step 1: Check if debug is enabled (default is off)
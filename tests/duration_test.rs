@@ -26,6 +26,7 @@ fn test_player_generates_all_samples() {
                 is_data: None,
             },
         ],
+        ..Default::default()
     };
 
     let mut player = Player::new(log);
@@ -96,6 +97,7 @@ fn test_player_generates_samples_after_last_event() {
             data: 0x00,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let mut player = Player::new(log);
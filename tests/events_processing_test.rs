@@ -30,6 +30,7 @@ fn test_pass1_to_pass2_conversion() {
                 is_data: None,
             },
         ],
+        ..Default::default()
     };
 
     let player = Player::new(log);
@@ -55,6 +56,7 @@ fn test_event_execution_timing() {
                 is_data: None,
             },
         ],
+        ..Default::default()
     };
 
     let mut player = Player::new(log);
@@ -99,6 +101,7 @@ fn test_delay_samples() {
             data: 0xFF,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let mut player = Player::new(log);
@@ -169,6 +172,7 @@ fn test_complete_playback() {
                 is_data: None,
             },
         ],
+        ..Default::default()
     };
 
     let mut player = Player::new(log);
@@ -186,7 +190,10 @@ fn test_complete_playback() {
 
 #[test]
 fn test_empty_log() {
-    let log = EventLog { events: vec![] };
+    let log = EventLog {
+        events: vec![],
+        ..Default::default()
+    };
 
     let player = Player::new(log);
 
@@ -218,6 +225,7 @@ fn test_event_order_preservation() {
                 is_data: None,
             },
         ],
+        ..Default::default()
     };
 
     let mut player = Player::new(log);
@@ -244,6 +252,7 @@ fn test_buffer_boundaries() {
             data: 0x00,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let mut player = Player::new(log);
@@ -275,6 +284,7 @@ fn test_total_samples_calculation() {
             data: 0x00,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let player = Player::new(log);
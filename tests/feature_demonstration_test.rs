@@ -14,7 +14,7 @@ mod feature_demonstrations {
         //! Demonstrates clear_schedule for seamless phrase transitions
         //! (Migrated from examples/clear_schedule_demo.rs)
 
-        let player = Player::new_interactive();
+        let mut player = Player::new_interactive();
 
         println!("🎮 Clear Schedule Demo - Seamless Phrase Transitions");
 
@@ -33,8 +33,7 @@ mod feature_demonstrations {
 
         // Verify events were scheduled
         {
-            let queue = player.get_event_queue();
-            let q = queue.lock().unwrap();
+            let q = player.scheduled_events();
             assert!(!q.is_empty(), "Phrase 1 should have scheduled events");
         }
 
@@ -43,8 +42,7 @@ mod feature_demonstrations {
 
         // Verify schedule was cleared
         {
-            let queue = player.get_event_queue();
-            let q = queue.lock().unwrap();
+            let q = player.scheduled_events();
             assert_eq!(q.len(), 0, "Schedule should be cleared");
         }
 
@@ -58,8 +56,7 @@ mod feature_demonstrations {
 
         // Verify new events are scheduled
         {
-            let queue = player.get_event_queue();
-            let q = queue.lock().unwrap();
+            let q = player.scheduled_events();
             assert!(!q.is_empty(), "Phrase 2 should have scheduled events");
         }
 
@@ -93,7 +90,7 @@ mod feature_demonstrations {
 
         println!("🎮 Interactive Mode Demo");
 
-        let player = Player::new_interactive();
+        let mut player = Player::new_interactive();
         assert!(
             player.is_interactive(),
             "Player should be in interactive mode"
@@ -133,8 +130,7 @@ mod feature_demonstrations {
 
         // Verify events are scheduled
         {
-            let queue = player.get_event_queue();
-            let q = queue.lock().unwrap();
+            let q = player.scheduled_events();
             assert!(
                 !q.is_empty(),
                 "Interactive mode should have scheduled events"
@@ -196,7 +192,7 @@ mod feature_demonstrations {
         assert!(log3.validate(), "Third JSON should be valid");
 
         // Demonstrate that these could be played in interactive mode
-        let player = Player::new_interactive();
+        let mut player = Player::new_interactive();
 
         // Convert events from first JSON and schedule them
         for event in &log1.events {
@@ -206,8 +202,7 @@ mod feature_demonstrations {
 
         // Verify events are scheduled
         {
-            let queue = player.get_event_queue();
-            let q = queue.lock().unwrap();
+            let q = player.scheduled_events();
             assert!(!q.is_empty(), "Events from JSON should be scheduled");
         }
 
@@ -1,4 +1,6 @@
 use ym2151_log_play_server::events::EventLog;
+use ym2151_log_play_server::formats::{load_event_log, save_event_log};
+use ym2151_log_play_server::player::Player;
 
 #[test]
 fn test_load_simple_fixture() {
@@ -79,3 +81,222 @@ fn test_event_time_span() {
         "Last event should be well after 50k samples"
     );
 }
+
+#[test]
+fn test_load_vgm_fixture() {
+    let loaded = load_event_log("tests/fixtures/simple.vgm").expect("Failed to load simple.vgm");
+
+    assert_eq!(loaded.log.events.len(), 7);
+    assert_eq!(loaded.log.clock, Some(4_000_000));
+    assert!(loaded.log.validate());
+    // The SN76489 write in the fixture is skipped and reported
+    assert_eq!(loaded.warnings.len(), 1);
+}
+
+#[test]
+fn test_load_vgz_fixture_matches_vgm() {
+    let vgm = load_event_log("tests/fixtures/simple.vgm").unwrap();
+    let vgz = load_event_log("tests/fixtures/simple.vgz").unwrap();
+
+    assert_eq!(vgm.log.events.len(), vgz.log.events.len());
+    for (a, b) in vgm.log.events.iter().zip(&vgz.log.events) {
+        assert_eq!((a.time, a.addr, a.data), (b.time, b.addr, b.data));
+    }
+}
+
+#[test]
+fn test_render_vgm_fixture_offline() {
+    let loaded = load_event_log("tests/fixtures/simple.vgm").unwrap();
+    let mut player = Player::new(loaded.log);
+    assert_eq!(player.clock(), 4_000_000);
+
+    let mut buffer = vec![0i16; 4096];
+    player.generate_samples(&mut buffer);
+}
+
+#[test]
+fn test_json_fixture_converts_to_vgm_and_back() {
+    let original = EventLog::from_file("tests/fixtures/complex.json").unwrap();
+    let path = std::env::temp_dir().join("ym2151_integration_complex.vgm");
+
+    save_event_log(&path, &original).expect("Failed to export VGM");
+    let loaded = load_event_log(&path).expect("Failed to import exported VGM");
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(loaded.log.events.len(), original.events.len());
+    assert!(loaded.warnings.is_empty());
+    for (a, b) in loaded.log.events.iter().zip(&original.events) {
+        assert_eq!((a.addr, a.data), (b.addr, b.data));
+    }
+}
+
+#[test]
+fn test_load_s98_fixture() {
+    let loaded = load_event_log("tests/fixtures/simple.s98").expect("Failed to load simple.s98");
+
+    assert_eq!(loaded.log.events.len(), 4);
+    assert_eq!(loaded.log.clock, Some(4_000_000));
+    assert_eq!(loaded.log.loop_time, Some(0.01));
+    assert!(loaded.log.validate());
+    // The PSG write in the fixture is skipped and reported
+    assert_eq!(loaded.warnings.len(), 1);
+
+    let metadata = loaded.log.metadata.expect("S98 tag should be imported");
+    assert_eq!(metadata.title.as_deref(), Some("テスト曲"));
+    assert_eq!(metadata.author.as_deref(), Some("cat2151"));
+    assert_eq!(metadata.date.as_deref(), Some("2026"));
+}
+
+#[test]
+fn test_json_fixture_exports_to_s98_fixture() {
+    let log = EventLog::from_file("tests/fixtures/complex.json").unwrap();
+    let expected = std::fs::read("tests/fixtures/complex.s98").unwrap();
+
+    assert_eq!(
+        ym2151_log_play_server::formats::s98::export(&log).unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn test_s98_fixture_round_trip() {
+    let original = load_event_log("tests/fixtures/simple.s98").unwrap().log;
+    let path = std::env::temp_dir().join("ym2151_integration_simple.s98");
+
+    save_event_log(&path, &original).expect("Failed to export S98");
+    let reloaded = load_event_log(&path).expect("Failed to import exported S98");
+    let _ = std::fs::remove_file(&path);
+
+    // The exported file only has the OPM device, so nothing is skipped any more
+    assert!(reloaded.warnings.is_empty());
+    assert_eq!(reloaded.log.clock, original.clock);
+    assert_eq!(reloaded.log.metadata, original.metadata);
+    assert_eq!(reloaded.log.events.len(), original.events.len());
+
+    // Times come back on the OPM sample grid the Player would use
+    let rate = 62500.0;
+    for (a, b) in reloaded.log.events.iter().zip(&original.events) {
+        assert_eq!((a.addr, a.data), (b.addr, b.data));
+        assert!((a.time - b.time).abs() <= 4.0 / rate);
+    }
+    let loop_time = reloaded.log.loop_time.unwrap();
+    assert!((loop_time - 0.01).abs() <= 1.0 / rate);
+}
+
+#[test]
+fn test_load_mdx_fixture() {
+    let loaded = load_event_log("tests/fixtures/simple.mdx").expect("Failed to load simple.mdx");
+    let log = &loaded.log;
+
+    assert_eq!(log.clock, Some(4_000_000));
+    assert!(log.validate());
+    // Channel P (ADPCM) and the PDX bank are reported
+    assert_eq!(loaded.warnings.len(), 2);
+    let metadata = log.metadata.as_ref().expect("MDX title should be imported");
+    assert_eq!(metadata.title.as_deref(), Some("simple test song"));
+
+    // Channel A plays o4a twice; channel B loops o4e until channel A ends
+    let key_ons: Vec<u8> = log
+        .events
+        .iter()
+        .filter(|e| e.addr == 0x08 && e.data & 0x78 != 0)
+        .map(|e| e.data)
+        .collect();
+    assert_eq!(key_ons.iter().filter(|&&data| data == 0x78).count(), 2);
+    assert_eq!(key_ons.iter().filter(|&&data| data == 0x79).count(), 6);
+
+    let mut player = Player::new(loaded.log.clone());
+    let mut buffer = vec![0i16; 4096];
+    player.generate_samples(&mut buffer);
+}
+
+#[test]
+fn test_midi_fixture_with_voice_bank() {
+    use ym2151_log_play_server::formats::smf::{self, SmfOptions, VoiceBank};
+
+    let bytes = std::fs::read("tests/fixtures/simple.mid").unwrap();
+    let options = SmfOptions {
+        voice_bank: VoiceBank::from_file("tests/fixtures/voice_bank.json").unwrap(),
+        ..Default::default()
+    };
+    let import = smf::import(&bytes, &options).expect("Failed to convert simple.mid");
+    let log = &import.log;
+
+    assert!(log.validate());
+    // Only the percussion part is reported; program 1 comes from the bank
+    assert_eq!(import.warnings().len(), 1);
+    let metadata = log
+        .metadata
+        .as_ref()
+        .expect("track name should be the title");
+    assert_eq!(metadata.title.as_deref(), Some("MIDI sketch"));
+
+    // Three melody notes, the last one after the tempo doubles
+    let key_on_times: Vec<f64> = log
+        .events
+        .iter()
+        .filter(|e| e.addr == 0x08 && e.data & 0x78 != 0)
+        .map(|e| e.time)
+        .collect();
+    assert_eq!(key_on_times, vec![0.0, 0.5, 1.0]);
+    assert_eq!(log.events.last().unwrap().time, 1.25);
+
+    let mut player = Player::new(import.log.clone());
+    let mut buffer = vec![0i16; 4096];
+    player.generate_samples(&mut buffer);
+}
+
+#[test]
+fn test_json_fixture_exports_to_midi() {
+    let log = EventLog::from_file("output_ym2151.json").unwrap();
+    let path = std::env::temp_dir().join("ym2151_integration_output.mid");
+
+    save_event_log(&path, &log).expect("Failed to export MIDI");
+    let loaded = load_event_log(&path).expect("Failed to import exported MIDI");
+    let _ = std::fs::remove_file(&path);
+
+    let key_ons = |log: &EventLog| {
+        log.events
+            .iter()
+            .filter(|e| e.addr == 0x08 && e.data & 0x78 != 0)
+            .count()
+    };
+    // Every key-on of the log comes back as a note
+    assert_eq!(key_ons(&loaded.log), key_ons(&log));
+    assert!(loaded.log.validate());
+}
+
+#[test]
+fn test_extract_voices_to_opm_bank() {
+    use ym2151_log_play_server::voice;
+
+    let log = EventLog::from_file("output_ym2151.json").unwrap();
+    let extracted = voice::extract_voices(&log);
+    assert!(!extracted.is_empty());
+
+    let voices: Vec<_> = extracted.iter().map(|e| e.voice.clone()).collect();
+    let path = std::env::temp_dir().join("ym2151_integration_voices.opm");
+    voice::save_opm(&path, &voices).expect("Failed to save VOPM bank");
+    let reloaded = voice::load_opm(&path).expect("Failed to load VOPM bank");
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(reloaded.len(), voices.len());
+    for (a, b) in reloaded.iter().zip(&voices) {
+        assert!(a.same_sound(b));
+    }
+}
+
+#[test]
+fn test_disassembly_round_trips_sample_log() {
+    use ym2151_log_play_server::disasm;
+
+    let log = EventLog::from_file("output_ym2151.json").unwrap();
+    let text = disasm::disassemble(&log);
+    assert!(text.contains("KEY ON"));
+
+    let reassembled = disasm::assemble(&text).expect("Failed to assemble disassembly");
+    assert_eq!(reassembled.events.len(), log.events.len());
+    for (a, b) in log.events.iter().zip(&reassembled.events) {
+        assert_eq!((a.time, a.addr, a.data), (b.time, b.addr, b.data));
+    }
+}
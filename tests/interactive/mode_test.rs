@@ -16,15 +16,14 @@ fn test_interactive_player_creation() {
 
 #[test]
 fn test_schedule_register_write() {
-    let player = Player::new_interactive();
+    let mut player = Player::new_interactive();
 
     // Schedule a register write at 100 samples converted to seconds
     let time_samples = 100;
     player.schedule_register_write(time_samples, 0x08, 0x78);
 
     // Verify the event was added to the queue
-    let queue = player.get_event_queue();
-    let q = queue.lock().unwrap();
+    let q = player.scheduled_events();
 
     // Should have 1 addr-data pair event
     assert_eq!(q.len(), 1);
@@ -37,15 +36,14 @@ fn test_schedule_register_write() {
 
 #[test]
 fn test_multiple_register_writes() {
-    let player = Player::new_interactive();
+    let mut player = Player::new_interactive();
 
     // Schedule multiple writes
     player.schedule_register_write(100, 0x08, 0x00);
     player.schedule_register_write(200, 0x20, 0xC7);
     player.schedule_register_write(300, 0x28, 0x3E);
 
-    let queue = player.get_event_queue();
-    let q = queue.lock().unwrap();
+    let q = player.scheduled_events();
 
     // Should have 3 addr-data pair events
     assert_eq!(q.len(), 3);
@@ -71,8 +69,7 @@ fn test_interactive_generate_samples() {
     assert!(has_more);
 
     // Event should have been processed
-    let queue = player.get_event_queue();
-    let q = queue.lock().unwrap();
+    let q = player.scheduled_events();
     assert!(
         q.is_empty(),
         "Event should be processed and removed from queue"
@@ -143,6 +140,7 @@ fn test_non_interactive_mode_unaffected() {
             data: 0x00,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let player = Player::new(log);
@@ -20,6 +20,7 @@ fn test_tail_generation_continues_after_events() {
                 is_data: None,
             },
         ],
+        ..Default::default()
     };
 
     let mut player = Player::new(log);
@@ -64,6 +65,7 @@ fn test_tail_generation_stops_after_silence() {
             data: 0x00,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let mut player = Player::new(log);
@@ -102,6 +104,7 @@ fn test_tail_info_before_events_complete() {
             data: 0x00,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let player = Player::new(log);
@@ -122,6 +125,7 @@ fn test_should_continue_tail_during_events() {
             data: 0x00,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let player = Player::new(log);
@@ -143,6 +147,7 @@ fn test_silence_detection_resets_on_non_zero_sample() {
             data: 0x00,
             is_data: None,
         }],
+        ..Default::default()
     };
 
     let mut player = Player::new(log);